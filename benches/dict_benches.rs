@@ -0,0 +1,144 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use redis::redis::{
+    hash_table::{HashDict, HashNode, KeyspaceKeys},
+    redis_object::{Access, RedisObject},
+    swiss_table::SwissDict,
+};
+use std::{hint::black_box, io::Write, time::Duration};
+
+// keys in the dict while measuring, override with DICT_BENCH_KEYS for a quicker run
+const DEFAULT_AMOUNT_KEYS: usize = 10_000_000;
+
+// the operations both dicts share, so every bench runs the same code against either of them
+trait BenchDict {
+    fn new() -> Self;
+    fn insert(&mut self, node: Box<HashNode<Box<[u8]>, RedisObject, Access>>);
+    fn lookup(&mut self, key: &[u8]) -> Option<&RedisObject>;
+    fn delete(&mut self, key: &[u8]) -> bool;
+    fn finish_resizing(&mut self);
+}
+
+impl BenchDict for HashDict<Box<[u8]>, RedisObject, KeyspaceKeys> {
+    fn new() -> Self {
+        HashDict::default()
+    }
+
+    fn insert(&mut self, node: Box<HashNode<Box<[u8]>, RedisObject, Access>>) {
+        HashDict::insert(self, node)
+    }
+
+    fn lookup(&mut self, key: &[u8]) -> Option<&RedisObject> {
+        HashDict::lookup(self, key)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> bool {
+        HashDict::delete(self, key)
+    }
+
+    fn finish_resizing(&mut self) {
+        self.rehash_for(Duration::from_secs(60));
+    }
+}
+
+impl BenchDict for SwissDict {
+    fn new() -> Self {
+        SwissDict::new()
+    }
+
+    fn insert(&mut self, node: Box<HashNode<Box<[u8]>, RedisObject, Access>>) {
+        SwissDict::insert(self, node)
+    }
+
+    fn lookup(&mut self, key: &[u8]) -> Option<&RedisObject> {
+        SwissDict::lookup(self, key)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> bool {
+        SwissDict::delete(self, key)
+    }
+
+    fn finish_resizing(&mut self) {
+        self.rehash_for(Duration::from_secs(60));
+    }
+}
+
+fn amount_keys() -> usize {
+    std::env::var("DICT_BENCH_KEYS")
+        .ok()
+        .and_then(|keys| keys.parse().ok())
+        .unwrap_or(DEFAULT_AMOUNT_KEYS)
+}
+
+// formats key:<i> into the buffer so the benches do not measure allocations for the keys
+fn key(buf: &mut [u8; 32], i: usize) -> &[u8] {
+    let mut cursor = &mut buf[..];
+    write!(cursor, "key:{}", i).unwrap();
+    let len = 32 - cursor.len();
+    &buf[..len]
+}
+
+// spreads consecutive indexes over the whole key range so lookups do not walk the table in
+// insertion order
+fn scatter(i: usize, amount_keys: usize) -> usize {
+    i.wrapping_mul(0x9E3779B97F4A7C15) % amount_keys
+}
+
+fn bench_dict<D: BenchDict>(c: &mut Criterion, name: &str) {
+    let amount_keys = amount_keys();
+    let mut buf = [0u8; 32];
+
+    let mut dict = D::new();
+    for i in 0..amount_keys {
+        dict.insert(Box::new(HashNode::new_from_bytes(
+            key(&mut buf, i),
+            b"value",
+        )));
+    }
+    dict.finish_resizing();
+
+    let mut group = c.benchmark_group(format!("dict_{}_keys", amount_keys));
+    group.sample_size(20);
+
+    let mut i = 0;
+    group.bench_function(format!("{}_lookup_hit", name), |b| {
+        b.iter(|| {
+            i += 1;
+            let k = key(&mut buf, scatter(i, amount_keys));
+            black_box(dict.lookup(k).is_some())
+        });
+    });
+
+    let mut i = 0;
+    group.bench_function(format!("{}_lookup_miss", name), |b| {
+        b.iter(|| {
+            i += 1;
+            let k = key(&mut buf, amount_keys + scatter(i, amount_keys));
+            black_box(dict.lookup(k).is_some())
+        });
+    });
+
+    // inserts a new key and deletes it again so the dict stays the same size
+    let mut i = 0;
+    group.bench_function(format!("{}_insert_delete", name), |b| {
+        b.iter(|| {
+            i += 1;
+            let k = key(&mut buf, amount_keys + i);
+            dict.insert(Box::new(HashNode::new_from_bytes(k, b"value")));
+            black_box(dict.delete(k))
+        });
+    });
+
+    group.finish();
+}
+
+fn bench_chained(c: &mut Criterion) {
+    bench_dict::<HashDict<Box<[u8]>, RedisObject, KeyspaceKeys>>(c, "chained");
+}
+
+fn bench_swiss(c: &mut Criterion) {
+    bench_dict::<SwissDict>(c, "swiss");
+}
+
+criterion_group!(benches, bench_chained, bench_swiss);
+
+criterion_main!(benches);
@@ -253,12 +253,53 @@ pub fn bench_pop(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_range(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ziplist_range");
+
+    // a mix of ints and short strings like a typical list reply
+    let mut zl = ZipList::new();
+    let s6 = b"hello".to_vec().into_boxed_slice();
+    let s14 = vec![b'a'; 100].into_boxed_slice();
+    for i in 0..1000 {
+        match i % 3 {
+            0 => zl.push(ZipEntry::Int16(i as i16)),
+            1 => zl.push(ZipEntry::Str6BitsLength(&s6)),
+            _ => zl.push(ZipEntry::Str14BitsLength(&s14)),
+        }
+    }
+
+    group.bench_function("materialize_objects", |b| {
+        b.iter(|| {
+            // Bench: every element becomes a RedisObject before it is encoded
+            let mut reply = Vec::new();
+            for offset in zl.iter() {
+                reply.extend_from_slice(&zl.get_at_offset(offset).to_resp());
+            }
+            black_box(reply)
+        });
+    });
+
+    group.bench_function("borrowed_values", |b| {
+        b.iter(|| {
+            // Bench: elements are written into the reply straight from the ziplist
+            let mut reply = Vec::new();
+            for value in zl.values() {
+                value.write_resp(&mut reply);
+            }
+            black_box(reply)
+        });
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_push,
     bench_insert,
     bench_delete,
     bench_get,
-    bench_pop
+    bench_pop,
+    bench_range
 );
 criterion_main!(benches);
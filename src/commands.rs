@@ -1,11 +1,420 @@
 #[derive(Debug, PartialEq)]
 pub enum RedisCommand<'a> {
-    Get { key: &'a [u8] },
-    Set { key: &'a [u8], value: &'a [u8] },
-    Del { keys: Vec<&'a [u8]> },
+    Get {
+        key: &'a [u8],
+    },
+    Set {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    Del {
+        keys: Vec<&'a [u8]>,
+    },
     // list commands
-    LPush { key: &'a [u8], value: &'a [u8] },
-    RPush { key: &'a [u8], value: &'a [u8] },
-    LPop { key: &'a [u8] },
-    RPop { key: &'a [u8] },
+    LPush {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    RPush {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    LPop {
+        key: &'a [u8],
+    },
+    RPop {
+        key: &'a [u8],
+    },
+    LRange {
+        key: &'a [u8],
+        start: i64,
+        stop: i64,
+    },
+    Sort {
+        key: &'a [u8],
+        options: SortOptions<'a>,
+    },
+    // keyspace
+    Scan {
+        cursor: u64,
+        pattern: Option<&'a [u8]>,
+        count: usize,
+        object_type: Option<&'a [u8]>,
+    },
+    RandomKey,
+    Keys {
+        pattern: &'a [u8],
+    },
+    Exists {
+        keys: Vec<&'a [u8]>,
+    },
+    Type {
+        key: &'a [u8],
+    },
+    Rename {
+        key: &'a [u8],
+        new_key: &'a [u8],
+    },
+    RenameNx {
+        key: &'a [u8],
+        new_key: &'a [u8],
+    },
+    Copy {
+        source: &'a [u8],
+        destination: &'a [u8],
+        replace: bool,
+    },
+    Touch {
+        keys: Vec<&'a [u8]>,
+    },
+    Unlink {
+        keys: Vec<&'a [u8]>,
+    },
+    Dump {
+        key: &'a [u8],
+    },
+    Restore {
+        key: &'a [u8],
+        // milliseconds, 0 for no expire
+        ttl: i64,
+        payload: &'a [u8],
+        options: RestoreOptions,
+    },
+    // moves the keys to another server with RESTORE and deletes them here
+    Migrate {
+        host: &'a [u8],
+        port: u16,
+        keys: Vec<&'a [u8]>,
+        db: usize,
+        // milliseconds
+        timeout: u64,
+        // keep the keys here as well
+        copy: bool,
+        replace: bool,
+    },
+    // expires
+    Expire {
+        key: &'a [u8],
+        time: i64,
+        unit: TimeUnit,
+        // a unix time instead of a time from now
+        absolute: bool,
+        flags: ExpireFlags,
+    },
+    Ttl {
+        key: &'a [u8],
+        unit: TimeUnit,
+    },
+    ExpireTime {
+        key: &'a [u8],
+        unit: TimeUnit,
+    },
+    Persist {
+        key: &'a [u8],
+    },
+    // databases
+    Select {
+        index: usize,
+    },
+    SwapDb {
+        index1: usize,
+        index2: usize,
+    },
+    Move {
+        key: &'a [u8],
+        db: usize,
+    },
+    DbSize,
+    // lazy is the ASYNC option
+    FlushDb {
+        lazy: bool,
+    },
+    FlushAll {
+        lazy: bool,
+    },
+    // persistence
+    Save,
+    BgSave,
+    LastSave,
+    BgRewriteAof,
+    // replication, PSYNC, REPLCONF and WAIT are run by the server since they need the connection
+    ReplicaOf {
+        // None for NO ONE
+        master: Option<(&'a [u8], u16)>,
+    },
+    Psync {
+        replid: &'a [u8],
+        // the first byte of the stream the replica is missing
+        offset: i64,
+    },
+    ReplConf {
+        options: Vec<ReplConfOption<'a>>,
+    },
+    Wait {
+        numreplicas: usize,
+        // milliseconds, 0 waits forever
+        timeout: u64,
+    },
+    // cluster, ASKING is run by the server since it is a flag of the connection
+    Cluster {
+        subcommand: ClusterSubcommand<'a>,
+    },
+    Asking,
+    // server
+    Ping {
+        message: Option<&'a [u8]>,
+    },
+    Info {
+        sections: Vec<&'a [u8]>,
+    },
+    MemoryUsage {
+        key: &'a [u8],
+        samples: usize,
+    },
+    MemoryStats,
+    ObjectEncoding {
+        key: &'a [u8],
+    },
+    ObjectRefCount {
+        key: &'a [u8],
+    },
+    ObjectIdleTime {
+        key: &'a [u8],
+    },
+    ObjectFreq {
+        key: &'a [u8],
+    },
+    ObjectHelp,
+}
+
+// the option value pairs of REPLCONF
+#[derive(Debug, PartialEq)]
+pub enum ReplConfOption<'a> {
+    // the port the replica listens on, only used to show it in INFO
+    ListeningPort(u16),
+    IpAddress(&'a [u8]),
+    // what the replica understands, eg. psync2, nothing changes with it here
+    Capa(&'a [u8]),
+    // the replication offset the replica has processed
+    Ack(i64),
+    // sent by the master, the replica answers with an ACK
+    GetAck,
+}
+
+// the subcommands of CLUSTER
+#[derive(Debug, PartialEq)]
+pub enum ClusterSubcommand<'a> {
+    Info,
+    MyId,
+    Nodes,
+    Slots,
+    Shards,
+    KeySlot {
+        key: &'a [u8],
+    },
+    CountKeysInSlot {
+        slot: u16,
+    },
+    GetKeysInSlot {
+        slot: u16,
+        count: usize,
+    },
+    // ADDSLOTS and ADDSLOTSRANGE, the ranges are expanded to the slots in them
+    AddSlots {
+        slots: Vec<u16>,
+    },
+    DelSlots {
+        slots: Vec<u16>,
+    },
+    Meet {
+        ip: &'a [u8],
+        port: u16,
+        // port + 10000 when it isn't given
+        bus_port: Option<u16>,
+    },
+    SetSlot {
+        slot: u16,
+        state: SetSlotState<'a>,
+    },
+}
+
+// CLUSTER SETSLOT slot IMPORTING|MIGRATING|NODE node-id or STABLE
+#[derive(Debug, PartialEq)]
+pub enum SetSlotState<'a> {
+    // the id of the node the slot comes from
+    Importing(&'a [u8]),
+    // the id of the node the slot goes to
+    Migrating(&'a [u8]),
+    Stable,
+    Node(&'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+}
+
+// the NX, XX, GT and LT options of EXPIRE, XX can be combined with GT or LT
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExpireFlags {
+    // only when the key has no ttl
+    pub nx: bool,
+    // only when the key has a ttl
+    pub xx: bool,
+    // only when the new ttl is greater, a key without one counts as an infinite ttl
+    pub gt: bool,
+    // only when the new ttl is less
+    pub lt: bool,
+}
+
+impl<'a> RedisCommand<'a> {
+    // the keys the command reads or writes in the selected database, they are expired before the
+    // command runs. Commands that walk the keyspace or only look at other databases have none
+    pub fn keys(&self) -> Vec<&'a [u8]> {
+        match self {
+            RedisCommand::Get { key }
+            | RedisCommand::Set { key, .. }
+            | RedisCommand::LPush { key, .. }
+            | RedisCommand::RPush { key, .. }
+            | RedisCommand::LPop { key }
+            | RedisCommand::RPop { key }
+            | RedisCommand::LRange { key, .. }
+            | RedisCommand::Type { key }
+            | RedisCommand::Expire { key, .. }
+            | RedisCommand::Ttl { key, .. }
+            | RedisCommand::ExpireTime { key, .. }
+            | RedisCommand::Persist { key }
+            | RedisCommand::Move { key, .. }
+            | RedisCommand::MemoryUsage { key, .. }
+            | RedisCommand::ObjectEncoding { key }
+            | RedisCommand::ObjectRefCount { key }
+            | RedisCommand::ObjectIdleTime { key }
+            | RedisCommand::ObjectFreq { key }
+            | RedisCommand::Dump { key }
+            | RedisCommand::Restore { key, .. } => vec![*key],
+            RedisCommand::Del { keys }
+            | RedisCommand::Exists { keys }
+            | RedisCommand::Touch { keys }
+            | RedisCommand::Unlink { keys }
+            | RedisCommand::Migrate { keys, .. } => keys.clone(),
+            RedisCommand::Rename { key, new_key } | RedisCommand::RenameNx { key, new_key } => {
+                vec![*key, *new_key]
+            }
+            RedisCommand::Copy {
+                source,
+                destination,
+                ..
+            } => vec![*source, *destination],
+            RedisCommand::Sort { key, options } => {
+                let mut keys = vec![*key];
+                keys.extend(options.store);
+                keys
+            }
+            RedisCommand::Scan { .. }
+            | RedisCommand::RandomKey
+            | RedisCommand::Keys { .. }
+            | RedisCommand::Select { .. }
+            | RedisCommand::SwapDb { .. }
+            | RedisCommand::DbSize
+            | RedisCommand::FlushDb { .. }
+            | RedisCommand::FlushAll { .. }
+            | RedisCommand::Info { .. }
+            | RedisCommand::MemoryStats
+            | RedisCommand::ObjectHelp
+            | RedisCommand::Save
+            | RedisCommand::BgSave
+            | RedisCommand::LastSave
+            | RedisCommand::BgRewriteAof
+            | RedisCommand::ReplicaOf { .. }
+            | RedisCommand::Psync { .. }
+            | RedisCommand::ReplConf { .. }
+            | RedisCommand::Wait { .. }
+            | RedisCommand::Cluster { .. }
+            | RedisCommand::Asking
+            | RedisCommand::Ping { .. } => vec![],
+        }
+    }
+
+    // commands that can change the keyspace, each one that runs without an error counts as a
+    // change for the save points, is logged to the aof and sent to the replicas. Replicas with
+    // replica-read-only refuse them from their clients
+    pub fn is_write(&self) -> bool {
+        match self {
+            RedisCommand::Set { .. }
+            | RedisCommand::Del { .. }
+            | RedisCommand::LPush { .. }
+            | RedisCommand::RPush { .. }
+            | RedisCommand::LPop { .. }
+            | RedisCommand::RPop { .. }
+            | RedisCommand::Rename { .. }
+            | RedisCommand::RenameNx { .. }
+            | RedisCommand::Copy { .. }
+            | RedisCommand::Unlink { .. }
+            | RedisCommand::Restore { .. }
+            | RedisCommand::Expire { .. }
+            | RedisCommand::Persist { .. }
+            | RedisCommand::SwapDb { .. }
+            | RedisCommand::Move { .. }
+            | RedisCommand::FlushDb { .. }
+            | RedisCommand::FlushAll { .. } => true,
+            RedisCommand::Sort { options, .. } => options.store.is_some(),
+            RedisCommand::Migrate { copy, .. } => !copy,
+            RedisCommand::Get { .. }
+            | RedisCommand::LRange { .. }
+            | RedisCommand::Scan { .. }
+            | RedisCommand::RandomKey
+            | RedisCommand::Keys { .. }
+            | RedisCommand::Exists { .. }
+            | RedisCommand::Type { .. }
+            | RedisCommand::Touch { .. }
+            | RedisCommand::Dump { .. }
+            | RedisCommand::Ttl { .. }
+            | RedisCommand::ExpireTime { .. }
+            | RedisCommand::Select { .. }
+            | RedisCommand::DbSize
+            | RedisCommand::Save
+            | RedisCommand::BgSave
+            | RedisCommand::LastSave
+            | RedisCommand::BgRewriteAof
+            | RedisCommand::ReplicaOf { .. }
+            | RedisCommand::Psync { .. }
+            | RedisCommand::ReplConf { .. }
+            | RedisCommand::Wait { .. }
+            | RedisCommand::Cluster { .. }
+            | RedisCommand::Asking
+            | RedisCommand::Ping { .. }
+            | RedisCommand::Info { .. }
+            | RedisCommand::MemoryUsage { .. }
+            | RedisCommand::MemoryStats
+            | RedisCommand::ObjectEncoding { .. }
+            | RedisCommand::ObjectRefCount { .. }
+            | RedisCommand::ObjectIdleTime { .. }
+            | RedisCommand::ObjectFreq { .. }
+            | RedisCommand::ObjectHelp => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoreOptions {
+    pub replace: bool,
+    // the ttl is a unix time in milliseconds
+    pub absttl: bool,
+    // seconds, only one of idle_time and freq can be given
+    pub idle_time: Option<u64>,
+    pub freq: Option<u8>,
+    // RESTORE-ASKING, like after ASKING it runs in a slot this cluster node is importing
+    pub asking: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SortOptions<'a> {
+    pub by: Option<&'a [u8]>,
+    // offset and count
+    pub limit: Option<(i64, i64)>,
+    pub get: Vec<&'a [u8]>,
+    pub desc: bool,
+    pub alpha: bool,
+    pub store: Option<&'a [u8]>,
 }
@@ -0,0 +1,480 @@
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+use crate::{error::ConfigError, redis::ziplist::ValidationMode};
+
+// which encoding new lists are created with
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ListEncoding {
+    #[default]
+    ZipList,
+    ListPack,
+}
+
+// when the aof is fsynced, `appendfsync always|everysec|no`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AppendFsync {
+    // after every write command, before it is answered
+    Always,
+    // about once a second on a background thread, a crash loses at most that second
+    #[default]
+    EverySec,
+    // whenever the os flushes its page cache
+    No,
+}
+
+// `save <seconds> <changes>`, a snapshot is taken once both have passed since the last one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub list_encoding: ListEncoding,
+    // how deep the ziplists of rdb files and of the snapshot of a master are checked before they
+    // are loaded, RESTORE payloads are always checked deep
+    pub sanitize_dump_payload: ValidationMode,
+    // move dict buckets in the periodic tasks instead of only when keys are accessed
+    pub active_rehashing: bool,
+    // amount of logical databases, only read when the server starts
+    pub databases: usize,
+    // where rdb snapshots are saved and loaded from at startup
+    pub dir: PathBuf,
+    pub dbfilename: PathBuf,
+    // no save points turns snapshots off, SAVE and BGSAVE still work
+    pub save_points: Vec<SavePoint>,
+    // refuse rdb files with keys of types that can't be stored here instead of skipping them
+    pub rdb_strict_load: bool,
+    // log every write command to the aof, it is loaded instead of the snapshot at startup
+    pub appendonly: bool,
+    // like dbfilename the aof always goes in dir
+    pub appendfilename: PathBuf,
+    pub appendfsync: AppendFsync,
+    // load an aof that ends in the middle of a command up to the last whole one instead of
+    // refusing to start
+    pub aof_load_truncated: bool,
+    // the port the server binary listens on
+    pub port: u16,
+    // `replicaof <host> <port>`, start as a replica of that master
+    pub replicaof: Option<(String, u16)>,
+    // bytes of the replication stream kept for replicas that reconnect with PSYNC
+    pub repl_backlog_size: usize,
+    // replicas refuse write commands from their clients
+    pub replica_read_only: bool,
+    // seconds without data from the master or a replica before the link counts as down
+    pub repl_timeout: u64,
+    // seconds between the PINGs a master sends its replicas
+    pub repl_ping_replica_period: u64,
+    // run as a node of a redis cluster, it talks to the other nodes on port + 10000
+    pub cluster_enabled: bool,
+    // milliseconds a node can't be reached before it counts as failing
+    pub cluster_node_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            list_encoding: ListEncoding::default(),
+            sanitize_dump_payload: ValidationMode::default(),
+            active_rehashing: true,
+            databases: 16,
+            dir: PathBuf::from("."),
+            dbfilename: PathBuf::from("dump.rdb"),
+            // the defaults of redis 7, after an hour, 5 minutes or a minute
+            save_points: vec![
+                SavePoint {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SavePoint {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SavePoint {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+            rdb_strict_load: true,
+            appendonly: false,
+            appendfilename: PathBuf::from("appendonly.aof"),
+            appendfsync: AppendFsync::default(),
+            aof_load_truncated: true,
+            port: 1234,
+            replicaof: None,
+            repl_backlog_size: 1024 * 1024,
+            replica_read_only: true,
+            repl_timeout: 60,
+            repl_ping_replica_period: 10,
+            cluster_enabled: false,
+            cluster_node_timeout: 15000,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // set an option by its redis style name, eg. `list-encoding listpack`
+    pub fn set(&mut self, name: &[u8], value: &[u8]) -> Result<(), ConfigError> {
+        let invalid_value = || ConfigError::InvalidValue {
+            name: name.to_vec(),
+            value: value.to_vec(),
+        };
+
+        match name.to_ascii_lowercase().as_slice() {
+            b"list-encoding" => {
+                self.list_encoding = match value.to_ascii_lowercase().as_slice() {
+                    b"ziplist" => ListEncoding::ZipList,
+                    b"listpack" => ListEncoding::ListPack,
+                    _ => return Err(invalid_value()),
+                };
+            }
+            b"sanitize-dump-payload" => {
+                self.sanitize_dump_payload = match value.to_ascii_lowercase().as_slice() {
+                    b"no" => ValidationMode::Shallow,
+                    b"yes" => ValidationMode::Deep,
+                    _ => return Err(invalid_value()),
+                };
+            }
+            b"activerehashing" => {
+                self.active_rehashing = parse_yes_no(value).ok_or_else(invalid_value)?;
+            }
+            b"databases" => {
+                self.databases = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&databases| databases > 0)
+                    .ok_or_else(invalid_value)?;
+            }
+            b"dir" => {
+                if value.is_empty() {
+                    return Err(invalid_value());
+                }
+                self.dir = PathBuf::from(OsStr::from_bytes(value));
+            }
+            // like redis only a file name, it always goes in dir
+            b"dbfilename" => {
+                if value.is_empty() || value.contains(&b'/') {
+                    return Err(invalid_value());
+                }
+                self.dbfilename = PathBuf::from(OsStr::from_bytes(value));
+            }
+            b"rdb-strict-load" => {
+                self.rdb_strict_load = parse_yes_no(value).ok_or_else(invalid_value)?;
+            }
+            b"appendonly" => {
+                self.appendonly = parse_yes_no(value).ok_or_else(invalid_value)?;
+            }
+            b"appendfilename" => {
+                if value.is_empty() || value.contains(&b'/') {
+                    return Err(invalid_value());
+                }
+                self.appendfilename = PathBuf::from(OsStr::from_bytes(value));
+            }
+            b"appendfsync" => {
+                self.appendfsync = match value.to_ascii_lowercase().as_slice() {
+                    b"always" => AppendFsync::Always,
+                    b"everysec" => AppendFsync::EverySec,
+                    b"no" => AppendFsync::No,
+                    _ => return Err(invalid_value()),
+                };
+            }
+            b"aof-load-truncated" => {
+                self.aof_load_truncated = parse_yes_no(value).ok_or_else(invalid_value)?;
+            }
+            b"save" => {
+                self.save_points = parse_save_points(value).ok_or_else(invalid_value)?;
+            }
+            b"port" => {
+                self.port = parse_number(value).ok_or_else(invalid_value)?;
+            }
+            // an empty value is the same as `replicaof no one`
+            b"replicaof" | b"slaveof" => {
+                self.replicaof = parse_replicaof(value).ok_or_else(invalid_value)?;
+            }
+            b"repl-backlog-size" => {
+                self.repl_backlog_size = parse_number(value)
+                    .filter(|&size| size > 0)
+                    .ok_or_else(invalid_value)?;
+            }
+            b"replica-read-only" | b"slave-read-only" => {
+                self.replica_read_only = parse_yes_no(value).ok_or_else(invalid_value)?;
+            }
+            b"repl-timeout" => {
+                self.repl_timeout = parse_number(value)
+                    .filter(|&seconds| seconds > 0)
+                    .ok_or_else(invalid_value)?;
+            }
+            b"repl-ping-replica-period" | b"repl-ping-slave-period" => {
+                self.repl_ping_replica_period = parse_number(value)
+                    .filter(|&seconds| seconds > 0)
+                    .ok_or_else(invalid_value)?;
+            }
+            b"cluster-enabled" => {
+                self.cluster_enabled = parse_yes_no(value).ok_or_else(invalid_value)?;
+            }
+            b"cluster-node-timeout" => {
+                self.cluster_node_timeout = parse_number(value)
+                    .filter(|&milliseconds| milliseconds > 0)
+                    .ok_or_else(invalid_value)?;
+            }
+            _ => {
+                return Err(ConfigError::UnknownOption {
+                    name: name.to_vec(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// pairs of seconds and changes, eg. "3600 1 300 100". An empty value removes every save point
+fn parse_save_points(value: &[u8]) -> Option<Vec<SavePoint>> {
+    let numbers = std::str::from_utf8(value)
+        .ok()?
+        .split_ascii_whitespace()
+        .map(|number| number.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if numbers.len() % 2 != 0 {
+        return None;
+    }
+
+    Some(
+        numbers
+            .chunks(2)
+            .map(|pair| SavePoint {
+                seconds: pair[0],
+                changes: pair[1],
+            })
+            .collect(),
+    )
+}
+
+// "<host> <port>", or "no one" for none
+fn parse_replicaof(value: &[u8]) -> Option<Option<(String, u16)>> {
+    let value = std::str::from_utf8(value).ok()?;
+    let parts: Vec<&str> = value.split_ascii_whitespace().collect();
+    match parts.as_slice() {
+        [] => Some(None),
+        [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => Some(None),
+        [host, port] => Some(Some((host.to_string(), port.parse().ok()?))),
+        _ => None,
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &[u8]) -> Option<T> {
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+fn parse_yes_no(value: &[u8]) -> Option<bool> {
+    match value.to_ascii_lowercase().as_slice() {
+        b"yes" => Some(true),
+        b"no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_set() {
+        struct TestData {
+            name: &'static [u8],
+            value: &'static [u8],
+            expected: Result<ListEncoding, ConfigError>,
+        }
+
+        let tests = vec![
+            TestData {
+                name: b"list-encoding",
+                value: b"listpack",
+                expected: Ok(ListEncoding::ListPack),
+            },
+            TestData {
+                name: b"LIST-ENCODING",
+                value: b"ZipList",
+                expected: Ok(ListEncoding::ZipList),
+            },
+            TestData {
+                name: b"list-encoding",
+                value: b"quicklist",
+                expected: Err(ConfigError::InvalidValue {
+                    name: b"list-encoding".to_vec(),
+                    value: b"quicklist".to_vec(),
+                }),
+            },
+            TestData {
+                name: b"no-such-option",
+                value: b"yes",
+                expected: Err(ConfigError::UnknownOption {
+                    name: b"no-such-option".to_vec(),
+                }),
+            },
+        ];
+
+        for test in tests {
+            let mut config = Config::new();
+            let result = config
+                .set(test.name, test.value)
+                .map(|_| config.list_encoding);
+            assert_eq!(test.expected, result);
+        }
+    }
+
+    #[test]
+    fn test_config_set_sanitize_dump_payload() {
+        let mut config = Config::new();
+        assert_eq!(ValidationMode::Shallow, config.sanitize_dump_payload);
+
+        config.set(b"sanitize-dump-payload", b"yes").unwrap();
+        assert_eq!(ValidationMode::Deep, config.sanitize_dump_payload);
+
+        config.set(b"sanitize-dump-payload", b"no").unwrap();
+        assert_eq!(ValidationMode::Shallow, config.sanitize_dump_payload);
+
+        assert!(config.set(b"sanitize-dump-payload", b"maybe").is_err());
+    }
+
+    #[test]
+    fn test_config_set_activerehashing() {
+        let mut config = Config::new();
+        assert!(config.active_rehashing);
+
+        config.set(b"activerehashing", b"no").unwrap();
+        assert!(!config.active_rehashing);
+
+        config.set(b"ActiveRehashing", b"YES").unwrap();
+        assert!(config.active_rehashing);
+
+        assert!(config.set(b"activerehashing", b"1").is_err());
+    }
+
+    #[test]
+    fn test_config_set_databases() {
+        let mut config = Config::new();
+        assert_eq!(16, config.databases);
+
+        config.set(b"databases", b"4").unwrap();
+        assert_eq!(4, config.databases);
+
+        assert!(config.set(b"databases", b"0").is_err());
+        assert!(config.set(b"databases", b"-1").is_err());
+        assert!(config.set(b"databases", b"many").is_err());
+        assert_eq!(4, config.databases);
+    }
+
+    #[test]
+    fn test_config_set_persistence() {
+        let mut config = Config::new();
+        assert_eq!(
+            PathBuf::from("./dump.rdb"),
+            config.dir.join(&config.dbfilename)
+        );
+        assert_eq!(3, config.save_points.len());
+
+        config.set(b"dir", b"/tmp/redis").unwrap();
+        config.set(b"dbfilename", b"backup.rdb").unwrap();
+        assert_eq!(
+            PathBuf::from("/tmp/redis/backup.rdb"),
+            config.dir.join(&config.dbfilename)
+        );
+        assert!(config.set(b"dbfilename", b"../backup.rdb").is_err());
+        assert!(config.set(b"dbfilename", b"").is_err());
+
+        config.set(b"save", b"900 1  60 1000").unwrap();
+        assert_eq!(
+            vec![
+                SavePoint {
+                    seconds: 900,
+                    changes: 1
+                },
+                SavePoint {
+                    seconds: 60,
+                    changes: 1000
+                },
+            ],
+            config.save_points
+        );
+        assert!(config.set(b"save", b"900").is_err());
+        assert!(config.set(b"save", b"900 -1").is_err());
+        assert_eq!(2, config.save_points.len());
+
+        config.set(b"save", b"").unwrap();
+        assert!(config.save_points.is_empty());
+
+        assert!(config.rdb_strict_load);
+        config.set(b"rdb-strict-load", b"no").unwrap();
+        assert!(!config.rdb_strict_load);
+    }
+
+    #[test]
+    fn test_config_set_append_only() {
+        let mut config = Config::new();
+        assert!(!config.appendonly);
+        assert_eq!(AppendFsync::EverySec, config.appendfsync);
+        assert!(config.aof_load_truncated);
+
+        config.set(b"appendonly", b"yes").unwrap();
+        config.set(b"appendfilename", b"log.aof").unwrap();
+        config.set(b"appendfsync", b"Always").unwrap();
+        config.set(b"aof-load-truncated", b"no").unwrap();
+        assert!(config.appendonly);
+        assert_eq!(PathBuf::from("log.aof"), config.appendfilename);
+        assert_eq!(AppendFsync::Always, config.appendfsync);
+        assert!(!config.aof_load_truncated);
+
+        assert!(config.set(b"appendfilename", b"dir/log.aof").is_err());
+        assert!(config.set(b"appendfsync", b"sometimes").is_err());
+        assert!(config.set(b"appendonly", b"maybe").is_err());
+    }
+
+    #[test]
+    fn test_config_set_replication() {
+        let mut config = Config::new();
+        assert_eq!(None, config.replicaof);
+        assert_eq!(1024 * 1024, config.repl_backlog_size);
+        assert!(config.replica_read_only);
+
+        config.set(b"port", b"6380").unwrap();
+        config.set(b"replicaof", b"127.0.0.1 6379").unwrap();
+        config.set(b"repl-backlog-size", b"4096").unwrap();
+        config.set(b"slave-read-only", b"no").unwrap();
+        config.set(b"repl-timeout", b"5").unwrap();
+        assert_eq!(6380, config.port);
+        assert_eq!(Some(("127.0.0.1".to_string(), 6379)), config.replicaof);
+        assert_eq!(4096, config.repl_backlog_size);
+        assert!(!config.replica_read_only);
+        assert_eq!(5, config.repl_timeout);
+
+        config.set(b"replicaof", b"NO ONE").unwrap();
+        assert_eq!(None, config.replicaof);
+
+        assert!(config.set(b"port", b"65536").is_err());
+        assert!(config.set(b"replicaof", b"127.0.0.1").is_err());
+        assert!(config.set(b"replicaof", b"127.0.0.1 port").is_err());
+        assert!(config.set(b"repl-backlog-size", b"0").is_err());
+        assert!(config.set(b"repl-timeout", b"0").is_err());
+    }
+
+    #[test]
+    fn test_config_set_cluster() {
+        let mut config = Config::new();
+        assert!(!config.cluster_enabled);
+        assert_eq!(15000, config.cluster_node_timeout);
+
+        config.set(b"cluster-enabled", b"yes").unwrap();
+        config.set(b"cluster-node-timeout", b"5000").unwrap();
+        assert!(config.cluster_enabled);
+        assert_eq!(5000, config.cluster_node_timeout);
+
+        assert!(config.set(b"cluster-enabled", b"1").is_err());
+        assert!(config.set(b"cluster-node-timeout", b"0").is_err());
+    }
+}
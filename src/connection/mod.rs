@@ -4,15 +4,47 @@ mod write_buffer;
 pub use read_buffer::ReadBuffer;
 pub use write_buffer::WriteBuffer;
 
+use std::{io, time::Instant};
+
 use crate::{error::RedisError, net::Socket, protocol::parser::CommandParseState};
 
 const INIT_BUFFER_SIZE: usize = 4096;
 
+// what is on the other end of a connection, replication streams flow over the last two
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionKind {
+    Client,
+    // a replica that synced with PSYNC, it gets the stream of write commands
+    Replica,
+    // the master of this server, its commands are run without replies
+    Master,
+    // a link of the cluster bus, opened by this node or by another one
+    ClusterBus,
+}
+
+// a connection blocked in WAIT
+pub struct BlockedWait {
+    pub numreplicas: usize,
+    // the replication offset the replicas have to reach
+    pub offset: i64,
+    // None waits forever
+    pub deadline: Option<Instant>,
+}
+
 pub struct Connection {
     pub soc: Socket,
     pub command_parse_state: CommandParseState,
     pub read_buffer: ReadBuffer,
     pub write_buffer: WriteBuffer,
+    // index of the database picked with SELECT
+    pub db: usize,
+    pub kind: ConnectionKind,
+    // the replication offset after the last write command of the connection, what WAIT waits for
+    pub woff: i64,
+    // commands that come in while blocked stay in the read buffer until it is unblocked
+    pub blocked: Option<BlockedWait>,
+    // ASKING was sent, the next command may use a slot this node is importing
+    pub asking: bool,
 }
 
 impl Connection {
@@ -22,25 +54,49 @@ impl Connection {
             command_parse_state: CommandParseState::new(),
             read_buffer: ReadBuffer::new(),
             write_buffer: WriteBuffer::new(),
+            db: 0,
+            kind: ConnectionKind::Client,
+            woff: 0,
+            blocked: None,
+            asking: false,
         }
     }
 
+    // the connection itself and the capacity of its buffers
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Connection>()
+            + self.read_buffer.buf.capacity()
+            + self.write_buffer.buf.capacity()
+    }
+
     pub fn fill_read_buffer(&mut self) -> Result<(), RedisError> {
-        let read_result = self.soc.read(&mut self.read_buffer.buf)?;
+        let read_result = match self.soc.read(&mut self.read_buffer.buf) {
+            Ok(read_result) => read_result,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
 
         println!("bytes read: {}", read_result);
 
+        // the other end closed the connection
         if read_result == 0 {
-            return Ok(()); // TODO - this should maybe be an error or something
+            return Err(RedisError::ConnectionClosed);
         }
 
         Ok(())
     }
 
     pub fn flush_write_buffer(&mut self) -> Result<(), RedisError> {
-        if self.write_buffer.buf.len() > 0 {
-            let result = self.soc.write(self.write_buffer.buf.as_slice())?;
-            self.write_buffer.pos += result;
+        if self.write_buffer.pos < self.write_buffer.buf.len() {
+            match self
+                .soc
+                .write(&self.write_buffer.buf[self.write_buffer.pos..])
+            {
+                Ok(result) => self.write_buffer.pos += result,
+                // the socket buffer is full, the rest goes out once it is writable again
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
         }
 
         Ok(())
@@ -11,6 +11,9 @@ pub enum RedisError {
     CommandError(CommandError),
     ConnectionClosed,
     ConnectionError(ConnectionError),
+    ConfigError(ConfigError),
+    RdbError(RdbError),
+    AofError(AofError),
     Other(String),
 }
 
@@ -38,6 +41,24 @@ impl From<ConnectionError> for RedisError {
     }
 }
 
+impl From<ConfigError> for RedisError {
+    fn from(err: ConfigError) -> Self {
+        RedisError::ConfigError(err)
+    }
+}
+
+impl From<RdbError> for RedisError {
+    fn from(err: RdbError) -> Self {
+        RedisError::RdbError(err)
+    }
+}
+
+impl From<AofError> for RedisError {
+    fn from(err: AofError) -> Self {
+        RedisError::AofError(err)
+    }
+}
+
 #[derive(Debug)]
 pub enum RedisCommandError {
     KeyNotFound,
@@ -52,10 +73,73 @@ pub enum ProtocolError {
     Incomplete,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum CommandError {
     UnknownCommand { cmd: Vec<u8> },
     WrongNumberOfArguments { cmd: Vec<u8> },
+    NotAnInteger,
+    NotADouble,
+    SyntaxError,
+    WrongType,
+    InvalidCursor,
+    UnknownSubcommand { cmd: Vec<u8>, subcommand: Vec<u8> },
+    NoSuchKey,
+    SameObject,
+    InvalidDbIndex,
+    InvalidFirstDbIndex,
+    InvalidSecondDbIndex,
+    DbIndexOutOfRange,
+    UnsupportedOption { option: Vec<u8> },
+    ExpireNxNotCompatible,
+    ExpireGtLtNotCompatible,
+    InvalidExpireTime { cmd: Vec<u8> },
+    BusyKey,
+    InvalidTtl,
+    InvalidIdleTime,
+    InvalidFreq,
+    InvalidDumpPayload,
+    BadDataFormat,
+    BgSaveInProgress,
+    SaveFailed,
+    // BGSAVE while an aof rewrite runs, only one child at a time
+    ChildActive,
+    AofRewriteInProgress,
+    AofRewriteFailed,
+    // a write command from a client of a replica with replica-read-only on
+    ReadOnlyReplica,
+    WaitOnReplica,
+    NegativeTimeout,
+    UnknownReplConfOption { option: Vec<u8> },
+    // PSYNC against a replica that isn't synced with its own master
+    NoMasterLink,
+    // PSYNC, REPLCONF, WAIT and ASKING, the server runs them with the connection they came in on
+    ConnectionOnly { cmd: Vec<u8> },
+    // the append only file only has write commands and the SELECTs between them
+    NotAWrite { cmd: Vec<u8> },
+    // cluster
+    ClusterDisabled,
+    InvalidSlot,
+    InvalidKeysCount,
+    SlotBusy { slot: u16 },
+    SlotSpecifiedMultipleTimes { slot: u16 },
+    SlotUnassigned { slot: u16 },
+    InvalidNodeAddress { address: Vec<u8> },
+    UnknownNode { id: Vec<u8> },
+    NotSlotOwner { slot: u16 },
+    AlreadySlotOwner { slot: u16 },
+    SlotNotEmpty { slot: u16 },
+    NotAllowedInClusterMode { cmd: Vec<u8> },
+    // the redirects, the address is ip:port of the node to ask instead
+    Moved { slot: u16, address: String },
+    Ask { slot: u16, address: String },
+    CrossSlot,
+    ClusterDown,
+    SlotNotServed,
+    // some keys of a multi key command are in the middle of being migrated
+    TryAgain,
+    MigrateConnect,
+    MigrateIo,
+    MigrateTargetError { message: Vec<u8> },
 }
 
 #[derive(Debug)]
@@ -63,6 +147,118 @@ pub enum ConnectionError {
     WriteBufferOverflow,
 }
 
+// reasons a ziplist read from untrusted bytes (rdb files, RESTORE payloads) was rejected, offsets
+// are byte offsets into the ziplist
+#[derive(Debug, PartialEq)]
+pub enum ZipListError {
+    TooShort {
+        len: usize,
+    },
+    BytesMismatch {
+        zl_bytes: u32,
+        len: usize,
+    },
+    TailOutOfBounds {
+        zl_tail: u32,
+    },
+    TailMismatch {
+        zl_tail: u32,
+        tail_offset: usize,
+    },
+    LenMismatch {
+        zl_len: u16,
+        amount_entries: usize,
+    },
+    MissingEnd {
+        offset: usize,
+    },
+    InvalidPrevlen {
+        offset: usize,
+    },
+    PrevlenMismatch {
+        offset: usize,
+        prevlen: usize,
+        expected: usize,
+    },
+    InvalidEncoding {
+        offset: usize,
+        header: u8,
+    },
+    EntryOutOfBounds {
+        offset: usize,
+    },
+}
+
+// reasons a listpack read from untrusted bytes was rejected, offsets are byte offsets into the
+// listpack
+#[derive(Debug, PartialEq)]
+pub enum ListPackError {
+    TooShort { len: usize },
+    BytesMismatch { lp_bytes: u32, len: usize },
+    LenMismatch { lp_len: u16, amount_entries: usize },
+    MissingEnd { offset: usize },
+    InvalidEncoding { offset: usize, header: u8 },
+    EntryOutOfBounds { offset: usize },
+    BacklenMismatch { offset: usize },
+}
+
+// reasons rdb encoded data (DUMP payloads, rdb files) could not be read
+#[derive(Debug, PartialEq)]
+pub enum RdbError {
+    // a DUMP payload too short to hold the version and crc64
+    MissingFooter,
+    // the data ended in the middle of a value
+    UnexpectedEnd,
+    // a special string encoding where a length was expected, or a length that does not fit
+    InvalidLength,
+    InvalidStringEncoding { encoding: u8 },
+    InvalidLzf,
+    UnknownType { rdb_type: u8 },
+    InvalidContainer { container: u64 },
+    // an opcode in a module value that redis does not write
+    InvalidModuleValue,
+    // a type or opcode that can be skipped, with strict loading the file is rejected instead
+    UnsupportedType { rdb_type: u8 },
+    // a list without elements, redis never writes those
+    EmptyKey,
+    TrailingBytes,
+    // a file that does not start with REDIS and a 4 digit version
+    InvalidHeader,
+    // a database the server is not configured to have
+    InvalidDbIndex { index: usize },
+    UnsupportedVersion { version: u16 },
+    ChecksumMismatch { expected: u64, got: u64 },
+    ZipList(ZipListError),
+    ListPack(ListPackError),
+}
+
+// reasons the aof could not be replayed at startup, offsets are byte offsets into the file
+#[derive(Debug, PartialEq)]
+pub enum AofError {
+    // the file ends in the middle of a command and aof-load-truncated is off
+    Truncated { offset: usize },
+    InvalidFormat { offset: usize, err: ProtocolError },
+    InvalidCommand { offset: usize, err: CommandError },
+}
+
+impl From<ZipListError> for RdbError {
+    fn from(err: ZipListError) -> Self {
+        RdbError::ZipList(err)
+    }
+}
+
+impl From<ListPackError> for RdbError {
+    fn from(err: ListPackError) -> Self {
+        RdbError::ListPack(err)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    UnknownOption { name: Vec<u8> },
+    InvalidValue { name: Vec<u8>, value: Vec<u8> },
+}
+
 // pub fn handle_redis_error(error: &RedisError, write_buffer: &mut WriteBuffer) {
 //
 // }
@@ -93,7 +289,23 @@ pub fn handle_protocol_error(error: &ProtocolError, write_buf: &mut WriteBuffer)
 }
 
 pub fn handle_command_error(error: &CommandError, write_buf: &mut WriteBuffer) {
-    write_buf.append_bytes(b"-ERR ");
+    match error {
+        CommandError::WrongType => write_buf.append_bytes(b"-WRONGTYPE "),
+        CommandError::BusyKey => write_buf.append_bytes(b"-BUSYKEY "),
+        CommandError::ReadOnlyReplica => write_buf.append_bytes(b"-READONLY "),
+        CommandError::NoMasterLink => write_buf.append_bytes(b"-NOMASTERLINK "),
+        CommandError::Moved { .. } => write_buf.append_bytes(b"-MOVED "),
+        CommandError::Ask { .. } => write_buf.append_bytes(b"-ASK "),
+        CommandError::CrossSlot => write_buf.append_bytes(b"-CROSSSLOT "),
+        CommandError::ClusterDown | CommandError::SlotNotServed => {
+            write_buf.append_bytes(b"-CLUSTERDOWN ")
+        }
+        CommandError::TryAgain => write_buf.append_bytes(b"-TRYAGAIN "),
+        CommandError::MigrateConnect | CommandError::MigrateIo => {
+            write_buf.append_bytes(b"-IOERR ")
+        }
+        _ => write_buf.append_bytes(b"-ERR "),
+    }
 
     // add error string bytes depending on error
     match error {
@@ -107,6 +319,193 @@ pub fn handle_command_error(error: &CommandError, write_buf: &mut WriteBuffer) {
             write_buf.append_bytes(&cmd);
             write_buf.append_bytes(b"' command");
         }
+        CommandError::NotAnInteger => {
+            write_buf.append_bytes(b"value is not an integer or out of range");
+        }
+        CommandError::NotADouble => {
+            write_buf.append_bytes(b"One or more scores can't be converted into double");
+        }
+        CommandError::SyntaxError => {
+            write_buf.append_bytes(b"syntax error");
+        }
+        CommandError::WrongType => {
+            write_buf.append_bytes(b"Operation against a key holding the wrong kind of value");
+        }
+        CommandError::InvalidCursor => {
+            write_buf.append_bytes(b"invalid cursor");
+        }
+        CommandError::UnknownSubcommand { cmd, subcommand } => {
+            write_buf.append_bytes(b"unknown subcommand '");
+            write_buf.append_bytes(subcommand);
+            write_buf.append_bytes(b"'. Try ");
+            write_buf.append_bytes(&cmd.to_ascii_uppercase());
+            write_buf.append_bytes(b" HELP.");
+        }
+        CommandError::NoSuchKey => {
+            write_buf.append_bytes(b"no such key");
+        }
+        CommandError::SameObject => {
+            write_buf.append_bytes(b"source and destination objects are the same");
+        }
+        CommandError::InvalidDbIndex => {
+            write_buf.append_bytes(b"invalid DB index");
+        }
+        CommandError::InvalidFirstDbIndex => {
+            write_buf.append_bytes(b"invalid first DB index");
+        }
+        CommandError::InvalidSecondDbIndex => {
+            write_buf.append_bytes(b"invalid second DB index");
+        }
+        CommandError::DbIndexOutOfRange => {
+            write_buf.append_bytes(b"DB index is out of range");
+        }
+        CommandError::UnsupportedOption { option } => {
+            write_buf.append_bytes(b"Unsupported option ");
+            write_buf.append_bytes(option);
+        }
+        CommandError::ExpireNxNotCompatible => {
+            write_buf
+                .append_bytes(b"NX and XX, GT or LT options at the same time are not compatible");
+        }
+        CommandError::ExpireGtLtNotCompatible => {
+            write_buf.append_bytes(b"GT and LT options at the same time are not compatible");
+        }
+        CommandError::InvalidExpireTime { cmd } => {
+            write_buf.append_bytes(b"invalid expire time in '");
+            write_buf.append_bytes(&cmd.to_ascii_lowercase());
+            write_buf.append_bytes(b"' command");
+        }
+        CommandError::BusyKey => {
+            write_buf.append_bytes(b"Target key name already exists.");
+        }
+        CommandError::InvalidTtl => {
+            write_buf.append_bytes(b"Invalid TTL value, must be >= 0");
+        }
+        CommandError::InvalidIdleTime => {
+            write_buf.append_bytes(b"Invalid IDLETIME value, must be >= 0");
+        }
+        CommandError::InvalidFreq => {
+            write_buf.append_bytes(b"Invalid FREQ value, must be >= 0 and <= 255");
+        }
+        CommandError::InvalidDumpPayload => {
+            write_buf.append_bytes(b"DUMP payload version or checksum are wrong");
+        }
+        CommandError::BadDataFormat => {
+            write_buf.append_bytes(b"Bad data format");
+        }
+        CommandError::BgSaveInProgress => {
+            write_buf.append_bytes(b"Background save already in progress");
+        }
+        CommandError::SaveFailed => {
+            write_buf.append_bytes(b"Failed saving the rdb file, check the server log");
+        }
+        CommandError::ChildActive => {
+            write_buf
+                .append_bytes(b"Another child process is active (AOF?): can't BGSAVE right now");
+        }
+        CommandError::AofRewriteInProgress => {
+            write_buf.append_bytes(b"Background append only file rewriting already in progress");
+        }
+        CommandError::AofRewriteFailed => {
+            write_buf.append_bytes(
+                b"Can't execute an AOF background rewriting. Please check the server logs for more information.",
+            );
+        }
+        CommandError::ReadOnlyReplica => {
+            write_buf.append_bytes(b"You can't write against a read only replica.");
+        }
+        CommandError::WaitOnReplica => {
+            write_buf.append_bytes(b"WAIT cannot be used with replica instances.");
+        }
+        CommandError::NegativeTimeout => {
+            write_buf.append_bytes(b"timeout is negative");
+        }
+        CommandError::ConnectionOnly { cmd } => {
+            write_buf.append_bytes(&cmd.to_ascii_uppercase());
+            write_buf.append_bytes(b" can only be run by a connected client");
+        }
+        CommandError::NotAWrite { cmd } => {
+            write_buf.append_bytes(&cmd.to_ascii_uppercase());
+            write_buf.append_bytes(b" is not a write command");
+        }
+        CommandError::UnknownReplConfOption { option } => {
+            write_buf.append_bytes(b"Unrecognized REPLCONF option: ");
+            write_buf.append_bytes(option);
+        }
+        CommandError::NoMasterLink => {
+            write_buf.append_bytes(b"Can't SYNC while not connected with my master");
+        }
+        CommandError::ClusterDisabled => {
+            write_buf.append_bytes(b"This instance has cluster support disabled");
+        }
+        CommandError::InvalidSlot => {
+            write_buf.append_bytes(b"Invalid or out of range slot");
+        }
+        CommandError::InvalidKeysCount => {
+            write_buf.append_bytes(b"Invalid slot or number of keys");
+        }
+        CommandError::SlotBusy { slot } => {
+            write_buf.append_bytes(format!("Slot {} is already busy", slot).as_bytes());
+        }
+        CommandError::SlotSpecifiedMultipleTimes { slot } => {
+            write_buf.append_bytes(format!("Slot {} specified multiple times", slot).as_bytes());
+        }
+        CommandError::SlotUnassigned { slot } => {
+            write_buf.append_bytes(format!("Slot {} is already unassigned", slot).as_bytes());
+        }
+        CommandError::InvalidNodeAddress { address } => {
+            write_buf.append_bytes(b"Invalid node address specified: ");
+            write_buf.append_bytes(address);
+        }
+        CommandError::UnknownNode { id } => {
+            write_buf.append_bytes(b"I don't know about node ");
+            write_buf.append_bytes(id);
+        }
+        CommandError::NotSlotOwner { slot } => {
+            write_buf.append_bytes(format!("I'm not the owner of hash slot {}", slot).as_bytes());
+        }
+        CommandError::AlreadySlotOwner { slot } => {
+            write_buf
+                .append_bytes(format!("I'm already the owner of hash slot {}", slot).as_bytes());
+        }
+        CommandError::SlotNotEmpty { slot } => {
+            write_buf.append_bytes(
+                format!(
+                    "Can't assign hashslot {} to a different node while I still hold keys for this hash slot.",
+                    slot
+                )
+                .as_bytes(),
+            );
+        }
+        CommandError::NotAllowedInClusterMode { cmd } => {
+            write_buf.append_bytes(&cmd.to_ascii_uppercase());
+            write_buf.append_bytes(b" is not allowed in cluster mode");
+        }
+        CommandError::Moved { slot, address } | CommandError::Ask { slot, address } => {
+            write_buf.append_bytes(format!("{} {}", slot, address).as_bytes());
+        }
+        CommandError::CrossSlot => {
+            write_buf.append_bytes(b"Keys in request don't hash to the same slot");
+        }
+        CommandError::ClusterDown => {
+            write_buf.append_bytes(b"The cluster is down");
+        }
+        CommandError::SlotNotServed => {
+            write_buf.append_bytes(b"Hash slot not served");
+        }
+        CommandError::TryAgain => {
+            write_buf.append_bytes(b"Multiple keys request during rehashing of slot");
+        }
+        CommandError::MigrateConnect => {
+            write_buf.append_bytes(b"error or timeout connecting to the client");
+        }
+        CommandError::MigrateIo => {
+            write_buf.append_bytes(b"error or timeout reading to target instance");
+        }
+        CommandError::MigrateTargetError { message } => {
+            write_buf.append_bytes(b"Target instance replied with error: ");
+            write_buf.append_bytes(message);
+        }
     }
 
     write_buf.append_bytes(b"\r\n");
@@ -0,0 +1,321 @@
+// glob style matching with the same semantics as stringmatchlen in redis, used for KEYS, the
+// MATCH option of SCAN and anything else that filters keys or channels by a pattern.
+//
+// * matches any sequence, ? any single byte, [abc] [^abc] [a-z] a set of bytes and \ escapes the
+// next byte. An unterminated [ matches like a set that ends at the end of the pattern
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let mut skip_longer_matches = false;
+    match_from(pattern, string, &mut skip_longer_matches, 0)
+}
+
+// stars past this depth fail the match instead of recursing further, same limit as redis
+const MAX_NESTING: usize = 1000;
+
+// skip_longer_matches is set once the rest of a pattern after a star was tried against every
+// suffix of the string and none matched. Giving an earlier star more of the string only leaves
+// shorter suffixes for the same rest, so every star up the stack can stop right away. Without it
+// patterns like a*a*a*a*b take exponential time on a long run of a's
+fn match_from(
+    pattern: &[u8],
+    string: &[u8],
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    let mut p = 0;
+    let mut s = 0;
+
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                // consecutive stars are the same as one
+                while pattern.get(p + 1) == Some(&b'*') {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+
+                for start in s..string.len() {
+                    if match_from(
+                        &pattern[p + 1..],
+                        &string[start..],
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                }
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => s += 1,
+            b'[' => {
+                let (matched, end) = match_set(pattern, p + 1, string[s]);
+                if !matched {
+                    return false;
+                }
+                p = end;
+                s += 1;
+            }
+            byte => {
+                let (expected, end) = if byte == b'\\' && p + 1 < pattern.len() {
+                    (pattern[p + 1], p + 1)
+                } else {
+                    (byte, p)
+                };
+                if expected != string[s] {
+                    return false;
+                }
+                p = end;
+                s += 1;
+            }
+        }
+
+        p += 1;
+
+        // trailing stars match the empty rest of the string
+        if s == string.len() {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+        }
+    }
+
+    p == pattern.len() && s == string.len()
+}
+
+// matches a byte against the set starting after the [, returns whether it matched and the
+// position of the closing ] (or the last byte of the pattern for an unterminated set)
+fn match_set(pattern: &[u8], mut p: usize, byte: u8) -> (bool, usize) {
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+
+    let mut matched = false;
+    loop {
+        if p >= pattern.len() {
+            p -= 1;
+            break;
+        }
+
+        match pattern[p] {
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                matched |= pattern[p] == byte;
+            }
+            b']' => break,
+            start if p + 2 < pattern.len() && pattern[p + 1] == b'-' => {
+                let end = pattern[p + 2];
+                let (low, high) = if start > end {
+                    (end, start)
+                } else {
+                    (start, end)
+                };
+                matched |= (low..=high).contains(&byte);
+                p += 2;
+            }
+            other => matched |= other == byte,
+        }
+
+        p += 1;
+    }
+
+    (matched != negated, p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        struct TestData {
+            pattern: &'static [u8],
+            string: &'static [u8],
+            expected: bool,
+        }
+
+        let tests = vec![
+            TestData {
+                pattern: b"*",
+                string: b"anything",
+                expected: true,
+            },
+            // like stringmatchlen an empty string is never matched by a non empty pattern
+            TestData {
+                pattern: b"*",
+                string: b"",
+                expected: false,
+            },
+            TestData {
+                pattern: b"",
+                string: b"",
+                expected: true,
+            },
+            TestData {
+                pattern: b"",
+                string: b"a",
+                expected: false,
+            },
+            TestData {
+                pattern: b"user:*",
+                string: b"user:1000",
+                expected: true,
+            },
+            TestData {
+                pattern: b"user:*",
+                string: b"session:1000",
+                expected: false,
+            },
+            TestData {
+                pattern: b"*:*:name",
+                string: b"user:1000:name",
+                expected: true,
+            },
+            TestData {
+                pattern: b"a**b***",
+                string: b"axxb",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h?llo",
+                string: b"hello",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h?llo",
+                string: b"hllo",
+                expected: false,
+            },
+            TestData {
+                pattern: b"h[ae]llo",
+                string: b"hallo",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h[ae]llo",
+                string: b"hillo",
+                expected: false,
+            },
+            TestData {
+                pattern: b"h[^e]llo",
+                string: b"hallo",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h[^e]llo",
+                string: b"hello",
+                expected: false,
+            },
+            TestData {
+                pattern: b"h[a-b]llo",
+                string: b"hbllo",
+                expected: true,
+            },
+            // reversed ranges are swapped
+            TestData {
+                pattern: b"h[b-a]llo",
+                string: b"hallo",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h[\\]]llo",
+                string: b"h]llo",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h\\*llo",
+                string: b"h*llo",
+                expected: true,
+            },
+            TestData {
+                pattern: b"h\\*llo",
+                string: b"hello",
+                expected: false,
+            },
+            // a trailing backslash matches itself
+            TestData {
+                pattern: b"a\\",
+                string: b"a\\",
+                expected: true,
+            },
+            // an unterminated set ends at the end of the pattern
+            TestData {
+                pattern: b"a[bc",
+                string: b"ac",
+                expected: true,
+            },
+            TestData {
+                pattern: b"a[",
+                string: b"a[",
+                expected: false,
+            },
+            TestData {
+                pattern: b"key*",
+                string: b"key",
+                expected: true,
+            },
+            TestData {
+                pattern: b"key?",
+                string: b"key",
+                expected: false,
+            },
+            TestData {
+                pattern: b"*a",
+                string: b"banana",
+                expected: true,
+            },
+            TestData {
+                pattern: b"*n",
+                string: b"banana",
+                expected: false,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                test.expected,
+                glob_match(test.pattern, test.string),
+                "pattern {:?} string {:?}",
+                String::from_utf8_lossy(test.pattern),
+                String::from_utf8_lossy(test.string)
+            );
+        }
+    }
+
+    #[test]
+    fn test_glob_match_backtracking() {
+        // exponential without skipping longer matches, this would not finish
+        let mut pattern = b"a*".repeat(30);
+        pattern.push(b'b');
+        let string = vec![b'a'; 10_000];
+        assert!(!glob_match(&pattern, &string));
+
+        let mut string = string;
+        string.push(b'b');
+        assert!(glob_match(&pattern, &string));
+
+        // a star that gave up still lets an earlier star match with a different split
+        assert!(glob_match(b"*ab*c", b"abxabc"));
+    }
+
+    #[test]
+    fn test_glob_match_nesting_limit() {
+        let string = vec![b'a'; MAX_NESTING + 10];
+
+        let pattern = b"*a".repeat(MAX_NESTING);
+        assert!(glob_match(&pattern, &string));
+
+        // one star more than the limit fails even though the string would match
+        let pattern = b"*a".repeat(MAX_NESTING + 1);
+        assert!(!glob_match(&pattern, &string));
+    }
+}
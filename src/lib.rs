@@ -2,6 +2,9 @@ pub mod net;
 pub mod error;
 pub mod connection;
 pub mod commands;
+pub mod config;
+pub mod glob;
 pub mod protocol;
+pub mod rdb;
 pub mod redis;
 pub mod server;
@@ -1,12 +1,16 @@
 use libc::{
     AF_INET, EPOLL_CTL_ADD, EPOLL_CTL_MOD, F_GETFL, F_SETFL, O_NONBLOCK, SO_REUSEADDR, SOCK_STREAM,
     SOL_SOCKET, SOMAXCONN, accept, bind, close, connect, epoll_create1, epoll_ctl, epoll_event,
-    epoll_wait, fcntl, htonl, htons, in_addr, listen, read, setsockopt, sockaddr, sockaddr_in,
-    socklen_t, write,
+    epoll_wait, fcntl, getpeername, getsockname, htonl, htons, in_addr, listen, ntohl, read,
+    setsockopt, sockaddr, sockaddr_in, socklen_t, write,
 };
 use libc::{c_int, socket};
 use std::ffi::c_void;
-use std::{io, mem};
+use std::{
+    io::{self, Read},
+    mem,
+    net::Ipv4Addr,
+};
 
 pub struct Socket {
     pub fd: c_int,
@@ -99,6 +103,46 @@ impl Socket {
         }
     }
 
+    // the address of the other end of a connected socket
+    pub fn peer_ip(&self) -> io::Result<Ipv4Addr> {
+        unsafe {
+            let mut address: sockaddr_in = mem::zeroed();
+            let mut socklen = mem::size_of::<sockaddr_in>() as socklen_t;
+
+            let return_value = getpeername(
+                self.fd,
+                &mut address as *mut sockaddr_in as *mut sockaddr,
+                &mut socklen,
+            );
+
+            if return_value == -1 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(Ipv4Addr::from(ntohl(address.sin_addr.s_addr)))
+            }
+        }
+    }
+
+    // the address of this end of a connected socket, the one the other end connected to
+    pub fn local_ip(&self) -> io::Result<Ipv4Addr> {
+        unsafe {
+            let mut address: sockaddr_in = mem::zeroed();
+            let mut socklen = mem::size_of::<sockaddr_in>() as socklen_t;
+
+            let return_value = getsockname(
+                self.fd,
+                &mut address as *mut sockaddr_in as *mut sockaddr,
+                &mut socklen,
+            );
+
+            if return_value == -1 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(Ipv4Addr::from(ntohl(address.sin_addr.s_addr)))
+            }
+        }
+    }
+
     pub fn read(&self, buffer: &mut Vec<u8>) -> io::Result<usize> {
         read_socket(self.fd, buffer)
     }
@@ -293,3 +337,30 @@ pub fn make_ipv4_address(ip: u32, port: u16) -> sockaddr_in {
         sin_zero: [0; 8],
     }
 }
+
+// reads until buf holds a whole line, returns it without the CRLF and leaves what came after it
+// in buf
+pub fn read_line<R: Read>(stream: &mut R, buf: &mut Vec<u8>) -> io::Result<Vec<u8>> {
+    loop {
+        if let Some(end) = buf.windows(2).position(|window| window == b"\r\n") {
+            let line = buf[..end].to_vec();
+            buf.drain(..end + 2);
+            return Ok(line);
+        }
+        read_more(stream, buf)?;
+    }
+}
+
+pub fn read_more<R: Read>(stream: &mut R, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut chunk = [0u8; 16 * 1024];
+    let n = stream.read(&mut chunk)?;
+    if n == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the connection was closed",
+        ));
+    }
+
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(())
+}
@@ -1,8 +1,17 @@
 use crate::{
-    commands::RedisCommand,
+    commands::{
+        ClusterSubcommand, ExpireFlags, RedisCommand, ReplConfOption, RestoreOptions, SetSlotState,
+        SortOptions, TimeUnit,
+    },
     error::{CommandError, ProtocolError},
+    redis::{cluster::CLUSTER_SLOTS, redis_object::try_parse_int},
 };
 
+// amount of keys SCAN aims for without a COUNT, same as redis
+const DEFAULT_SCAN_COUNT: usize = 10;
+// elements of nested values looked at by MEMORY USAGE, same default as redis
+const DEFAULT_MEMORY_SAMPLES: usize = 5;
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum ParseState {
     Empty,
@@ -30,6 +39,19 @@ impl CommandParseState {
         }
     }
 
+    // the complete command as a resp array of bulk strings, the way a master sends it to its
+    // replicas
+    pub fn write_resp(&self, buf: &mut Vec<u8>) {
+        let mut strings = Vec::with_capacity(self.args.len() + 1);
+        strings.push(self.command_name.as_deref().unwrap_or_default());
+        strings.extend(self.args.iter().map(|arg| arg.as_slice()));
+        write_command(buf, &strings);
+    }
+
+    pub fn command_name(&self) -> &[u8] {
+        self.command_name.as_deref().unwrap_or_default()
+    }
+
     pub fn clear(&mut self) {
         self.command_name = None;
         self.args.clear();
@@ -143,6 +165,17 @@ fn parse_bulk_string<'a>(buf: &'a [u8], pos: &mut usize) -> Result<&'a [u8], Pro
     Ok(slice)
 }
 
+// a command as a resp array of bulk strings, the way clients send them
+pub fn write_command<A: AsRef<[u8]>>(buf: &mut Vec<u8>, args: &[A]) {
+    buf.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        let arg = arg.as_ref();
+        buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buf.extend_from_slice(arg);
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
 // replys
 
 pub fn parse_reply(buf: &[u8]) -> Result<Vec<u8>, ProtocolError> {
@@ -328,104 +361,893 @@ pub fn convert_command_parse_state_to_redis_command<'a>(
                 key: args[0].as_slice(),
             })
         }
+        b"LRANGE" | b"lrange" | b"LRange" => {
+            check_arity_error(3, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::LRange {
+                key: args[0].as_slice(),
+                start: parse_int_arg(&args[1])?,
+                stop: parse_int_arg(&args[2])?,
+            })
+        }
+        // sort
+        b"SORT" | b"sort" | b"Sort" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            parse_sort_args(args, false)
+        }
+        b"SORT_RO" | b"sort_ro" | b"Sort_Ro" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            parse_sort_args(args, true)
+        }
+        // keyspace
+        b"SCAN" | b"scan" | b"Scan" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            parse_scan_args(args)
+        }
+        b"RANDOMKEY" | b"randomkey" | b"RandomKey" => {
+            check_arity_error(0, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::RandomKey)
+        }
+        b"KEYS" | b"keys" | b"Keys" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Keys {
+                pattern: args[0].as_slice(),
+            })
+        }
+        b"EXISTS" | b"exists" | b"Exists" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            Ok(RedisCommand::Exists {
+                keys: args.iter().map(|a| a.as_slice()).collect(),
+            })
+        }
+        b"TYPE" | b"type" | b"Type" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Type {
+                key: args[0].as_slice(),
+            })
+        }
+        b"RENAME" | b"rename" | b"Rename" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Rename {
+                key: args[0].as_slice(),
+                new_key: args[1].as_slice(),
+            })
+        }
+        b"RENAMENX" | b"renamenx" | b"RenameNx" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::RenameNx {
+                key: args[0].as_slice(),
+                new_key: args[1].as_slice(),
+            })
+        }
+        // COPY source destination [REPLACE]
+        b"COPY" | b"copy" | b"Copy" => {
+            if args.len() < 2 {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            let mut replace = false;
+            for arg in &args[2..] {
+                if arg.eq_ignore_ascii_case(b"REPLACE") {
+                    replace = true;
+                } else {
+                    return Err(CommandError::SyntaxError);
+                }
+            }
+            Ok(RedisCommand::Copy {
+                source: args[0].as_slice(),
+                destination: args[1].as_slice(),
+                replace,
+            })
+        }
+        b"TOUCH" | b"touch" | b"Touch" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            Ok(RedisCommand::Touch {
+                keys: args.iter().map(|a| a.as_slice()).collect(),
+            })
+        }
+        b"UNLINK" | b"unlink" | b"Unlink" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            Ok(RedisCommand::Unlink {
+                keys: args.iter().map(|a| a.as_slice()).collect(),
+            })
+        }
+        b"DUMP" | b"dump" | b"Dump" => {
+            check_arity_error(1, args.len(), command_name)?;
+            Ok(RedisCommand::Dump {
+                key: args[0].as_slice(),
+            })
+        }
+        b"RESTORE" | b"restore" | b"Restore" => parse_restore_args(command_name, args),
+        b"RESTORE-ASKING" | b"restore-asking" | b"Restore-Asking" => {
+            let mut command = parse_restore_args(command_name, args)?;
+            if let RedisCommand::Restore { options, .. } = &mut command {
+                options.asking = true;
+            }
+            Ok(command)
+        }
+        b"MIGRATE" | b"migrate" | b"Migrate" => parse_migrate_args(command_name, args),
+        // expires
+        b"EXPIRE" | b"expire" | b"Expire" => {
+            parse_expire_args(command_name, args, TimeUnit::Seconds, false)
+        }
+        b"PEXPIRE" | b"pexpire" | b"PExpire" => {
+            parse_expire_args(command_name, args, TimeUnit::Milliseconds, false)
+        }
+        b"EXPIREAT" | b"expireat" | b"ExpireAt" => {
+            parse_expire_args(command_name, args, TimeUnit::Seconds, true)
+        }
+        b"PEXPIREAT" | b"pexpireat" | b"PExpireAt" => {
+            parse_expire_args(command_name, args, TimeUnit::Milliseconds, true)
+        }
+        b"TTL" | b"ttl" | b"Ttl" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Ttl {
+                key: args[0].as_slice(),
+                unit: TimeUnit::Seconds,
+            })
+        }
+        b"PTTL" | b"pttl" | b"PTtl" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Ttl {
+                key: args[0].as_slice(),
+                unit: TimeUnit::Milliseconds,
+            })
+        }
+        b"EXPIRETIME" | b"expiretime" | b"ExpireTime" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::ExpireTime {
+                key: args[0].as_slice(),
+                unit: TimeUnit::Seconds,
+            })
+        }
+        b"PEXPIRETIME" | b"pexpiretime" | b"PExpireTime" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::ExpireTime {
+                key: args[0].as_slice(),
+                unit: TimeUnit::Milliseconds,
+            })
+        }
+        b"PERSIST" | b"persist" | b"Persist" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Persist {
+                key: args[0].as_slice(),
+            })
+        }
+        // databases
+        b"SELECT" | b"select" | b"Select" => {
+            check_arity_error(1, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Select {
+                index: parse_db_index(&args[0], CommandError::InvalidDbIndex)?,
+            })
+        }
+        b"SWAPDB" | b"swapdb" | b"SwapDb" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::SwapDb {
+                index1: parse_db_index(&args[0], CommandError::InvalidFirstDbIndex)?,
+                index2: parse_db_index(&args[1], CommandError::InvalidSecondDbIndex)?,
+            })
+        }
+        b"MOVE" | b"move" | b"Move" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Move {
+                key: args[0].as_slice(),
+                db: parse_db_index(&args[1], CommandError::NotAnInteger)?,
+            })
+        }
+        b"DBSIZE" | b"dbsize" | b"DbSize" => {
+            check_arity_error(0, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::DbSize)
+        }
+        b"FLUSHDB" | b"flushdb" | b"FlushDb" => Ok(RedisCommand::FlushDb {
+            lazy: parse_flush_args(args)?,
+        }),
+        b"FLUSHALL" | b"flushall" | b"FlushAll" => Ok(RedisCommand::FlushAll {
+            lazy: parse_flush_args(args)?,
+        }),
+        // persistence
+        b"SAVE" | b"save" | b"Save" => {
+            check_arity_error(0, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Save)
+        }
+        // the SCHEDULE option of redis is not supported
+        b"BGSAVE" | b"bgsave" | b"BgSave" => {
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError);
+            }
+            Ok(RedisCommand::BgSave)
+        }
+        b"LASTSAVE" | b"lastsave" | b"LastSave" => {
+            check_arity_error(0, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::LastSave)
+        }
+        b"BGREWRITEAOF" | b"bgrewriteaof" | b"BgRewriteAof" => {
+            check_arity_error(0, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::BgRewriteAof)
+        }
+        // replication
+        b"REPLICAOF" | b"replicaof" | b"ReplicaOf" | b"SLAVEOF" | b"slaveof" | b"SlaveOf" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            parse_replicaof_args(args)
+        }
+        b"PSYNC" | b"psync" | b"Psync" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Psync {
+                replid: args[0].as_slice(),
+                offset: parse_int_arg(&args[1])?,
+            })
+        }
+        b"REPLCONF" | b"replconf" | b"ReplConf" => parse_replconf_args(args),
+        b"WAIT" | b"wait" | b"Wait" => {
+            check_arity_error(2, args.len(), command_name.as_slice())?;
+            let timeout = parse_int_arg(&args[1])?;
+            if timeout < 0 {
+                return Err(CommandError::NegativeTimeout);
+            }
+            Ok(RedisCommand::Wait {
+                numreplicas: parse_int_arg(&args[0])?.max(0) as usize,
+                timeout: timeout as u64,
+            })
+        }
+        // cluster
+        b"CLUSTER" | b"cluster" | b"Cluster" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            parse_cluster_args(command_name, args)
+        }
+        b"ASKING" | b"asking" | b"Asking" => {
+            check_arity_error(0, args.len(), command_name.as_slice())?;
+            Ok(RedisCommand::Asking)
+        }
+        // server
+        b"PING" | b"ping" | b"Ping" => match args.as_slice() {
+            [] => Ok(RedisCommand::Ping { message: None }),
+            [message] => Ok(RedisCommand::Ping {
+                message: Some(message.as_slice()),
+            }),
+            _ => Err(CommandError::WrongNumberOfArguments {
+                cmd: command_name.to_vec(),
+            }),
+        },
+        b"INFO" | b"info" | b"Info" => Ok(RedisCommand::Info {
+            sections: args.iter().map(|a| a.as_slice()).collect(),
+        }),
+        b"MEMORY" | b"memory" | b"Memory" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            parse_memory_args(command_name, args)
+        }
+        b"OBJECT" | b"object" | b"Object" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments {
+                    cmd: command_name.to_vec(),
+                });
+            }
+            parse_object_args(command_name, args)
+        }
         _ => Err(CommandError::UnknownCommand {
             cmd: command_name.to_vec(),
         }),
     }
 }
 
-#[inline(always)]
-fn check_arity_error(expected_len: usize, len: usize, cmd: &[u8]) -> Result<(), CommandError> {
-    if len != expected_len {
-        return Err(CommandError::WrongNumberOfArguments { cmd: cmd.to_vec() });
+// SORT key [BY pattern] [LIMIT offset count] [GET pattern ...] [ASC|DESC] [ALPHA] [STORE dest],
+// the read only variant treats STORE as a syntax error
+fn parse_sort_args(args: &[Vec<u8>], read_only: bool) -> Result<RedisCommand<'_>, CommandError> {
+    let mut options = SortOptions::default();
+
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_slice();
+        let remaining = args.len() - i - 1;
+
+        if arg.eq_ignore_ascii_case(b"ASC") {
+            options.desc = false;
+        } else if arg.eq_ignore_ascii_case(b"DESC") {
+            options.desc = true;
+        } else if arg.eq_ignore_ascii_case(b"ALPHA") {
+            options.alpha = true;
+        } else if arg.eq_ignore_ascii_case(b"LIMIT") && remaining >= 2 {
+            options.limit = Some((parse_int_arg(&args[i + 1])?, parse_int_arg(&args[i + 2])?));
+            i += 2;
+        } else if arg.eq_ignore_ascii_case(b"BY") && remaining >= 1 {
+            options.by = Some(args[i + 1].as_slice());
+            i += 1;
+        } else if arg.eq_ignore_ascii_case(b"GET") && remaining >= 1 {
+            options.get.push(args[i + 1].as_slice());
+            i += 1;
+        } else if arg.eq_ignore_ascii_case(b"STORE") && remaining >= 1 && !read_only {
+            options.store = Some(args[i + 1].as_slice());
+            i += 1;
+        } else {
+            return Err(CommandError::SyntaxError);
+        }
+
+        i += 1;
     }
 
-    Ok(())
+    Ok(RedisCommand::Sort {
+        key: args[0].as_slice(),
+        options,
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_number_to_usize() {
-        struct TestData {
-            buffer: &'static [u8],
-            expected: Result<usize, ProtocolError>,
+// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+fn parse_scan_args(args: &[Vec<u8>]) -> Result<RedisCommand<'_>, CommandError> {
+    // cursors are unsigned so they can not go through try_parse_int
+    let cursor = std::str::from_utf8(&args[0])
+        .ok()
+        .and_then(|cursor| cursor.parse::<u64>().ok())
+        .ok_or(CommandError::InvalidCursor)?;
+
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    let mut object_type = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_slice();
+        if i + 1 >= args.len() {
+            return Err(CommandError::SyntaxError);
+        }
+        let value = args[i + 1].as_slice();
+
+        if arg.eq_ignore_ascii_case(b"MATCH") {
+            pattern = Some(value);
+        } else if arg.eq_ignore_ascii_case(b"COUNT") {
+            count = match parse_int_arg(value)? {
+                count if count < 1 => return Err(CommandError::SyntaxError),
+                count => count as usize,
+            };
+        } else if arg.eq_ignore_ascii_case(b"TYPE") {
+            object_type = Some(value);
+        } else {
+            return Err(CommandError::SyntaxError);
         }
 
-        let tests = vec![
-            TestData {
-                buffer: b"0\r\n",
-                expected: Ok(0),
-            },
-            TestData {
-                buffer: b"1\r\n",
-                expected: Ok(1),
-            },
-            TestData {
-                buffer: b"15\r\n",
-                expected: Ok(15),
-            },
-            TestData {
-                buffer: b"123\r\n",
-                expected: Ok(123),
-            },
-            TestData {
-                buffer: b"83210\r\n",
-                expected: Ok(83210),
-            },
-            TestData {
-                buffer: b"752019\r\n",
-                expected: Ok(752019),
-            },
-            TestData {
-                buffer: b"752019\r5",
-                expected: Err(ProtocolError::ExpectedByte {
-                    expected: b'\n',
-                    got: b'5',
-                }),
-            },
-            TestData {
-                buffer: b"75f019\r\n",
-                expected: Err(ProtocolError::UnexpectedByte(b'f')),
-            },
-            TestData {
-                buffer: b"75019\r",
-                expected: Err(ProtocolError::Incomplete),
-            },
-            TestData {
-                buffer: b"750",
-                expected: Err(ProtocolError::Incomplete),
-            },
-        ];
+        i += 2;
+    }
 
-        for test in tests {
-            let mut position = 0;
-            let result = parse_number_to_usize(test.buffer, &mut position);
+    Ok(RedisCommand::Scan {
+        cursor,
+        pattern,
+        count,
+        object_type,
+    })
+}
 
-            match (&result, &test.expected) {
-                (Ok(val), Ok(expected)) => assert_eq!(expected, val),
-                (Err(e), Err(expected)) => {
-                    assert_eq!(expected, e)
-                }
-                _ => panic!("Expected {:?}, got {:?}", test.expected, result),
+// MEMORY USAGE key [SAMPLES count] and MEMORY STATS
+fn parse_memory_args<'a>(
+    command_name: &[u8],
+    args: &'a [Vec<u8>],
+) -> Result<RedisCommand<'a>, CommandError> {
+    let subcommand = args[0].as_slice();
+    // like redis the arity errors name the subcommand as well, memory|usage
+    let wrong_arity = || CommandError::WrongNumberOfArguments {
+        cmd: [command_name, b"|", subcommand].concat(),
+    };
+
+    if subcommand.eq_ignore_ascii_case(b"USAGE") {
+        if args.len() < 2 {
+            return Err(wrong_arity());
+        }
+
+        let mut samples = DEFAULT_MEMORY_SAMPLES;
+        let mut i = 2;
+        while i < args.len() {
+            if !args[i].eq_ignore_ascii_case(b"SAMPLES") || i + 1 >= args.len() {
+                return Err(CommandError::SyntaxError);
             }
+            // 0 samples every element
+            samples = match parse_int_arg(&args[i + 1])? {
+                samples if samples < 0 => return Err(CommandError::SyntaxError),
+                samples => samples as usize,
+            };
+            i += 2;
+        }
+
+        Ok(RedisCommand::MemoryUsage {
+            key: args[1].as_slice(),
+            samples,
+        })
+    } else if subcommand.eq_ignore_ascii_case(b"STATS") {
+        if args.len() != 1 {
+            return Err(wrong_arity());
         }
+        Ok(RedisCommand::MemoryStats)
+    } else {
+        Err(CommandError::UnknownSubcommand {
+            cmd: command_name.to_vec(),
+            subcommand: subcommand.to_vec(),
+        })
     }
+}
 
-    #[test]
-    fn test_parse_bulk_string() {
-        struct TestData {
-            buffer: &'static [u8],
-            expected: Result<&'static [u8], ProtocolError>,
+// OBJECT ENCODING|REFCOUNT|IDLETIME|FREQ key and OBJECT HELP
+fn parse_object_args<'a>(
+    command_name: &[u8],
+    args: &'a [Vec<u8>],
+) -> Result<RedisCommand<'a>, CommandError> {
+    let subcommand = args[0].as_slice();
+    let wrong_arity = || CommandError::WrongNumberOfArguments {
+        cmd: [command_name, b"|", subcommand].concat(),
+    };
+    let key = || match args {
+        [_, key] => Ok(key.as_slice()),
+        _ => Err(wrong_arity()),
+    };
+
+    if subcommand.eq_ignore_ascii_case(b"ENCODING") {
+        Ok(RedisCommand::ObjectEncoding { key: key()? })
+    } else if subcommand.eq_ignore_ascii_case(b"REFCOUNT") {
+        Ok(RedisCommand::ObjectRefCount { key: key()? })
+    } else if subcommand.eq_ignore_ascii_case(b"IDLETIME") {
+        Ok(RedisCommand::ObjectIdleTime { key: key()? })
+    } else if subcommand.eq_ignore_ascii_case(b"FREQ") {
+        Ok(RedisCommand::ObjectFreq { key: key()? })
+    } else if subcommand.eq_ignore_ascii_case(b"HELP") {
+        if args.len() != 1 {
+            return Err(wrong_arity());
         }
+        Ok(RedisCommand::ObjectHelp)
+    } else {
+        Err(CommandError::UnknownSubcommand {
+            cmd: command_name.to_vec(),
+            subcommand: subcommand.to_vec(),
+        })
+    }
+}
 
-        let tests = vec![
-            TestData {
-                buffer: b"$3\r\nSET\r\n",
-                expected: Ok(b"SET"),
-            },
+// RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]. Like redis
+// IDLETIME and FREQ can't be given together
+fn parse_restore_args<'a>(
+    command_name: &[u8],
+    args: &'a [Vec<u8>],
+) -> Result<RedisCommand<'a>, CommandError> {
+    if args.len() < 3 {
+        return Err(CommandError::WrongNumberOfArguments {
+            cmd: command_name.to_vec(),
+        });
+    }
+
+    let ttl = parse_int_arg(&args[1])?;
+
+    let mut options = RestoreOptions::default();
+    let mut i = 3;
+    while i < args.len() {
+        let arg = args[i].as_slice();
+        let has_value = i + 1 < args.len();
+        if arg.eq_ignore_ascii_case(b"REPLACE") {
+            options.replace = true;
+        } else if arg.eq_ignore_ascii_case(b"ABSTTL") {
+            options.absttl = true;
+        } else if arg.eq_ignore_ascii_case(b"IDLETIME") && has_value && options.freq.is_none() {
+            options.idle_time = match parse_int_arg(&args[i + 1])? {
+                idle_time if idle_time < 0 => return Err(CommandError::InvalidIdleTime),
+                idle_time => Some(idle_time as u64),
+            };
+            i += 1;
+        } else if arg.eq_ignore_ascii_case(b"FREQ") && has_value && options.idle_time.is_none() {
+            options.freq = match parse_int_arg(&args[i + 1])? {
+                freq @ 0..=255 => Some(freq as u8),
+                _ => return Err(CommandError::InvalidFreq),
+            };
+            i += 1;
+        } else {
+            return Err(CommandError::SyntaxError);
+        }
+        i += 1;
+    }
+
+    Ok(RedisCommand::Restore {
+        key: args[0].as_slice(),
+        ttl,
+        payload: args[2].as_slice(),
+        options,
+    })
+}
+
+// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [KEYS key ...], with KEYS the
+// key argument is the empty string. AUTH isn't there since this server has no passwords
+fn parse_migrate_args<'a>(
+    command_name: &[u8],
+    args: &'a [Vec<u8>],
+) -> Result<RedisCommand<'a>, CommandError> {
+    if args.len() < 5 {
+        return Err(CommandError::WrongNumberOfArguments {
+            cmd: command_name.to_vec(),
+        });
+    }
+
+    let port = parse_port(&args[1])?;
+    let db = parse_db_index(&args[3], CommandError::NotAnInteger)?;
+    // like redis a timeout of 0 or less is a second
+    let timeout = match parse_int_arg(&args[4])? {
+        timeout if timeout <= 0 => 1000,
+        timeout => timeout as u64,
+    };
+
+    let mut copy = false;
+    let mut replace = false;
+    let mut keys = None;
+    let mut i = 5;
+    while i < args.len() {
+        let arg = args[i].as_slice();
+        if arg.eq_ignore_ascii_case(b"COPY") {
+            copy = true;
+        } else if arg.eq_ignore_ascii_case(b"REPLACE") {
+            replace = true;
+        } else if arg.eq_ignore_ascii_case(b"KEYS") && args[2].is_empty() {
+            keys = Some(args[i + 1..].iter().map(|key| key.as_slice()).collect());
+            break;
+        } else {
+            return Err(CommandError::SyntaxError);
+        }
+        i += 1;
+    }
+
+    Ok(RedisCommand::Migrate {
+        host: args[0].as_slice(),
+        port,
+        keys: keys.unwrap_or_else(|| vec![args[2].as_slice()]),
+        db,
+        timeout,
+        copy,
+        replace,
+    })
+}
+
+// CLUSTER INFO|MYID|NODES|SLOTS|SHARDS, KEYSLOT key, COUNTKEYSINSLOT slot, GETKEYSINSLOT slot
+// count, ADDSLOTS slot [slot ...], ADDSLOTSRANGE start end [start end ...], DELSLOTS and
+// DELSLOTSRANGE the same way, MEET ip port [bus-port] and SETSLOT slot IMPORTING|MIGRATING|NODE
+// node-id or SETSLOT slot STABLE
+fn parse_cluster_args<'a>(
+    command_name: &[u8],
+    args: &'a [Vec<u8>],
+) -> Result<RedisCommand<'a>, CommandError> {
+    let subcommand = args[0].as_slice();
+    let name = subcommand.to_ascii_uppercase();
+
+    let subcommand = match (name.as_slice(), &args[1..]) {
+        (b"INFO", []) => ClusterSubcommand::Info,
+        (b"MYID", []) => ClusterSubcommand::MyId,
+        (b"NODES", []) => ClusterSubcommand::Nodes,
+        (b"SLOTS", []) => ClusterSubcommand::Slots,
+        (b"SHARDS", []) => ClusterSubcommand::Shards,
+        (b"KEYSLOT", [key]) => ClusterSubcommand::KeySlot { key },
+        (b"COUNTKEYSINSLOT", [slot]) => ClusterSubcommand::CountKeysInSlot {
+            slot: parse_slot(slot)?,
+        },
+        (b"GETKEYSINSLOT", [slot, count]) => ClusterSubcommand::GetKeysInSlot {
+            slot: parse_slot(slot)?,
+            count: match parse_int_arg(count)? {
+                count if count < 0 => return Err(CommandError::InvalidKeysCount),
+                count => count as usize,
+            },
+        },
+        (b"ADDSLOTS", slots) if !slots.is_empty() => ClusterSubcommand::AddSlots {
+            slots: slots
+                .iter()
+                .map(|slot| parse_slot(slot))
+                .collect::<Result<_, _>>()?,
+        },
+        (b"ADDSLOTSRANGE", ranges) if !ranges.is_empty() && ranges.len() % 2 == 0 => {
+            ClusterSubcommand::AddSlots {
+                slots: parse_slot_ranges(ranges)?,
+            }
+        }
+        (b"DELSLOTS", slots) if !slots.is_empty() => ClusterSubcommand::DelSlots {
+            slots: slots
+                .iter()
+                .map(|slot| parse_slot(slot))
+                .collect::<Result<_, _>>()?,
+        },
+        (b"DELSLOTSRANGE", ranges) if !ranges.is_empty() && ranges.len() % 2 == 0 => {
+            ClusterSubcommand::DelSlots {
+                slots: parse_slot_ranges(ranges)?,
+            }
+        }
+        (b"MEET", [ip, port]) => ClusterSubcommand::Meet {
+            ip,
+            port: parse_port(port)?,
+            bus_port: None,
+        },
+        (b"MEET", [ip, port, bus_port]) => ClusterSubcommand::Meet {
+            ip,
+            port: parse_port(port)?,
+            bus_port: Some(parse_port(bus_port)?),
+        },
+        (b"SETSLOT", [slot, state, rest @ ..]) => {
+            let slot = parse_slot(slot)?;
+            let state = match (state.to_ascii_uppercase().as_slice(), rest) {
+                (b"IMPORTING", [id]) => SetSlotState::Importing(id),
+                (b"MIGRATING", [id]) => SetSlotState::Migrating(id),
+                (b"NODE", [id]) => SetSlotState::Node(id),
+                (b"STABLE", []) => SetSlotState::Stable,
+                _ => return Err(CommandError::SyntaxError),
+            };
+            ClusterSubcommand::SetSlot { slot, state }
+        }
+        (
+            b"INFO" | b"MYID" | b"NODES" | b"SLOTS" | b"SHARDS" | b"KEYSLOT" | b"COUNTKEYSINSLOT"
+            | b"GETKEYSINSLOT" | b"ADDSLOTS" | b"ADDSLOTSRANGE" | b"DELSLOTS" | b"DELSLOTSRANGE"
+            | b"MEET" | b"SETSLOT",
+            _,
+        ) => {
+            return Err(CommandError::WrongNumberOfArguments {
+                cmd: [command_name, b"|", subcommand].concat(),
+            });
+        }
+        _ => {
+            return Err(CommandError::UnknownSubcommand {
+                cmd: command_name.to_vec(),
+                subcommand: subcommand.to_vec(),
+            });
+        }
+    };
+
+    Ok(RedisCommand::Cluster { subcommand })
+}
+
+fn parse_slot(arg: &[u8]) -> Result<u16, CommandError> {
+    match try_parse_int(arg) {
+        Some(slot) if (0..CLUSTER_SLOTS as i64).contains(&slot) => Ok(slot as u16),
+        _ => Err(CommandError::InvalidSlot),
+    }
+}
+
+// pairs of start and end slots, both included
+fn parse_slot_ranges(args: &[Vec<u8>]) -> Result<Vec<u16>, CommandError> {
+    let mut slots = Vec::new();
+    for pair in args.chunks(2) {
+        let (start, end) = (parse_slot(&pair[0])?, parse_slot(&pair[1])?);
+        if start > end {
+            return Err(CommandError::SyntaxError);
+        }
+        slots.extend(start..=end);
+    }
+    Ok(slots)
+}
+
+fn parse_port(arg: &[u8]) -> Result<u16, CommandError> {
+    u16::try_from(parse_int_arg(arg)?).map_err(|_| CommandError::NotAnInteger)
+}
+
+#[inline(always)]
+fn check_arity_error(expected_len: usize, len: usize, cmd: &[u8]) -> Result<(), CommandError> {
+    if len != expected_len {
+        return Err(CommandError::WrongNumberOfArguments { cmd: cmd.to_vec() });
+    }
+
+    Ok(())
+}
+
+// EXPIRE key time [NX|XX|GT|LT ...] and the PEXPIRE, EXPIREAT and PEXPIREAT variants. Like
+// redis the options can be repeated and XX goes together with GT or LT
+fn parse_expire_args<'a>(
+    command_name: &[u8],
+    args: &'a [Vec<u8>],
+    unit: TimeUnit,
+    absolute: bool,
+) -> Result<RedisCommand<'a>, CommandError> {
+    if args.len() < 2 {
+        return Err(CommandError::WrongNumberOfArguments {
+            cmd: command_name.to_vec(),
+        });
+    }
+
+    let time = parse_int_arg(&args[1])?;
+
+    let mut flags = ExpireFlags::default();
+    for arg in &args[2..] {
+        if arg.eq_ignore_ascii_case(b"NX") {
+            flags.nx = true;
+        } else if arg.eq_ignore_ascii_case(b"XX") {
+            flags.xx = true;
+        } else if arg.eq_ignore_ascii_case(b"GT") {
+            flags.gt = true;
+        } else if arg.eq_ignore_ascii_case(b"LT") {
+            flags.lt = true;
+        } else {
+            return Err(CommandError::UnsupportedOption {
+                option: arg.to_vec(),
+            });
+        }
+    }
+
+    if flags.nx && (flags.xx || flags.gt || flags.lt) {
+        return Err(CommandError::ExpireNxNotCompatible);
+    }
+    if flags.gt && flags.lt {
+        return Err(CommandError::ExpireGtLtNotCompatible);
+    }
+
+    Ok(RedisCommand::Expire {
+        key: args[0].as_slice(),
+        time,
+        unit,
+        absolute,
+        flags,
+    })
+}
+
+// REPLICAOF host port or REPLICAOF NO ONE
+fn parse_replicaof_args(args: &[Vec<u8>]) -> Result<RedisCommand<'_>, CommandError> {
+    if args[0].eq_ignore_ascii_case(b"NO") && args[1].eq_ignore_ascii_case(b"ONE") {
+        return Ok(RedisCommand::ReplicaOf { master: None });
+    }
+
+    let port = parse_port(&args[1])?;
+    Ok(RedisCommand::ReplicaOf {
+        master: Some((args[0].as_slice(), port)),
+    })
+}
+
+// REPLCONF option value [option value ...]
+fn parse_replconf_args(args: &[Vec<u8>]) -> Result<RedisCommand<'_>, CommandError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(CommandError::SyntaxError);
+    }
+
+    let mut options = Vec::with_capacity(args.len() / 2);
+    for pair in args.chunks(2) {
+        let (option, value) = (pair[0].as_slice(), pair[1].as_slice());
+        let option = match option.to_ascii_lowercase().as_slice() {
+            b"listening-port" => ReplConfOption::ListeningPort(parse_port(value)?),
+            b"ip-address" => ReplConfOption::IpAddress(value),
+            b"capa" => ReplConfOption::Capa(value),
+            b"ack" => ReplConfOption::Ack(parse_int_arg(value)?),
+            b"getack" => ReplConfOption::GetAck,
+            _ => {
+                return Err(CommandError::UnknownReplConfOption {
+                    option: option.to_vec(),
+                });
+            }
+        };
+        options.push(option);
+    }
+
+    Ok(RedisCommand::ReplConf { options })
+}
+
+// the amount of databases is only known when the command runs, so only negative indexes are out
+// of range here
+fn parse_db_index(arg: &[u8], invalid: CommandError) -> Result<usize, CommandError> {
+    match try_parse_int(arg) {
+        Some(index) if index < 0 => Err(CommandError::DbIndexOutOfRange),
+        Some(index) => Ok(index as usize),
+        None => Err(invalid),
+    }
+}
+
+// FLUSHDB and FLUSHALL [SYNC|ASYNC], returns whether ASYNC was given
+fn parse_flush_args(args: &[Vec<u8>]) -> Result<bool, CommandError> {
+    match args {
+        [] => Ok(false),
+        [mode] if mode.eq_ignore_ascii_case(b"SYNC") => Ok(false),
+        [mode] if mode.eq_ignore_ascii_case(b"ASYNC") => Ok(true),
+        _ => Err(CommandError::SyntaxError),
+    }
+}
+
+#[inline(always)]
+fn parse_int_arg(arg: &[u8]) -> Result<i64, CommandError> {
+    try_parse_int(arg).ok_or(CommandError::NotAnInteger)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number_to_usize() {
+        struct TestData {
+            buffer: &'static [u8],
+            expected: Result<usize, ProtocolError>,
+        }
+
+        let tests = vec![
+            TestData {
+                buffer: b"0\r\n",
+                expected: Ok(0),
+            },
+            TestData {
+                buffer: b"1\r\n",
+                expected: Ok(1),
+            },
+            TestData {
+                buffer: b"15\r\n",
+                expected: Ok(15),
+            },
+            TestData {
+                buffer: b"123\r\n",
+                expected: Ok(123),
+            },
+            TestData {
+                buffer: b"83210\r\n",
+                expected: Ok(83210),
+            },
+            TestData {
+                buffer: b"752019\r\n",
+                expected: Ok(752019),
+            },
+            TestData {
+                buffer: b"752019\r5",
+                expected: Err(ProtocolError::ExpectedByte {
+                    expected: b'\n',
+                    got: b'5',
+                }),
+            },
+            TestData {
+                buffer: b"75f019\r\n",
+                expected: Err(ProtocolError::UnexpectedByte(b'f')),
+            },
+            TestData {
+                buffer: b"75019\r",
+                expected: Err(ProtocolError::Incomplete),
+            },
+            TestData {
+                buffer: b"750",
+                expected: Err(ProtocolError::Incomplete),
+            },
+        ];
+
+        for test in tests {
+            let mut position = 0;
+            let result = parse_number_to_usize(test.buffer, &mut position);
+
+            match (&result, &test.expected) {
+                (Ok(val), Ok(expected)) => assert_eq!(expected, val),
+                (Err(e), Err(expected)) => {
+                    assert_eq!(expected, e)
+                }
+                _ => panic!("Expected {:?}, got {:?}", test.expected, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_bulk_string() {
+        struct TestData {
+            buffer: &'static [u8],
+            expected: Result<&'static [u8], ProtocolError>,
+        }
+
+        let tests = vec![
+            TestData {
+                buffer: b"$3\r\nSET\r\n",
+                expected: Ok(b"SET"),
+            },
             TestData {
                 buffer: b"$3\r\nGET\r\n",
                 expected: Ok(b"GET"),
@@ -598,6 +1420,13 @@ mod tests {
 
             assert_eq!(test.expected_position, position);
             assert_eq!(test.expected_state, parse_state);
+
+            // a whole command is written back exactly the way it was sent
+            if parse_state.state == ParseState::Complete {
+                let mut resp = Vec::new();
+                parse_state.write_resp(&mut resp);
+                assert_eq!(test.buffer, resp.as_slice());
+            }
         }
     }
 
@@ -828,9 +1657,7 @@ mod tests {
                     current_string: 1,
                     state: ParseState::Complete,
                 },
-                expected_command: RedisCommand::LPop {
-                    key: b"hello",
-                },
+                expected_command: RedisCommand::LPop { key: b"hello" },
             },
             TestData {
                 parse_state: CommandParseState {
@@ -840,15 +1667,1119 @@ mod tests {
                     current_string: 1,
                     state: ParseState::Complete,
                 },
-                expected_command: RedisCommand::RPop {
+                expected_command: RedisCommand::RPop { key: b"hello" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"LRANGE".to_vec()),
+                    args: vec![b"hello".to_vec(), b"0".to_vec(), b"-1".to_vec()],
+                    expected_strings: 4,
+                    current_string: 4,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::LRange {
                     key: b"hello",
+                    start: 0,
+                    stop: -1,
                 },
             },
-        ];
-
-        for test in tests {
-            let command = convert_command_parse_state_to_redis_command(&test.parse_state).unwrap();
-            assert_eq!(test.expected_command, command);
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"SORT".to_vec()),
+                    args: vec![
+                        b"hello".to_vec(),
+                        b"BY".to_vec(),
+                        b"w_*".to_vec(),
+                        b"limit".to_vec(),
+                        b"0".to_vec(),
+                        b"10".to_vec(),
+                        b"GET".to_vec(),
+                        b"#".to_vec(),
+                        b"GET".to_vec(),
+                        b"o_*".to_vec(),
+                        b"DESC".to_vec(),
+                        b"ALPHA".to_vec(),
+                        b"STORE".to_vec(),
+                        b"dest".to_vec(),
+                    ],
+                    expected_strings: 15,
+                    current_string: 15,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Sort {
+                    key: b"hello",
+                    options: SortOptions {
+                        by: Some(b"w_*"),
+                        limit: Some((0, 10)),
+                        get: vec![b"#", b"o_*"],
+                        desc: true,
+                        alpha: true,
+                        store: Some(b"dest"),
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"SORT_RO".to_vec()),
+                    args: vec![b"hello".to_vec(), b"desc".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Sort {
+                    key: b"hello",
+                    options: SortOptions {
+                        desc: true,
+                        ..Default::default()
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"SCAN".to_vec()),
+                    args: vec![
+                        b"42".to_vec(),
+                        b"match".to_vec(),
+                        b"user:*".to_vec(),
+                        b"COUNT".to_vec(),
+                        b"100".to_vec(),
+                        b"type".to_vec(),
+                        b"list".to_vec(),
+                    ],
+                    expected_strings: 8,
+                    current_string: 8,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Scan {
+                    cursor: 42,
+                    pattern: Some(b"user:*"),
+                    count: 100,
+                    object_type: Some(b"list"),
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"scan".to_vec()),
+                    args: vec![b"18446744073709551615".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Scan {
+                    cursor: u64::MAX,
+                    pattern: None,
+                    count: DEFAULT_SCAN_COUNT,
+                    object_type: None,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"RANDOMKEY".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::RandomKey,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"SAVE".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Save,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"bgsave".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::BgSave,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"LastSave".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::LastSave,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"BGREWRITEAOF".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::BgRewriteAof,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"INFO".to_vec()),
+                    args: vec![b"keyspace".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Info {
+                    sections: vec![b"keyspace"],
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"MEMORY".to_vec()),
+                    args: vec![b"usage".to_vec(), b"hello".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::MemoryUsage {
+                    key: b"hello",
+                    samples: DEFAULT_MEMORY_SAMPLES,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"memory".to_vec()),
+                    args: vec![
+                        b"USAGE".to_vec(),
+                        b"hello".to_vec(),
+                        b"SAMPLES".to_vec(),
+                        b"0".to_vec(),
+                    ],
+                    expected_strings: 5,
+                    current_string: 5,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::MemoryUsage {
+                    key: b"hello",
+                    samples: 0,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"MEMORY".to_vec()),
+                    args: vec![b"STATS".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::MemoryStats,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"OBJECT".to_vec()),
+                    args: vec![b"ENCODING".to_vec(), b"hello".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ObjectEncoding { key: b"hello" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"OBJECT".to_vec()),
+                    args: vec![b"refcount".to_vec(), b"hello".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ObjectRefCount { key: b"hello" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"OBJECT".to_vec()),
+                    args: vec![b"IdleTime".to_vec(), b"hello".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ObjectIdleTime { key: b"hello" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"OBJECT".to_vec()),
+                    args: vec![b"FREQ".to_vec(), b"hello".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ObjectFreq { key: b"hello" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"OBJECT".to_vec()),
+                    args: vec![b"HELP".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ObjectHelp,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"EXISTS".to_vec()),
+                    args: vec![b"hello".to_vec(), b"hello".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Exists {
+                    keys: vec![b"hello", b"hello"],
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"keys".to_vec()),
+                    args: vec![b"user:*".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Keys { pattern: b"user:*" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"SWAPDB".to_vec()),
+                    args: vec![b"0".to_vec(), b"15".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::SwapDb {
+                    index1: 0,
+                    index2: 15,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"move".to_vec()),
+                    args: vec![b"hello".to_vec(), b"3".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Move {
+                    key: b"hello",
+                    db: 3,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"FLUSHALL".to_vec()),
+                    args: vec![b"async".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::FlushAll { lazy: true },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"FLUSHDB".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::FlushDb { lazy: false },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"PEXPIRE".to_vec()),
+                    args: vec![
+                        b"hello".to_vec(),
+                        b"1500".to_vec(),
+                        b"xx".to_vec(),
+                        b"GT".to_vec(),
+                    ],
+                    expected_strings: 5,
+                    current_string: 5,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Expire {
+                    key: b"hello",
+                    time: 1500,
+                    unit: TimeUnit::Milliseconds,
+                    absolute: false,
+                    flags: ExpireFlags {
+                        xx: true,
+                        gt: true,
+                        ..Default::default()
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"expireat".to_vec()),
+                    args: vec![b"hello".to_vec(), b"4102444800".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Expire {
+                    key: b"hello",
+                    time: 4102444800,
+                    unit: TimeUnit::Seconds,
+                    absolute: true,
+                    flags: ExpireFlags::default(),
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"PTTL".to_vec()),
+                    args: vec![b"hello".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Ttl {
+                    key: b"hello",
+                    unit: TimeUnit::Milliseconds,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"EXPIRETIME".to_vec()),
+                    args: vec![b"hello".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ExpireTime {
+                    key: b"hello",
+                    unit: TimeUnit::Seconds,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"renamenx".to_vec()),
+                    args: vec![b"hello".to_vec(), b"world".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::RenameNx {
+                    key: b"hello",
+                    new_key: b"world",
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"COPY".to_vec()),
+                    args: vec![b"hello".to_vec(), b"world".to_vec(), b"replace".to_vec()],
+                    expected_strings: 4,
+                    current_string: 4,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Copy {
+                    source: b"hello",
+                    destination: b"world",
+                    replace: true,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"UNLINK".to_vec()),
+                    args: vec![b"hello".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Unlink {
+                    keys: vec![b"hello"],
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"DUMP".to_vec()),
+                    args: vec![b"hello".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Dump { key: b"hello" },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"RESTORE".to_vec()),
+                    args: vec![b"hello".to_vec(), b"0".to_vec(), b"payload".to_vec()],
+                    expected_strings: 4,
+                    current_string: 4,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Restore {
+                    key: b"hello",
+                    ttl: 0,
+                    payload: b"payload",
+                    options: RestoreOptions::default(),
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"restore".to_vec()),
+                    args: vec![
+                        b"hello".to_vec(),
+                        b"1000".to_vec(),
+                        b"payload".to_vec(),
+                        b"replace".to_vec(),
+                        b"ABSTTL".to_vec(),
+                        b"IdleTime".to_vec(),
+                        b"10".to_vec(),
+                    ],
+                    expected_strings: 8,
+                    current_string: 8,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Restore {
+                    key: b"hello",
+                    ttl: 1000,
+                    payload: b"payload",
+                    options: RestoreOptions {
+                        replace: true,
+                        absttl: true,
+                        idle_time: Some(10),
+                        freq: None,
+                        asking: false,
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"RESTORE".to_vec()),
+                    args: vec![
+                        b"hello".to_vec(),
+                        b"0".to_vec(),
+                        b"payload".to_vec(),
+                        b"FREQ".to_vec(),
+                        b"255".to_vec(),
+                    ],
+                    expected_strings: 6,
+                    current_string: 6,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Restore {
+                    key: b"hello",
+                    ttl: 0,
+                    payload: b"payload",
+                    options: RestoreOptions {
+                        freq: Some(255),
+                        ..Default::default()
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"REPLICAOF".to_vec()),
+                    args: vec![b"127.0.0.1".to_vec(), b"6379".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ReplicaOf {
+                    master: Some((b"127.0.0.1", 6379)),
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"slaveof".to_vec()),
+                    args: vec![b"no".to_vec(), b"one".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ReplicaOf { master: None },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"PSYNC".to_vec()),
+                    args: vec![b"?".to_vec(), b"-1".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Psync {
+                    replid: b"?",
+                    offset: -1,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"REPLCONF".to_vec()),
+                    args: vec![
+                        b"listening-port".to_vec(),
+                        b"6380".to_vec(),
+                        b"capa".to_vec(),
+                        b"psync2".to_vec(),
+                    ],
+                    expected_strings: 5,
+                    current_string: 5,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ReplConf {
+                    options: vec![
+                        ReplConfOption::ListeningPort(6380),
+                        ReplConfOption::Capa(b"psync2"),
+                    ],
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"ReplConf".to_vec()),
+                    args: vec![
+                        b"ACK".to_vec(),
+                        b"120".to_vec(),
+                        b"GETACK".to_vec(),
+                        b"*".to_vec(),
+                    ],
+                    expected_strings: 5,
+                    current_string: 5,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::ReplConf {
+                    options: vec![ReplConfOption::Ack(120), ReplConfOption::GetAck],
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"WAIT".to_vec()),
+                    args: vec![b"1".to_vec(), b"100".to_vec()],
+                    expected_strings: 3,
+                    current_string: 3,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Wait {
+                    numreplicas: 1,
+                    timeout: 100,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"MIGRATE".to_vec()),
+                    args: vec![
+                        b"127.0.0.1".to_vec(),
+                        b"6380".to_vec(),
+                        b"".to_vec(),
+                        b"0".to_vec(),
+                        b"-1".to_vec(),
+                        b"copy".to_vec(),
+                        b"KEYS".to_vec(),
+                        b"a".to_vec(),
+                        b"b".to_vec(),
+                    ],
+                    expected_strings: 10,
+                    current_string: 10,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Migrate {
+                    host: b"127.0.0.1",
+                    port: 6380,
+                    keys: vec![b"a", b"b"],
+                    db: 0,
+                    timeout: 1000,
+                    copy: true,
+                    replace: false,
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"RESTORE-ASKING".to_vec()),
+                    args: vec![b"key".to_vec(), b"0".to_vec(), b"payload".to_vec()],
+                    expected_strings: 4,
+                    current_string: 4,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Restore {
+                    key: b"key",
+                    ttl: 0,
+                    payload: b"payload",
+                    options: RestoreOptions {
+                        asking: true,
+                        ..Default::default()
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"CLUSTER".to_vec()),
+                    args: vec![
+                        b"addslotsrange".to_vec(),
+                        b"0".to_vec(),
+                        b"2".to_vec(),
+                        b"16383".to_vec(),
+                        b"16383".to_vec(),
+                    ],
+                    expected_strings: 6,
+                    current_string: 6,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Cluster {
+                    subcommand: ClusterSubcommand::AddSlots {
+                        slots: vec![0, 1, 2, 16383],
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"cluster".to_vec()),
+                    args: vec![
+                        b"MEET".to_vec(),
+                        b"127.0.0.1".to_vec(),
+                        b"7000".to_vec(),
+                        b"17001".to_vec(),
+                    ],
+                    expected_strings: 5,
+                    current_string: 5,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Cluster {
+                    subcommand: ClusterSubcommand::Meet {
+                        ip: b"127.0.0.1",
+                        port: 7000,
+                        bus_port: Some(17001),
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"CLUSTER".to_vec()),
+                    args: vec![
+                        b"SETSLOT".to_vec(),
+                        b"100".to_vec(),
+                        b"migrating".to_vec(),
+                        b"id".to_vec(),
+                    ],
+                    expected_strings: 5,
+                    current_string: 5,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Cluster {
+                    subcommand: ClusterSubcommand::SetSlot {
+                        slot: 100,
+                        state: SetSlotState::Migrating(b"id"),
+                    },
+                },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"Asking".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Asking,
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"ping".to_vec()),
+                    args: vec![],
+                    expected_strings: 1,
+                    current_string: 1,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Ping { message: None },
+            },
+            TestData {
+                parse_state: CommandParseState {
+                    command_name: Some(b"PING".to_vec()),
+                    args: vec![b"hello".to_vec()],
+                    expected_strings: 2,
+                    current_string: 2,
+                    state: ParseState::Complete,
+                },
+                expected_command: RedisCommand::Ping {
+                    message: Some(b"hello"),
+                },
+            },
+        ];
+
+        for test in tests {
+            let command = convert_command_parse_state_to_redis_command(&test.parse_state).unwrap();
+            assert_eq!(test.expected_command, command);
+        }
+    }
+
+    #[test]
+    fn test_convert_errors() {
+        struct TestData {
+            command_name: &'static [u8],
+            args: Vec<&'static [u8]>,
+            expected: CommandError,
+        }
+
+        let tests = vec![
+            TestData {
+                command_name: b"SAVE",
+                args: vec![b"now"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"SAVE".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"BGSAVE",
+                args: vec![b"SCHEDULE"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"DUMP",
+                args: vec![b"a", b"b"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"DUMP".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"0"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"RESTORE".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"soon", b"payload"],
+                expected: CommandError::NotAnInteger,
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"0", b"payload", b"KEEPTTL"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"0", b"payload", b"IDLETIME"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"0", b"payload", b"IDLETIME", b"-1"],
+                expected: CommandError::InvalidIdleTime,
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"0", b"payload", b"FREQ", b"256"],
+                expected: CommandError::InvalidFreq,
+            },
+            TestData {
+                command_name: b"RESTORE",
+                args: vec![b"key", b"0", b"payload", b"FREQ", b"1", b"IDLETIME", b"1"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"SORT",
+                args: vec![],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"SORT".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"SORT_RO",
+                args: vec![b"hello", b"STORE", b"dest"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"SORT",
+                args: vec![b"hello", b"LIMIT", b"0"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"SORT",
+                args: vec![b"hello", b"LIMIT", b"zero", b"10"],
+                expected: CommandError::NotAnInteger,
+            },
+            TestData {
+                command_name: b"SORT",
+                args: vec![b"hello", b"SIDEWAYS"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"SCAN",
+                args: vec![],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"SCAN".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"SCAN",
+                args: vec![b"-1"],
+                expected: CommandError::InvalidCursor,
+            },
+            TestData {
+                command_name: b"SCAN",
+                args: vec![b"0", b"COUNT", b"0"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"SCAN",
+                args: vec![b"0", b"COUNT", b"ten"],
+                expected: CommandError::NotAnInteger,
+            },
+            TestData {
+                command_name: b"SCAN",
+                args: vec![b"0", b"MATCH"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"MEMORY",
+                args: vec![],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"MEMORY".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"MEMORY",
+                args: vec![b"USAGE"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"MEMORY|USAGE".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"MEMORY",
+                args: vec![b"usage", b"hello", b"SAMPLES", b"-1"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"MEMORY",
+                args: vec![b"usage", b"hello", b"SAMPLES"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"MEMORY",
+                args: vec![b"stats", b"extra"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"MEMORY|stats".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"MEMORY",
+                args: vec![b"doctor"],
+                expected: CommandError::UnknownSubcommand {
+                    cmd: b"MEMORY".to_vec(),
+                    subcommand: b"doctor".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"OBJECT",
+                args: vec![],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"OBJECT".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"OBJECT",
+                args: vec![b"encoding"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"OBJECT|encoding".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"OBJECT",
+                args: vec![b"FREQ", b"a", b"b"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"OBJECT|FREQ".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"OBJECT",
+                args: vec![b"help", b"extra"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"OBJECT|help".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"OBJECT",
+                args: vec![b"lru", b"hello"],
+                expected: CommandError::UnknownSubcommand {
+                    cmd: b"OBJECT".to_vec(),
+                    subcommand: b"lru".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"EXPIRE",
+                args: vec![b"hello"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"EXPIRE".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"EXPIRE",
+                args: vec![b"hello", b"soon"],
+                expected: CommandError::NotAnInteger,
+            },
+            TestData {
+                command_name: b"EXPIRE",
+                args: vec![b"hello", b"10", b"NX", b"GT"],
+                expected: CommandError::ExpireNxNotCompatible,
+            },
+            TestData {
+                command_name: b"PEXPIREAT",
+                args: vec![b"hello", b"10", b"GT", b"LT"],
+                expected: CommandError::ExpireGtLtNotCompatible,
+            },
+            TestData {
+                command_name: b"EXPIRE",
+                args: vec![b"hello", b"10", b"KEEPTTL"],
+                expected: CommandError::UnsupportedOption {
+                    option: b"KEEPTTL".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"SELECT",
+                args: vec![b"one"],
+                expected: CommandError::InvalidDbIndex,
+            },
+            TestData {
+                command_name: b"SELECT",
+                args: vec![b"-1"],
+                expected: CommandError::DbIndexOutOfRange,
+            },
+            TestData {
+                command_name: b"SWAPDB",
+                args: vec![b"0", b"one"],
+                expected: CommandError::InvalidSecondDbIndex,
+            },
+            TestData {
+                command_name: b"MOVE",
+                args: vec![b"hello", b"one"],
+                expected: CommandError::NotAnInteger,
+            },
+            TestData {
+                command_name: b"FLUSHDB",
+                args: vec![b"LATER"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"FLUSHALL",
+                args: vec![b"SYNC", b"ASYNC"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"KEYS",
+                args: vec![b"*", b"extra"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"KEYS".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"EXISTS",
+                args: vec![],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"EXISTS".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"RENAME",
+                args: vec![b"hello"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"RENAME".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"COPY",
+                args: vec![b"hello"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"COPY".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"COPY",
+                args: vec![b"hello", b"world", b"DB", b"1"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"REPLCONF",
+                args: vec![b"ack"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"REPLCONF",
+                args: vec![b"rdb-only", b"1"],
+                expected: CommandError::UnknownReplConfOption {
+                    option: b"rdb-only".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"REPLICAOF",
+                args: vec![b"127.0.0.1", b"70000"],
+                expected: CommandError::NotAnInteger,
+            },
+            TestData {
+                command_name: b"PSYNC",
+                args: vec![b"?"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"PSYNC".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"WAIT",
+                args: vec![b"1", b"-1"],
+                expected: CommandError::NegativeTimeout,
+            },
+            // KEYS only comes with an empty key
+            TestData {
+                command_name: b"MIGRATE",
+                args: vec![b"127.0.0.1", b"6380", b"key", b"0", b"100", b"KEYS", b"a"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"CLUSTER",
+                args: vec![b"ADDSLOTS", b"16384"],
+                expected: CommandError::InvalidSlot,
+            },
+            TestData {
+                command_name: b"CLUSTER",
+                args: vec![b"DELSLOTSRANGE", b"10", b"5"],
+                expected: CommandError::SyntaxError,
+            },
+            TestData {
+                command_name: b"CLUSTER",
+                args: vec![b"GETKEYSINSLOT", b"0", b"-1"],
+                expected: CommandError::InvalidKeysCount,
+            },
+            TestData {
+                command_name: b"CLUSTER",
+                args: vec![b"keyslot"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"CLUSTER|keyslot".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"CLUSTER",
+                args: vec![b"FAILOVER"],
+                expected: CommandError::UnknownSubcommand {
+                    cmd: b"CLUSTER".to_vec(),
+                    subcommand: b"FAILOVER".to_vec(),
+                },
+            },
+            TestData {
+                command_name: b"PING",
+                args: vec![b"hello", b"world"],
+                expected: CommandError::WrongNumberOfArguments {
+                    cmd: b"PING".to_vec(),
+                },
+            },
+        ];
+
+        for test in tests {
+            let parse_state = CommandParseState {
+                command_name: Some(test.command_name.to_vec()),
+                expected_strings: test.args.len() + 1,
+                current_string: test.args.len() + 1,
+                args: test.args.iter().map(|a| a.to_vec()).collect(),
+                state: ParseState::Complete,
+            };
+
+            let result = convert_command_parse_state_to_redis_command(&parse_state);
+            assert_eq!(Err(test.expected), result);
         }
     }
 }
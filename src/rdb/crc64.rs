@@ -0,0 +1,70 @@
+// the crc64 redis puts at the end of rdb files and DUMP payloads, the Jones polynomial with
+// reflected input and output, a zero initial value and no final xor
+const POLY: u64 = 0xad93d23594c935a9;
+
+static TABLE: [u64; 256] = make_table();
+
+const fn make_table() -> [u64; 256] {
+    // the input is reflected so the table is built from the reflected polynomial
+    let poly = POLY.reverse_bits();
+    let mut table = [0u64; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+// continues `crc` over `bytes`, start with 0
+pub fn crc64(mut crc: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        crc = TABLE[((crc ^ byte as u64) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64() {
+        struct TestData {
+            bytes: &'static [u8],
+            expected: u64,
+        }
+
+        // the check value of the crc64 test in redis
+        let tests = vec![
+            TestData {
+                bytes: b"",
+                expected: 0,
+            },
+            TestData {
+                bytes: b"123456789",
+                expected: 0xe9c6d914c4b8d9ca,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(test.expected, crc64(0, test.bytes));
+        }
+
+        // the crc can be computed in pieces
+        let crc = crc64(crc64(0, b"12345"), b"6789");
+        assert_eq!(0xe9c6d914c4b8d9ca, crc);
+    }
+}
@@ -0,0 +1,422 @@
+use std::io::{self, Write};
+
+use crate::{
+    error::RdbError,
+    rdb::{
+        RDB_MAX_VERSION, RDB_MODULE_OPCODE_UINT, RDB_VERSION, RdbReader, crc64, is_supported_type,
+        object_type, type_name, write_len, write_string, write_value,
+    },
+    redis::{redis_object::RedisObject, ziplist::ValidationMode},
+};
+
+// "REDIS" followed by the version as 4 ascii digits
+const RDB_MAGIC: &[u8] = b"REDIS";
+const RDB_HEADER_SIZE: usize = 9;
+const RDB_CHECKSUM_SIZE: usize = 8;
+// the oldest version that can be loaded, the one of redis 3.0
+const RDB_MIN_VERSION: u16 = 6;
+
+// opcodes that can be where the type byte of a key is expected
+const RDB_OPCODE_SLOT_INFO: u8 = 244;
+const RDB_OPCODE_FUNCTION2: u8 = 245;
+const RDB_OPCODE_FUNCTION_PRE_GA: u8 = 246;
+const RDB_OPCODE_MODULE_AUX: u8 = 247;
+const RDB_OPCODE_IDLE: u8 = 248;
+const RDB_OPCODE_FREQ: u8 = 249;
+const RDB_OPCODE_AUX: u8 = 250;
+const RDB_OPCODE_RESIZEDB: u8 = 251;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
+const RDB_OPCODE_EXPIRETIME: u8 = 253;
+const RDB_OPCODE_SELECTDB: u8 = 254;
+const RDB_OPCODE_EOF: u8 = 255;
+
+// the version redis 7.0 writes next to RDB_VERSION, tools like redis-check-rdb print it
+const REDIS_VERSION: &str = "7.0.0";
+
+// writes an rdb file to `out` while keeping the crc64 of everything written so far. The header
+// goes out when it is created and the EOF opcode and checksum in `finish`
+pub struct RdbWriter<W: Write> {
+    out: W,
+    crc: u64,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> RdbWriter<W> {
+    // `ctime` is the unix time in seconds the snapshot is taken at
+    pub fn new(out: W, ctime: i64) -> io::Result<Self> {
+        let mut writer = RdbWriter {
+            out,
+            crc: 0,
+            buf: Vec::new(),
+        };
+
+        write!(writer.buf, "REDIS{:04}", RDB_VERSION)?;
+        writer.write_aux(b"redis-ver", REDIS_VERSION.as_bytes());
+        writer.write_aux(b"redis-bits", b"64");
+        writer.write_aux(b"ctime", ctime.to_string().as_bytes());
+        writer.flush_buf()?;
+
+        Ok(writer)
+    }
+
+    // starts the keys of a database, the sizes let the loader size its dicts up front
+    pub fn select_db(&mut self, index: usize, keys: usize, expires: usize) -> io::Result<()> {
+        self.buf.push(RDB_OPCODE_SELECTDB);
+        write_len(&mut self.buf, index as u64);
+        self.buf.push(RDB_OPCODE_RESIZEDB);
+        write_len(&mut self.buf, keys as u64);
+        write_len(&mut self.buf, expires as u64);
+        self.flush_buf()
+    }
+
+    // `expire` is a unix time in milliseconds
+    pub fn write_key(
+        &mut self,
+        key: &[u8],
+        value: &RedisObject,
+        expire: Option<i64>,
+    ) -> io::Result<()> {
+        if let Some(expire) = expire {
+            self.buf.push(RDB_OPCODE_EXPIRETIME_MS);
+            self.buf.extend_from_slice(&expire.to_le_bytes());
+        }
+
+        self.buf.push(object_type(value));
+        write_string(&mut self.buf, key);
+        write_value(&mut self.buf, value);
+
+        self.flush_buf()
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.buf.push(RDB_OPCODE_EOF);
+        self.flush_buf()?;
+        self.out.write_all(&self.crc.to_le_bytes())?;
+        Ok(self.out)
+    }
+
+    fn write_aux(&mut self, key: &[u8], value: &[u8]) {
+        self.buf.push(RDB_OPCODE_AUX);
+        write_string(&mut self.buf, key);
+        write_string(&mut self.buf, value);
+    }
+
+    fn flush_buf(&mut self) -> io::Result<()> {
+        self.crc = crc64(self.crc, &self.buf);
+        self.out.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+// a key read from an rdb file
+pub struct RdbKey<'a> {
+    pub db: usize,
+    pub key: &'a [u8],
+    pub value: RedisObject,
+    // unix time in milliseconds
+    pub expire: Option<i64>,
+    // written by servers with an lru or lfu maxmemory policy
+    pub idle_time: Option<u64>,
+    pub freq: Option<u8>,
+}
+
+// reads a whole rdb file and hands every key to `f`, which decides what to do with the database
+// index and expired keys. Aux fields are skipped. The checksum is checked before anything is read
+// unless it is 0, which is what redis writes with rdbchecksum off.
+//
+// Keys of types that can't be stored here, module data and functions are rejected when `strict`
+// is set, otherwise they are skipped with a warning
+pub fn load(
+    data: &[u8],
+    mode: ValidationMode,
+    strict: bool,
+    mut f: impl FnMut(RdbKey) -> Result<(), RdbError>,
+) -> Result<(), RdbError> {
+    if data.len() < RDB_HEADER_SIZE + 1 + RDB_CHECKSUM_SIZE || !data.starts_with(RDB_MAGIC) {
+        return Err(RdbError::InvalidHeader);
+    }
+    let version = std::str::from_utf8(&data[RDB_MAGIC.len()..RDB_HEADER_SIZE])
+        .ok()
+        .and_then(|version| version.parse::<u16>().ok())
+        .ok_or(RdbError::InvalidHeader)?;
+    if !(RDB_MIN_VERSION..=RDB_MAX_VERSION).contains(&version) {
+        return Err(RdbError::UnsupportedVersion { version });
+    }
+
+    let (body, crc) = data.split_at(data.len() - RDB_CHECKSUM_SIZE);
+    let expected = u64::from_le_bytes(crc.try_into().unwrap());
+    if expected != 0 {
+        let got = crc64(0, body);
+        if expected != got {
+            return Err(RdbError::ChecksumMismatch { expected, got });
+        }
+    }
+
+    let unsupported = |rdb_type: u8, what: String| {
+        if strict {
+            return Err(RdbError::UnsupportedType { rdb_type });
+        }
+        eprintln!("Warning: skipped {} in the rdb file", what);
+        Ok(())
+    };
+
+    let mut reader = RdbReader::new(&body[RDB_HEADER_SIZE..], mode);
+    let mut db = 0;
+    // the opcodes in front of a key apply to it
+    let mut expire = None;
+    let mut idle_time = None;
+    let mut freq = None;
+    loop {
+        match reader.read_u8()? {
+            RDB_OPCODE_EOF => break,
+            RDB_OPCODE_SELECTDB => {
+                db = usize::try_from(reader.read_len()?).map_err(|_| RdbError::InvalidLength)?;
+            }
+            // sizes to allocate up front, both for the whole db and for a cluster slot
+            RDB_OPCODE_RESIZEDB => {
+                reader.read_len()?;
+                reader.read_len()?;
+            }
+            RDB_OPCODE_SLOT_INFO => {
+                reader.read_len()?;
+                reader.read_len()?;
+                reader.read_len()?;
+            }
+            RDB_OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            }
+            RDB_OPCODE_EXPIRETIME_MS => {
+                let bytes = reader.read_bytes(8)?;
+                expire = Some(i64::from_le_bytes(bytes.try_into().unwrap()));
+            }
+            RDB_OPCODE_EXPIRETIME => {
+                let bytes = reader.read_bytes(4)?;
+                expire = Some(i32::from_le_bytes(bytes.try_into().unwrap()) as i64 * 1000);
+            }
+            RDB_OPCODE_IDLE => idle_time = Some(reader.read_len()?),
+            RDB_OPCODE_FREQ => freq = Some(reader.read_u8()?),
+            RDB_OPCODE_MODULE_AUX => {
+                unsupported(RDB_OPCODE_MODULE_AUX, "module data".to_string())?;
+                // the module id and when it is loaded, which is always written as a uint
+                reader.read_len()?;
+                if reader.read_len()? != RDB_MODULE_OPCODE_UINT {
+                    return Err(RdbError::InvalidModuleValue);
+                }
+                reader.read_len()?;
+                reader.skip_module_value()?;
+            }
+            RDB_OPCODE_FUNCTION2 => {
+                unsupported(RDB_OPCODE_FUNCTION2, "a function library".to_string())?;
+                reader.read_string()?;
+            }
+            // only written by the release candidates of redis 7.0
+            RDB_OPCODE_FUNCTION_PRE_GA => {
+                return Err(RdbError::UnsupportedType {
+                    rdb_type: RDB_OPCODE_FUNCTION_PRE_GA,
+                });
+            }
+            rdb_type => {
+                let key = reader.read_string()?;
+                let (expire, idle_time, freq) = (expire.take(), idle_time.take(), freq.take());
+
+                if !is_supported_type(rdb_type) {
+                    // the type is checked first, unknown ones are rejected either way
+                    reader.skip_object(rdb_type)?;
+                    let what = format!(
+                        "the {} '{}'",
+                        type_name(rdb_type),
+                        String::from_utf8_lossy(&key)
+                    );
+                    unsupported(rdb_type, what)?;
+                    continue;
+                }
+
+                let value = reader.read_object(rdb_type)?;
+                f(RdbKey {
+                    db,
+                    key: &key,
+                    value,
+                    expire,
+                    idle_time,
+                    freq,
+                })?;
+            }
+        }
+    }
+
+    if !reader.is_empty() {
+        return Err(RdbError::TrailingBytes);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::{
+        listpack::{ListPack, ListPackEntry},
+        ziplist::{ZipEntry, ZipList},
+    };
+
+    // the database, key, value and expire time
+    type Key = (usize, Vec<u8>, RedisObject, Option<i64>);
+
+    fn write_file(keys: &[(usize, &[u8], RedisObject, Option<i64>)]) -> Vec<u8> {
+        let mut writer = RdbWriter::new(Vec::new(), 1_700_000_000).unwrap();
+        let mut current_db = None;
+        for (db, key, value, expire) in keys {
+            if current_db != Some(*db) {
+                writer.select_db(*db, 1, 0).unwrap();
+                current_db = Some(*db);
+            }
+            writer.write_key(key, value, *expire).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn load_all(data: &[u8]) -> Result<Vec<Key>, RdbError> {
+        let mut keys = Vec::new();
+        load(data, ValidationMode::Deep, true, |key| {
+            keys.push((key.db, key.key.to_vec(), key.value, key.expire));
+            Ok(())
+        })?;
+        Ok(keys)
+    }
+
+    #[test]
+    fn test_write_and_load() {
+        let mut ziplist = ZipList::new();
+        ziplist.push(ZipEntry::from_bytes(b"a"));
+        ziplist.push(ZipEntry::from_bytes(b"100"));
+        let mut listpack = ListPack::new();
+        listpack.push(ListPackEntry::from_bytes(b"b"));
+
+        let keys: Vec<(usize, &[u8], RedisObject, Option<i64>)> = vec![
+            (0, b"string", RedisObject::new_from_bytes(b"hello"), None),
+            (0, b"int", RedisObject::Int(-5), Some(1_700_000_000_123)),
+            (0, b"ziplist", RedisObject::List(ziplist), None),
+            (3, b"listpack", RedisObject::ListPack(listpack), Some(42)),
+        ];
+
+        let data = write_file(&keys);
+        assert!(data.starts_with(b"REDIS0010\xFA\x09redis-ver\x057.0.0"));
+
+        let loaded = load_all(&data).unwrap();
+        assert_eq!(keys.len(), loaded.len());
+        for ((db, key, value, expire), loaded) in keys.into_iter().zip(loaded) {
+            assert_eq!((db, key.to_vec(), value, expire), loaded);
+        }
+    }
+
+    #[test]
+    fn test_load_redis_file() {
+        // written like redis 7.0 does, aux fields, both kinds of expire times and an int encoded value
+        #[rustfmt::skip]
+        let mut data = b"REDIS0010\
+            \xFA\x09redis-ver\x057.0.0\
+            \xFA\x0Aredis-bits\xC0\x40\
+            \xFE\x00\xFB\x01\x01\
+            \xFC\x00\x00\x00\x00\x00\x00\x00\x01\
+            \x00\x03key\x05value\
+            \xFD\x01\x00\x00\x00\
+            \x00\x04key2\xC0\x07\
+            \xFF"
+            .to_vec();
+        let crc = crc64(0, &data);
+        data.extend_from_slice(&crc.to_le_bytes());
+
+        assert_eq!(
+            Ok(vec![
+                (
+                    0,
+                    b"key".to_vec(),
+                    RedisObject::new_from_bytes(b"value"),
+                    Some(1 << 56)
+                ),
+                (0, b"key2".to_vec(), RedisObject::Int(7), Some(1000)),
+            ]),
+            load_all(&data)
+        );
+
+        // a checksum of 0 is not checked
+        let len = data.len();
+        data[len - 8..].fill(0);
+        assert!(load_all(&data).is_ok());
+    }
+
+    #[test]
+    fn test_load_errors() {
+        struct TestData {
+            data: Vec<u8>,
+            expected: RdbError,
+        }
+
+        let valid = write_file(&[(0, b"key", RedisObject::Int(1), None)]);
+        let corrupted = |f: fn(&mut Vec<u8>)| {
+            let mut data = valid.clone();
+            f(&mut data);
+            data
+        };
+        // the data with a checksum that matches again
+        let with_crc = |mut data: Vec<u8>| {
+            let len = data.len();
+            let crc = crc64(0, &data[..len - 8]);
+            data[len - 8..].copy_from_slice(&crc.to_le_bytes());
+            data
+        };
+
+        let tests = vec![
+            TestData {
+                data: b"REDIS".to_vec(),
+                expected: RdbError::InvalidHeader,
+            },
+            TestData {
+                data: corrupted(|d| d[0] = b'X'),
+                expected: RdbError::InvalidHeader,
+            },
+            TestData {
+                data: corrupted(|d| d[5..9].copy_from_slice(b"00aa")),
+                expected: RdbError::InvalidHeader,
+            },
+            TestData {
+                data: corrupted(|d| d[5..9].copy_from_slice(b"0099")),
+                expected: RdbError::UnsupportedVersion { version: 99 },
+            },
+            // older than redis 3.0
+            TestData {
+                data: corrupted(|d| d[5..9].copy_from_slice(b"0005")),
+                expected: RdbError::UnsupportedVersion { version: 5 },
+            },
+            TestData {
+                data: corrupted(|d| *d.last_mut().unwrap() ^= 1),
+                expected: RdbError::ChecksumMismatch {
+                    expected: u64::from_le_bytes(valid[valid.len() - 8..].try_into().unwrap())
+                        ^ (1 << 56),
+                    got: u64::from_le_bytes(valid[valid.len() - 8..].try_into().unwrap()),
+                },
+            },
+            // the EOF opcode turned into the string type
+            TestData {
+                data: with_crc(corrupted(|d| {
+                    let len = d.len();
+                    d[len - 9] = 0;
+                })),
+                expected: RdbError::UnexpectedEnd,
+            },
+            TestData {
+                data: with_crc(corrupted(|d| {
+                    let len = d.len();
+                    d.insert(len - 8, 0);
+                })),
+                expected: RdbError::TrailingBytes,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(Err(test.expected), load_all(&test.data));
+        }
+    }
+}
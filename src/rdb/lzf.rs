@@ -0,0 +1,135 @@
+// decompression of the lzf format redis compresses long strings with. The input is a sequence of
+// literal runs and back references:
+//
+// 000LLLLL <L+1 literal bytes>
+// LLLooooo oooooooo          copy L+2 bytes from o+1 bytes back
+// 111ooooo LLLLLLLL oooooooo copy L+9 bytes from o+1 bytes back
+
+// a long back reference of 3 bytes copies at most 7 + 255 + 2 bytes, nothing expands more
+const MAX_EXPANSION: usize = 264 / 3;
+
+// `len` is the decompressed length stored next to the data, None means the data is corrupt
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    // the length comes from the untrusted data as well, it is only reserved once it is known that
+    // the input could produce that much
+    if len > input.len().saturating_mul(MAX_EXPANSION) {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(len);
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let ctrl = input[pos] as usize;
+        pos += 1;
+
+        if ctrl < 32 {
+            let run = ctrl + 1;
+            if pos + run > input.len() || output.len() + run > len {
+                return None;
+            }
+            output.extend_from_slice(&input[pos..pos + run]);
+            pos += run;
+            continue;
+        }
+
+        let mut run = ctrl >> 5;
+        if run == 7 {
+            run += *input.get(pos)? as usize;
+            pos += 1;
+        }
+        run += 2;
+
+        let back = ((ctrl & 0x1F) << 8) + *input.get(pos)? as usize + 1;
+        pos += 1;
+        if back > output.len() || output.len() + run > len {
+            return None;
+        }
+
+        // the reference can overlap the bytes it produces, so they are copied one at a time
+        let start = output.len() - back;
+        for i in 0..run {
+            output.push(output[start + i]);
+        }
+    }
+
+    if output.len() != len {
+        return None;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        struct TestData {
+            input: Vec<u8>,
+            len: usize,
+            expected: Option<Vec<u8>>,
+        }
+
+        let tests = vec![
+            // a single literal run
+            TestData {
+                input: vec![2, b'a', b'b', b'c'],
+                len: 3,
+                expected: Some(b"abc".to_vec()),
+            },
+            // "ab" then copy 4 bytes from 2 back, the copy overlaps what it writes
+            TestData {
+                input: vec![1, b'a', b'b', 0b010_00000, 1],
+                len: 6,
+                expected: Some(b"ababab".to_vec()),
+            },
+            // a long back reference with the extra length byte, 7 + 3 + 2 bytes from 1 back
+            TestData {
+                input: vec![0, b'x', 0b111_00000, 3, 0],
+                len: 13,
+                expected: Some(vec![b'x'; 13]),
+            },
+            // a reference before the start of the output
+            TestData {
+                input: vec![0, b'a', 0b001_00000, 5],
+                len: 4,
+                expected: None,
+            },
+            // a literal run cut short
+            TestData {
+                input: vec![4, b'a', b'b'],
+                len: 5,
+                expected: None,
+            },
+            // more output than the stored length
+            TestData {
+                input: vec![2, b'a', b'b', b'c'],
+                len: 2,
+                expected: None,
+            },
+            // less output than the stored length
+            TestData {
+                input: vec![2, b'a', b'b', b'c'],
+                len: 4,
+                expected: None,
+            },
+            // the longest back reference, 7 + 255 + 2 bytes from 1 back
+            TestData {
+                input: vec![0, b'x', 0b111_00000, 255, 0],
+                len: 265,
+                expected: Some(vec![b'x'; 265]),
+            },
+            // a length no input of this size decompresses to is not reserved
+            TestData {
+                input: vec![0, b'a'],
+                len: usize::MAX >> 1,
+                expected: None,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(test.expected, decompress(&test.input, test.len));
+        }
+    }
+}
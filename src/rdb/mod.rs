@@ -0,0 +1,908 @@
+mod crc64;
+mod file;
+mod lzf;
+
+use std::borrow::Cow;
+
+pub use crc64::crc64;
+pub use file::{RdbKey, RdbWriter, load};
+
+use crate::{
+    error::RdbError,
+    redis::{
+        listpack::{ListPack, ListPackEntry},
+        redis_object::{RedisObject, try_parse_int},
+        ziplist::{ValidationMode, ZipEntry, ZipList, ZipListValue},
+    },
+};
+
+// the version written behind dumps, 10 is the first one with listpack encoded lists (redis 7.0)
+pub const RDB_VERSION: u16 = 10;
+// the newest version that can be read, the versions after 10 only added types that are not
+// supported here, loading a file skips or rejects them
+pub const RDB_MAX_VERSION: u16 = 12;
+
+// value types
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_LIST_ZIPLIST: u8 = 10;
+const RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+
+// types that can be read but not stored here, loading a file skips or rejects them
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_MODULE_2: u8 = 7;
+const RDB_TYPE_HASH_ZIPMAP: u8 = 9;
+const RDB_TYPE_SET_INTSET: u8 = 11;
+const RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
+const RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+const RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+const RDB_TYPE_HASH_LISTPACK: u8 = 16;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const RDB_TYPE_SET_LISTPACK: u8 = 20;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+// hashes with field expires (redis 7.4), the ones without the _PRE_GA start with the minimum one
+const RDB_TYPE_HASH_METADATA_PRE_GA: u8 = 22;
+const RDB_TYPE_HASH_LISTPACK_EX_PRE_GA: u8 = 23;
+const RDB_TYPE_HASH_METADATA: u8 = 24;
+const RDB_TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// the values modules write, a list of opcodes and values that ends with the EOF opcode
+const RDB_MODULE_OPCODE_EOF: u64 = 0;
+const RDB_MODULE_OPCODE_SINT: u64 = 1;
+const RDB_MODULE_OPCODE_UINT: u64 = 2;
+const RDB_MODULE_OPCODE_FLOAT: u64 = 3;
+const RDB_MODULE_OPCODE_DOUBLE: u64 = 4;
+const RDB_MODULE_OPCODE_STRING: u64 = 5;
+
+// the scores of RDB_TYPE_ZSET are strings with these lengths standing for special values
+const RDB_ZSET_SCORE_NAN: u8 = 253;
+const RDB_ZSET_SCORE_POS_INF: u8 = 254;
+const RDB_ZSET_SCORE_NEG_INF: u8 = 255;
+
+// stream ids are two big endian u64s when they are written raw
+const STREAM_ID_SIZE: usize = 16;
+
+// the top two bits of the first byte of a length, the 32 and 64 bit lengths use the whole byte
+const RDB_6BITLEN: u8 = 0;
+const RDB_14BITLEN: u8 = 1;
+const RDB_32BITLEN: u8 = 0x80;
+const RDB_64BITLEN: u8 = 0x81;
+const RDB_ENCVAL: u8 = 3;
+
+// special string encodings in the low 6 bits of an RDB_ENCVAL length
+const RDB_ENC_INT8: u8 = 0;
+const RDB_ENC_INT16: u8 = 1;
+const RDB_ENC_INT32: u8 = 2;
+const RDB_ENC_LZF: u8 = 3;
+
+// strings longer than this are never int encoded, "-2147483648" is 11 characters
+const INT_ENCODING_MAX_LEN: usize = 11;
+
+// how the nodes of a quicklist 2 hold their elements
+const QUICKLIST_NODE_CONTAINER_PLAIN: u64 = 1;
+const QUICKLIST_NODE_CONTAINER_PACKED: u64 = 2;
+
+// the rdb version and crc64 behind a DUMP payload
+const DUMP_FOOTER_SIZE: usize = 10;
+
+// DUMP, the value in rdb format followed by the rdb version and the crc64 of everything in front
+// of it, both little endian
+pub fn dump(object: &RedisObject) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_object(&mut payload, object);
+    payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let crc = crc64(0, &payload);
+    payload.extend_from_slice(&crc.to_le_bytes());
+    payload
+}
+
+// the value of a DUMP payload, the footer is checked before anything is read and the value has
+// to take up the rest of the payload
+pub fn restore(payload: &[u8], mode: ValidationMode) -> Result<RedisObject, RdbError> {
+    if payload.len() < DUMP_FOOTER_SIZE {
+        return Err(RdbError::MissingFooter);
+    }
+
+    let (data, crc) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([data[data.len() - 2], data[data.len() - 1]]);
+    if version > RDB_MAX_VERSION {
+        return Err(RdbError::UnsupportedVersion { version });
+    }
+
+    let expected = u64::from_le_bytes(crc.try_into().unwrap());
+    let got = crc64(0, data);
+    if expected != got {
+        return Err(RdbError::ChecksumMismatch { expected, got });
+    }
+
+    let mut reader = RdbReader::new(&data[..data.len() - 2], mode);
+    let rdb_type = reader.read_u8()?;
+    let object = reader.read_object(rdb_type)?;
+    if !reader.is_empty() {
+        return Err(RdbError::TrailingBytes);
+    }
+
+    Ok(object)
+}
+
+// the type byte and the value, the same encoding rdb files use for the values of keys
+pub fn write_object(buf: &mut Vec<u8>, object: &RedisObject) {
+    buf.push(object_type(object));
+    write_value(buf, object);
+}
+
+pub fn object_type(object: &RedisObject) -> u8 {
+    match object {
+        RedisObject::String(_) | RedisObject::Int(_) => RDB_TYPE_STRING,
+        RedisObject::List(_) => RDB_TYPE_LIST_ZIPLIST,
+        // redis 7 writes its listpack lists as a quicklist
+        RedisObject::ListPack(_) => RDB_TYPE_LIST_QUICKLIST_2,
+    }
+}
+
+pub fn write_value(buf: &mut Vec<u8>, object: &RedisObject) {
+    match object {
+        RedisObject::String(s) => write_string(buf, s),
+        RedisObject::Int(i) => write_int(buf, *i),
+        // the ziplist layout is the one redis uses, so the bytes go out as they are
+        RedisObject::List(list) => write_string(buf, list.as_bytes()),
+        // a single packed node
+        RedisObject::ListPack(list) => {
+            write_len(buf, 1);
+            write_len(buf, QUICKLIST_NODE_CONTAINER_PACKED);
+            write_string(buf, list.as_bytes());
+        }
+    }
+}
+
+pub fn write_len(buf: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        buf.push((RDB_6BITLEN << 6) | len as u8);
+    } else if len < 1 << 14 {
+        buf.push((RDB_14BITLEN << 6) | (len >> 8) as u8);
+        buf.push(len as u8);
+    } else if len <= u32::MAX as u64 {
+        buf.push(RDB_32BITLEN);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        buf.push(RDB_64BITLEN);
+        buf.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+// strings that are the canonical form of a small enough int are stored as the int, like redis
+// does. Nothing is compressed
+pub fn write_string(buf: &mut Vec<u8>, s: &[u8]) {
+    if s.len() <= INT_ENCODING_MAX_LEN
+        && let Some(i) = try_parse_int(s)
+        && i.to_string().as_bytes() == s
+        && write_int_encoded(buf, i)
+    {
+        return;
+    }
+
+    write_len(buf, s.len() as u64);
+    buf.extend_from_slice(s);
+}
+
+fn write_int(buf: &mut Vec<u8>, i: i64) {
+    if !write_int_encoded(buf, i) {
+        let digits = i.to_string();
+        write_len(buf, digits.len() as u64);
+        buf.extend_from_slice(digits.as_bytes());
+    }
+}
+
+// only ints that fit in 32 bits have a special encoding
+fn write_int_encoded(buf: &mut Vec<u8>, i: i64) -> bool {
+    let encval = RDB_ENCVAL << 6;
+    if let Ok(i) = i8::try_from(i) {
+        buf.push(encval | RDB_ENC_INT8);
+        buf.extend_from_slice(&i.to_le_bytes());
+    } else if let Ok(i) = i16::try_from(i) {
+        buf.push(encval | RDB_ENC_INT16);
+        buf.extend_from_slice(&i.to_le_bytes());
+    } else if let Ok(i) = i32::try_from(i) {
+        buf.push(encval | RDB_ENC_INT32);
+        buf.extend_from_slice(&i.to_le_bytes());
+    } else {
+        return false;
+    }
+    true
+}
+
+// reads rdb encoded values from untrusted bytes, every read is bounds checked. Ziplists and
+// listpacks are checked as deep as `mode` asks for
+pub struct RdbReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    mode: ValidationMode,
+}
+
+impl<'a> RdbReader<'a> {
+    pub fn new(data: &'a [u8], mode: ValidationMode) -> Self {
+        RdbReader { data, pos: 0, mode }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, RdbError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], RdbError> {
+        let end = match self.pos.checked_add(n) {
+            Some(end) if end <= self.data.len() => end,
+            _ => return Err(RdbError::UnexpectedEnd),
+        };
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_len(&mut self) -> Result<u64, RdbError> {
+        match self.read_len_or_encoding()? {
+            (len, false) => Ok(len),
+            (_, true) => Err(RdbError::InvalidLength),
+        }
+    }
+
+    // a length, or the special encoding of a string when the flag is set
+    fn read_len_or_encoding(&mut self) -> Result<(u64, bool), RdbError> {
+        let first = self.read_u8()?;
+        match first >> 6 {
+            RDB_6BITLEN => Ok(((first & 0x3F) as u64, false)),
+            RDB_14BITLEN => {
+                let second = self.read_u8()?;
+                Ok(((((first & 0x3F) as u64) << 8) | second as u64, false))
+            }
+            RDB_ENCVAL => Ok(((first & 0x3F) as u64, true)),
+            _ => match first {
+                RDB_32BITLEN => {
+                    let bytes = self.read_bytes(4)?;
+                    Ok((u32::from_be_bytes(bytes.try_into().unwrap()) as u64, false))
+                }
+                RDB_64BITLEN => {
+                    let bytes = self.read_bytes(8)?;
+                    Ok((u64::from_be_bytes(bytes.try_into().unwrap()), false))
+                }
+                _ => Err(RdbError::InvalidLength),
+            },
+        }
+    }
+
+    // plain strings are borrowed from the data, int encoded and compressed ones are decoded
+    pub fn read_string(&mut self) -> Result<Cow<'a, [u8]>, RdbError> {
+        let (len, encoded) = self.read_len_or_encoding()?;
+        if !encoded {
+            return Ok(Cow::Borrowed(self.read_len_bytes(len)?));
+        }
+
+        let i = match len as u8 {
+            RDB_ENC_INT8 => self.read_u8()? as i8 as i64,
+            RDB_ENC_INT16 => i16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()) as i64,
+            RDB_ENC_INT32 => i32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()) as i64,
+            RDB_ENC_LZF => {
+                let compressed_len = self.read_len()?;
+                let len = usize::try_from(self.read_len()?).map_err(|_| RdbError::InvalidLength)?;
+                let compressed = self.read_len_bytes(compressed_len)?;
+                let s = lzf::decompress(compressed, len).ok_or(RdbError::InvalidLzf)?;
+                return Ok(Cow::Owned(s));
+            }
+            encoding => return Err(RdbError::InvalidStringEncoding { encoding }),
+        };
+        Ok(Cow::Owned(i.to_string().into_bytes()))
+    }
+
+    pub fn read_object(&mut self, rdb_type: u8) -> Result<RedisObject, RdbError> {
+        match rdb_type {
+            RDB_TYPE_STRING => Ok(RedisObject::new_from_bytes(&self.read_string()?)),
+            RDB_TYPE_LIST => {
+                let mut list = ZipList::new();
+                for _ in 0..self.read_len()? {
+                    list.push(ZipEntry::from_bytes(&self.read_string()?));
+                }
+                non_empty_list(RedisObject::List(list))
+            }
+            RDB_TYPE_LIST_ZIPLIST => {
+                let list = ZipList::from_bytes_validated(&self.read_string()?, self.mode)?;
+                non_empty_list(RedisObject::List(list))
+            }
+            RDB_TYPE_LIST_QUICKLIST => self.read_quicklist(),
+            RDB_TYPE_LIST_QUICKLIST_2 => self.read_quicklist_2(),
+            _ => Err(RdbError::UnknownType { rdb_type }),
+        }
+    }
+
+    // reads past a value of a type that can't be stored here, see `type_name`
+    pub fn skip_object(&mut self, rdb_type: u8) -> Result<(), RdbError> {
+        match rdb_type {
+            RDB_TYPE_SET => {
+                for _ in 0..self.read_len()? {
+                    self.read_string()?;
+                }
+            }
+            RDB_TYPE_HASH => {
+                for _ in 0..self.read_len()? {
+                    self.read_string()?;
+                    self.read_string()?;
+                }
+            }
+            RDB_TYPE_ZSET => {
+                for _ in 0..self.read_len()? {
+                    self.read_string()?;
+                    match self.read_u8()? {
+                        RDB_ZSET_SCORE_NAN | RDB_ZSET_SCORE_POS_INF | RDB_ZSET_SCORE_NEG_INF => {}
+                        len => {
+                            self.read_bytes(len as usize)?;
+                        }
+                    }
+                }
+            }
+            RDB_TYPE_ZSET_2 => {
+                for _ in 0..self.read_len()? {
+                    self.read_string()?;
+                    self.read_bytes(8)?;
+                }
+            }
+            RDB_TYPE_MODULE_2 => {
+                // the module id
+                self.read_len()?;
+                self.skip_module_value()?;
+            }
+            // a single blob, a ziplist, listpack, intset or zipmap
+            RDB_TYPE_HASH_ZIPMAP
+            | RDB_TYPE_SET_INTSET
+            | RDB_TYPE_ZSET_ZIPLIST
+            | RDB_TYPE_HASH_ZIPLIST
+            | RDB_TYPE_HASH_LISTPACK
+            | RDB_TYPE_ZSET_LISTPACK
+            | RDB_TYPE_SET_LISTPACK
+            | RDB_TYPE_HASH_LISTPACK_EX_PRE_GA => {
+                self.read_string()?;
+            }
+            RDB_TYPE_HASH_LISTPACK_EX => {
+                self.read_bytes(8)?;
+                self.read_string()?;
+            }
+            RDB_TYPE_HASH_METADATA_PRE_GA | RDB_TYPE_HASH_METADATA => {
+                if rdb_type == RDB_TYPE_HASH_METADATA {
+                    self.read_bytes(8)?;
+                }
+                for _ in 0..self.read_len()? {
+                    // the ttl of the field, an offset from the minimum one after the pre ga
+                    if rdb_type == RDB_TYPE_HASH_METADATA {
+                        self.read_len()?;
+                    } else {
+                        self.read_bytes(8)?;
+                    }
+                    self.read_string()?;
+                    self.read_string()?;
+                }
+            }
+            RDB_TYPE_STREAM_LISTPACKS
+            | RDB_TYPE_STREAM_LISTPACKS_2
+            | RDB_TYPE_STREAM_LISTPACKS_3 => {
+                self.skip_stream(rdb_type)?;
+            }
+            _ => return Err(RdbError::UnknownType { rdb_type }),
+        }
+        Ok(())
+    }
+
+    // the opcodes of a module value, after the module id
+    pub fn skip_module_value(&mut self) -> Result<(), RdbError> {
+        loop {
+            match self.read_len()? {
+                RDB_MODULE_OPCODE_EOF => return Ok(()),
+                RDB_MODULE_OPCODE_SINT | RDB_MODULE_OPCODE_UINT => {
+                    self.read_len()?;
+                }
+                RDB_MODULE_OPCODE_FLOAT => {
+                    self.read_bytes(4)?;
+                }
+                RDB_MODULE_OPCODE_DOUBLE => {
+                    self.read_bytes(8)?;
+                }
+                RDB_MODULE_OPCODE_STRING => {
+                    self.read_string()?;
+                }
+                _ => return Err(RdbError::InvalidModuleValue),
+            }
+        }
+    }
+
+    // the nodes, the metadata, then the consumer groups with their pending entries and consumers.
+    // The later versions added more ids and times
+    fn skip_stream(&mut self, rdb_type: u8) -> Result<(), RdbError> {
+        for _ in 0..self.read_len()? {
+            // the master id of the node and its listpack
+            self.read_string()?;
+            self.read_string()?;
+        }
+
+        // the length and last id, then the first id, max deleted id and entries added
+        let metadata = if rdb_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+            8
+        } else {
+            3
+        };
+        for _ in 0..metadata {
+            self.read_len()?;
+        }
+
+        for _ in 0..self.read_len()? {
+            // the name and last delivered id, then the entries read
+            self.read_string()?;
+            self.read_len()?;
+            self.read_len()?;
+            if rdb_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+                self.read_len()?;
+            }
+
+            // the id, delivery time and delivery count of each pending entry
+            for _ in 0..self.read_len()? {
+                self.read_bytes(STREAM_ID_SIZE + 8)?;
+                self.read_len()?;
+            }
+
+            for _ in 0..self.read_len()? {
+                // the name, seen time and active time
+                self.read_string()?;
+                self.read_bytes(8)?;
+                if rdb_type >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    self.read_bytes(8)?;
+                }
+                for _ in 0..self.read_len()? {
+                    self.read_bytes(STREAM_ID_SIZE)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // a list of ziplists, one node is used as it is and more are merged into the first. The
+    // merged nodes are walked so they are always checked deep
+    fn read_quicklist(&mut self) -> Result<RedisObject, RdbError> {
+        let nodes = self.read_len()?;
+        let mode = if nodes > 1 {
+            ValidationMode::Deep
+        } else {
+            self.mode
+        };
+
+        let mut list: Option<ZipList> = None;
+        for _ in 0..nodes {
+            let node = ZipList::from_bytes_validated(&self.read_string()?, mode)?;
+            match &mut list {
+                None => list = Some(node),
+                Some(list) => {
+                    for value in node.values() {
+                        push_zip_list_value(list, value);
+                    }
+                }
+            }
+        }
+
+        let list = list.unwrap_or_else(ZipList::new);
+        non_empty_list(RedisObject::List(list))
+    }
+
+    // the quicklist of redis 7, nodes are a listpack or a single plain element. They are merged
+    // the same way as the nodes of the older quicklist
+    fn read_quicklist_2(&mut self) -> Result<RedisObject, RdbError> {
+        let nodes = self.read_len()?;
+        let mode = if nodes > 1 {
+            ValidationMode::Deep
+        } else {
+            self.mode
+        };
+
+        let mut list: Option<ListPack> = None;
+        for _ in 0..nodes {
+            let node = match self.read_len()? {
+                QUICKLIST_NODE_CONTAINER_PLAIN => {
+                    let mut node = ListPack::new();
+                    node.push(ListPackEntry::from_bytes(&self.read_string()?));
+                    node
+                }
+                QUICKLIST_NODE_CONTAINER_PACKED => {
+                    ListPack::from_bytes_validated(&self.read_string()?, mode)?
+                }
+                container => return Err(RdbError::InvalidContainer { container }),
+            };
+
+            match &mut list {
+                None => list = Some(node),
+                Some(list) => {
+                    for offset in node.iter() {
+                        let value = node.get_at_offset(offset).to_bytes();
+                        list.push(ListPackEntry::from_bytes(&value));
+                    }
+                }
+            }
+        }
+
+        let list = list.unwrap_or_default();
+        non_empty_list(RedisObject::ListPack(list))
+    }
+
+    fn read_len_bytes(&mut self, len: u64) -> Result<&'a [u8], RdbError> {
+        let len = usize::try_from(len).map_err(|_| RdbError::UnexpectedEnd)?;
+        self.read_bytes(len)
+    }
+}
+
+// the kind of value a type byte holds, for the types that are only skipped
+pub fn type_name(rdb_type: u8) -> &'static str {
+    match rdb_type {
+        RDB_TYPE_STRING => "string",
+        RDB_TYPE_LIST
+        | RDB_TYPE_LIST_ZIPLIST
+        | RDB_TYPE_LIST_QUICKLIST
+        | RDB_TYPE_LIST_QUICKLIST_2 => "list",
+        RDB_TYPE_SET | RDB_TYPE_SET_INTSET | RDB_TYPE_SET_LISTPACK => "set",
+        RDB_TYPE_ZSET | RDB_TYPE_ZSET_2 | RDB_TYPE_ZSET_ZIPLIST | RDB_TYPE_ZSET_LISTPACK => "zset",
+        RDB_TYPE_HASH
+        | RDB_TYPE_HASH_ZIPMAP
+        | RDB_TYPE_HASH_ZIPLIST
+        | RDB_TYPE_HASH_LISTPACK
+        | RDB_TYPE_HASH_METADATA_PRE_GA
+        | RDB_TYPE_HASH_LISTPACK_EX_PRE_GA
+        | RDB_TYPE_HASH_METADATA
+        | RDB_TYPE_HASH_LISTPACK_EX => "hash",
+        RDB_TYPE_STREAM_LISTPACKS | RDB_TYPE_STREAM_LISTPACKS_2 | RDB_TYPE_STREAM_LISTPACKS_3 => {
+            "stream"
+        }
+        RDB_TYPE_MODULE_2 => "module",
+        _ => "unknown",
+    }
+}
+
+// whether read_object can read the type, the others can only be skipped
+pub fn is_supported_type(rdb_type: u8) -> bool {
+    matches!(
+        rdb_type,
+        RDB_TYPE_STRING
+            | RDB_TYPE_LIST
+            | RDB_TYPE_LIST_ZIPLIST
+            | RDB_TYPE_LIST_QUICKLIST
+            | RDB_TYPE_LIST_QUICKLIST_2
+    )
+}
+
+// Helpers
+
+fn push_zip_list_value(list: &mut ZipList, value: ZipListValue) {
+    match value {
+        ZipListValue::Int(i) => list.push(ZipEntry::from_bytes(i.to_string().as_bytes())),
+        ZipListValue::Str(s) => list.push(ZipEntry::from_bytes(s)),
+    }
+}
+
+// redis never writes lists without elements
+fn non_empty_list(object: RedisObject) -> Result<RedisObject, RdbError> {
+    let is_empty = match &object {
+        RedisObject::List(list) => list.is_empty(),
+        RedisObject::ListPack(list) => list.is_empty(),
+        _ => false,
+    };
+    if is_empty {
+        return Err(RdbError::EmptyKey);
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ZipListError;
+
+    // a DUMP payload around an rdb encoded value
+    fn payload(value: &[u8]) -> Vec<u8> {
+        let mut payload = value.to_vec();
+        payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+        let crc = crc64(0, &payload);
+        payload.extend_from_slice(&crc.to_le_bytes());
+        payload
+    }
+
+    fn zip_list(values: &[&[u8]]) -> ZipList {
+        let mut list = ZipList::new();
+        for value in values {
+            list.push(ZipEntry::from_bytes(value));
+        }
+        list
+    }
+
+    fn list_pack(values: &[&[u8]]) -> ListPack {
+        let mut list = ListPack::new();
+        for value in values {
+            list.push(ListPackEntry::from_bytes(value));
+        }
+        list
+    }
+
+    #[test]
+    fn test_write_object() {
+        struct TestData {
+            object: RedisObject,
+            expected: Vec<u8>,
+        }
+
+        let tests = vec![
+            TestData {
+                object: RedisObject::new_from_bytes(b"bar"),
+                expected: b"\x00\x03bar".to_vec(),
+            },
+            TestData {
+                object: RedisObject::new_from_bytes(&[b'x'; 100]),
+                expected: [&b"\x00\x40\x64"[..], &[b'x'; 100]].concat(),
+            },
+            // ints get the smallest encoding that fits
+            TestData {
+                object: RedisObject::Int(-1),
+                expected: vec![0, 0xC0, 0xFF],
+            },
+            TestData {
+                object: RedisObject::Int(1000),
+                expected: vec![0, 0xC1, 0xE8, 0x03],
+            },
+            TestData {
+                object: RedisObject::Int(-70000),
+                expected: vec![0, 0xC2, 0x90, 0xEE, 0xFE, 0xFF],
+            },
+            TestData {
+                object: RedisObject::Int(i64::MAX),
+                expected: b"\x00\x139223372036854775807".to_vec(),
+            },
+            // strings that only look like ints stay strings
+            TestData {
+                object: RedisObject::new_from_bytes(b"01"),
+                expected: b"\x00\x0201".to_vec(),
+            },
+            TestData {
+                object: RedisObject::List(zip_list(&[b"a", b"1"])),
+                expected: [
+                    &[RDB_TYPE_LIST_ZIPLIST, 16][..],
+                    zip_list(&[b"a", b"1"]).as_bytes(),
+                ]
+                .concat(),
+            },
+            TestData {
+                object: RedisObject::ListPack(list_pack(&[b"a"])),
+                expected: [
+                    &[RDB_TYPE_LIST_QUICKLIST_2, 1, 2, 10][..],
+                    list_pack(&[b"a"]).as_bytes(),
+                ]
+                .concat(),
+            },
+        ];
+
+        for test in tests {
+            let mut buf = Vec::new();
+            write_object(&mut buf, &test.object);
+            assert_eq!(test.expected, buf, "{:?}", test.object);
+        }
+    }
+
+    #[test]
+    fn test_write_len() {
+        struct TestData {
+            len: u64,
+            expected: Vec<u8>,
+        }
+
+        let tests = vec![
+            TestData {
+                len: 63,
+                expected: vec![63],
+            },
+            TestData {
+                len: 64,
+                expected: vec![0x40, 64],
+            },
+            TestData {
+                len: 16383,
+                expected: vec![0x7F, 0xFF],
+            },
+            TestData {
+                len: 16384,
+                expected: vec![0x80, 0, 0, 0x40, 0],
+            },
+            TestData {
+                len: 1 << 32,
+                expected: vec![0x81, 0, 0, 0, 1, 0, 0, 0, 0],
+            },
+        ];
+
+        for test in tests {
+            let mut buf = Vec::new();
+            write_len(&mut buf, test.len);
+            assert_eq!(test.expected, buf);
+
+            let mut reader = RdbReader::new(&buf, ValidationMode::Deep);
+            assert_eq!(Ok(test.len), reader.read_len());
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let objects = vec![
+            RedisObject::new_from_bytes(b""),
+            RedisObject::new_from_bytes(b"hello"),
+            RedisObject::new_from_bytes(&[b'x'; 20000]),
+            RedisObject::Int(0),
+            RedisObject::Int(-123456),
+            RedisObject::Int(i64::MIN),
+            RedisObject::List(zip_list(&[b"a", b"-5", &[b'b'; 300], b"123456789012"])),
+            RedisObject::ListPack(list_pack(&[b"a", b"-5", &[b'b'; 5000], b"4000"])),
+        ];
+
+        for object in objects {
+            let dumped = dump(&object);
+            assert_eq!(Ok(object), restore(&dumped, ValidationMode::Deep));
+        }
+    }
+
+    #[test]
+    fn test_restore_redis_payload() {
+        // DUMP of the value 10 from the redis documentation, written by a version 9 server
+        let payload = b"\x00\xC0\x0A\x09\x00\xBE\x6D\x06\x89\x5A\x28\x00\x0A";
+        assert_eq!(
+            Ok(RedisObject::Int(10)),
+            restore(payload, ValidationMode::Deep)
+        );
+    }
+
+    #[test]
+    fn test_read_object() {
+        struct TestData {
+            value: Vec<u8>,
+            expected: Result<RedisObject, RdbError>,
+        }
+
+        let mut quicklist = vec![RDB_TYPE_LIST_QUICKLIST, 2];
+        write_string(&mut quicklist, zip_list(&[b"a", b"1"]).as_bytes());
+        write_string(&mut quicklist, zip_list(&[b"b"]).as_bytes());
+
+        let mut quicklist_2 = vec![RDB_TYPE_LIST_QUICKLIST_2, 3];
+        write_len(&mut quicklist_2, QUICKLIST_NODE_CONTAINER_PACKED);
+        write_string(&mut quicklist_2, list_pack(&[b"a", b"1"]).as_bytes());
+        write_len(&mut quicklist_2, QUICKLIST_NODE_CONTAINER_PLAIN);
+        write_string(&mut quicklist_2, &[b'p'; 100]);
+        write_len(&mut quicklist_2, QUICKLIST_NODE_CONTAINER_PACKED);
+        write_string(&mut quicklist_2, list_pack(&[b"b"]).as_bytes());
+
+        let mut corrupt_node = zip_list(&[b"a"]).as_bytes().to_vec();
+        corrupt_node[11] = 0x81;
+        let mut corrupt_quicklist = vec![RDB_TYPE_LIST_QUICKLIST, 2];
+        write_string(&mut corrupt_quicklist, zip_list(&[b"a"]).as_bytes());
+        write_string(&mut corrupt_quicklist, &corrupt_node);
+
+        let tests = vec![
+            // "aaaaaaaaaa" compressed to a literal and a back reference
+            TestData {
+                value: vec![0, 0xC3, 5, 10, 0, b'a', 0b111_00000, 0, 0],
+                expected: Ok(RedisObject::new_from_bytes(b"aaaaaaaaaa")),
+            },
+            TestData {
+                value: vec![0, 0xC3, 5, 11, 0, b'a', 0b111_00000, 0, 0],
+                expected: Err(RdbError::InvalidLzf),
+            },
+            TestData {
+                value: vec![0, 0xC4],
+                expected: Err(RdbError::InvalidStringEncoding { encoding: 4 }),
+            },
+            TestData {
+                value: vec![0, 0x05, b'a'],
+                expected: Err(RdbError::UnexpectedEnd),
+            },
+            TestData {
+                value: vec![RDB_TYPE_LIST, 2, 1, b'a', 0xC0, 7],
+                expected: Ok(RedisObject::List(zip_list(&[b"a", b"7"]))),
+            },
+            TestData {
+                value: vec![RDB_TYPE_LIST, 0],
+                expected: Err(RdbError::EmptyKey),
+            },
+            TestData {
+                value: quicklist,
+                expected: Ok(RedisObject::List(zip_list(&[b"a", b"1", b"b"]))),
+            },
+            TestData {
+                value: quicklist_2,
+                expected: Ok(RedisObject::ListPack(list_pack(&[
+                    b"a",
+                    b"1",
+                    &[b'p'; 100],
+                    b"b",
+                ]))),
+            },
+            // merged nodes are checked deep even when the reader is shallow
+            TestData {
+                value: corrupt_quicklist,
+                expected: Err(RdbError::ZipList(ZipListError::InvalidEncoding {
+                    offset: 11,
+                    header: 0x81,
+                })),
+            },
+            TestData {
+                value: vec![RDB_TYPE_LIST_QUICKLIST_2, 1, 3, 0],
+                expected: Err(RdbError::InvalidContainer { container: 3 }),
+            },
+            TestData {
+                value: [&[RDB_TYPE_LIST_ZIPLIST, 11][..], ZipList::new().as_bytes()].concat(),
+                expected: Err(RdbError::EmptyKey),
+            },
+            TestData {
+                value: vec![4],
+                expected: Err(RdbError::UnknownType { rdb_type: 4 }),
+            },
+        ];
+
+        for test in tests {
+            let mut reader = RdbReader::new(&test.value, ValidationMode::Shallow);
+            let result = reader
+                .read_u8()
+                .and_then(|rdb_type| reader.read_object(rdb_type));
+            assert_eq!(test.expected, result, "{:?}", test.value);
+        }
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        struct TestData {
+            payload: Vec<u8>,
+            expected: RdbError,
+        }
+
+        let mut wrong_crc = payload(b"\x00\x03bar");
+        wrong_crc[1] = 2;
+        let mut newer_version = b"\x00\x03bar".to_vec();
+        newer_version.extend_from_slice(&13u16.to_le_bytes());
+        newer_version.extend_from_slice(&crc64(0, &newer_version).to_le_bytes());
+
+        let tests = vec![
+            TestData {
+                payload: vec![0; 9],
+                expected: RdbError::MissingFooter,
+            },
+            TestData {
+                payload: wrong_crc,
+                expected: RdbError::ChecksumMismatch {
+                    expected: crc64(0, b"\x00\x03bar\x0A\x00"),
+                    got: crc64(0, b"\x00\x02bar\x0A\x00"),
+                },
+            },
+            TestData {
+                payload: newer_version,
+                expected: RdbError::UnsupportedVersion { version: 13 },
+            },
+            TestData {
+                payload: payload(b"\x00\x02bar"),
+                expected: RdbError::TrailingBytes,
+            },
+            TestData {
+                payload: payload(b""),
+                expected: RdbError::UnexpectedEnd,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                Err(test.expected),
+                restore(&test.payload, ValidationMode::Deep)
+            );
+        }
+    }
+}
@@ -0,0 +1,976 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    commands::RedisCommand,
+    config::AppendFsync,
+    error::{AofError, CommandError, ProtocolError, RedisError},
+    protocol::parser::{
+        CommandParseState, convert_command_parse_state_to_redis_command, parse_command,
+        write_command,
+    },
+    redis::{
+        Redis, RedisResult, db::unix_time_ms, redis_object::RedisObject, ziplist::ZipListValue,
+    },
+};
+
+// how often appendfsync everysec fsyncs the log
+const AOF_FSYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+// the append only file state, like the aof fields of the server struct in redis
+pub(super) struct AofState {
+    // the log commands are appended to, None while appendonly is off
+    file: Option<File>,
+    // length of the log, a failed write is cut off again so no half command is left behind
+    size: u64,
+    // commands logged since the last write to the file
+    buf: Vec<u8>,
+    // the database the last logged command ran in, a SELECT goes first when it changes
+    selected_db: Option<usize>,
+    // written to the file but not fsynced yet
+    unsynced: bool,
+    last_fsync: Instant,
+    fsync_thread: Option<FsyncThread>,
+    last_write_ok: bool,
+    rewrite: Option<RewriteChild>,
+    // BGREWRITEAOF while a BGSAVE runs, started by the periodic tasks once it is done
+    rewrite_scheduled: bool,
+    last_rewrite_ok: bool,
+}
+
+// does the everysec fsyncs so a slow disk never blocks the event loop, like the bio thread of
+// redis. It owns a clone of the file so the log can be swapped while an fsync runs
+struct FsyncThread {
+    jobs: Sender<File>,
+    in_progress: Arc<AtomicBool>,
+}
+
+struct RewriteChild {
+    pid: libc::pid_t,
+    // the commands logged since the fork, the child's keyspace doesn't have them
+    buf: Vec<u8>,
+}
+
+impl AofState {
+    pub(super) fn new() -> Self {
+        AofState {
+            file: None,
+            size: 0,
+            buf: Vec::new(),
+            selected_db: None,
+            unsynced: false,
+            last_fsync: Instant::now(),
+            fsync_thread: None,
+            last_write_ok: true,
+            rewrite: None,
+            rewrite_scheduled: false,
+            last_rewrite_ok: true,
+        }
+    }
+}
+
+impl FsyncThread {
+    fn spawn() -> Self {
+        let (jobs, files) = mpsc::channel::<File>();
+        let in_progress = Arc::new(AtomicBool::new(false));
+        let done = Arc::clone(&in_progress);
+
+        // ends once the sender is dropped together with the server
+        thread::spawn(move || {
+            for file in files {
+                if let Err(err) = file.sync_data() {
+                    eprintln!("Warning: fsync of the aof failed: {}", err);
+                }
+                done.store(false, Ordering::Release);
+            }
+        });
+
+        FsyncThread { jobs, in_progress }
+    }
+}
+
+impl Redis {
+    // the aof has every write so it is loaded instead of the snapshot when it is on. Without a
+    // log yet the snapshot is loaded and written out as the start of one, so turning appendonly
+    // on doesn't start the server empty
+    pub fn load_data(&mut self) -> Result<(), RedisError> {
+        if !self.config.appendonly {
+            return self.load_rdb();
+        }
+
+        if self.aof_path().exists() {
+            self.load_append_only_file()?;
+            self.open_append_only_file()?;
+        } else {
+            self.load_rdb()?;
+            self.restart_append_only_file()?;
+        }
+
+        Ok(())
+    }
+
+    // replaces the log with one written from the keyspace, for a keyspace that didn't come from
+    // it like the snapshot of a master. A running rewrite would put the old keyspace back so it
+    // is stopped
+    pub(super) fn restart_append_only_file(&mut self) -> io::Result<()> {
+        if let Some(child) = self.aof.rewrite.take() {
+            unsafe {
+                libc::kill(child.pid, libc::SIGUSR1);
+                libc::waitpid(child.pid, std::ptr::null_mut(), 0);
+            }
+            let _ = fs::remove_file(self.aof_rewrite_temp_path(child.pid));
+        }
+
+        // what is buffered for the old log is part of the keyspace already
+        self.aof.buf.clear();
+
+        let temp_path = self
+            .config
+            .dir
+            .join(format!("temp-rewriteaof-{}.aof", std::process::id()));
+        self.write_aof_rewrite(&temp_path)?;
+        fs::rename(&temp_path, self.aof_path())?;
+        self.open_append_only_file()
+    }
+
+    // replays the log through execute_command. A log that ends in the middle of a command, like
+    // after a crash during a write, is cut back to the last whole one with aof-load-truncated
+    pub(super) fn load_append_only_file(&mut self) -> Result<(), RedisError> {
+        let path = self.aof_path();
+        let data = fs::read(&path)?;
+
+        let mut state = CommandParseState::new();
+        let mut pos = 0;
+        // the end of the last whole command
+        let mut valid = 0;
+        let mut db = 0;
+        while pos < data.len() {
+            match parse_command(&data, &mut pos, &mut state) {
+                Ok(()) => {}
+                Err(ProtocolError::Incomplete) => break,
+                Err(err) => return Err(AofError::InvalidFormat { offset: valid, err }.into()),
+            }
+
+            let command = convert_command_parse_state_to_redis_command(&state)
+                .map_err(|err| AofError::InvalidCommand { offset: valid, err })?;
+            if !command.is_write() && !matches!(command, RedisCommand::Select { .. }) {
+                let err = CommandError::NotAWrite {
+                    cmd: state.command_name().to_vec(),
+                };
+                return Err(AofError::InvalidCommand { offset: valid, err }.into());
+            }
+            self.execute_command_in_db(&mut db, &command);
+
+            state.clear();
+            valid = pos;
+        }
+
+        if valid < data.len() {
+            if !self.config.aof_load_truncated {
+                return Err(AofError::Truncated { offset: valid }.into());
+            }
+            eprintln!(
+                "Warning: the aof ends in the middle of a command, loaded it up to byte {} and \
+                 cut off the rest because aof-load-truncated is on",
+                valid
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(valid as u64)?;
+        }
+
+        // everything replayed is on disk already
+        self.rdb.dirty = 0;
+        Ok(())
+    }
+
+    // logs a write command that ran without an error, see `propagated_args` for what is written
+    pub(super) fn feed_append_only_file(&mut self, command: &RedisCommand, result: &RedisResult) {
+        if self.aof.file.is_none() {
+            return;
+        }
+        let Some(args) = self.propagated_args(command, result) else {
+            return;
+        };
+
+        let mut resp = Vec::new();
+        if self.aof.selected_db != Some(self.db) {
+            write_command(&mut resp, &[&b"SELECT"[..], self.db.to_string().as_bytes()]);
+            self.aof.selected_db = Some(self.db);
+        }
+        write_command(&mut resp, &args);
+
+        self.aof.buf.extend_from_slice(&resp);
+        if let Some(child) = &mut self.aof.rewrite {
+            child.buf.extend_from_slice(&resp);
+        }
+
+        // the command is on disk before it is answered
+        if self.config.appendfsync == AppendFsync::Always {
+            self.flush_append_only_file();
+        }
+    }
+
+    // writes the logged commands and fsyncs like appendfsync says. Called by the server before it
+    // waits for events, with appendfsync always after every write command
+    pub fn flush_append_only_file(&mut self) {
+        let Some(file) = &mut self.aof.file else {
+            return;
+        };
+        let always = self.config.appendfsync == AppendFsync::Always;
+
+        if !self.aof.buf.is_empty() {
+            match file.write_all(&self.aof.buf) {
+                Ok(()) => {
+                    self.aof.size += self.aof.buf.len() as u64;
+                    self.aof.buf.clear();
+                    self.aof.unsynced = true;
+                    self.aof.last_write_ok = true;
+                }
+                // like redis the write was promised to be on disk already, there's no way back
+                Err(err) if always => {
+                    eprintln!(
+                        "Can't recover from an aof write error with appendfsync always: {}",
+                        err
+                    );
+                    std::process::exit(1);
+                }
+                // the commands stay buffered and are written again next time
+                Err(err) => {
+                    eprintln!("Warning: writing the aof failed: {}", err);
+                    let _ = file.set_len(self.aof.size);
+                    self.aof.last_write_ok = false;
+                    return;
+                }
+            }
+        }
+
+        if !self.aof.unsynced {
+            return;
+        }
+        match self.config.appendfsync {
+            AppendFsync::Always => {
+                if let Err(err) = file.sync_data() {
+                    eprintln!("Can't recover from an aof fsync error: {}", err);
+                    std::process::exit(1);
+                }
+                self.aof.unsynced = false;
+            }
+            AppendFsync::EverySec => {
+                let thread = self.aof.fsync_thread.get_or_insert_with(FsyncThread::spawn);
+                // with a slow disk the last one may still run, it's tried again next time
+                if self.aof.last_fsync.elapsed() < AOF_FSYNC_INTERVAL
+                    || thread.in_progress.load(Ordering::Acquire)
+                {
+                    return;
+                }
+                match file.try_clone() {
+                    Ok(file) => {
+                        thread.in_progress.store(true, Ordering::Release);
+                        let _ = thread.jobs.send(file);
+                        self.aof.last_fsync = Instant::now();
+                        self.aof.unsynced = false;
+                    }
+                    Err(err) => eprintln!("Warning: can't fsync the aof: {}", err),
+                }
+            }
+            AppendFsync::No => {}
+        }
+    }
+
+    // forks a child that writes the commands building its copy of the keyspace to a new log,
+    // see `check_aof_rewrite_done` for how it replaces the old one
+    pub(super) fn bgrewriteaof(&mut self) -> RedisResult {
+        if self.aof.rewrite.is_some() {
+            return RedisResult::Error(RedisError::CommandError(
+                CommandError::AofRewriteInProgress,
+            ));
+        }
+        if self.bgsave_in_progress() {
+            self.aof.rewrite_scheduled = true;
+            return RedisResult::SimpleString(
+                b"+Background append only file rewriting scheduled\r\n",
+            );
+        }
+
+        match self.fork_aof_rewrite() {
+            Ok(()) => {
+                RedisResult::SimpleString(b"+Background append only file rewriting started\r\n")
+            }
+            Err(err) => {
+                eprintln!("Warning: can't fork for BGREWRITEAOF: {}", err);
+                RedisResult::Error(RedisError::CommandError(CommandError::AofRewriteFailed))
+            }
+        }
+    }
+
+    // reaps the rewrite child without blocking, or starts a scheduled rewrite once no child
+    // runs. Called from the periodic tasks
+    pub(super) fn check_aof_rewrite_done(&mut self) {
+        let Some(child) = &self.aof.rewrite else {
+            if self.aof.rewrite_scheduled
+                && !self.bgsave_in_progress()
+                && let Err(err) = self.fork_aof_rewrite()
+            {
+                eprintln!("Warning: can't fork for BGREWRITEAOF: {}", err);
+            }
+            return;
+        };
+
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(child.pid, &mut status, libc::WNOHANG) };
+        if pid == 0 {
+            return;
+        }
+
+        let child = self.aof.rewrite.take().unwrap();
+        let temp_path = self.aof_rewrite_temp_path(child.pid);
+        let result =
+            if pid == child.pid && libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0 {
+                self.finish_aof_rewrite(&temp_path, &child.buf)
+            } else {
+                Err(io::Error::other("the rewrite child failed"))
+            };
+
+        match result {
+            Ok(()) => self.aof.last_rewrite_ok = true,
+            Err(err) => {
+                eprintln!("Warning: background aof rewrite failed: {}", err);
+                let _ = fs::remove_file(&temp_path);
+                self.aof.last_rewrite_ok = false;
+            }
+        }
+    }
+
+    pub(super) fn aof_enabled(&self) -> bool {
+        self.aof.file.is_some()
+    }
+
+    pub(super) fn aof_rewrite_in_progress(&self) -> bool {
+        self.aof.rewrite.is_some()
+    }
+
+    pub(super) fn aof_rewrite_scheduled(&self) -> bool {
+        self.aof.rewrite_scheduled
+    }
+
+    pub(super) fn aof_last_rewrite_ok(&self) -> bool {
+        self.aof.last_rewrite_ok
+    }
+
+    pub(super) fn aof_last_write_ok(&self) -> bool {
+        self.aof.last_write_ok
+    }
+
+    fn open_append_only_file(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.aof_path())?;
+        self.aof.size = file.metadata()?.len();
+        self.aof.file = Some(file);
+        self.aof.selected_db = None;
+        Ok(())
+    }
+
+    fn fork_aof_rewrite(&mut self) -> io::Result<()> {
+        match unsafe { libc::fork() } {
+            -1 => {
+                self.aof.last_rewrite_ok = false;
+                Err(io::Error::last_os_error())
+            }
+            // like the BGSAVE child it must not return into the event loop
+            0 => {
+                let temp_path = self.aof_rewrite_temp_path(std::process::id() as libc::pid_t);
+                let code = match self.write_aof_rewrite(&temp_path) {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        eprintln!("Warning: failed writing the aof rewrite: {}", err);
+                        1
+                    }
+                };
+                unsafe { libc::_exit(code) }
+            }
+            pid => {
+                self.aof.rewrite = Some(RewriteChild {
+                    pid,
+                    buf: Vec::new(),
+                });
+                self.aof.rewrite_scheduled = false;
+                // the commands after the fork start with a SELECT in both logs
+                self.aof.selected_db = None;
+                Ok(())
+            }
+        }
+    }
+
+    // appends what ran during the rewrite to the new log and puts it in place of the old one. The
+    // file stays open so the log carries on in it
+    fn finish_aof_rewrite(&mut self, temp_path: &Path, buf: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(temp_path)?;
+        file.write_all(buf)?;
+        file.sync_data()?;
+
+        // what is still buffered for the old log is in `buf` as well
+        self.flush_append_only_file();
+        fs::rename(temp_path, self.aof_path())?;
+
+        if self.aof.file.is_some() {
+            self.aof.size = file.metadata()?.len();
+            self.aof.file = Some(file);
+        }
+        Ok(())
+    }
+
+    // the shortest log that builds the keyspace, keys that already expired are left out
+    fn write_aof_rewrite(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let now = unix_time_ms();
+
+        let mut buf = Vec::new();
+        for (index, db) in self.dbs.iter().enumerate() {
+            if db.dict.is_empty() {
+                continue;
+            }
+
+            write_command(&mut buf, &[&b"SELECT"[..], index.to_string().as_bytes()]);
+            for (key, value) in db.dict.iter() {
+                let expire = db.expire(key);
+                if expire.is_some_and(|expire| expire <= now) {
+                    continue;
+                }
+
+                write_key_commands(&mut buf, key, value);
+                if let Some(expire) = expire {
+                    write_command(
+                        &mut buf,
+                        &[&b"PEXPIREAT"[..], key, expire.to_string().as_bytes()],
+                    );
+                }
+                out.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        out.write_all(&buf)?;
+
+        out.flush()?;
+        out.get_ref().sync_all()
+    }
+
+    // the arguments a write command is logged and sent to replicas with. Relative times are
+    // logged as the unix time they ended up as, so replaying the log later builds the same
+    // keyspace
+    pub(super) fn propagated_args<'a>(
+        &self,
+        command: &RedisCommand<'a>,
+        result: &RedisResult,
+    ) -> Option<Vec<Cow<'a, [u8]>>> {
+        let name = |name: &'static [u8]| Cow::Borrowed(name);
+        let arg = |arg: &'a [u8]| Cow::Borrowed(arg);
+        let int = |int: i64| Cow::Owned(int.to_string().into_bytes());
+        let db = &self.dbs[self.db];
+
+        let args = match command {
+            RedisCommand::Set { key, value } => vec![name(b"SET"), arg(key), arg(value)],
+            RedisCommand::Del { keys } => {
+                let mut args = vec![name(b"DEL")];
+                args.extend(keys.iter().map(|key| arg(key)));
+                args
+            }
+            RedisCommand::Unlink { keys } => {
+                let mut args = vec![name(b"UNLINK")];
+                args.extend(keys.iter().map(|key| arg(key)));
+                args
+            }
+            RedisCommand::LPush { key, value } => vec![name(b"LPUSH"), arg(key), arg(value)],
+            RedisCommand::RPush { key, value } => vec![name(b"RPUSH"), arg(key), arg(value)],
+            RedisCommand::LPop { key } => vec![name(b"LPOP"), arg(key)],
+            RedisCommand::RPop { key } => vec![name(b"RPOP"), arg(key)],
+            RedisCommand::Rename { key, new_key } => {
+                vec![name(b"RENAME"), arg(key), arg(new_key)]
+            }
+            RedisCommand::RenameNx { key, new_key } => {
+                vec![name(b"RENAMENX"), arg(key), arg(new_key)]
+            }
+            RedisCommand::Copy {
+                source,
+                destination,
+                replace,
+            } => {
+                let mut args = vec![name(b"COPY"), arg(source), arg(destination)];
+                if *replace {
+                    args.push(name(b"REPLACE"));
+                }
+                args
+            }
+            // a restored key that already expired is deleted instead
+            RedisCommand::Restore {
+                key,
+                payload,
+                options,
+                ..
+            } => match db.dict.peek(key) {
+                Some(_) => {
+                    let expire = db.expire(key);
+                    let mut args = vec![
+                        name(b"RESTORE"),
+                        arg(key),
+                        int(expire.unwrap_or(0)),
+                        arg(payload),
+                    ];
+                    if expire.is_some() {
+                        args.push(name(b"ABSTTL"));
+                    }
+                    if options.replace {
+                        args.push(name(b"REPLACE"));
+                    }
+                    if let Some(idle_time) = options.idle_time {
+                        args.extend([name(b"IDLETIME"), int(idle_time as i64)]);
+                    }
+                    if let Some(freq) = options.freq {
+                        args.extend([name(b"FREQ"), int(freq as i64)]);
+                    }
+                    args
+                }
+                None => vec![name(b"DEL"), arg(key)],
+            },
+            // every variant as the unix time the key expires at, a time that already passed
+            // deleted the key
+            RedisCommand::Expire { key, .. } => match (db.dict.peek(key), db.expire(key)) {
+                (Some(_), Some(when)) => vec![name(b"PEXPIREAT"), arg(key), int(when)],
+                (None, _) if matches!(result, RedisResult::Int(1)) => {
+                    vec![name(b"DEL"), arg(key)]
+                }
+                _ => return None,
+            },
+            // the keys left for the target, +NOKEY means none were there
+            RedisCommand::Migrate { keys, .. } => match result {
+                RedisResult::SimpleString(b"+OK\r\n") => {
+                    let mut args = vec![name(b"DEL")];
+                    args.extend(keys.iter().map(|key| arg(key)));
+                    args
+                }
+                _ => return None,
+            },
+            RedisCommand::Persist { key } => vec![name(b"PERSIST"), arg(key)],
+            RedisCommand::SwapDb { index1, index2 } => {
+                vec![name(b"SWAPDB"), int(*index1 as i64), int(*index2 as i64)]
+            }
+            RedisCommand::Move { key, db } => vec![name(b"MOVE"), arg(key), int(*db as i64)],
+            RedisCommand::FlushDb { lazy } => {
+                let mut args = vec![name(b"FLUSHDB")];
+                if *lazy {
+                    args.push(name(b"ASYNC"));
+                }
+                args
+            }
+            RedisCommand::FlushAll { lazy } => {
+                let mut args = vec![name(b"FLUSHALL")];
+                if *lazy {
+                    args.push(name(b"ASYNC"));
+                }
+                args
+            }
+            RedisCommand::Sort { key, options } => {
+                let store = options.store?;
+                let mut args = vec![name(b"SORT"), arg(key)];
+                if let Some(by) = options.by {
+                    args.extend([name(b"BY"), arg(by)]);
+                }
+                if let Some((offset, count)) = options.limit {
+                    args.extend([name(b"LIMIT"), int(offset), int(count)]);
+                }
+                for get in &options.get {
+                    args.extend([name(b"GET"), arg(get)]);
+                }
+                if options.desc {
+                    args.push(name(b"DESC"));
+                }
+                if options.alpha {
+                    args.push(name(b"ALPHA"));
+                }
+                args.extend([name(b"STORE"), arg(store)]);
+                args
+            }
+            _ => return None,
+        };
+
+        Some(args)
+    }
+
+    fn aof_path(&self) -> PathBuf {
+        self.config.dir.join(&self.config.appendfilename)
+    }
+
+    // the same name redis gives the file of its rewrite child
+    fn aof_rewrite_temp_path(&self, pid: libc::pid_t) -> PathBuf {
+        self.config
+            .dir
+            .join(format!("temp-rewriteaof-bg-{}.aof", pid))
+    }
+}
+
+// RPUSH only takes a single value here, so a list is an RPUSH per element
+fn write_key_commands(buf: &mut Vec<u8>, key: &[u8], value: &RedisObject) {
+    match value {
+        RedisObject::String(_) | RedisObject::Int(_) => {
+            write_command(buf, &[&b"SET"[..], key, &value.to_bytes()]);
+        }
+        RedisObject::List(list) => {
+            for value in list.values() {
+                let value = match value {
+                    ZipListValue::Int(int) => Cow::Owned(int.to_string().into_bytes()),
+                    ZipListValue::Str(s) => Cow::Borrowed(s),
+                };
+                write_command(buf, &[&b"RPUSH"[..], key, &value]);
+            }
+        }
+        RedisObject::ListPack(list) => {
+            for offset in list.iter() {
+                let value = list.get_at_offset(offset).to_bytes();
+                write_command(buf, &[&b"RPUSH"[..], key, &value]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{ExpireFlags, TimeUnit},
+        config::Config,
+        redis::resp_or_error,
+    };
+
+    // a redis with appendonly on in its own directory, the tests run in parallel
+    fn redis_in_temp_dir(name: &str, appendfsync: AppendFsync) -> Redis {
+        let dir = std::env::temp_dir().join(format!("redis-aof-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut config = Config::new();
+        config.dir = dir;
+        config.appendonly = true;
+        config.appendfsync = appendfsync;
+        let mut redis = Redis::with_config(config);
+        redis.load_data().unwrap();
+        redis
+    }
+
+    fn set(redis: &mut Redis, key: &'static [u8], value: &'static [u8]) {
+        redis.execute_command(&RedisCommand::Set { key, value });
+    }
+
+    fn expire(redis: &mut Redis, key: &'static [u8], time: i64, absolute: bool) -> RedisResult {
+        redis.execute_command(&RedisCommand::Expire {
+            key,
+            time,
+            unit: TimeUnit::Seconds,
+            absolute,
+            flags: ExpireFlags::default(),
+        })
+    }
+
+    fn loaded(redis: &Redis) -> Redis {
+        let mut loaded = Redis::with_config(redis.config.clone());
+        loaded.load_data().unwrap();
+        loaded
+    }
+
+    #[test]
+    fn test_append_and_load() {
+        let mut redis = redis_in_temp_dir("append", AppendFsync::Always);
+        set(&mut redis, b"key", b"value");
+        // reads, failed commands and writes that changed nothing are not logged
+        redis.execute_command(&RedisCommand::Get { key: b"key" });
+        redis.execute_command(&RedisCommand::Rename {
+            key: b"missing",
+            new_key: b"other",
+        });
+        redis.execute_command(&RedisCommand::Del {
+            keys: vec![b"missing"],
+        });
+        redis.execute_command(&RedisCommand::LPop { key: b"missing" });
+        redis.execute_command(&RedisCommand::RenameNx {
+            key: b"key",
+            new_key: b"key",
+        });
+        let mut db = 1;
+        redis.execute_command_in_db(
+            &mut db,
+            &RedisCommand::RPush {
+                key: b"list",
+                value: b"a",
+            },
+        );
+        redis.execute_command(&RedisCommand::Select { index: 0 });
+        expire(&mut redis, b"key", 100, false);
+        let when = redis.dbs[0].expire(b"key").unwrap();
+
+        // appendfsync always writes before the command is answered
+        let expected = format!(
+            "*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n\
+             *2\r\n$6\r\nSELECT\r\n$1\r\n1\r\n*3\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n\
+             *2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n*3\r\n$9\r\nPEXPIREAT\r\n$3\r\nkey\r\n$13\r\n{}\r\n",
+            when
+        );
+        assert_eq!(expected.as_bytes(), fs::read(redis.aof_path()).unwrap());
+
+        // a time in the past deletes the key
+        set(&mut redis, b"gone", b"soon");
+        assert!(matches!(
+            expire(&mut redis, b"gone", 1, true),
+            RedisResult::Int(1)
+        ));
+        let log = fs::read(redis.aof_path()).unwrap();
+        assert!(log.ends_with(b"*2\r\n$3\r\nDEL\r\n$4\r\ngone\r\n"));
+
+        let loaded = loaded(&redis);
+        assert_eq!(Some(when), loaded.dbs[0].expire(b"key"));
+        assert!(loaded.dbs[0].dict.peek(b"gone").is_none());
+        assert_eq!(
+            redis.dbs[1].dict.peek(b"list"),
+            loaded.dbs[1].dict.peek(b"list")
+        );
+        assert_eq!(0, loaded.rdb.dirty);
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_flush_policies() {
+        for appendfsync in [AppendFsync::EverySec, AppendFsync::No] {
+            let mut redis = redis_in_temp_dir("policies", appendfsync);
+            set(&mut redis, b"key", b"value");
+            // buffered until the server is about to wait for events
+            assert!(fs::read(redis.aof_path()).unwrap().is_empty());
+
+            redis.flush_append_only_file();
+            assert!(!fs::read(redis.aof_path()).unwrap().is_empty());
+            assert_eq!(
+                appendfsync == AppendFsync::EverySec,
+                redis.aof.fsync_thread.is_some()
+            );
+            assert!(loaded(&redis).dbs[0].dict.peek(b"key").is_some());
+
+            fs::remove_dir_all(&redis.config.dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let mut redis = redis_in_temp_dir("truncated", AppendFsync::Always);
+        set(&mut redis, b"key", b"value");
+        let whole = fs::read(redis.aof_path()).unwrap();
+
+        let mut truncated = whole.clone();
+        truncated.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$4\r\nnext");
+        fs::write(redis.aof_path(), &truncated).unwrap();
+
+        let mut config = redis.config.clone();
+        config.aof_load_truncated = false;
+        assert!(matches!(
+            Redis::with_config(config).load_data(),
+            Err(RedisError::AofError(AofError::Truncated { offset })) if offset == whole.len()
+        ));
+        assert_eq!(truncated, fs::read(redis.aof_path()).unwrap());
+
+        // cut back to the last whole command
+        let loaded = loaded(&redis);
+        assert!(loaded.dbs[0].dict.peek(b"key").is_some());
+        assert!(loaded.dbs[0].dict.peek(b"next").is_none());
+        assert_eq!(whole, fs::read(redis.aof_path()).unwrap());
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_errors() {
+        struct TestData {
+            log: &'static [u8],
+            expected: AofError,
+        }
+
+        let tests = vec![
+            TestData {
+                log: b"*1\r\n$4\r\nNOPE\r\n",
+                expected: AofError::InvalidCommand {
+                    offset: 0,
+                    err: CommandError::UnknownCommand {
+                        cmd: b"NOPE".to_vec(),
+                    },
+                },
+            },
+            // only writes are logged, anything else would run without a client
+            TestData {
+                log: b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*3\r\n$4\r\nWAIT\r\n$1\r\n0\r\n$1\r\n0\r\n",
+                expected: AofError::InvalidCommand {
+                    offset: 27,
+                    err: CommandError::NotAWrite {
+                        cmd: b"WAIT".to_vec(),
+                    },
+                },
+            },
+            TestData {
+                log: b"*1\r\n$8\r\nFLUSHALL\r\nSET key value\r\n",
+                expected: AofError::InvalidFormat {
+                    offset: 18,
+                    err: ProtocolError::ExpectedByte {
+                        expected: b'*',
+                        got: b'S',
+                    },
+                },
+            },
+        ];
+
+        let redis = redis_in_temp_dir("errors", AppendFsync::Always);
+        for test in tests {
+            fs::write(redis.aof_path(), test.log).unwrap();
+            let mut loaded = Redis::with_config(redis.config.clone());
+            match loaded.load_data() {
+                Err(RedisError::AofError(err)) => assert_eq!(test.expected, err),
+                other => panic!("expected {:?}, got {:?}", test.expected, other.err()),
+            }
+        }
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_from_snapshot() {
+        let mut redis = redis_in_temp_dir("snapshot", AppendFsync::Always);
+        fs::remove_file(redis.aof_path()).unwrap();
+        redis.aof.file = None;
+        set(&mut redis, b"key", b"value");
+        expire(&mut redis, b"key", 100, false);
+        assert_eq!(Ok(b"+OK\r\n".to_vec()), resp_or_error(redis.save()));
+
+        // without a log yet it starts out as the snapshot
+        let loaded = loaded(&redis);
+        assert!(loaded.aof_enabled());
+        assert_eq!(redis.dbs[0].expire(b"key"), loaded.dbs[0].expire(b"key"));
+        let log = fs::read(redis.aof_path()).unwrap();
+        assert!(log.starts_with(
+            b"*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n"
+        ));
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_bgrewriteaof() {
+        let mut redis = redis_in_temp_dir("rewrite", AppendFsync::EverySec);
+        for _ in 0..100 {
+            set(&mut redis, b"key", b"value");
+        }
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"list",
+            value: b"1",
+        });
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"list",
+            value: b"two",
+        });
+        redis.flush_append_only_file();
+        let before = fs::metadata(redis.aof_path()).unwrap().len();
+
+        assert_eq!(
+            Ok(b"+Background append only file rewriting started\r\n".to_vec()),
+            resp_or_error(redis.execute_command(&RedisCommand::BgRewriteAof))
+        );
+        assert_eq!(
+            Err(CommandError::AofRewriteInProgress),
+            resp_or_error(redis.execute_command(&RedisCommand::BgRewriteAof))
+        );
+        assert_eq!(
+            Err(CommandError::ChildActive),
+            resp_or_error(redis.execute_command(&RedisCommand::BgSave))
+        );
+        // written after the fork, it goes to the new log from the rewrite buffer
+        let mut db = 2;
+        redis.execute_command_in_db(
+            &mut db,
+            &RedisCommand::Set {
+                key: b"after",
+                value: b"fork",
+            },
+        );
+        redis.execute_command(&RedisCommand::Select { index: 0 });
+
+        while redis.aof_rewrite_in_progress() {
+            std::thread::sleep(Duration::from_millis(10));
+            redis.check_aof_rewrite_done();
+        }
+        assert!(redis.aof_last_rewrite_ok());
+        redis.flush_append_only_file();
+        assert!(fs::metadata(redis.aof_path()).unwrap().len() < before);
+
+        // the log carries on in the new file
+        set(&mut redis, b"last", b"write");
+        redis.flush_append_only_file();
+
+        let loaded = loaded(&redis);
+        assert_eq!(
+            redis.dbs[0].dict.peek(b"key"),
+            loaded.dbs[0].dict.peek(b"key")
+        );
+        assert_eq!(
+            redis.dbs[0].dict.peek(b"list"),
+            loaded.dbs[0].dict.peek(b"list")
+        );
+        assert!(loaded.dbs[2].dict.peek(b"after").is_some());
+        assert!(loaded.dbs[0].dict.peek(b"last").is_some());
+        assert!(
+            !redis
+                .config
+                .dir
+                .join(format!("temp-rewriteaof-bg-{}.aof", std::process::id()))
+                .exists()
+        );
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_bgrewriteaof_scheduled() {
+        let mut redis = redis_in_temp_dir("scheduled", AppendFsync::Always);
+        set(&mut redis, b"key", b"value");
+
+        assert_eq!(
+            Ok(b"+Background saving started\r\n".to_vec()),
+            resp_or_error(redis.execute_command(&RedisCommand::BgSave))
+        );
+        assert_eq!(
+            Ok(b"+Background append only file rewriting scheduled\r\n".to_vec()),
+            resp_or_error(redis.execute_command(&RedisCommand::BgRewriteAof))
+        );
+
+        while redis.bgsave_in_progress() {
+            std::thread::sleep(Duration::from_millis(10));
+            redis.check_bgsave_done();
+        }
+        redis.check_aof_rewrite_done();
+        assert!(redis.aof_rewrite_in_progress());
+        assert!(!redis.aof_rewrite_scheduled());
+        while redis.aof_rewrite_in_progress() {
+            std::thread::sleep(Duration::from_millis(10));
+            redis.check_aof_rewrite_done();
+        }
+        assert!(redis.aof_last_rewrite_ok());
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+}
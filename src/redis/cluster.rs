@@ -0,0 +1,2180 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    mem,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use libc::c_int;
+
+use crate::{
+    commands::{ClusterSubcommand, RedisCommand, SetSlotState},
+    error::{CommandError, RedisError},
+    redis::{Redis, RedisResult, crc16::crc16, db::unix_time_ms, replication::random_replid},
+};
+
+pub const CLUSTER_SLOTS: usize = 16384;
+// like redis the bus of a node listens on its port + 10000
+pub const BUS_PORT_OFFSET: u16 = 10000;
+
+// node flags, the same bits as in redis
+const FLAG_MYSELF: u16 = 1;
+const FLAG_MASTER: u16 = 2;
+// this node didn't get an answer in time
+const FLAG_PFAIL: u16 = 8;
+// a majority of the masters agrees it is down
+const FLAG_FAIL: u16 = 16;
+// the node was never heard from, its id is a random one until it answers
+const FLAG_HANDSHAKE: u16 = 32;
+// the first message to the node is a MEET, which makes it add this one
+const FLAG_MEET: u16 = 128;
+
+// failure reports of other masters count for this many node timeouts
+const FAIL_REPORT_VALIDITY_MULT: u32 = 2;
+// a master with slots is only taken back after it was failing for this many node timeouts
+const FAIL_UNDO_TIME_MULT: u32 = 2;
+// the cron runs with the periodic tasks, ten times a second
+const CRON_RUNS_PER_PING: u64 = 10;
+
+// the messages on the bus, the same idea as the binary protocol of redis but not compatible with
+// it. Every message starts with the header, PING, PONG and MEET carry gossip entries after it
+const SIGNATURE: &[u8; 4] = b"RCmb";
+const MESSAGE_VERSION: u16 = 1;
+const ID_LEN: usize = 40;
+const IP_LEN: usize = 46;
+const HEADER_LEN: usize = 4 + 4 + 2 + 2 + 2 + 8 + 8 + ID_LEN + 2 + 2 + 2 + CLUSTER_SLOTS / 8;
+const GOSSIP_LEN: usize = ID_LEN + IP_LEN + 2 + 2 + 2;
+// far more gossip than any cluster needs
+const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+
+// 40 hex characters like a replid
+type NodeId = [u8; ID_LEN];
+
+// the view of the cluster of this node, like clusterState in redis. Every node is a master, there
+// are no cluster replicas and no failover
+pub(super) struct ClusterState {
+    myself: NodeId,
+    // the highest epoch seen in the cluster
+    current_epoch: u64,
+    // every known node, this one included
+    nodes: Vec<ClusterNode>,
+    // the node that serves each slot
+    slots: Vec<Option<NodeId>>,
+    // slots of this node that move to another node, and slots it takes over from another one
+    migrating: BTreeMap<u16, NodeId>,
+    importing: BTreeMap<u16, NodeId>,
+    // every slot is served by a node that isn't failing
+    state_ok: bool,
+    node_timeout: Duration,
+    // messages for the server to send, with the fd of the link they go over
+    outbox: Vec<(c_int, Vec<u8>)>,
+    // links the server has to close
+    dropped_links: Vec<c_int>,
+    cron_runs: u64,
+}
+
+struct ClusterNode {
+    id: NodeId,
+    // empty for this node until another node tells it how it is reached
+    ip: String,
+    port: u16,
+    bus_port: u16,
+    flags: u16,
+    // the epoch the slots of the node were claimed in, the highest one wins a slot
+    config_epoch: u64,
+    // the connection this node opened to it, pings go out over it and the pongs come back
+    link: Option<c_int>,
+    link_created: Instant,
+    // the ping that wasn't answered yet
+    ping_sent: Option<Instant>,
+    pong_received: Option<Instant>,
+    fail_time: Option<Instant>,
+    // the masters that said it is failing and when
+    fail_reports: Vec<(NodeId, Instant)>,
+    created: Instant,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MessageKind {
+    Ping = 0,
+    Pong = 1,
+    Meet = 2,
+    // a node is failing, sent to every node once a majority agrees
+    Fail = 3,
+    // the receiver claims slots that the sender gives to another node with a config epoch at least
+    // as high
+    Update = 4,
+}
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    kind: MessageKind,
+    current_epoch: u64,
+    config_epoch: u64,
+    sender: NodeId,
+    port: u16,
+    bus_port: u16,
+    flags: u16,
+    // a bit for every slot the sender serves
+    slots: Vec<u8>,
+    gossip: Vec<Gossip>,
+    // the node a FAIL is about
+    failing: Option<NodeId>,
+    update: Option<SlotsUpdate>,
+}
+
+// what the sender knows about another node
+#[derive(Debug, PartialEq)]
+struct Gossip {
+    id: NodeId,
+    ip: String,
+    port: u16,
+    bus_port: u16,
+    flags: u16,
+}
+
+// the node that serves the slots of an UPDATE as the sender sees it
+#[derive(Debug, PartialEq)]
+struct SlotsUpdate {
+    owner: NodeId,
+    config_epoch: u64,
+    slots: Vec<u8>,
+}
+
+impl ClusterState {
+    pub(super) fn new(port: u16, node_timeout: Duration) -> Self {
+        let mut myself = ClusterNode::new(
+            random_node_id(),
+            String::new(),
+            port,
+            port.saturating_add(BUS_PORT_OFFSET),
+            FLAG_MYSELF | FLAG_MASTER,
+        );
+        myself.link_created = Instant::now();
+
+        ClusterState {
+            myself: myself.id,
+            current_epoch: 0,
+            nodes: vec![myself],
+            slots: vec![None; CLUSTER_SLOTS],
+            migrating: BTreeMap::new(),
+            importing: BTreeMap::new(),
+            state_ok: false,
+            node_timeout,
+            outbox: Vec::new(),
+            dropped_links: Vec::new(),
+            cron_runs: 0,
+        }
+    }
+
+    fn node_index(&self, id: &[u8]) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    fn node(&self, id: &[u8]) -> Option<&ClusterNode> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    fn myself(&self) -> &ClusterNode {
+        &self.nodes[self
+            .node_index(&self.myself)
+            .expect("myself is always a node")]
+    }
+
+    fn myself_mut(&mut self) -> &mut ClusterNode {
+        let index = self
+            .node_index(&self.myself)
+            .expect("myself is always a node");
+        &mut self.nodes[index]
+    }
+
+    // a node that was handed to MEET or that the gossip of another node is about. It is pinged
+    // to learn its id, a MEET makes it add this node as well
+    fn start_handshake(&mut self, ip: String, port: u16, bus_port: u16, meet: bool) {
+        let in_progress = self.nodes.iter().any(|node| {
+            node.flags & FLAG_HANDSHAKE != 0
+                && node.ip == ip
+                && node.port == port
+                && node.bus_port == bus_port
+        });
+        if in_progress {
+            return;
+        }
+
+        let flags = FLAG_HANDSHAKE | if meet { FLAG_MEET } else { 0 };
+        self.nodes.push(ClusterNode::new(
+            random_node_id(),
+            ip,
+            port,
+            bus_port,
+            flags,
+        ));
+    }
+
+    fn remove_node(&mut self, index: usize) {
+        let node = self.nodes.remove(index);
+        if let Some(fd) = node.link {
+            self.dropped_links.push(fd);
+        }
+        for owner in &mut self.slots {
+            if *owner == Some(node.id) {
+                *owner = None;
+            }
+        }
+        self.migrating.retain(|_, target| *target != node.id);
+        self.importing.retain(|_, source| *source != node.id);
+        for other in &mut self.nodes {
+            other
+                .fail_reports
+                .retain(|(reporter, _)| *reporter != node.id);
+        }
+    }
+
+    fn drop_link(&mut self, index: usize) {
+        if let Some(fd) = self.nodes[index].link.take() {
+            self.dropped_links.push(fd);
+        }
+    }
+
+    // masters that serve at least one slot, a majority of them has to agree a node is failing
+    fn size(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| self.slots.contains(&Some(node.id)))
+            .count()
+    }
+
+    fn build_message(&self, kind: MessageKind, receiver: Option<NodeId>) -> Message {
+        let myself = self.myself();
+
+        let mut slots = vec![0u8; CLUSTER_SLOTS / 8];
+        for (slot, owner) in self.slots.iter().enumerate() {
+            if *owner == Some(self.myself) {
+                slots[slot / 8] |= 1 << (slot % 8);
+            }
+        }
+
+        // unlike redis every node goes into every ping, clusters here are small
+        let gossip = match kind {
+            MessageKind::Fail | MessageKind::Update => Vec::new(),
+            _ => self
+                .nodes
+                .iter()
+                .filter(|node| {
+                    node.flags & (FLAG_MYSELF | FLAG_HANDSHAKE) == 0 && Some(node.id) != receiver
+                })
+                .map(|node| Gossip {
+                    id: node.id,
+                    ip: node.ip.clone(),
+                    port: node.port,
+                    bus_port: node.bus_port,
+                    flags: node.flags,
+                })
+                .collect(),
+        };
+
+        Message {
+            kind,
+            current_epoch: self.current_epoch,
+            config_epoch: myself.config_epoch,
+            sender: self.myself,
+            port: myself.port,
+            bus_port: myself.bus_port,
+            flags: myself.flags & !FLAG_MYSELF,
+            slots,
+            gossip,
+            failing: None,
+            update: None,
+        }
+    }
+
+    // a PING or MEET over the link of the node, the time of the first unanswered one is kept
+    // when the link is created again
+    fn send_ping(&mut self, index: usize, kind: MessageKind) {
+        let node = &self.nodes[index];
+        let Some(fd) = node.link else {
+            return;
+        };
+        let message = self.build_message(kind, Some(node.id)).encode();
+        self.outbox.push((fd, message));
+        self.nodes[index].ping_sent.get_or_insert_with(Instant::now);
+    }
+
+    fn broadcast_fail(&mut self, failing: NodeId) {
+        let mut message = self.build_message(MessageKind::Fail, None);
+        message.failing = Some(failing);
+        let message = message.encode();
+
+        for node in &self.nodes {
+            if let Some(fd) = node.link
+                && node.flags & FLAG_HANDSHAKE == 0
+            {
+                self.outbox.push((fd, message.clone()));
+            }
+        }
+    }
+
+    // the slots the sender claims go to it when they are free or served by a node with a lower
+    // config epoch. Returns the slots this node lost. The sender is told with an UPDATE over the
+    // link fd about the other nodes that keep its slots
+    fn update_slots(&mut self, fd: c_int, sender: usize, slots: &[u8]) -> Vec<u16> {
+        let (sender_id, epoch) = (self.nodes[sender].id, self.nodes[sender].config_epoch);
+        let mut lost = Vec::new();
+        let mut kept_by = BTreeMap::new();
+
+        for slot in 0..CLUSTER_SLOTS {
+            if slots[slot / 8] & (1 << (slot % 8)) == 0 {
+                continue;
+            }
+            let owner = self.slots[slot];
+            if owner == Some(sender_id) || self.importing.contains_key(&(slot as u16)) {
+                continue;
+            }
+
+            let owner_epoch = owner
+                .and_then(|owner| self.node(&owner))
+                .map(|node| node.config_epoch);
+            if owner_epoch.is_none_or(|owner_epoch| owner_epoch < epoch) {
+                if owner == Some(self.myself) {
+                    lost.push(slot as u16);
+                    self.migrating.remove(&(slot as u16));
+                }
+                self.slots[slot] = Some(sender_id);
+            } else if let Some(owner) = owner.filter(|owner| *owner != self.myself) {
+                // the slots of this node are claimed in its own pings
+                let owner_slots = kept_by
+                    .entry(owner)
+                    .or_insert_with(|| vec![0u8; CLUSTER_SLOTS / 8]);
+                owner_slots[slot / 8] |= 1 << (slot % 8);
+            }
+        }
+
+        for (owner, slots) in kept_by {
+            let mut message = self.build_message(MessageKind::Update, None);
+            message.update = Some(SlotsUpdate {
+                owner,
+                config_epoch: self.node(&owner).map_or(0, |node| node.config_epoch),
+                slots,
+            });
+            self.outbox.push((fd, message.encode()));
+        }
+
+        lost
+    }
+
+    // the sender gives slots this node serves to their old owner. They were moved here with
+    // SETSLOT NODE and the old owner stopped claiming them, but a collision raised its config
+    // epoch to the one of this node or above. Like for SETSLOT NODE the importing node wins, a
+    // config epoch above the one of the old owner makes the sender give the slots to this node
+    fn handle_update(&mut self, update: &SlotsUpdate) {
+        if let Some(index) = self.node_index(&update.owner) {
+            let node = &mut self.nodes[index];
+            node.config_epoch = node.config_epoch.max(update.config_epoch);
+        }
+
+        let serves_any = (0..CLUSTER_SLOTS).any(|slot| {
+            update.slots[slot / 8] & (1 << (slot % 8)) != 0 && self.slots[slot] == Some(self.myself)
+        });
+        if !serves_any || self.myself().config_epoch > update.config_epoch {
+            return;
+        }
+
+        self.current_epoch = self.current_epoch.max(update.config_epoch) + 1;
+        let epoch = self.current_epoch;
+        self.myself_mut().config_epoch = epoch;
+        eprintln!(
+            "Config epoch raised to {} to take slots back from node {}",
+            epoch,
+            id_str(&update.owner)
+        );
+    }
+
+    // two masters with the same config epoch, the one with the smaller id takes a new one so a
+    // slot both claim goes to one of them in the end
+    fn handle_config_epoch_collision(&mut self, sender: usize) {
+        let sender = &self.nodes[sender];
+        if sender.config_epoch != self.myself().config_epoch || sender.id <= self.myself {
+            return;
+        }
+
+        self.current_epoch += 1;
+        let epoch = self.current_epoch;
+        self.myself_mut().config_epoch = epoch;
+    }
+
+    // a new config epoch for this node without asking the others, like redis does it for slots
+    // that were moved here with SETSLOT NODE
+    fn bump_config_epoch(&mut self) {
+        let max_epoch = self
+            .nodes
+            .iter()
+            .map(|node| node.config_epoch)
+            .max()
+            .unwrap_or(0)
+            .max(self.current_epoch);
+        let config_epoch = self.myself().config_epoch;
+        if config_epoch == 0 || config_epoch != max_epoch {
+            self.current_epoch += 1;
+            let epoch = self.current_epoch;
+            self.myself_mut().config_epoch = epoch;
+        }
+    }
+
+    fn valid_fail_reports(&self, index: usize) -> usize {
+        let validity = self.node_timeout * FAIL_REPORT_VALIDITY_MULT;
+        self.nodes[index]
+            .fail_reports
+            .iter()
+            .filter(|(_, time)| time.elapsed() <= validity)
+            .count()
+    }
+
+    // a node this one can't reach is failing once a majority of the masters with slots says so,
+    // this one included. The other nodes are told with a FAIL
+    fn mark_failing_if_needed(&mut self, index: usize) {
+        let node = &self.nodes[index];
+        if node.flags & FLAG_PFAIL == 0 || node.flags & FLAG_FAIL != 0 {
+            return;
+        }
+
+        let needed = self.size() / 2 + 1;
+        if self.valid_fail_reports(index) + 1 < needed {
+            return;
+        }
+
+        let node = &mut self.nodes[index];
+        node.flags = (node.flags & !FLAG_PFAIL) | FLAG_FAIL;
+        node.fail_time = Some(Instant::now());
+        let id = node.id;
+        eprintln!("Marking node {} as failing (quorum reached)", id_str(&id));
+        self.broadcast_fail(id);
+    }
+
+    // a failing node that answers again is taken back right away when it has no slots. A master
+    // with slots only after a while, the others may not have noticed it is back yet
+    fn clear_failure_if_needed(&mut self, index: usize) {
+        let node = &self.nodes[index];
+        if node.flags & FLAG_FAIL == 0 {
+            return;
+        }
+
+        let serves_slots = self.slots.contains(&Some(node.id));
+        let undo_time = self.node_timeout * FAIL_UNDO_TIME_MULT;
+        if !serves_slots
+            || node
+                .fail_time
+                .is_none_or(|fail_time| fail_time.elapsed() > undo_time)
+        {
+            eprintln!("Clear FAIL state for node {}", id_str(&node.id));
+            let node = &mut self.nodes[index];
+            node.flags &= !FLAG_FAIL;
+            node.fail_time = None;
+        }
+    }
+
+    fn update_state(&mut self) {
+        self.state_ok = self.slots.iter().all(|owner| {
+            owner
+                .and_then(|owner| self.node(&owner))
+                .is_some_and(|node| node.flags & FLAG_FAIL == 0)
+        });
+    }
+
+    // the node that serves the slot as ip:port, for the redirects
+    fn address(&self, id: &NodeId) -> String {
+        match self.node(id) {
+            Some(node) => format!("{}:{}", node.ip, node.port),
+            None => String::new(),
+        }
+    }
+}
+
+impl ClusterNode {
+    fn new(id: NodeId, ip: String, port: u16, bus_port: u16, flags: u16) -> Self {
+        let now = Instant::now();
+        ClusterNode {
+            id,
+            ip,
+            port,
+            bus_port,
+            flags,
+            config_epoch: 0,
+            link: None,
+            link_created: now,
+            ping_sent: None,
+            pong_received: None,
+            fail_time: None,
+            fail_reports: Vec::new(),
+            created: now,
+        }
+    }
+
+    // the flags like CLUSTER NODES shows them
+    fn flags_string(&self) -> String {
+        let mut flags = Vec::new();
+        if self.flags & FLAG_MYSELF != 0 {
+            flags.push("myself");
+        }
+        if self.flags & FLAG_MASTER != 0 {
+            flags.push("master");
+        }
+        if self.flags & FLAG_PFAIL != 0 {
+            flags.push("fail?");
+        }
+        if self.flags & FLAG_FAIL != 0 {
+            flags.push("fail");
+        }
+        if self.flags & FLAG_HANDSHAKE != 0 {
+            flags.push("handshake");
+        }
+        if flags.is_empty() {
+            flags.push("noflags");
+        }
+        flags.join(",")
+    }
+}
+
+impl MessageKind {
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            0 => Some(MessageKind::Ping),
+            1 => Some(MessageKind::Pong),
+            2 => Some(MessageKind::Meet),
+            3 => Some(MessageKind::Fail),
+            4 => Some(MessageKind::Update),
+            _ => None,
+        }
+    }
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.gossip.len() * GOSSIP_LEN + ID_LEN);
+        buf.extend_from_slice(SIGNATURE);
+        // the length goes here once it is known
+        buf.extend_from_slice(&[0; 4]);
+        buf.extend_from_slice(&MESSAGE_VERSION.to_be_bytes());
+        buf.extend_from_slice(&(self.kind as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.gossip.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.current_epoch.to_be_bytes());
+        buf.extend_from_slice(&self.config_epoch.to_be_bytes());
+        buf.extend_from_slice(&self.sender);
+        buf.extend_from_slice(&self.port.to_be_bytes());
+        buf.extend_from_slice(&self.bus_port.to_be_bytes());
+        buf.extend_from_slice(&self.flags.to_be_bytes());
+        buf.extend_from_slice(&self.slots);
+
+        for gossip in &self.gossip {
+            buf.extend_from_slice(&gossip.id);
+            let mut ip = [0u8; IP_LEN];
+            let len = gossip.ip.len().min(IP_LEN);
+            ip[..len].copy_from_slice(&gossip.ip.as_bytes()[..len]);
+            buf.extend_from_slice(&ip);
+            buf.extend_from_slice(&gossip.port.to_be_bytes());
+            buf.extend_from_slice(&gossip.bus_port.to_be_bytes());
+            buf.extend_from_slice(&gossip.flags.to_be_bytes());
+        }
+        if let Some(failing) = &self.failing {
+            buf.extend_from_slice(failing);
+        }
+        if let Some(update) = &self.update {
+            buf.extend_from_slice(&update.owner);
+            buf.extend_from_slice(&update.config_epoch.to_be_bytes());
+            buf.extend_from_slice(&update.slots);
+        }
+
+        let len = buf.len() as u32;
+        buf[4..8].copy_from_slice(&len.to_be_bytes());
+        buf
+    }
+
+    // a whole message, as long as message_len says
+    fn decode(buf: &[u8]) -> Option<Message> {
+        let mut reader = Reader { buf, pos: 8 };
+        if reader.u16()? != MESSAGE_VERSION {
+            return None;
+        }
+        let kind = MessageKind::from_code(reader.u16()?)?;
+        let amount_gossip = reader.u16()? as usize;
+
+        let mut message = Message {
+            kind,
+            current_epoch: reader.u64()?,
+            config_epoch: reader.u64()?,
+            sender: reader.id()?,
+            port: reader.u16()?,
+            bus_port: reader.u16()?,
+            flags: reader.u16()?,
+            slots: reader.bytes(CLUSTER_SLOTS / 8)?.to_vec(),
+            gossip: Vec::with_capacity(amount_gossip),
+            failing: None,
+            update: None,
+        };
+
+        for _ in 0..amount_gossip {
+            let id = reader.id()?;
+            let ip = reader.bytes(IP_LEN)?;
+            let len = ip.iter().position(|&b| b == 0).unwrap_or(IP_LEN);
+            message.gossip.push(Gossip {
+                id,
+                ip: std::str::from_utf8(&ip[..len]).ok()?.to_string(),
+                port: reader.u16()?,
+                bus_port: reader.u16()?,
+                flags: reader.u16()?,
+            });
+        }
+        if kind == MessageKind::Fail {
+            message.failing = Some(reader.id()?);
+        }
+        if kind == MessageKind::Update {
+            message.update = Some(SlotsUpdate {
+                owner: reader.id()?,
+                config_epoch: reader.u64()?,
+                slots: reader.bytes(CLUSTER_SLOTS / 8)?.to_vec(),
+            });
+        }
+
+        (reader.pos == buf.len()).then_some(message)
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    // ids are only hex characters so they can be shown as they are
+    fn id(&mut self) -> Option<NodeId> {
+        let id: NodeId = self.bytes(ID_LEN)?.try_into().ok()?;
+        id.iter().all(u8::is_ascii_hexdigit).then_some(id)
+    }
+}
+
+// the length of the message at the start of buf, None while it didn't all come in yet
+pub fn message_len(buf: &[u8]) -> Result<Option<usize>, RedisError> {
+    if buf.len() < 8 {
+        return Ok(None);
+    }
+    if &buf[..4] != SIGNATURE {
+        return Err(RedisError::Other(
+            "invalid message on the cluster bus".to_string(),
+        ));
+    }
+
+    let len = u32::from_be_bytes(buf[4..8].try_into().unwrap()) as usize;
+    if !(HEADER_LEN..=MAX_MESSAGE_LEN).contains(&len) {
+        return Err(RedisError::Other(format!(
+            "invalid message length {} on the cluster bus",
+            len
+        )));
+    }
+
+    Ok((buf.len() >= len).then_some(len))
+}
+
+// the slot of a key. When it has a {tag} that isn't empty only the tag is hashed, so keys with
+// the same tag end up in the same slot
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|&b| b == b'{')
+        .and_then(|start| {
+            let tag = &key[start + 1..];
+            let end = tag.iter().position(|&b| b == b'}')?;
+            (end > 0).then(|| &tag[..end])
+        })
+        .unwrap_or(key);
+
+    crc16(hashed) & (CLUSTER_SLOTS as u16 - 1)
+}
+
+// the keys of every slot, like the slots_to_keys radix tree of redis 6 the entries are the slot
+// as two big endian bytes followed by the key so the keys of a slot are next to each other. Kept
+// by db 0 of a cluster node
+pub(super) struct SlotKeys {
+    counts: Vec<usize>,
+    keys: BTreeSet<Vec<u8>>,
+}
+
+impl SlotKeys {
+    pub(super) fn new() -> Self {
+        SlotKeys {
+            counts: vec![0; CLUSTER_SLOTS],
+            keys: BTreeSet::new(),
+        }
+    }
+
+    fn entry(slot: u16, key: &[u8]) -> Vec<u8> {
+        [&slot.to_be_bytes()[..], key].concat()
+    }
+
+    // a key that wasn't in the keyspace yet
+    pub(super) fn add(&mut self, key: &[u8]) {
+        let slot = key_hash_slot(key);
+        if self.keys.insert(Self::entry(slot, key)) {
+            self.counts[slot as usize] += 1;
+        }
+    }
+
+    pub(super) fn remove(&mut self, key: &[u8]) {
+        let slot = key_hash_slot(key);
+        if self.keys.remove(&Self::entry(slot, key)) {
+            self.counts[slot as usize] -= 1;
+        }
+    }
+
+    pub(super) fn count(&self, slot: u16) -> usize {
+        self.counts[slot as usize]
+    }
+
+    pub(super) fn keys(&self, slot: u16) -> impl Iterator<Item = &[u8]> {
+        let prefix = slot.to_be_bytes();
+        self.keys
+            .range(prefix.to_vec()..)
+            .take_while(move |entry| entry.starts_with(&prefix))
+            .map(|entry| &entry[2..])
+    }
+}
+
+impl Redis {
+    pub fn cluster_enabled(&self) -> bool {
+        self.cluster.is_some()
+    }
+
+    // opens the links to the nodes that don't have one, connect returns the fd of the new
+    // connection. The first message over it is a PING, or a MEET for a node given to CLUSTER MEET
+    pub fn connect_cluster_links(&mut self, mut connect: impl FnMut(&str, u16) -> Option<c_int>) {
+        let Some(cluster) = &mut self.cluster else {
+            return;
+        };
+
+        for index in 0..cluster.nodes.len() {
+            let node = &mut cluster.nodes[index];
+            if node.link.is_some() || node.flags & FLAG_MYSELF != 0 {
+                continue;
+            }
+            let Some(fd) = connect(&node.ip, node.bus_port) else {
+                continue;
+            };
+
+            node.link = Some(fd);
+            node.link_created = Instant::now();
+            let kind = if node.flags & FLAG_MEET != 0 {
+                node.flags &= !FLAG_MEET;
+                MessageKind::Meet
+            } else {
+                MessageKind::Ping
+            };
+            cluster.send_ping(index, kind);
+        }
+    }
+
+    // a message that came in over the bus, peer_ip and local_ip are the addresses of the two
+    // ends of its connection. Only a broken message is an error, the connection is closed then
+    pub fn process_cluster_message(
+        &mut self,
+        fd: c_int,
+        peer_ip: &str,
+        local_ip: &str,
+        data: &[u8],
+    ) -> Result<(), RedisError> {
+        let Some(cluster) = &mut self.cluster else {
+            return Err(RedisError::Other("cluster support is disabled".to_string()));
+        };
+        let message = Message::decode(data)
+            .ok_or_else(|| RedisError::Other("invalid message on the cluster bus".to_string()))?;
+        let now = Instant::now();
+
+        cluster.current_epoch = cluster.current_epoch.max(message.current_epoch);
+
+        let mut sender = cluster.node_index(&message.sender);
+        if matches!(message.kind, MessageKind::Ping | MessageKind::Meet) {
+            // the other nodes reach this one at the address they connected to, like redis a MEET
+            // always updates it
+            if message.kind == MessageKind::Meet || cluster.myself().ip.is_empty() {
+                cluster.myself_mut().ip = local_ip.to_string();
+            }
+
+            // a node that was told to meet this one joins the cluster
+            if sender.is_none() && message.kind == MessageKind::Meet {
+                cluster.nodes.push(ClusterNode::new(
+                    message.sender,
+                    peer_ip.to_string(),
+                    message.port,
+                    message.bus_port,
+                    FLAG_MASTER,
+                ));
+                sender = Some(cluster.nodes.len() - 1);
+            }
+
+            let reply = cluster.build_message(MessageKind::Pong, Some(message.sender));
+            cluster.outbox.push((fd, reply.encode()));
+        }
+
+        // the answer to a ping over the link of a node
+        if message.kind == MessageKind::Pong
+            && let Some(index) = cluster.nodes.iter().position(|node| node.link == Some(fd))
+        {
+            let node = &mut cluster.nodes[index];
+            if node.flags & FLAG_HANDSHAKE != 0 {
+                // the node is known under its id already
+                if sender.is_some() {
+                    cluster.remove_node(index);
+                    return Ok(());
+                }
+                node.id = message.sender;
+                node.flags = (node.flags & !FLAG_HANDSHAKE) | FLAG_MASTER;
+                sender = Some(index);
+                eprintln!("Handshake with node {} completed", id_str(&node.id));
+            } else if node.id != message.sender {
+                // another node listens at that address now
+                cluster.drop_link(index);
+                return Ok(());
+            }
+
+            let node = &mut cluster.nodes[index];
+            node.pong_received = Some(now);
+            node.ping_sent = None;
+            node.flags &= !FLAG_PFAIL;
+            cluster.clear_failure_if_needed(index);
+        }
+
+        // nodes that aren't part of the cluster only get the pong
+        let Some(sender) = sender else {
+            return Ok(());
+        };
+
+        let mut lost_slots = Vec::new();
+        match message.kind {
+            MessageKind::Ping | MessageKind::Pong | MessageKind::Meet => {
+                let node = &mut cluster.nodes[sender];
+                node.config_epoch = node.config_epoch.max(message.config_epoch);
+                lost_slots = cluster.update_slots(fd, sender, &message.slots);
+                cluster.handle_config_epoch_collision(sender);
+
+                let sender_id = message.sender;
+                for gossip in &message.gossip {
+                    if gossip.id == cluster.myself {
+                        continue;
+                    }
+                    match cluster.node_index(&gossip.id) {
+                        Some(index) => {
+                            let reports = &mut cluster.nodes[index].fail_reports;
+                            reports.retain(|(reporter, _)| *reporter != sender_id);
+                            if gossip.flags & (FLAG_PFAIL | FLAG_FAIL) != 0 {
+                                reports.push((sender_id, now));
+                                cluster.mark_failing_if_needed(index);
+                            }
+                        }
+                        None if !gossip.ip.is_empty() => cluster.start_handshake(
+                            gossip.ip.clone(),
+                            gossip.port,
+                            gossip.bus_port,
+                            false,
+                        ),
+                        None => {}
+                    }
+                }
+            }
+            MessageKind::Fail => {
+                if let Some(failing) = message.failing
+                    && failing != cluster.myself
+                    && let Some(index) = cluster.node_index(&failing)
+                    && cluster.nodes[index].flags & FLAG_FAIL == 0
+                {
+                    let node = &mut cluster.nodes[index];
+                    node.flags = (node.flags & !FLAG_PFAIL) | FLAG_FAIL;
+                    node.fail_time = Some(now);
+                    eprintln!(
+                        "FAIL message received from {} about {}",
+                        id_str(&message.sender),
+                        id_str(&failing)
+                    );
+                }
+            }
+            MessageKind::Update => {
+                if let Some(update) = &message.update {
+                    cluster.handle_update(update);
+                }
+            }
+        }
+
+        cluster.update_state();
+        self.delete_keys_in_slots(&lost_slots);
+        Ok(())
+    }
+
+    pub fn take_cluster_messages(&mut self) -> Vec<(c_int, Vec<u8>)> {
+        match &mut self.cluster {
+            Some(cluster) => mem::take(&mut cluster.outbox),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn take_dropped_cluster_links(&mut self) -> Vec<c_int> {
+        match &mut self.cluster {
+            Some(cluster) => mem::take(&mut cluster.dropped_links),
+            None => Vec::new(),
+        }
+    }
+
+    // a connection of the bus was closed, the link of its node is opened again by the cron
+    pub fn cluster_link_closed(&mut self, fd: c_int) {
+        let Some(cluster) = &mut self.cluster else {
+            return;
+        };
+
+        for node in &mut cluster.nodes {
+            if node.link == Some(fd) {
+                node.link = None;
+            }
+        }
+        cluster.outbox.retain(|(link, _)| *link != fd);
+    }
+
+    // like getNodeByQuery in redis, the error is the redirect or why the command can't run here.
+    // All keys have to be in the same slot. A slot that is migrating sends the keys that are
+    // gone already to its target, and the target only takes commands for the slot after ASKING
+    pub fn cluster_redirect(
+        &self,
+        command: &RedisCommand,
+        asking: bool,
+    ) -> Result<(), CommandError> {
+        let Some(cluster) = &self.cluster else {
+            return Ok(());
+        };
+        let keys = command.keys();
+        let Some(first) = keys.first() else {
+            return Ok(());
+        };
+
+        let slot = key_hash_slot(first);
+        if keys[1..].iter().any(|key| key_hash_slot(key) != slot) {
+            return Err(CommandError::CrossSlot);
+        }
+        if !cluster.state_ok {
+            return Err(CommandError::ClusterDown);
+        }
+        let Some(owner) = cluster.slots[slot as usize] else {
+            return Err(CommandError::SlotNotServed);
+        };
+
+        // the keys are only ever in db 0 of a cluster node
+        let now = unix_time_ms();
+        let db = &self.dbs[0];
+        let missing = keys
+            .iter()
+            .filter(|key| db.dict.peek(key).is_none() || db.is_expired(key, now))
+            .count();
+
+        if owner == cluster.myself {
+            return match cluster.migrating.get(&slot) {
+                Some(_) if missing > 0 && missing < keys.len() => Err(CommandError::TryAgain),
+                Some(target) if missing > 0 => Err(CommandError::Ask {
+                    slot,
+                    address: cluster.address(target),
+                }),
+                _ => Ok(()),
+            };
+        }
+
+        let asking =
+            asking || matches!(command, RedisCommand::Restore { options, .. } if options.asking);
+        if asking && cluster.importing.contains_key(&slot) {
+            if keys.len() > 1 && missing > 0 {
+                return Err(CommandError::TryAgain);
+            }
+            return Ok(());
+        }
+
+        Err(CommandError::Moved {
+            slot,
+            address: cluster.address(&owner),
+        })
+    }
+
+    // pings the nodes, notices the ones that don't answer and drops handshakes that went nowhere.
+    // Called from the periodic tasks
+    pub(super) fn cluster_cron(&mut self) {
+        let Some(cluster) = &mut self.cluster else {
+            return;
+        };
+        let node_timeout = cluster.node_timeout;
+        cluster.cron_runs += 1;
+
+        let handshake_timeout = node_timeout.max(Duration::from_secs(1));
+        while let Some(index) = cluster.nodes.iter().position(|node| {
+            node.flags & FLAG_HANDSHAKE != 0 && node.created.elapsed() > handshake_timeout
+        }) {
+            cluster.remove_node(index);
+        }
+
+        // once a second the node heard from least recently, redis picks it out of a few random
+        // ones
+        if cluster.cron_runs % CRON_RUNS_PER_PING == 0
+            && let Some(index) = (0..cluster.nodes.len())
+                .filter(|&index| {
+                    let node = &cluster.nodes[index];
+                    node.link.is_some()
+                        && node.ping_sent.is_none()
+                        && node.flags & (FLAG_MYSELF | FLAG_HANDSHAKE) == 0
+                })
+                .min_by_key(|&index| cluster.nodes[index].pong_received)
+        {
+            cluster.send_ping(index, MessageKind::Ping);
+        }
+
+        for index in 0..cluster.nodes.len() {
+            let node = &cluster.nodes[index];
+            if node.flags & (FLAG_MYSELF | FLAG_HANDSHAKE) != 0 {
+                continue;
+            }
+
+            let Some(ping_sent) = node.ping_sent else {
+                // a node is pinged at least every half node timeout
+                if node
+                    .pong_received
+                    .is_none_or(|pong| pong.elapsed() > node_timeout / 2)
+                {
+                    cluster.send_ping(index, MessageKind::Ping);
+                }
+                continue;
+            };
+
+            // the link may be what is broken, a new one is opened
+            let delay = ping_sent.elapsed();
+            if delay > node_timeout / 2 && node.link_created.elapsed() > node_timeout {
+                cluster.drop_link(index);
+            }
+
+            let node = &mut cluster.nodes[index];
+            if delay > node_timeout && node.flags & (FLAG_PFAIL | FLAG_FAIL) == 0 {
+                node.flags |= FLAG_PFAIL;
+                eprintln!("Node {} is possibly failing", id_str(&node.id));
+                cluster.mark_failing_if_needed(index);
+            }
+        }
+
+        cluster.update_state();
+    }
+
+    pub(super) fn cluster_command(&mut self, subcommand: &ClusterSubcommand) -> RedisResult {
+        let offset = self.replication_offset();
+        let Some(cluster) = &mut self.cluster else {
+            return error(CommandError::ClusterDisabled);
+        };
+
+        match subcommand {
+            ClusterSubcommand::Info => cluster_info(cluster),
+            ClusterSubcommand::MyId => bulk_string(&cluster.myself),
+            ClusterSubcommand::Nodes => cluster_nodes(cluster),
+            ClusterSubcommand::Slots => cluster_slots(cluster),
+            ClusterSubcommand::Shards => cluster_shards(cluster, offset),
+            ClusterSubcommand::KeySlot { key } => RedisResult::Int(key_hash_slot(key) as i64),
+            ClusterSubcommand::CountKeysInSlot { slot } => {
+                RedisResult::Int(self.slot_keys().count(*slot) as i64)
+            }
+            ClusterSubcommand::GetKeysInSlot { slot, count } => {
+                let keys = self.keys_in_slot(*slot, *count);
+                let mut reply = Vec::new();
+                Self::write_array_header(&mut reply, keys.len());
+                for key in keys {
+                    write_bulk(&mut reply, &key);
+                }
+                RedisResult::BulkString(reply)
+            }
+            ClusterSubcommand::AddSlots { slots } => add_slots(cluster, slots),
+            ClusterSubcommand::DelSlots { slots } => del_slots(cluster, slots),
+            ClusterSubcommand::Meet { ip, port, bus_port } => {
+                let address = || CommandError::InvalidNodeAddress {
+                    address: [ip, &b":"[..], port.to_string().as_bytes()].concat(),
+                };
+                let Some(ip) = std::str::from_utf8(ip)
+                    .ok()
+                    .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+                else {
+                    return error(address());
+                };
+                let Some(bus_port) = bus_port.or_else(|| port.checked_add(BUS_PORT_OFFSET)) else {
+                    return error(address());
+                };
+
+                cluster.start_handshake(ip.to_string(), *port, bus_port, true);
+                RedisResult::SimpleString(b"+OK\r\n")
+            }
+            ClusterSubcommand::SetSlot { slot, state } => self.set_slot(*slot, state),
+        }
+    }
+
+    pub(super) fn write_cluster_info(&self, info: &mut Vec<u8>) {
+        write!(info, "cluster_enabled:{}\r\n", self.cluster_enabled() as u8).unwrap();
+    }
+
+    // CLUSTER SETSLOT, the steps of moving a slot: IMPORTING on the target, MIGRATING on the
+    // source, MIGRATE of the keys and NODE on both once the source has no keys left in it
+    fn set_slot(&mut self, slot: u16, state: &SetSlotState) -> RedisResult {
+        let keys_left = self.slot_keys().count(slot) > 0;
+        let cluster = self.cluster.as_mut().expect("only called in cluster mode");
+        let owner = cluster.slots[slot as usize];
+        let known_node = |cluster: &ClusterState, id: &[u8]| {
+            cluster
+                .node(id)
+                .filter(|node| node.flags & FLAG_HANDSHAKE == 0)
+                .map(|node| node.id)
+                .ok_or_else(|| CommandError::UnknownNode { id: id.to_vec() })
+        };
+
+        match state {
+            SetSlotState::Importing(id) => {
+                if owner == Some(cluster.myself) {
+                    return error(CommandError::AlreadySlotOwner { slot });
+                }
+                match known_node(cluster, id) {
+                    Ok(id) => cluster.importing.insert(slot, id),
+                    Err(err) => return error(err),
+                };
+            }
+            SetSlotState::Migrating(id) => {
+                if owner != Some(cluster.myself) {
+                    return error(CommandError::NotSlotOwner { slot });
+                }
+                match known_node(cluster, id) {
+                    Ok(id) => cluster.migrating.insert(slot, id),
+                    Err(err) => return error(err),
+                };
+            }
+            SetSlotState::Stable => {
+                cluster.migrating.remove(&slot);
+                cluster.importing.remove(&slot);
+            }
+            SetSlotState::Node(id) => {
+                let id = match known_node(cluster, id) {
+                    Ok(id) => id,
+                    Err(err) => return error(err),
+                };
+                if owner == Some(cluster.myself) && id != cluster.myself && keys_left {
+                    return error(CommandError::SlotNotEmpty { slot });
+                }
+
+                cluster.migrating.remove(&slot);
+                // the new config epoch makes the other nodes take the slot from the old owner
+                if id == cluster.myself && cluster.importing.remove(&slot).is_some() {
+                    cluster.bump_config_epoch();
+                }
+                cluster.slots[slot as usize] = Some(id);
+                cluster.update_state();
+            }
+        }
+
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+
+    fn slot_keys(&self) -> &SlotKeys {
+        self.dbs[0]
+            .slot_keys
+            .as_ref()
+            .expect("db 0 of a cluster node keeps the keys of every slot")
+    }
+
+    // like in redis keys that expired but weren't deleted yet are still in their slot
+    fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<Vec<u8>> {
+        self.slot_keys()
+            .keys(slot)
+            .take(count)
+            .map(|key| key.to_vec())
+            .collect()
+    }
+
+    // the keys of slots another node took over with a higher config epoch, they can't be reached
+    // here anymore
+    fn delete_keys_in_slots(&mut self, slots: &[u16]) {
+        if slots.is_empty() {
+            return;
+        }
+
+        let keys: Vec<Vec<u8>> = slots
+            .iter()
+            .flat_map(|&slot| self.keys_in_slot(slot, usize::MAX))
+            .collect();
+        for key in keys {
+            self.dbs[0].delete(&key);
+        }
+    }
+}
+
+fn cluster_info(cluster: &ClusterState) -> RedisResult {
+    let (mut assigned, mut pfail, mut fail) = (0, 0, 0);
+    for owner in cluster.slots.iter().flatten() {
+        assigned += 1;
+        match cluster.node(owner) {
+            Some(node) if node.flags & FLAG_FAIL != 0 => fail += 1,
+            Some(node) if node.flags & FLAG_PFAIL != 0 => pfail += 1,
+            _ => {}
+        }
+    }
+
+    let mut info = Vec::new();
+    write!(
+        info,
+        "cluster_state:{}\r\n\
+         cluster_slots_assigned:{}\r\n\
+         cluster_slots_ok:{}\r\n\
+         cluster_slots_pfail:{}\r\n\
+         cluster_slots_fail:{}\r\n\
+         cluster_known_nodes:{}\r\n\
+         cluster_size:{}\r\n\
+         cluster_current_epoch:{}\r\n\
+         cluster_my_epoch:{}\r\n",
+        if cluster.state_ok { "ok" } else { "fail" },
+        assigned,
+        assigned - pfail - fail,
+        pfail,
+        fail,
+        cluster.nodes.len(),
+        cluster.size(),
+        cluster.current_epoch,
+        cluster.myself().config_epoch,
+    )
+    .unwrap();
+    bulk_string(&info)
+}
+
+// a line for every node: id ip:port@bus-port flags master ping-sent pong-received config-epoch
+// link-state slots. The slots of this node that are moving are shown as [slot->-target] and
+// [slot-<-source]
+fn cluster_nodes(cluster: &ClusterState) -> RedisResult {
+    let mut nodes = String::new();
+    for node in &cluster.nodes {
+        let myself = node.flags & FLAG_MYSELF != 0;
+        let connected = myself || node.link.is_some();
+        nodes.push_str(&format!(
+            "{} {}:{}@{} {} - {} {} {} {}",
+            id_str(&node.id),
+            node.ip,
+            node.port,
+            node.bus_port,
+            node.flags_string(),
+            unix_time_of(node.ping_sent),
+            unix_time_of(node.pong_received),
+            node.config_epoch,
+            if connected {
+                "connected"
+            } else {
+                "disconnected"
+            },
+        ));
+
+        for (start, end) in slot_ranges(cluster, &node.id) {
+            if start == end {
+                nodes.push_str(&format!(" {}", start));
+            } else {
+                nodes.push_str(&format!(" {}-{}", start, end));
+            }
+        }
+        if myself {
+            for (slot, target) in &cluster.migrating {
+                nodes.push_str(&format!(" [{}->-{}]", slot, id_str(target)));
+            }
+            for (slot, source) in &cluster.importing {
+                nodes.push_str(&format!(" [{}-<-{}]", slot, id_str(source)));
+            }
+        }
+        nodes.push('\n');
+    }
+    bulk_string(nodes.as_bytes())
+}
+
+// every range of slots with the node that serves it: start, end, [ip, port, id]
+fn cluster_slots(cluster: &ClusterState) -> RedisResult {
+    let mut ranges = Vec::new();
+    let mut amount_ranges = 0;
+    for node in &cluster.nodes {
+        for (start, end) in slot_ranges(cluster, &node.id) {
+            write!(ranges, "*3\r\n:{}\r\n:{}\r\n*3\r\n", start, end).unwrap();
+            write_bulk(&mut ranges, node.ip.as_bytes());
+            write!(ranges, ":{}\r\n", node.port).unwrap();
+            write_bulk(&mut ranges, &node.id);
+            amount_ranges += 1;
+        }
+    }
+
+    let mut reply = Vec::with_capacity(ranges.len() + 16);
+    Redis::write_array_header(&mut reply, amount_ranges);
+    reply.extend_from_slice(&ranges);
+    RedisResult::BulkString(reply)
+}
+
+// a shard for every master, the ranges of its slots as start and end pairs and the master as the
+// only node in it
+fn cluster_shards(cluster: &ClusterState, offset: i64) -> RedisResult {
+    let masters: Vec<&ClusterNode> = cluster
+        .nodes
+        .iter()
+        .filter(|node| node.flags & FLAG_HANDSHAKE == 0)
+        .collect();
+
+    let mut reply = Vec::new();
+    Redis::write_array_header(&mut reply, masters.len());
+    for node in masters {
+        let ranges = slot_ranges(cluster, &node.id);
+        reply.extend_from_slice(b"*4\r\n$5\r\nslots\r\n");
+        Redis::write_array_header(&mut reply, ranges.len() * 2);
+        for (start, end) in ranges {
+            write!(reply, ":{}\r\n:{}\r\n", start, end).unwrap();
+        }
+
+        // only this node knows its own offset
+        let offset = if node.flags & FLAG_MYSELF != 0 {
+            offset
+        } else {
+            0
+        };
+        let failing = node.flags & (FLAG_PFAIL | FLAG_FAIL) != 0;
+        reply.extend_from_slice(b"$5\r\nnodes\r\n*1\r\n*14\r\n$2\r\nid\r\n");
+        write_bulk(&mut reply, &node.id);
+        write!(reply, "$4\r\nport\r\n:{}\r\n$2\r\nip\r\n", node.port).unwrap();
+        write_bulk(&mut reply, node.ip.as_bytes());
+        reply.extend_from_slice(b"$8\r\nendpoint\r\n");
+        write_bulk(&mut reply, node.ip.as_bytes());
+        write!(
+            reply,
+            "$4\r\nrole\r\n$6\r\nmaster\r\n$18\r\nreplication-offset\r\n:{}\r\n$6\r\nhealth\r\n",
+            offset
+        )
+        .unwrap();
+        write_bulk(&mut reply, if failing { b"failed" } else { b"online" });
+    }
+    RedisResult::BulkString(reply)
+}
+
+// CLUSTER ADDSLOTS, none are added when one of them is taken
+fn add_slots(cluster: &mut ClusterState, slots: &[u16]) -> RedisResult {
+    let mut seen = vec![false; CLUSTER_SLOTS];
+    for &slot in slots {
+        if cluster.slots[slot as usize].is_some() {
+            return error(CommandError::SlotBusy { slot });
+        }
+        if mem::replace(&mut seen[slot as usize], true) {
+            return error(CommandError::SlotSpecifiedMultipleTimes { slot });
+        }
+    }
+
+    for &slot in slots {
+        cluster.slots[slot as usize] = Some(cluster.myself);
+        cluster.importing.remove(&slot);
+    }
+    cluster.update_state();
+    RedisResult::SimpleString(b"+OK\r\n")
+}
+
+fn del_slots(cluster: &mut ClusterState, slots: &[u16]) -> RedisResult {
+    let mut seen = vec![false; CLUSTER_SLOTS];
+    for &slot in slots {
+        if cluster.slots[slot as usize].is_none() {
+            return error(CommandError::SlotUnassigned { slot });
+        }
+        if mem::replace(&mut seen[slot as usize], true) {
+            return error(CommandError::SlotSpecifiedMultipleTimes { slot });
+        }
+    }
+
+    for &slot in slots {
+        cluster.slots[slot as usize] = None;
+        cluster.migrating.remove(&slot);
+        cluster.importing.remove(&slot);
+    }
+    cluster.update_state();
+    RedisResult::SimpleString(b"+OK\r\n")
+}
+
+// the ranges of slots the node serves, start and end included
+fn slot_ranges(cluster: &ClusterState, id: &NodeId) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (slot, owner) in cluster.slots.iter().enumerate() {
+        if *owner != Some(*id) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == slot => *end = slot,
+            _ => ranges.push((slot, slot)),
+        }
+    }
+    ranges
+}
+
+// CLUSTER NODES shows the times as unix time in milliseconds, 0 for never
+fn unix_time_of(instant: Option<Instant>) -> i64 {
+    instant.map_or(0, |instant| {
+        unix_time_ms() - instant.elapsed().as_millis() as i64
+    })
+}
+
+fn random_node_id() -> NodeId {
+    random_replid()
+        .into_bytes()
+        .try_into()
+        .expect("a replid is 40 characters")
+}
+
+fn id_str(id: &NodeId) -> &str {
+    std::str::from_utf8(id).unwrap_or_default()
+}
+
+fn write_bulk(reply: &mut Vec<u8>, bytes: &[u8]) {
+    write!(reply, "${}\r\n", bytes.len()).unwrap();
+    reply.extend_from_slice(bytes);
+    reply.extend_from_slice(b"\r\n");
+}
+
+fn bulk_string(bytes: &[u8]) -> RedisResult {
+    let mut reply = Vec::with_capacity(bytes.len() + 16);
+    write_bulk(&mut reply, bytes);
+    RedisResult::BulkString(reply)
+}
+
+fn error(err: CommandError) -> RedisResult {
+    RedisResult::Error(RedisError::CommandError(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, thread};
+
+    use super::*;
+    use crate::{commands::RestoreOptions, config::Config, redis::resp_or_error};
+
+    fn cluster_node(port: u16, node_timeout: &[u8]) -> Redis {
+        let mut config = Config::new();
+        config.set(b"cluster-enabled", b"yes").unwrap();
+        config.set(b"cluster-node-timeout", node_timeout).unwrap();
+        config.port = port;
+        Redis::with_config(config)
+    }
+
+    fn cluster(redis: &mut Redis, subcommand: ClusterSubcommand) -> Result<Vec<u8>, CommandError> {
+        resp_or_error(redis.execute_command(&RedisCommand::Cluster { subcommand }))
+    }
+
+    fn my_id(redis: &Redis) -> Vec<u8> {
+        redis.cluster.as_ref().unwrap().myself.to_vec()
+    }
+
+    fn info_field(redis: &mut Redis, field: &str) -> String {
+        let info = String::from_utf8(cluster(redis, ClusterSubcommand::Info).unwrap()).unwrap();
+        info.split("\r\n")
+            .find_map(|line| line.strip_prefix(&format!("{}:", field)))
+            .unwrap()
+            .to_string()
+    }
+
+    // nodes that talk over a simulated bus, node i listens on port 7000 + i. A node that is down
+    // gets no messages and its own are lost
+    struct Network {
+        nodes: Vec<Redis>,
+        down: Vec<bool>,
+        // the node and fd at the other end of every link
+        peers: HashMap<(usize, c_int), (usize, c_int)>,
+        next_fd: c_int,
+    }
+
+    impl Network {
+        fn new(amount_nodes: usize, node_timeout: &[u8]) -> Self {
+            Network {
+                nodes: (0..amount_nodes)
+                    .map(|i| cluster_node(7000 + i as u16, node_timeout))
+                    .collect(),
+                down: vec![false; amount_nodes],
+                peers: HashMap::new(),
+                next_fd: 100,
+            }
+        }
+
+        // the first node meets all others
+        fn join(amount_nodes: usize, node_timeout: &[u8]) -> Self {
+            let mut network = Self::new(amount_nodes, node_timeout);
+            for i in 1..amount_nodes {
+                let port = 7000 + i as u16;
+                let subcommand = ClusterSubcommand::Meet {
+                    ip: b"127.0.0.1",
+                    port,
+                    bus_port: None,
+                };
+                cluster(&mut network.nodes[0], subcommand).unwrap();
+            }
+            network.run_until(|network| {
+                network
+                    .nodes
+                    .iter_mut()
+                    .all(|node| info_field(node, "cluster_known_nodes") == amount_nodes.to_string())
+            });
+            network
+        }
+
+        fn cron(&mut self) {
+            for i in 0..self.nodes.len() {
+                if self.down[i] {
+                    continue;
+                }
+                self.nodes[i].cluster_cron();
+
+                let Network {
+                    nodes,
+                    down,
+                    peers,
+                    next_fd,
+                } = self;
+                nodes[i].connect_cluster_links(|_, bus_port| {
+                    let j = (bus_port - BUS_PORT_OFFSET - 7000) as usize;
+                    if down[j] {
+                        return None;
+                    }
+                    let (fd, peer_fd) = (*next_fd, *next_fd + 1);
+                    *next_fd += 2;
+                    peers.insert((i, fd), (j, peer_fd));
+                    peers.insert((j, peer_fd), (i, fd));
+                    Some(fd)
+                });
+            }
+        }
+
+        // delivers messages until nothing is sent anymore
+        fn deliver(&mut self) {
+            loop {
+                let mut delivered = false;
+                for i in 0..self.nodes.len() {
+                    for fd in self.nodes[i].take_dropped_cluster_links() {
+                        if let Some((j, peer_fd)) = self.peers.remove(&(i, fd)) {
+                            self.peers.remove(&(j, peer_fd));
+                            self.nodes[j].cluster_link_closed(peer_fd);
+                        }
+                    }
+
+                    for (fd, message) in self.nodes[i].take_cluster_messages() {
+                        let Some(&(j, peer_fd)) = self.peers.get(&(i, fd)) else {
+                            continue;
+                        };
+                        if self.down[i] || self.down[j] {
+                            continue;
+                        }
+                        self.nodes[j]
+                            .process_cluster_message(peer_fd, "127.0.0.1", "127.0.0.1", &message)
+                            .unwrap();
+                        delivered = true;
+                    }
+                }
+                if !delivered {
+                    break;
+                }
+            }
+        }
+
+        fn run_until(&mut self, mut done: impl FnMut(&mut Network) -> bool) {
+            for _ in 0..100 {
+                self.cron();
+                self.deliver();
+                if done(self) {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            panic!("the cluster didn't get there in time");
+        }
+
+        fn add_slots(&mut self, node: usize, slots: std::ops::Range<u16>) {
+            let slots = slots.collect();
+            cluster(&mut self.nodes[node], ClusterSubcommand::AddSlots { slots }).unwrap();
+        }
+
+        fn state_ok(&mut self, node: usize) -> bool {
+            info_field(&mut self.nodes[node], "cluster_state") == "ok"
+        }
+    }
+
+    #[test]
+    fn test_key_hash_slot() {
+        struct TestData {
+            key: &'static [u8],
+            expected: u16,
+        }
+
+        let tests = vec![
+            TestData {
+                key: b"foo",
+                expected: 12182,
+            },
+            TestData {
+                key: b"somekey",
+                expected: 11058,
+            },
+            TestData {
+                key: b"hello",
+                expected: 866,
+            },
+            // only the tag is hashed
+            TestData {
+                key: b"foo{hash_tag}",
+                expected: 2515,
+            },
+            TestData {
+                key: b"bar{hash_tag}",
+                expected: 2515,
+            },
+            TestData {
+                key: b"{foo}bar",
+                expected: 12182,
+            },
+            // the first { and the first } after it
+            TestData {
+                key: b"x{foo}{bar}",
+                expected: 12182,
+            },
+            // an empty or unclosed tag hashes the whole key
+            TestData {
+                key: b"{}foo",
+                expected: key_hash_slot_whole(b"{}foo"),
+            },
+            TestData {
+                key: b"{foo",
+                expected: key_hash_slot_whole(b"{foo"),
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                test.expected,
+                key_hash_slot(test.key),
+                "{}",
+                String::from_utf8_lossy(test.key)
+            );
+        }
+    }
+
+    const fn key_hash_slot_whole(key: &[u8]) -> u16 {
+        // the crc of the whole key, computed bit by bit to not depend on key_hash_slot
+        let mut crc = 0u16;
+        let mut i = 0;
+        while i < key.len() {
+            crc ^= (key[i] as u16) << 8;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 0x8000 != 0 {
+                    (crc << 1) ^ 0x1021
+                } else {
+                    crc << 1
+                };
+                bit += 1;
+            }
+            i += 1;
+        }
+        crc & (CLUSTER_SLOTS as u16 - 1)
+    }
+
+    #[test]
+    fn test_message_encoding() {
+        let mut slots = vec![0u8; CLUSTER_SLOTS / 8];
+        slots[0] = 0b101;
+        let messages = vec![
+            Message {
+                kind: MessageKind::Ping,
+                current_epoch: 7,
+                config_epoch: 3,
+                sender: [b'a'; ID_LEN],
+                port: 7000,
+                bus_port: 17000,
+                flags: FLAG_MASTER,
+                slots: slots.clone(),
+                gossip: vec![Gossip {
+                    id: [b'b'; ID_LEN],
+                    ip: "127.0.0.1".to_string(),
+                    port: 7001,
+                    bus_port: 17001,
+                    flags: FLAG_MASTER | FLAG_PFAIL,
+                }],
+                failing: None,
+                update: None,
+            },
+            Message {
+                kind: MessageKind::Fail,
+                current_epoch: 1,
+                config_epoch: 1,
+                sender: [b'c'; ID_LEN],
+                port: 7002,
+                bus_port: 17002,
+                flags: FLAG_MASTER,
+                slots: slots.clone(),
+                gossip: Vec::new(),
+                failing: Some([b'd'; ID_LEN]),
+                update: None,
+            },
+            Message {
+                kind: MessageKind::Update,
+                current_epoch: 9,
+                config_epoch: 2,
+                sender: [b'e'; ID_LEN],
+                port: 7003,
+                bus_port: 17003,
+                flags: FLAG_MASTER,
+                slots: vec![0u8; CLUSTER_SLOTS / 8],
+                gossip: Vec::new(),
+                failing: None,
+                update: Some(SlotsUpdate {
+                    owner: [b'f'; ID_LEN],
+                    config_epoch: 8,
+                    slots,
+                }),
+            },
+        ];
+
+        for message in messages {
+            let encoded = message.encode();
+            assert_eq!(Ok(None), message_len(&encoded[..7]).map_err(|_| ()));
+            assert_eq!(
+                Ok(None),
+                message_len(&encoded[..encoded.len() - 1]).map_err(|_| ())
+            );
+            assert_eq!(
+                Ok(Some(encoded.len())),
+                message_len(&encoded).map_err(|_| ())
+            );
+            assert_eq!(Some(message), Message::decode(&encoded));
+        }
+
+        assert!(message_len(b"*1\r\n$4\r\nPING\r\n").is_err());
+        let mut too_short = b"RCmb".to_vec();
+        too_short.extend_from_slice(&10u32.to_be_bytes());
+        assert!(message_len(&too_short).is_err());
+
+        // ids are hex
+        let mut message = Network::new(1, b"15000").nodes[0]
+            .cluster
+            .as_ref()
+            .unwrap()
+            .build_message(MessageKind::Ping, None)
+            .encode();
+        assert!(Message::decode(&message).is_some());
+        message[40] = b'z';
+        assert!(Message::decode(&message).is_none());
+    }
+
+    #[test]
+    fn test_cluster_commands() {
+        let mut redis = Redis::new();
+        assert_eq!(
+            Err(CommandError::ClusterDisabled),
+            cluster(&mut redis, ClusterSubcommand::Info)
+        );
+
+        let mut redis = cluster_node(7000, b"15000");
+        let id = my_id(&redis);
+        assert_eq!("fail", info_field(&mut redis, "cluster_state"));
+
+        struct TestData {
+            subcommand: ClusterSubcommand<'static>,
+            expected: Result<Vec<u8>, CommandError>,
+        }
+
+        let tests = vec![
+            TestData {
+                subcommand: ClusterSubcommand::KeySlot { key: b"somekey" },
+                expected: Ok(b":11058\r\n".to_vec()),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::AddSlots {
+                    slots: vec![1, 2, 1],
+                },
+                expected: Err(CommandError::SlotSpecifiedMultipleTimes { slot: 1 }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::DelSlots { slots: vec![5] },
+                expected: Err(CommandError::SlotUnassigned { slot: 5 }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::AddSlots {
+                    slots: vec![0, 1, 2, 5],
+                },
+                expected: Ok(b"+OK\r\n".to_vec()),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::AddSlots { slots: vec![2] },
+                expected: Err(CommandError::SlotBusy { slot: 2 }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::DelSlots { slots: vec![1] },
+                expected: Ok(b"+OK\r\n".to_vec()),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::Meet {
+                    ip: b"localhost",
+                    port: 7001,
+                    bus_port: None,
+                },
+                expected: Err(CommandError::InvalidNodeAddress {
+                    address: b"localhost:7001".to_vec(),
+                }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::SetSlot {
+                    slot: 3,
+                    state: SetSlotState::Migrating(b"0000000000000000000000000000000000000000"),
+                },
+                expected: Err(CommandError::NotSlotOwner { slot: 3 }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::SetSlot {
+                    slot: 0,
+                    state: SetSlotState::Importing(b"0000000000000000000000000000000000000000"),
+                },
+                expected: Err(CommandError::AlreadySlotOwner { slot: 0 }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::SetSlot {
+                    slot: 0,
+                    state: SetSlotState::Migrating(b"0000000000000000000000000000000000000000"),
+                },
+                expected: Err(CommandError::UnknownNode {
+                    id: b"0000000000000000000000000000000000000000".to_vec(),
+                }),
+            },
+            TestData {
+                subcommand: ClusterSubcommand::CountKeysInSlot { slot: 12182 },
+                expected: Ok(b":0\r\n".to_vec()),
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                test.expected,
+                cluster(&mut redis, test.subcommand),
+                "{:?}",
+                test.expected
+            );
+        }
+
+        // the ip is only known once another node told this one
+        let mut expected = b"*3\r\n".to_vec();
+        for slot in [0, 2, 5] {
+            let range = format!("*3\r\n:{slot}\r\n:{slot}\r\n*3\r\n$0\r\n\r\n:7000\r\n$40\r\n");
+            expected.extend_from_slice(range.as_bytes());
+            expected.extend_from_slice(&id);
+            expected.extend_from_slice(b"\r\n");
+        }
+        assert_eq!(Ok(expected), cluster(&mut redis, ClusterSubcommand::Slots));
+
+        // an empty slot can't be given away, it is then the last slot of the node
+        redis.execute_command(&RedisCommand::Set {
+            key: b"{a}1",
+            value: b"v",
+        });
+        let slot_of_a = key_hash_slot(b"a");
+        cluster(
+            &mut redis,
+            ClusterSubcommand::AddSlots {
+                slots: vec![slot_of_a],
+            },
+        )
+        .unwrap();
+        let nodes =
+            String::from_utf8(cluster(&mut redis, ClusterSubcommand::Nodes).unwrap()).unwrap();
+        assert!(
+            nodes.contains(&format!(
+                "myself,master - 0 0 0 connected 0 2 5 {}\n",
+                slot_of_a
+            )),
+            "{}",
+            nodes
+        );
+        assert_eq!(
+            Ok(b":1\r\n".to_vec()),
+            cluster(
+                &mut redis,
+                ClusterSubcommand::CountKeysInSlot { slot: slot_of_a }
+            )
+        );
+
+        for slot in 0..CLUSTER_SLOTS as u16 {
+            let cluster = redis.cluster.as_mut().unwrap();
+            cluster.slots[slot as usize] = Some(cluster.myself);
+        }
+        redis.cluster.as_mut().unwrap().update_state();
+        assert_eq!("ok", info_field(&mut redis, "cluster_state"));
+        assert_eq!("16384", info_field(&mut redis, "cluster_slots_assigned"));
+        assert_eq!("1", info_field(&mut redis, "cluster_size"));
+
+        // a cluster node only has db 0
+        assert_eq!(
+            Err(CommandError::NotAllowedInClusterMode {
+                cmd: b"select".to_vec()
+            }),
+            resp_or_error(redis.execute_command(&RedisCommand::Select { index: 1 }))
+        );
+        assert!(resp_or_error(redis.execute_command(&RedisCommand::Select { index: 0 })).is_ok());
+    }
+
+    #[test]
+    fn test_slot_keys() {
+        let mut redis = cluster_node(7000, b"15000");
+        let slot = key_hash_slot(b"foo");
+        let count = |redis: &mut Redis, slot| {
+            cluster(redis, ClusterSubcommand::CountKeysInSlot { slot }).unwrap()
+        };
+        let keys = |redis: &mut Redis, count| {
+            cluster(redis, ClusterSubcommand::GetKeysInSlot { slot, count }).unwrap()
+        };
+
+        for key in [&b"foo"[..], b"{foo}a", b"{foo}b", b"bar"] {
+            redis.execute_command(&RedisCommand::Set { key, value: b"v" });
+        }
+        // an overwrite is not a new key
+        redis.execute_command(&RedisCommand::Set {
+            key: b"foo",
+            value: b"w",
+        });
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"{foo}list",
+            value: b"v",
+        });
+        assert_eq!(b":4\r\n".to_vec(), count(&mut redis, slot));
+        assert_eq!(
+            b"*2\r\n$3\r\nfoo\r\n$6\r\n{foo}a\r\n".to_vec(),
+            keys(&mut redis, 2)
+        );
+
+        redis.execute_command(&RedisCommand::Del {
+            keys: vec![b"foo", b"missing"],
+        });
+        redis.execute_command(&RedisCommand::Rename {
+            key: b"{foo}a",
+            new_key: b"bar",
+        });
+        assert_eq!(b":2\r\n".to_vec(), count(&mut redis, slot));
+        assert_eq!(b":1\r\n".to_vec(), count(&mut redis, key_hash_slot(b"bar")));
+        assert_eq!(
+            b"*2\r\n$6\r\n{foo}b\r\n$9\r\n{foo}list\r\n".to_vec(),
+            keys(&mut redis, 10)
+        );
+
+        // the index is emptied with the keyspace but kept
+        redis.execute_command(&RedisCommand::FlushAll { lazy: false });
+        assert_eq!(b":0\r\n".to_vec(), count(&mut redis, slot));
+        redis.execute_command(&RedisCommand::Set {
+            key: b"foo",
+            value: b"v",
+        });
+        assert_eq!(b":1\r\n".to_vec(), count(&mut redis, slot));
+    }
+
+    #[test]
+    fn test_cluster_redirect() {
+        let mut network = Network::join(2, b"15000");
+        network.add_slots(0, 0..8192);
+        network.add_slots(1, 8192..CLUSTER_SLOTS as u16);
+        network.run_until(|network| network.state_ok(0) && network.state_ok(1));
+        let ids: Vec<Vec<u8>> = network.nodes.iter().map(my_id).collect();
+
+        // foo is in slot 12182 of the second node, hello in slot 866 of the first one
+        let get = RedisCommand::Get { key: b"foo" };
+        let redirect =
+            |redis: &Redis, command: &RedisCommand, asking| redis.cluster_redirect(command, asking);
+        let first = &network.nodes[0];
+        assert_eq!(
+            Err(CommandError::Moved {
+                slot: 12182,
+                address: "127.0.0.1:7001".to_string()
+            }),
+            redirect(first, &get, false)
+        );
+        assert_eq!(
+            Ok(()),
+            redirect(first, &RedisCommand::Get { key: b"hello" }, false)
+        );
+        assert_eq!(
+            Err(CommandError::CrossSlot),
+            redirect(
+                first,
+                &RedisCommand::Del {
+                    keys: vec![b"foo", b"hello"]
+                },
+                false
+            )
+        );
+        assert_eq!(Ok(()), redirect(first, &RedisCommand::DbSize, false));
+
+        // slot 12182 moves to the first node
+        let importing = ClusterSubcommand::SetSlot {
+            slot: 12182,
+            state: SetSlotState::Importing(&ids[1]),
+        };
+        let migrating = ClusterSubcommand::SetSlot {
+            slot: 12182,
+            state: SetSlotState::Migrating(&ids[0]),
+        };
+        cluster(&mut network.nodes[0], importing).unwrap();
+        cluster(&mut network.nodes[1], migrating).unwrap();
+        network.nodes[1].execute_command(&RedisCommand::Set {
+            key: b"foo",
+            value: b"bar",
+        });
+
+        let (first, second) = (&network.nodes[0], &network.nodes[1]);
+        let ask = Err(CommandError::Ask {
+            slot: 12182,
+            address: "127.0.0.1:7000".to_string(),
+        });
+        assert_eq!(Ok(()), redirect(second, &get, false));
+        assert_eq!(
+            ask,
+            redirect(second, &RedisCommand::Get { key: b"{foo}x" }, false)
+        );
+        assert_eq!(
+            Err(CommandError::TryAgain),
+            redirect(
+                second,
+                &RedisCommand::Del {
+                    keys: vec![b"foo", b"{foo}x"]
+                },
+                false
+            )
+        );
+        assert!(matches!(
+            redirect(first, &get, false),
+            Err(CommandError::Moved { slot: 12182, .. })
+        ));
+        assert_eq!(Ok(()), redirect(first, &get, true));
+        let restore = RedisCommand::Restore {
+            key: b"foo",
+            ttl: 0,
+            payload: b"",
+            options: RestoreOptions {
+                asking: true,
+                ..Default::default()
+            },
+        };
+        assert_eq!(Ok(()), redirect(first, &restore, false));
+
+        // the slot goes to the first node with a new config epoch, the second one learns about it
+        // from the gossip and drops its keys
+        network.nodes[1].execute_command(&RedisCommand::Del { keys: vec![b"foo"] });
+        let node = ClusterSubcommand::SetSlot {
+            slot: 12182,
+            state: SetSlotState::Node(&ids[0]),
+        };
+        cluster(&mut network.nodes[0], node).unwrap();
+        network.run_until(|network| {
+            network.nodes[1].cluster.as_ref().unwrap().slots[12182]
+                == Some(ids[0][..].try_into().unwrap())
+        });
+        assert_eq!(Ok(()), redirect(&network.nodes[0], &get, false));
+        assert_eq!(
+            Err(CommandError::Moved {
+                slot: 12182,
+                address: "127.0.0.1:7000".to_string()
+            }),
+            redirect(&network.nodes[1], &get, false)
+        );
+        let epochs: Vec<u64> = (0..2)
+            .map(|i| {
+                info_field(&mut network.nodes[i], "cluster_my_epoch")
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        assert!(epochs[0] > epochs[1], "{:?}", epochs);
+    }
+
+    #[test]
+    fn test_cluster_update() {
+        let mut network = Network::join(3, b"100");
+        network.add_slots(0, 0..5000);
+        network.add_slots(1, 5000..10000);
+        network.add_slots(2, 10000..CLUSTER_SLOTS as u16);
+        network.run_until(|network| (0..3).all(|i| network.state_ok(i)));
+        let ids: Vec<NodeId> = network
+            .nodes
+            .iter()
+            .map(|node| node.cluster.as_ref().unwrap().myself)
+            .collect();
+
+        // slot 12182 moves from the third node to the second one
+        for (i, state) in [
+            (1, SetSlotState::Importing(&ids[2])),
+            (2, SetSlotState::Migrating(&ids[1])),
+            (1, SetSlotState::Node(&ids[1])),
+            (2, SetSlotState::Node(&ids[1])),
+        ] {
+            let subcommand = ClusterSubcommand::SetSlot { slot: 12182, state };
+            cluster(&mut network.nodes[i], subcommand).unwrap();
+        }
+
+        // a collision raised the config epoch of the third node above the new one of the second
+        // node before the first node heard about the move, the claim of the second node loses
+        let epoch = network.nodes[1]
+            .cluster
+            .as_ref()
+            .unwrap()
+            .myself()
+            .config_epoch
+            + 5;
+        for i in [0, 2] {
+            let cluster = network.nodes[i].cluster.as_mut().unwrap();
+            cluster.current_epoch = epoch;
+            let index = cluster.node_index(&ids[2]).unwrap();
+            cluster.nodes[index].config_epoch = epoch;
+        }
+
+        // the first node sends an UPDATE and the second node takes a config epoch above it
+        network.run_until(|network| {
+            network.nodes[0].cluster.as_ref().unwrap().slots[12182] == Some(ids[1])
+        });
+        let second = network.nodes[1].cluster.as_ref().unwrap();
+        assert!(second.myself().config_epoch > epoch);
+        assert_eq!(Some(ids[1]), second.slots[12182]);
+        assert_eq!(
+            Some(ids[1]),
+            network.nodes[2].cluster.as_ref().unwrap().slots[12182]
+        );
+    }
+
+    #[test]
+    fn test_cluster_failure_detection() {
+        let mut network = Network::join(3, b"100");
+        network.add_slots(0, 0..5000);
+        network.add_slots(1, 5000..10000);
+        network.add_slots(2, 10000..CLUSTER_SLOTS as u16);
+        network.run_until(|network| (0..3).all(|i| network.state_ok(i)));
+
+        // the two nodes that are left are a majority
+        network.down[2] = true;
+        network.run_until(|network| !network.state_ok(0) && !network.state_ok(1));
+        for i in 0..2 {
+            assert_eq!(
+                "10000",
+                info_field(&mut network.nodes[i], "cluster_slots_ok")
+            );
+            assert_eq!(
+                "6384",
+                info_field(&mut network.nodes[i], "cluster_slots_fail")
+            );
+        }
+
+        // a master with slots is taken back a while after it answers again
+        network.down[2] = false;
+        network.run_until(|network| (0..3).all(|i| network.state_ok(i)));
+        assert_eq!("0", info_field(&mut network.nodes[0], "cluster_slots_fail"));
+    }
+}
@@ -0,0 +1,64 @@
+// the crc16 redis cluster hashes keys with, CCITT XMODEM: the polynomial 0x1021, a zero initial
+// value, no reflection and no final xor
+const POLY: u16 = 0x1021;
+
+static TABLE: [u16; 256] = make_table();
+
+const fn make_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ POLY
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc = (crc << 8) ^ TABLE[((crc >> 8) as u8 ^ byte) as usize];
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        struct TestData {
+            bytes: &'static [u8],
+            expected: u16,
+        }
+
+        // the check value from the cluster spec of redis
+        let tests = vec![
+            TestData {
+                bytes: b"",
+                expected: 0,
+            },
+            TestData {
+                bytes: b"123456789",
+                expected: 0x31c3,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(test.expected, crc16(test.bytes));
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::{
+    error::{CommandError, RedisError},
+    redis::{
+        Dict, Redis, RedisResult,
+        cluster::SlotKeys,
+        hash_table::{HashDict, HashNode},
+        redis_object::{Access, RedisObject},
+    },
+};
+
+// unix time in milliseconds each key with a ttl expires at
+pub(crate) type Expires = HashDict<Box<[u8]>, i64>;
+
+type KeyNode = HashNode<Box<[u8]>, RedisObject, Access>;
+
+// a logical database, like redisDb in redis the keyspace and the expire times of the keys that
+// have a ttl. A key is only ever in expires while it is in the dict as well
+pub(crate) struct Db {
+    pub(super) dict: Dict,
+    pub(super) expires: Expires,
+    // where the active expire cycle continues scanning the expires
+    pub(super) expires_cursor: u64,
+    // the keys of every hash slot, only db 0 of a cluster node has them
+    pub(super) slot_keys: Option<SlotKeys>,
+    // bytes of the entries, keys and values of the dict and of the entries of expires, kept up
+    // to date on every change so MEMORY STATS doesn't have to walk the keyspace
+    pub(super) dataset_bytes: usize,
+    pub(super) expires_bytes: usize,
+}
+
+impl Db {
+    pub(super) fn new() -> Self {
+        Db {
+            dict: Dict::default(),
+            expires: Expires::new(),
+            expires_cursor: 0,
+            slot_keys: None,
+            dataset_bytes: 0,
+            expires_bytes: 0,
+        }
+    }
+
+    // inserts or overwrites the key, an overwritten key loses its old ttl
+    pub(super) fn insert(&mut self, node: Box<KeyNode>, expire: Option<i64>) {
+        match expire {
+            Some(when) => self.set_expire(node.key(), when),
+            None => {
+                self.remove_expire(node.key());
+            }
+        }
+        match self.dict.peek(node.key()) {
+            Some(old) => self.dataset_bytes -= self.dict.entry_size(node.key()) + old.heap_usage(),
+            None => {
+                if let Some(slot_keys) = &mut self.slot_keys {
+                    slot_keys.add(node.key());
+                }
+            }
+        }
+        self.dataset_bytes += self.dict.entry_size(node.key()) + node.value.heap_usage();
+        self.dict.insert(node);
+    }
+
+    // changes the value of the key in place, what it allocated or freed is accounted for. None
+    // for a missing key
+    pub(super) fn modify<R>(
+        &mut self,
+        key: &[u8],
+        f: impl FnOnce(&mut RedisObject) -> R,
+    ) -> Option<R> {
+        let value = self.dict.lookup_mut(key)?;
+        let before = value.heap_usage();
+        let result = f(value);
+        self.dataset_bytes = self.dataset_bytes - before + value.heap_usage();
+        Some(result)
+    }
+
+    // takes the key out together with its expire time
+    pub(super) fn remove(&mut self, key: &[u8]) -> Option<(Box<KeyNode>, Option<i64>)> {
+        let node = self.dict.remove(key)?;
+        self.dataset_bytes -= self.dict.entry_size(key) + node.value.heap_usage();
+        if let Some(slot_keys) = &mut self.slot_keys {
+            slot_keys.remove(key);
+        }
+        Some((node, self.remove_expire(key)))
+    }
+
+    pub(super) fn delete(&mut self, key: &[u8]) -> bool {
+        self.remove(key).is_some()
+    }
+
+    pub(super) fn expire(&self, key: &[u8]) -> Option<i64> {
+        self.expires.peek(key).copied()
+    }
+
+    pub(super) fn set_expire(&mut self, key: &[u8], when: i64) {
+        match self.expires.lookup_mut(key) {
+            Some(expire) => *expire = when,
+            None => {
+                self.expires_bytes += self.expires.entry_size(key);
+                self.expires
+                    .insert(Box::new(HashNode::new(Box::from(key), when)));
+            }
+        }
+    }
+
+    pub(super) fn remove_expire(&mut self, key: &[u8]) -> Option<i64> {
+        // most keyspaces have no ttls at all, this keeps SET from hashing the key a second time
+        if self.expires.is_empty() {
+            return None;
+        }
+        let node = self.expires.remove(key)?;
+        self.expires_bytes -= self.expires.entry_size(key);
+        Some(node.value)
+    }
+
+    pub(super) fn is_expired(&self, key: &[u8], now: i64) -> bool {
+        self.expire(key).is_some_and(|when| when <= now)
+    }
+
+    // deletes the key when its ttl ran out. Returns whether it did, see Redis::expire_if_needed
+    // for the one that also propagates the deletion
+    pub(super) fn expire_if_needed(&mut self, key: &[u8], now: i64) -> bool {
+        if !self.is_expired(key, now) {
+            return false;
+        }
+        self.delete(key)
+    }
+
+    // a cluster node keeps an empty index of the slots
+    pub(super) fn flush(&mut self) {
+        let slot_keys = self.slot_keys.as_ref().map(|_| SlotKeys::new());
+        *self = Db {
+            slot_keys,
+            ..Db::new()
+        };
+    }
+}
+
+impl Redis {
+    pub(super) fn select(&mut self, index: usize) -> RedisResult {
+        // a cluster node only has db 0
+        if self.cluster_enabled() && index != 0 {
+            return not_allowed_in_cluster_mode(b"select");
+        }
+        if index >= self.dbs.len() {
+            return db_index_out_of_range();
+        }
+
+        self.db = index;
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+
+    // connections keep the index they selected, so they see the data of the other database
+    // right away
+    pub(super) fn swap_db(&mut self, index1: usize, index2: usize) -> RedisResult {
+        if self.cluster_enabled() {
+            return not_allowed_in_cluster_mode(b"swapdb");
+        }
+        if index1 >= self.dbs.len() || index2 >= self.dbs.len() {
+            return db_index_out_of_range();
+        }
+
+        self.dbs.swap(index1, index2);
+        self.rdb.dirty += 1;
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+
+    // the node is moved over like in RENAME and keeps its ttl, a key that already exists in the
+    // other database is left alone
+    pub(super) fn move_key(&mut self, key: &[u8], db: usize) -> RedisResult {
+        if self.cluster_enabled() {
+            return not_allowed_in_cluster_mode(b"move");
+        }
+        if db >= self.dbs.len() {
+            return db_index_out_of_range();
+        }
+        if db == self.db {
+            return RedisResult::Error(RedisError::CommandError(CommandError::SameObject));
+        }
+
+        self.expire_if_needed(db, key, unix_time_ms());
+        if self.dbs[db].dict.peek(key).is_some() {
+            return RedisResult::Int(0);
+        }
+
+        match self.dbs[self.db].remove(key) {
+            Some((node, expire)) => {
+                self.dbs[db].insert(node, expire);
+                self.rdb.dirty += 1;
+                RedisResult::Int(1)
+            }
+            None => RedisResult::Int(0),
+        }
+    }
+
+    // there is no background thread to free the old dict on, so ASYNC frees it inline as well.
+    // Every deleted key is a change, and like in redis flushing empty databases is one too so
+    // the aof and the replicas still get it
+    pub(super) fn flush_db(&mut self, _lazy: bool) -> RedisResult {
+        self.rdb.dirty += self.dbs[self.db].dict.len() as u64 + 1;
+        self.dbs[self.db].flush();
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+
+    pub(super) fn flush_all(&mut self, _lazy: bool) -> RedisResult {
+        for db in &mut self.dbs {
+            self.rdb.dirty += db.dict.len() as u64;
+            db.flush();
+        }
+        self.rdb.dirty += 1;
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+}
+
+pub(super) fn unix_time_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_millis() as i64
+}
+
+fn db_index_out_of_range() -> RedisResult {
+    RedisResult::Error(RedisError::CommandError(CommandError::DbIndexOutOfRange))
+}
+
+fn not_allowed_in_cluster_mode(cmd: &[u8]) -> RedisResult {
+    RedisResult::Error(RedisError::CommandError(
+        CommandError::NotAllowedInClusterMode { cmd: cmd.to_vec() },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commands::RedisCommand, config::Config, redis::resp_or_error};
+
+    fn reply(
+        redis: &mut Redis,
+        db: &mut usize,
+        command: RedisCommand,
+    ) -> Result<Vec<u8>, CommandError> {
+        resp_or_error(redis.execute_command_in_db(db, &command))
+    }
+
+    #[test]
+    fn test_databases() {
+        let mut config = Config::new();
+        config.databases = 4;
+        let mut redis = Redis::with_config(config);
+
+        // the selected databases of two connections
+        let mut first = 0;
+        let mut second = 0;
+
+        struct TestData {
+            second_connection: bool,
+            command: RedisCommand<'static>,
+            expected: Result<&'static [u8], CommandError>,
+        }
+
+        // runs in order, every command sees what the ones before it did
+        let tests = vec![
+            TestData {
+                second_connection: false,
+                command: RedisCommand::Set {
+                    key: b"key",
+                    value: b"zero",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Select { index: 4 },
+                expected: Err(CommandError::DbIndexOutOfRange),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Select { index: 1 },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Get { key: b"key" },
+                expected: Ok(b"$-1\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Set {
+                    key: b"key",
+                    value: b"one",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::Get { key: b"key" },
+                expected: Ok(b"$4\r\nzero\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::Set {
+                    key: b"other",
+                    value: b"zero",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::DbSize,
+                expected: Ok(b":2\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::DbSize,
+                expected: Ok(b":1\r\n"),
+            },
+            // both connections keep their index and see the data of the other one
+            TestData {
+                second_connection: false,
+                command: RedisCommand::SwapDb {
+                    index1: 0,
+                    index2: 1,
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::Get { key: b"key" },
+                expected: Ok(b"$3\r\none\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Get { key: b"key" },
+                expected: Ok(b"$4\r\nzero\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::SwapDb {
+                    index1: 0,
+                    index2: 4,
+                },
+                expected: Err(CommandError::DbIndexOutOfRange),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Move { key: b"key", db: 1 },
+                expected: Err(CommandError::SameObject),
+            },
+            // the key already exists in database 0
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Move { key: b"key", db: 0 },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Move {
+                    key: b"other",
+                    db: 2,
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Move {
+                    key: b"missing",
+                    db: 2,
+                },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::Exists {
+                    keys: vec![b"other"],
+                },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::Info {
+                    sections: vec![b"keyspace"],
+                },
+                expected: Ok(
+                    b"$78\r\n# Keyspace\r\ndb0:keys=1,expires=0\r\ndb1:keys=1,expires=0\r\n\
+                    db2:keys=1,expires=0\r\n\r\n",
+                ),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::FlushDb { lazy: false },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: true,
+                command: RedisCommand::DbSize,
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::DbSize,
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::FlushAll { lazy: true },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                second_connection: false,
+                command: RedisCommand::Info {
+                    sections: vec![b"keyspace"],
+                },
+                expected: Ok(b"$12\r\n# Keyspace\r\n\r\n"),
+            },
+        ];
+
+        for test in tests {
+            let db = if test.second_connection {
+                &mut second
+            } else {
+                &mut first
+            };
+            let expected = test.expected.map(|reply| reply.to_vec());
+            assert_eq!(expected, reply(&mut redis, db, test.command));
+        }
+    }
+}
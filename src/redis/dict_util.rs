@@ -0,0 +1,68 @@
+use std::{
+    cell::Cell,
+    hash::{BuildHasher, RandomState},
+};
+
+// helpers shared by HashDict and SwissDict, the two dicts differ in how they store their entries
+// but scan, sample and check their iterators the same way
+
+// entries sampled by random_entry to pick one from, same as GETFAIR_NUM_ENTRIES in redis
+pub(super) const FAIR_RANDOM_SAMPLES: usize = 15;
+
+// xorshift generator for picking random buckets, a cell so sampling can happen through a shared
+// borrow of the dict
+pub(super) struct Rng(Cell<u64>);
+
+impl Rng {
+    pub(super) fn new() -> Self {
+        // xorshift gets stuck on a zero state
+        Rng(Cell::new(RandomState::new().hash_one(0u64) | 1))
+    }
+
+    // the same draws on every run
+    #[cfg(test)]
+    pub(super) fn with_seed(seed: u64) -> Self {
+        Rng(Cell::new(seed | 1))
+    }
+
+    pub(super) fn next(&self) -> u64 {
+        let mut x = self.0.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0.set(x);
+        x
+    }
+
+    // a uniformly picked entry of a sample, None if the sample is empty
+    pub(super) fn pick<E: Copy>(&self, entries: &[E]) -> Option<E> {
+        if entries.is_empty() {
+            return None;
+        }
+        Some(entries[self.next() as usize % entries.len()])
+    }
+}
+
+// sets the bits above the mask so the increment carries straight into the masked bits, then
+// increments the reversed cursor
+pub(super) fn next_scan_cursor(cursor: u64, mask: u64) -> u64 {
+    (cursor | !mask)
+        .reverse_bits()
+        .wrapping_add(1)
+        .reverse_bits()
+}
+
+// the table pointers, sizes, used counts and resizing position of a dict mixed into one number,
+// same integer mix as the dict fingerprint in redis
+pub(super) fn fingerprint(parts: [u64; 7]) -> u64 {
+    parts.iter().fold(0u64, |hash, &part| {
+        let mut hash = hash.wrapping_add(part);
+        hash = (!hash).wrapping_add(hash << 21);
+        hash ^= hash >> 24;
+        hash = hash.wrapping_add(hash << 3).wrapping_add(hash << 8);
+        hash ^= hash >> 14;
+        hash = hash.wrapping_add(hash << 2).wrapping_add(hash << 4);
+        hash ^= hash >> 28;
+        hash.wrapping_add(hash << 31)
+    })
+}
@@ -0,0 +1,338 @@
+use std::io::Write;
+
+use crate::{
+    commands::RestoreOptions,
+    error::{CommandError, RdbError, RedisError},
+    rdb,
+    redis::{
+        Redis, RedisResult, db::unix_time_ms, hash_table::HashNode, redis_object::Access,
+        ziplist::ValidationMode,
+    },
+};
+
+impl Redis {
+    // the value in the format of the rdb file followed by the rdb version and a crc64, so it can
+    // be given to RESTORE on this or a redis server
+    pub(super) fn dump(&self, key: &[u8]) -> RedisResult {
+        match self.dbs[self.db].dict.peek(key) {
+            Some(value) => {
+                let payload = rdb::dump(value);
+                let mut reply = Vec::with_capacity(payload.len() + 16);
+                write!(reply, "${}\r\n", payload.len()).unwrap();
+                reply.extend_from_slice(&payload);
+                reply.extend_from_slice(b"\r\n");
+                RedisResult::BulkString(reply)
+            }
+            None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+        }
+    }
+
+    // the payload is checked before anything is inserted, a corrupted payload must never end up
+    // in the keyspace. Any client can send one and the list accessors trust the entries they
+    // walk, so its ziplists and listpacks are always checked deep whatever sanitize-dump-payload
+    // says
+    pub(super) fn restore(
+        &mut self,
+        key: &[u8],
+        ttl: i64,
+        payload: &[u8],
+        options: &RestoreOptions,
+    ) -> RedisResult {
+        let db = &mut self.dbs[self.db];
+        if !options.replace && db.dict.peek(key).is_some() {
+            return RedisResult::Error(RedisError::CommandError(CommandError::BusyKey));
+        }
+        if ttl < 0 {
+            return RedisResult::Error(RedisError::CommandError(CommandError::InvalidTtl));
+        }
+
+        let value = match rdb::restore(payload, ValidationMode::Deep) {
+            Ok(value) => value,
+            Err(
+                RdbError::MissingFooter
+                | RdbError::UnsupportedVersion { .. }
+                | RdbError::ChecksumMismatch { .. },
+            ) => {
+                return RedisResult::Error(RedisError::CommandError(
+                    CommandError::InvalidDumpPayload,
+                ));
+            }
+            Err(_) => {
+                return RedisResult::Error(RedisError::CommandError(CommandError::BadDataFormat));
+            }
+        };
+
+        let now = unix_time_ms();
+        let expire = match ttl {
+            0 => None,
+            ttl if options.absttl => Some(ttl),
+            ttl => Some(now.saturating_add(ttl)),
+        };
+
+        // like redis a key restored already expired only removes the one it replaces
+        if expire.is_some_and(|expire| expire <= now) {
+            if db.delete(key) {
+                self.rdb.dirty += 1;
+            }
+            return RedisResult::SimpleString(b"+OK\r\n");
+        }
+
+        let mut node = HashNode::new_from_object(key, value);
+        if let Some(idle_time) = options.idle_time {
+            node.set_metadata(Access::with_idle_time(idle_time));
+        } else if let Some(freq) = options.freq {
+            node.set_metadata(Access::with_frequency(freq));
+        }
+        db.insert(Box::new(node), expire);
+        self.rdb.dirty += 1;
+
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commands::RedisCommand, config::ListEncoding, redis::resp_or_error};
+
+    fn dump_payload(redis: &mut Redis, key: &'static [u8]) -> Vec<u8> {
+        let reply = resp_or_error(redis.execute_command(&RedisCommand::Dump { key })).unwrap();
+        let start = reply.iter().position(|&b| b == b'\n').unwrap() + 1;
+        reply[start..reply.len() - 2].to_vec()
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"string",
+            value: b"hello",
+        });
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"ziplist",
+            value: b"a",
+        });
+        redis.config.list_encoding = ListEncoding::ListPack;
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"listpack",
+            value: b"1000",
+        });
+
+        assert_eq!(
+            Ok(b"$-1\r\n".to_vec()),
+            resp_or_error(redis.execute_command(&RedisCommand::Dump { key: b"missing" }))
+        );
+
+        for (key, restored) in [
+            (&b"string"[..], &b"string2"[..]),
+            (b"ziplist", b"ziplist2"),
+            (b"listpack", b"listpack2"),
+        ] {
+            let payload = dump_payload(&mut redis, key);
+            let reply = redis.execute_command(&RedisCommand::Restore {
+                key: restored,
+                ttl: 0,
+                payload: &payload,
+                options: RestoreOptions::default(),
+            });
+            assert_eq!(Ok(b"+OK\r\n".to_vec()), resp_or_error(reply));
+            assert_eq!(
+                redis.dbs[0].dict.peek(key),
+                redis.dbs[0].dict.peek(restored)
+            );
+        }
+    }
+
+    #[test]
+    fn test_restore() {
+        struct TestData {
+            key: &'static [u8],
+            ttl: i64,
+            payload: Vec<u8>,
+            options: RestoreOptions,
+            expected: Result<&'static [u8], CommandError>,
+        }
+
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"key",
+            value: b"10",
+        });
+        let payload = dump_payload(&mut redis, b"key");
+        let mut bad_crc = payload.clone();
+        *bad_crc.last_mut().unwrap() ^= 1;
+        // a string whose length runs past the value, with a valid footer
+        let mut truncated = vec![0, 5, b'a'];
+        truncated.extend_from_slice(&rdb::RDB_VERSION.to_le_bytes());
+        truncated.extend_from_slice(&rdb::crc64(0, &truncated).to_le_bytes());
+
+        let tests = vec![
+            TestData {
+                key: b"key",
+                ttl: 0,
+                payload: payload.clone(),
+                options: RestoreOptions::default(),
+                expected: Err(CommandError::BusyKey),
+            },
+            TestData {
+                key: b"key",
+                ttl: 0,
+                payload: payload.clone(),
+                options: RestoreOptions {
+                    replace: true,
+                    ..Default::default()
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                key: b"new",
+                ttl: -1,
+                payload: payload.clone(),
+                options: RestoreOptions::default(),
+                expected: Err(CommandError::InvalidTtl),
+            },
+            TestData {
+                key: b"new",
+                ttl: 0,
+                payload: bad_crc,
+                options: RestoreOptions::default(),
+                expected: Err(CommandError::InvalidDumpPayload),
+            },
+            TestData {
+                key: b"new",
+                ttl: 0,
+                payload: b"short".to_vec(),
+                options: RestoreOptions::default(),
+                expected: Err(CommandError::InvalidDumpPayload),
+            },
+            TestData {
+                key: b"new",
+                ttl: 0,
+                payload: truncated,
+                options: RestoreOptions::default(),
+                expected: Err(CommandError::BadDataFormat),
+            },
+        ];
+
+        for test in tests {
+            let reply = redis.execute_command(&RedisCommand::Restore {
+                key: test.key,
+                ttl: test.ttl,
+                payload: &test.payload,
+                options: test.options,
+            });
+            assert_eq!(test.expected.map(|e| e.to_vec()), resp_or_error(reply));
+        }
+        assert!(redis.dbs[0].dict.peek(b"new").is_none());
+    }
+
+    #[test]
+    fn test_restore_corrupt_ziplist() {
+        // RESTORE checks every entry even without sanitize-dump-payload
+        let mut redis = Redis::new();
+        assert_eq!(ValidationMode::Shallow, redis.config.sanitize_dump_payload);
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"list",
+            value: b"a",
+        });
+        let payload = dump_payload(&mut redis, b"list");
+
+        // the type, the length of the ziplist string and the 10 byte ziplist header come before
+        // the prevlen and the encoding of the first entry, a string of 1 byte
+        let encoding = 1 + 1 + 10 + 1;
+        assert_eq!(0x01, payload[encoding]);
+        let mut data = payload[..payload.len() - 8].to_vec();
+        data[encoding] = 0x3F;
+        let mut corrupt = data.clone();
+        corrupt.extend_from_slice(&rdb::crc64(0, &data).to_le_bytes());
+
+        let reply = redis.execute_command(&RedisCommand::Restore {
+            key: b"corrupt",
+            ttl: 0,
+            payload: &corrupt,
+            options: RestoreOptions::default(),
+        });
+        assert_eq!(Err(CommandError::BadDataFormat), resp_or_error(reply));
+        assert!(redis.dbs[0].dict.peek(b"corrupt").is_none());
+    }
+
+    #[test]
+    fn test_restore_options() {
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"key",
+            value: b"value",
+        });
+        let payload = dump_payload(&mut redis, b"key");
+        let restore = |redis: &mut Redis, key, ttl, options| {
+            let reply = redis.execute_command(&RedisCommand::Restore {
+                key,
+                ttl,
+                payload: &payload,
+                options,
+            });
+            assert_eq!(Ok(b"+OK\r\n".to_vec()), resp_or_error(reply));
+        };
+
+        restore(&mut redis, b"ttl", 10_000, RestoreOptions::default());
+        let expire = redis.dbs[0].expire(b"ttl").unwrap();
+        assert!(
+            (expire - unix_time_ms() - 10_000).abs() < 1000,
+            "{}",
+            expire
+        );
+
+        let when = unix_time_ms() + 60_000;
+        let absttl = RestoreOptions {
+            absttl: true,
+            ..Default::default()
+        };
+        restore(&mut redis, b"absttl", when, absttl);
+        assert_eq!(Some(when), redis.dbs[0].expire(b"absttl"));
+
+        // already expired, the replaced key is removed
+        let expired = RestoreOptions {
+            replace: true,
+            absttl: true,
+            ..Default::default()
+        };
+        restore(&mut redis, b"key", 1, expired);
+        assert!(redis.dbs[0].dict.peek(b"key").is_none());
+
+        let idle_time = RestoreOptions {
+            idle_time: Some(1000),
+            ..Default::default()
+        };
+        restore(&mut redis, b"idle", 0, idle_time);
+        let idle = redis.dbs[0].dict.metadata(b"idle").unwrap().idle_time();
+        assert!((1000..=1001).contains(&idle), "{}", idle);
+
+        let freq = RestoreOptions {
+            freq: Some(100),
+            ..Default::default()
+        };
+        restore(&mut redis, b"freq", 0, freq);
+        assert_eq!(
+            100,
+            redis.dbs[0].dict.metadata(b"freq").unwrap().frequency()
+        );
+
+        // replacing a key gives it the access of the restored one
+        let idle_time = RestoreOptions {
+            replace: true,
+            idle_time: Some(2000),
+            ..Default::default()
+        };
+        restore(&mut redis, b"idle", 0, idle_time);
+        let idle = redis.dbs[0].dict.metadata(b"idle").unwrap().idle_time();
+        assert!((2000..=2001).contains(&idle), "{}", idle);
+
+        let freq = RestoreOptions {
+            replace: true,
+            freq: Some(50),
+            ..Default::default()
+        };
+        restore(&mut redis, b"freq", 0, freq);
+        assert_eq!(50, redis.dbs[0].dict.metadata(b"freq").unwrap().frequency());
+    }
+}
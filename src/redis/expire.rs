@@ -0,0 +1,541 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    commands::{ExpireFlags, RedisCommand, TimeUnit},
+    error::{CommandError, RedisError},
+    redis::{
+        Redis, RedisResult,
+        db::{Db, unix_time_ms},
+    },
+};
+
+// keys of the expires looked at per round of the active expire cycle, same as redis
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+// a database gets another round while more than this percentage of the looked at keys expired
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10;
+// a quarter of the interval of the periodic tasks, the same share redis gives its cycle
+const ACTIVE_EXPIRE_CYCLE_BUDGET: Duration = Duration::from_millis(25);
+
+impl Redis {
+    // the lazy half of expiring, the keys of a command are deleted before it runs when their ttl
+    // ran out so no command ever sees them
+    pub(super) fn expire_command_keys(&mut self, command: &RedisCommand) {
+        if self.dbs[self.db].expires.is_empty() {
+            return;
+        }
+
+        let now = unix_time_ms();
+        for key in command.keys() {
+            self.expire_if_needed(self.db, key, now);
+        }
+    }
+
+    // deletes the key of the database when its ttl ran out. Returns whether it did. Replicas
+    // don't expire keys on their own clock, which may be ahead of the one of their master, they
+    // wait for the DEL their master sends
+    pub(super) fn expire_if_needed(&mut self, db: usize, key: &[u8], now: i64) -> bool {
+        if self.is_replica() || !self.dbs[db].expire_if_needed(key, now) {
+            return false;
+        }
+        self.propagate_deletion(db, key);
+        true
+    }
+
+    // like propagateDeletion in redis, a key that expired is deleted with a DEL in the aof and on
+    // the replicas
+    fn propagate_deletion(&mut self, db: usize, key: &[u8]) {
+        let selected = std::mem::replace(&mut self.db, db);
+        let del = RedisCommand::Del { keys: vec![key] };
+        let result = RedisResult::Int(1);
+        self.feed_append_only_file(&del, &result);
+        self.feed_replication(&del, &result);
+        self.db = selected;
+    }
+
+    // EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT. A time that already passed deletes the key
+    pub(super) fn expire(
+        &mut self,
+        key: &[u8],
+        time: i64,
+        unit: TimeUnit,
+        absolute: bool,
+        flags: ExpireFlags,
+    ) -> RedisResult {
+        let now = unix_time_ms();
+        let when = match unit {
+            TimeUnit::Seconds => time.checked_mul(1000),
+            TimeUnit::Milliseconds => Some(time),
+        };
+        let when = match when {
+            Some(when) if absolute => when,
+            Some(when) => match when.checked_add(now) {
+                Some(when) => when,
+                None => return invalid_expire_time(unit, absolute),
+            },
+            None => return invalid_expire_time(unit, absolute),
+        };
+
+        let is_replica = self.is_replica();
+        let db = &mut self.dbs[self.db];
+        if db.dict.peek(key).is_none() {
+            return RedisResult::Int(0);
+        }
+
+        let allowed = match db.expire(key) {
+            Some(current) => {
+                !flags.nx && (!flags.gt || when > current) && (!flags.lt || when < current)
+            }
+            // no ttl is an infinite one, nothing is greater
+            None => !flags.xx && !flags.gt,
+        };
+        if !allowed {
+            return RedisResult::Int(0);
+        }
+
+        // a replica gets the time of its master and waits for the DEL of it, its own clock may be
+        // ahead
+        if when <= now && !is_replica {
+            db.delete(key);
+        } else {
+            db.set_expire(key, when);
+        }
+        self.rdb.dirty += 1;
+        RedisResult::Int(1)
+    }
+
+    // TTL and PTTL, -2 for a missing key and -1 for a key without a ttl
+    pub(super) fn ttl(&self, key: &[u8], unit: TimeUnit) -> RedisResult {
+        let db = &self.dbs[self.db];
+        if db.dict.peek(key).is_none() {
+            return RedisResult::Int(-2);
+        }
+
+        match db.expire(key) {
+            Some(when) => {
+                let ttl = (when - unix_time_ms()).max(0);
+                match unit {
+                    // rounded like redis, a key with 1.6 seconds left has a ttl of 2
+                    TimeUnit::Seconds => RedisResult::Int((ttl + 500) / 1000),
+                    TimeUnit::Milliseconds => RedisResult::Int(ttl),
+                }
+            }
+            None => RedisResult::Int(-1),
+        }
+    }
+
+    // EXPIRETIME and PEXPIRETIME, the unix time the key expires at
+    pub(super) fn expire_time(&self, key: &[u8], unit: TimeUnit) -> RedisResult {
+        let db = &self.dbs[self.db];
+        if db.dict.peek(key).is_none() {
+            return RedisResult::Int(-2);
+        }
+
+        match (db.expire(key), unit) {
+            (Some(when), TimeUnit::Seconds) => RedisResult::Int(when / 1000),
+            (Some(when), TimeUnit::Milliseconds) => RedisResult::Int(when),
+            (None, _) => RedisResult::Int(-1),
+        }
+    }
+
+    pub(super) fn persist(&mut self, key: &[u8]) -> RedisResult {
+        let db = &mut self.dbs[self.db];
+        if db.dict.peek(key).is_none() || db.remove_expire(key).is_none() {
+            return RedisResult::Int(0);
+        }
+        self.rdb.dirty += 1;
+        RedisResult::Int(1)
+    }
+
+    // the active half of expiring, reclaims keys that are never read again. Goes through the
+    // databases in turn, continuing with the one after where the last run ran out of time. Only
+    // masters run it
+    pub(super) fn active_expire_cycle(&mut self) {
+        if self.is_replica() {
+            return;
+        }
+        let start = Instant::now();
+        let now = unix_time_ms();
+
+        for _ in 0..self.dbs.len() {
+            let index = self.active_expire_db;
+            self.active_expire_db = (index + 1) % self.dbs.len();
+
+            while !self.dbs[index].expires.is_empty() {
+                let (sampled, expired_keys) = self.dbs[index].active_expire_round(now);
+                let expired = expired_keys.len();
+                for key in expired_keys {
+                    self.propagate_deletion(index, &key);
+                }
+                if start.elapsed() >= ACTIVE_EXPIRE_CYCLE_BUDGET {
+                    return;
+                }
+                // few of the keys were stale, the rest of the database most likely is not either
+                if sampled == 0 || expired * 100 <= sampled * ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Db {
+    // scans the expires from where the last round stopped until enough keys were looked at, and
+    // deletes the expired ones. Returns the amount of keys looked at and the deleted keys
+    fn active_expire_round(&mut self, now: i64) -> (usize, Vec<Vec<u8>>) {
+        let mut sampled = 0;
+        let mut expired: Vec<Vec<u8>> = Vec::new();
+        // bounds the work on a table that is mostly empty buckets
+        let mut max_buckets = ACTIVE_EXPIRE_KEYS_PER_LOOP * 20;
+
+        let mut cursor = self.expires_cursor;
+        while sampled < ACTIVE_EXPIRE_KEYS_PER_LOOP && max_buckets > 0 {
+            cursor = self.expires.scan(cursor, |key, &when| {
+                sampled += 1;
+                if when <= now {
+                    expired.push(key.to_vec());
+                }
+            });
+            max_buckets -= 1;
+            if cursor == 0 {
+                break;
+            }
+        }
+        self.expires_cursor = cursor;
+
+        for key in &expired {
+            self.delete(key);
+        }
+        (sampled, expired)
+    }
+}
+
+fn invalid_expire_time(unit: TimeUnit, absolute: bool) -> RedisResult {
+    let cmd: &[u8] = match (unit, absolute) {
+        (TimeUnit::Seconds, false) => b"expire",
+        (TimeUnit::Milliseconds, false) => b"pexpire",
+        (TimeUnit::Seconds, true) => b"expireat",
+        (TimeUnit::Milliseconds, true) => b"pexpireat",
+    };
+    RedisResult::Error(RedisError::CommandError(CommandError::InvalidExpireTime {
+        cmd: cmd.to_vec(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::resp_or_error;
+
+    // 2100-01-01, far enough away for the ttls in the tests to stay put
+    const FUTURE: i64 = 4_102_444_800;
+
+    fn expire_at(key: &'static [u8], time: i64, flags: ExpireFlags) -> RedisCommand<'static> {
+        RedisCommand::Expire {
+            key,
+            time,
+            unit: TimeUnit::Seconds,
+            absolute: true,
+            flags,
+        }
+    }
+
+    #[test]
+    fn test_expire_commands() {
+        let mut redis = Redis::new();
+        for key in [&b"key"[..], b"other"] {
+            redis.execute_command(&RedisCommand::Set {
+                key,
+                value: b"value",
+            });
+        }
+
+        let nx = ExpireFlags {
+            nx: true,
+            ..Default::default()
+        };
+        let xx = ExpireFlags {
+            xx: true,
+            ..Default::default()
+        };
+        let gt = ExpireFlags {
+            gt: true,
+            ..Default::default()
+        };
+        let lt = ExpireFlags {
+            lt: true,
+            ..Default::default()
+        };
+
+        struct TestData {
+            command: RedisCommand<'static>,
+            expected: Result<&'static [u8], CommandError>,
+        }
+
+        // runs in order, every command sees the ttls set by the ones before it
+        let tests = vec![
+            TestData {
+                command: RedisCommand::Ttl {
+                    key: b"missing",
+                    unit: TimeUnit::Seconds,
+                },
+                expected: Ok(b":-2\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Ttl {
+                    key: b"key",
+                    unit: TimeUnit::Seconds,
+                },
+                expected: Ok(b":-1\r\n"),
+            },
+            TestData {
+                command: expire_at(b"missing", FUTURE, ExpireFlags::default()),
+                expected: Ok(b":0\r\n"),
+            },
+            // no ttl counts as an infinite one
+            TestData {
+                command: expire_at(b"key", FUTURE, xx),
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: expire_at(b"key", FUTURE, gt),
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: expire_at(b"key", FUTURE, nx),
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: expire_at(b"key", FUTURE + 10, nx),
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: expire_at(b"key", FUTURE + 10, lt),
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: expire_at(b"key", FUTURE + 10, gt),
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::ExpireTime {
+                    key: b"key",
+                    unit: TimeUnit::Seconds,
+                },
+                expected: Ok(b":4102444810\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Expire {
+                    key: b"key",
+                    time: (FUTURE + 20) * 1000 + 5,
+                    unit: TimeUnit::Milliseconds,
+                    absolute: true,
+                    flags: ExpireFlags::default(),
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::ExpireTime {
+                    key: b"key",
+                    unit: TimeUnit::Milliseconds,
+                },
+                expected: Ok(b":4102444820005\r\n"),
+            },
+            // a rename takes the ttl along
+            TestData {
+                command: RedisCommand::Rename {
+                    key: b"key",
+                    new_key: b"renamed",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                command: RedisCommand::ExpireTime {
+                    key: b"renamed",
+                    unit: TimeUnit::Seconds,
+                },
+                expected: Ok(b":4102444820\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Copy {
+                    source: b"renamed",
+                    destination: b"copy",
+                    replace: false,
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::ExpireTime {
+                    key: b"copy",
+                    unit: TimeUnit::Seconds,
+                },
+                expected: Ok(b":4102444820\r\n"),
+            },
+            // overwriting a key drops its ttl
+            TestData {
+                command: RedisCommand::Set {
+                    key: b"copy",
+                    value: b"value",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Ttl {
+                    key: b"copy",
+                    unit: TimeUnit::Milliseconds,
+                },
+                expected: Ok(b":-1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Persist { key: b"renamed" },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Persist { key: b"renamed" },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Expire {
+                    key: b"other",
+                    time: 100,
+                    unit: TimeUnit::Seconds,
+                    absolute: false,
+                    flags: ExpireFlags::default(),
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Ttl {
+                    key: b"other",
+                    unit: TimeUnit::Seconds,
+                },
+                expected: Ok(b":100\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Expire {
+                    key: b"other",
+                    time: i64::MAX / 100,
+                    unit: TimeUnit::Seconds,
+                    absolute: false,
+                    flags: ExpireFlags::default(),
+                },
+                expected: Err(CommandError::InvalidExpireTime {
+                    cmd: b"expire".to_vec(),
+                }),
+            },
+            // a time in the past deletes the key
+            TestData {
+                command: RedisCommand::Expire {
+                    key: b"other",
+                    time: -1,
+                    unit: TimeUnit::Seconds,
+                    absolute: false,
+                    flags: ExpireFlags::default(),
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Exists {
+                    keys: vec![b"other"],
+                },
+                expected: Ok(b":0\r\n"),
+            },
+        ];
+
+        for test in tests {
+            let expected = test.expected.map(|reply| reply.to_vec());
+            assert_eq!(
+                expected,
+                resp_or_error(redis.execute_command(&test.command))
+            );
+        }
+
+        // none of the deleted or persisted keys is left behind in the expires
+        assert_eq!(0, redis.dbs[0].expires.len());
+    }
+
+    fn set_with_ttl(redis: &mut Redis, key: &[u8], milliseconds: i64) {
+        redis.execute_command(&RedisCommand::Set {
+            key,
+            value: b"value",
+        });
+        redis.execute_command(&RedisCommand::Expire {
+            key,
+            time: milliseconds,
+            unit: TimeUnit::Milliseconds,
+            absolute: false,
+            flags: ExpireFlags::default(),
+        });
+    }
+
+    #[test]
+    fn test_lazy_expire() {
+        let mut redis = Redis::new();
+        set_with_ttl(&mut redis, b"key", 1);
+        set_with_ttl(&mut redis, b"other", 1);
+        std::thread::sleep(Duration::from_millis(5));
+
+        // still there until something looks at it
+        assert_eq!(2, redis.dbs[0].dict.len());
+
+        let reply = redis.execute_command(&RedisCommand::Get { key: b"key" });
+        assert_eq!(Ok(b"$-1\r\n".to_vec()), resp_or_error(reply));
+        assert_eq!(1, redis.dbs[0].dict.len());
+        assert_eq!(1, redis.dbs[0].expires.len());
+
+        // KEYS leaves it out without deleting it, RANDOMKEY deletes it when it is picked
+        let reply = redis.execute_command(&RedisCommand::Keys { pattern: b"*" });
+        assert_eq!(Ok(b"*0\r\n".to_vec()), resp_or_error(reply));
+        let reply = redis.execute_command(&RedisCommand::RandomKey);
+        assert_eq!(Ok(b"$-1\r\n".to_vec()), resp_or_error(reply));
+        assert_eq!(0, redis.dbs[0].dict.len());
+        assert_eq!(0, redis.dbs[0].expires.len());
+    }
+
+    #[test]
+    fn test_active_expire_cycle() {
+        let mut redis = Redis::new();
+        for i in 0..1000 {
+            set_with_ttl(&mut redis, format!("volatile:{}", i).as_bytes(), 1);
+        }
+        for i in 0..100 {
+            redis.execute_command(&RedisCommand::Set {
+                key: format!("persistent:{}", i).as_bytes(),
+                value: b"value",
+            });
+        }
+        // a database other than the first gets its keys reclaimed as well
+        redis.db = 3;
+        for i in 0..100 {
+            set_with_ttl(&mut redis, format!("volatile:{}", i).as_bytes(), 1);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+
+        // the cycle keeps going while most of the sampled keys are expired, a run or two
+        // reclaims everything without a single key being read
+        for _ in 0..10 {
+            redis.run_periodic_tasks();
+        }
+
+        assert_eq!(100, redis.dbs[0].dict.len());
+        assert_eq!(0, redis.dbs[0].expires.len());
+        assert_eq!(0, redis.dbs[3].dict.len());
+    }
+
+    #[test]
+    fn test_expires_rehashed_when_idle() {
+        let mut redis = Redis::new();
+        let mut amount_keys = 0;
+        while redis.dbs[0].expires.rehash_progress().is_none() {
+            let key = format!("key{}", amount_keys);
+            set_with_ttl(&mut redis, key.as_bytes(), 3_600_000);
+            amount_keys += 1;
+        }
+
+        // nothing looks the keys up, the periodic tasks finish the resize of the expires as well
+        for _ in 0..10 {
+            redis.run_periodic_tasks();
+        }
+        assert!(redis.dbs[0].expires.rehash_progress().is_none());
+        assert!(redis.dbs[0].dict.rehash_progress().is_none());
+    }
+}
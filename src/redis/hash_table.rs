@@ -1,103 +1,578 @@
-use crate::redis::redis_object::RedisObject;
+use std::{
+    borrow::Borrow,
+    hash::{BuildHasher, RandomState},
+    marker::PhantomData,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::redis::{
+    dict_util::{FAIR_RANDOM_SAMPLES, Rng, fingerprint, next_scan_cursor},
+    redis_object::{Access, RedisObject},
+    siphash::siphash13,
+};
 
 const REHASHING_SPEED: usize = 1;
+// buckets moved between time checks when rehashing on a time budget
+const REHASHING_BATCH: usize = 100;
 const MAX_LOAD_FACTOR: usize = 1;
+// tables that are less than this percentage full get shrunk
+const MIN_FILL_PERCENT: usize = 10;
 const INIT_HT_SIZE: usize = 4;
+// empty buckets in a row after which sampling jumps to another random bucket
+const SAMPLE_MAX_EMPTY_RUN: usize = 5;
+
+// how a dict hashes and compares its keys, like the dictType callbacks in redis. Keys are looked
+// up by their borrowed form, a dict with Box<[u8]> keys is queried with &[u8]
+pub trait DictType {
+    type Key: ?Sized;
+    // kept in every entry next to the value, like the entry metadata of a dictType in redis.
+    // Dicts that don't need any use ()
+    type Metadata: Copy + Default;
+
+    fn hash(key: &Self::Key) -> u64;
+    fn key_eq(a: &Self::Key, b: &Self::Key) -> bool;
+
+    // called by lookups that count as an access of the entry, `random` is any uniformly random
+    // number
+    fn touch(_metadata: &mut Self::Metadata, _random: u64) {}
+}
+
+// binary safe byte string keys hashed with the seeded siphash
+pub struct ByteKeys;
+
+impl DictType for ByteKeys {
+    type Key = [u8];
+    type Metadata = ();
+
+    #[inline]
+    fn hash(key: &[u8]) -> u64 {
+        hash_bytes(key)
+    }
+
+    #[inline]
+    fn key_eq(a: &[u8], b: &[u8]) -> bool {
+        a == b
+    }
+}
+
+// the keys of the keyspace, byte strings like ByteKeys whose entries also record when and how
+// often the key was accessed for OBJECT IDLETIME and FREQ
+pub struct KeyspaceKeys;
+
+impl DictType for KeyspaceKeys {
+    type Key = [u8];
+    type Metadata = Access;
+
+    #[inline]
+    fn hash(key: &[u8]) -> u64 {
+        hash_bytes(key)
+    }
+
+    #[inline]
+    fn key_eq(a: &[u8], b: &[u8]) -> bool {
+        a == b
+    }
+
+    #[inline]
+    fn touch(access: &mut Access, random: u64) {
+        access.touch(random);
+    }
+}
 
-pub enum ResizeState {
+pub enum ResizeState<K, V, M = ()> {
     NotResizing,
     Resizing {
-        new_ht: HashTable,
+        new_ht: HashTable<K, V, M>,
         resizing_pos: usize,
     },
 }
 
-pub struct HashDict {
-    main_ht: HashTable,
-    state: ResizeState,
+pub struct HashDict<K, V, T: DictType = ByteKeys> {
+    main_ht: HashTable<K, V, T::Metadata>,
+    state: ResizeState<K, V, T::Metadata>,
+    rng: Rng,
+    dict_type: PhantomData<T>,
 }
 
-impl HashDict {
+impl<K, V> HashDict<K, V> {
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// dicts with another DictType are created through default, like a HashMap with another hasher
+impl<K, V, T: DictType> Default for HashDict<K, V, T> {
+    fn default() -> Self {
         HashDict {
             main_ht: HashTable::new(INIT_HT_SIZE),
             state: ResizeState::NotResizing,
+            rng: Rng::new(),
+            dict_type: PhantomData,
         }
     }
+}
 
-    pub fn insert(&mut self, node: Box<HashNode>) {
+impl<K, V, T> HashDict<K, V, T>
+where
+    K: Borrow<T::Key>,
+    T: DictType,
+{
+    pub fn insert(&mut self, mut node: Box<HashNode<K, V, T::Metadata>>) {
         self.try_finish_resizing();
+        node.hash = T::hash(node.key.borrow());
 
         match &mut self.state {
             ResizeState::NotResizing => {
-                self.main_ht.insert(node);
+                self.main_ht.insert::<T>(node);
 
-                // check if resize is needed
-                let load_factor = self.main_ht.used / (self.main_ht.mask + 1);
-                if load_factor >= MAX_LOAD_FACTOR {
-                    self.start_resizing();
-                }
+                self.grow_if_needed();
             }
             ResizeState::Resizing {
                 new_ht,
                 resizing_pos,
             } => {
                 Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_SPEED);
-                new_ht.insert(node);
+
+                // the key might not have been moved yet, it would overwrite the new value once
+                // its bucket gets moved over
+                self.main_ht.remove::<T>(node.hash, node.key.borrow());
+                new_ht.insert::<T>(node);
             }
         }
     }
 
-    pub fn lookup(&mut self, key: &[u8]) -> Option<&RedisObject> {
+    // lookups count as an access of the key, see peek for one that does not
+    pub fn lookup(&mut self, key: &T::Key) -> Option<&V> {
+        self.lookup_mut(key).map(|value| &*value)
+    }
+
+    pub fn lookup_mut(&mut self, key: &T::Key) -> Option<&mut V> {
         self.try_finish_resizing();
+        let hash = T::hash(key);
+        let random = self.rng.next();
 
-        match &mut self.state {
-            ResizeState::NotResizing => self.main_ht.lookup(key),
+        let node = match &mut self.state {
+            ResizeState::NotResizing => self.main_ht.node_mut::<T>(hash, key),
             ResizeState::Resizing {
                 new_ht,
                 resizing_pos,
             } => {
                 Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_SPEED);
-                new_ht.lookup(key).or_else(|| self.main_ht.lookup(key))
+                new_ht
+                    .node_mut::<T>(hash, key)
+                    .or_else(|| self.main_ht.node_mut::<T>(hash, key))
             }
+        }?;
+        T::touch(&mut node.metadata, random);
+        Some(&mut node.value)
+    }
+
+    // the metadata of the entry, without counting this as an access of it
+    pub fn metadata(&self, key: &T::Key) -> Option<T::Metadata> {
+        let hash = T::hash(key);
+
+        match &self.state {
+            ResizeState::NotResizing => self.main_ht.node::<T>(hash, key),
+            ResizeState::Resizing { new_ht, .. } => new_ht
+                .node::<T>(hash, key)
+                .or_else(|| self.main_ht.node::<T>(hash, key)),
+        }
+        .map(|node| node.metadata)
+    }
+
+    // lookup that does not help the resizing along, so it only needs a shared borrow and can be
+    // used while other values of the dict are borrowed
+    pub fn peek(&self, key: &T::Key) -> Option<&V> {
+        let hash = T::hash(key);
+
+        match &self.state {
+            ResizeState::NotResizing => self.main_ht.lookup::<T>(hash, key),
+            ResizeState::Resizing { new_ht, .. } => new_ht
+                .lookup::<T>(hash, key)
+                .or_else(|| self.main_ht.lookup::<T>(hash, key)),
         }
     }
 
-    pub fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut RedisObject> {
+    pub fn delete(&mut self, key: &T::Key) -> bool {
+        self.remove(key).is_some()
+    }
+
+    // takes the entry out of the dict and hands back its node instead of dropping it, so it can
+    // be inserted again under another key without copying the value
+    pub fn remove(&mut self, key: &T::Key) -> Option<Box<HashNode<K, V, T::Metadata>>> {
         self.try_finish_resizing();
+        let hash = T::hash(key);
 
         match &mut self.state {
-            ResizeState::NotResizing => self.main_ht.lookup_mut(key),
+            ResizeState::NotResizing => {
+                let removed = self.main_ht.remove::<T>(hash, key);
+                if removed.is_some() {
+                    self.shrink_if_needed();
+                }
+                removed
+            }
             ResizeState::Resizing {
                 new_ht,
                 resizing_pos,
             } => {
                 Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_SPEED);
-                new_ht.lookup_mut(key).or_else(|| self.main_ht.lookup_mut(key))
+                // inserts take the key out of the old table, so it is only ever in one of them
+                self.main_ht
+                    .remove::<T>(hash, key)
+                    .or_else(|| new_ht.remove::<T>(hash, key))
             }
         }
     }
 
-    pub fn delete(&mut self, key: &[u8]) -> bool {
+    pub fn len(&self) -> usize {
+        match &self.state {
+            ResizeState::NotResizing => self.main_ht.used,
+            ResizeState::Resizing { new_ht, .. } => self.main_ht.used + new_ht.used,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // explicit check for the servers periodic tasks, deletes that happened while a resize was in
+    // progress can leave the table far too big without triggering a shrink themselves. Returns
+    // true if a resize was started
+    pub fn resize_if_needed(&mut self) -> bool {
         self.try_finish_resizing();
 
-        match &mut self.state {
-            ResizeState::NotResizing => self.main_ht.delete(key),
+        match self.state {
+            ResizeState::NotResizing => self.grow_if_needed() || self.shrink_if_needed(),
+            ResizeState::Resizing { .. } => false,
+        }
+    }
+
+    // moves buckets to the new table until the time budget runs out, so a resize also finishes
+    // when no keys are being accessed. Returns the amount of buckets moved
+    pub fn rehash_for(&mut self, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut moved = 0;
+
+        loop {
+            self.try_finish_resizing();
+
+            match &mut self.state {
+                ResizeState::NotResizing => return moved,
+                ResizeState::Resizing {
+                    new_ht,
+                    resizing_pos,
+                } => {
+                    let before = *resizing_pos;
+                    Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_BATCH);
+                    moved += *resizing_pos - before;
+                }
+            }
+
+            if start.elapsed() >= budget {
+                self.try_finish_resizing();
+                return moved;
+            }
+        }
+    }
+
+    // buckets of the old table moved so far and the size of the old table, none when there is
+    // no resize in progress
+    pub fn rehash_progress(&self) -> Option<(usize, usize)> {
+        match &self.state {
+            ResizeState::NotResizing => None,
+            ResizeState::Resizing { resizing_pos, .. } => {
+                Some((*resizing_pos, self.main_ht.table.len()))
+            }
+        }
+    }
+
+    // bytes of the bucket arrays, the nodes are counted per key by entry_size
+    pub fn table_overhead(&self) -> usize {
+        let bucket_size = std::mem::size_of::<Option<Box<HashNode<K, V, T::Metadata>>>>();
+        (0..2)
+            .filter_map(|index| self.table(index))
+            .map(|table| table.table.capacity() * bucket_size)
+            .sum()
+    }
+
+    // read only iterator over every entry. The shared borrow keeps the dict from being changed
+    // while the iterator is alive, which also pauses the incremental rehashing, so every entry
+    // is returned exactly once
+    pub fn iter(&self) -> Iter<'_, K, V, T> {
+        Iter {
+            dict: self,
+            table: 0,
+            bucket: 0,
+            current: None,
+        }
+    }
+
+    // iterator that does not hold on to the dict, it is advanced with `UnsafeIter::next` and
+    // can delete the entry it returned last. Any other structural change to the dict while it
+    // is in use, including the rehash step of a lookup, invalidates it and panics in debug builds
+    pub fn unsafe_iter(&self) -> UnsafeIter {
+        UnsafeIter {
+            table: 0,
+            bucket: 0,
+            depth: 0,
+            fingerprint: self.fingerprint(),
+        }
+    }
+
+    // a random entry, picked uniformly from a sample of contiguous buckets so a key in a long
+    // chain is not more or less likely to be returned than one that is alone in its bucket
+    pub fn random_entry(&mut self) -> Option<(&T::Key, &V)> {
+        self.rehash_step(1);
+
+        let entries = self.sample_entries(FAIR_RANDOM_SAMPLES);
+        // sampling can give up on very sparse tables
+        self.rng
+            .pick(&entries)
+            .or_else(|| self.random_bucket_entry())
+    }
+
+    // up to `n` entries starting at a random bucket and following the buckets after it, jumping
+    // to another random bucket after a run of empty ones. Fewer than `n` entries can be returned
+    // even if the dict holds more, and an entry can show up more than once during a resize
+    pub fn sample(&mut self, n: usize) -> Vec<(&T::Key, &V)> {
+        self.rehash_step(n);
+        self.sample_entries(n)
+    }
+
+    // calls `f` for every key in the bucket the cursor points at and returns the cursor of the
+    // next call, a scan starts and ends at cursor 0. Uses the reverse binary cursor from redis,
+    // the cursor is incremented from its high bits down so buckets that get split by a grow or
+    // merged by a shrink between two calls are not skipped. Every key that is in the dict for
+    // the whole scan is returned at least once, keys can be returned more than once
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&T::Key, &V)) -> u64 {
+        let mut cursor = cursor;
+
+        match &self.state {
+            ResizeState::NotResizing => {
+                let mask = self.main_ht.mask as u64;
+                self.main_ht.scan_bucket::<T>(cursor & mask, &mut f);
+                cursor = next_scan_cursor(cursor, mask);
+            }
+            ResizeState::Resizing { new_ht, .. } => {
+                // buckets of the old table before the resizing position are already empty so
+                // both tables are visited
+                let (small, large) = if self.main_ht.mask <= new_ht.mask {
+                    (&self.main_ht, new_ht)
+                } else {
+                    (new_ht, &self.main_ht)
+                };
+                let small_mask = small.mask as u64;
+                let large_mask = large.mask as u64;
+
+                small.scan_bucket::<T>(cursor & small_mask, &mut f);
+
+                // every bucket of the larger table that the small bucket expands into
+                loop {
+                    large.scan_bucket::<T>(cursor & large_mask, &mut f);
+                    cursor = next_scan_cursor(cursor, large_mask);
+
+                    if cursor & (small_mask ^ large_mask) == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        cursor
+    }
+
+    fn sample_entries(&self, n: usize) -> Vec<(&T::Key, &V)> {
+        let n = n.min(self.len());
+        let mut entries = Vec::with_capacity(n);
+        if n == 0 {
+            return entries;
+        }
+
+        let (new_ht, resizing_pos) = match &self.state {
+            ResizeState::NotResizing => (None, 0),
             ResizeState::Resizing {
                 new_ht,
                 resizing_pos,
-            } => {
-                Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_SPEED);
-                let main_result = self.main_ht.delete(key);
-                let new_result = new_ht.delete(key);
-                main_result || new_result
+            } => (Some(new_ht), *resizing_pos),
+        };
+        let max_mask = new_ht.map_or(self.main_ht.mask, |new_ht| {
+            new_ht.mask.max(self.main_ht.mask)
+        });
+
+        let mut bucket = self.rng.next() as usize & max_mask;
+        let mut empty_run = 0;
+        let mut max_steps = n * 10;
+
+        while entries.len() < n && max_steps > 0 {
+            max_steps -= 1;
+
+            for (table_index, table) in [Some(&self.main_ht), new_ht].into_iter().enumerate() {
+                let Some(table) = table else {
+                    continue;
+                };
+
+                // the buckets of the old table before the resizing position are empty
+                if table_index == 0 && bucket < resizing_pos {
+                    match new_ht {
+                        Some(new_ht) if bucket >= new_ht.table.len() => bucket = resizing_pos,
+                        Some(_) => continue,
+                        None => {}
+                    }
+                }
+                if bucket >= table.table.len() {
+                    continue;
+                }
+
+                let mut current = table.table[bucket].as_deref();
+                if current.is_none() {
+                    empty_run += 1;
+                    if empty_run >= SAMPLE_MAX_EMPTY_RUN && empty_run > n {
+                        bucket = self.rng.next() as usize & max_mask;
+                        empty_run = 0;
+                    }
+                    continue;
+                }
+
+                empty_run = 0;
+                while let Some(node) = current {
+                    entries.push((node.key.borrow(), &node.value));
+                    if entries.len() == n {
+                        return entries;
+                    }
+                    current = node.next.as_deref();
+                }
             }
+
+            bucket = (bucket + 1) & max_mask;
         }
+
+        entries
+    }
+
+    // random non empty bucket and a random entry of its chain, only used when sampling found
+    // nothing
+    fn random_bucket_entry(&self) -> Option<(&T::Key, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let head = match &self.state {
+            ResizeState::NotResizing => loop {
+                let bucket = self.rng.next() as usize & self.main_ht.mask;
+                if let Some(node) = self.main_ht.table[bucket].as_deref() {
+                    break node;
+                }
+            },
+            // buckets before the resizing position are empty so they are not picked
+            ResizeState::Resizing {
+                new_ht,
+                resizing_pos,
+            } => loop {
+                let old_size = self.main_ht.table.len();
+                let range = old_size + new_ht.table.len() - resizing_pos;
+                let bucket = resizing_pos + self.rng.next() as usize % range;
+                let node = if bucket >= old_size {
+                    new_ht.table[bucket - old_size].as_deref()
+                } else {
+                    self.main_ht.table[bucket].as_deref()
+                };
+                if let Some(node) = node {
+                    break node;
+                }
+            },
+        };
+
+        let mut chain_len = 0;
+        let mut current = Some(head);
+        while let Some(node) = current {
+            chain_len += 1;
+            current = node.next.as_deref();
+        }
+
+        let mut node = head;
+        for _ in 0..self.rng.next() as usize % chain_len {
+            node = node.next.as_deref()?;
+        }
+        Some((node.key.borrow(), &node.value))
+    }
+
+    fn rehash_step(&mut self, nwork: usize) {
+        self.try_finish_resizing();
+
+        if let ResizeState::Resizing {
+            new_ht,
+            resizing_pos,
+        } = &mut self.state
+        {
+            Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, nwork);
+        }
+
+        self.try_finish_resizing();
+    }
+
+    // the main table and, while resizing, the new table
+    fn table(&self, index: usize) -> Option<&HashTable<K, V, T::Metadata>> {
+        match (index, &self.state) {
+            (0, _) => Some(&self.main_ht),
+            (1, ResizeState::Resizing { new_ht, .. }) => Some(new_ht),
+            _ => None,
+        }
+    }
+
+    fn table_mut(&mut self, index: usize) -> Option<&mut HashTable<K, V, T::Metadata>> {
+        match (index, &mut self.state) {
+            (0, _) => Some(&mut self.main_ht),
+            (1, ResizeState::Resizing { new_ht, .. }) => Some(new_ht),
+            _ => None,
+        }
+    }
+
+    // changes whenever entries are added or removed or buckets are moved
+    fn fingerprint(&self) -> u64 {
+        let mut parts = [0u64; 7];
+        parts[0] = self.main_ht.table.as_ptr() as u64;
+        parts[1] = self.main_ht.table.len() as u64;
+        parts[2] = self.main_ht.used as u64;
+        if let ResizeState::Resizing {
+            new_ht,
+            resizing_pos,
+        } = &self.state
+        {
+            parts[3] = new_ht.table.as_ptr() as u64;
+            parts[4] = new_ht.table.len() as u64;
+            parts[5] = new_ht.used as u64;
+            parts[6] = *resizing_pos as u64;
+        }
+
+        fingerprint(parts)
+    }
+
+    fn grow_if_needed(&mut self) -> bool {
+        let load_factor = self.main_ht.used / (self.main_ht.mask + 1);
+        if load_factor >= MAX_LOAD_FACTOR {
+            self.start_resizing(self.main_ht.table.capacity() * 2);
+            return true;
+        }
+
+        false
+    }
+
+    fn shrink_if_needed(&mut self) -> bool {
+        let size = self.main_ht.mask + 1;
+        if size > INIT_HT_SIZE && self.main_ht.used * 100 / size < MIN_FILL_PERCENT {
+            // smallest table that still fits every key
+            let new_size = self.main_ht.used.next_power_of_two().max(INIT_HT_SIZE);
+            self.start_resizing(new_size);
+            return true;
+        }
+
+        false
     }
 
     fn help_resizing(
-        main_ht: &mut HashTable,
-        new_ht: &mut HashTable,
+        main_ht: &mut HashTable<K, V, T::Metadata>,
+        new_ht: &mut HashTable<K, V, T::Metadata>,
         resizing_pos: &mut usize,
         nwork: usize,
     ) {
@@ -124,7 +599,8 @@ impl HashDict {
             // insert all entries from the linked list in the new bucket
             loop {
                 let next = current_entry.next.take();
-                new_ht.insert(current_entry);
+                main_ht.used -= 1;
+                new_ht.insert::<T>(current_entry);
 
                 match next {
                     Some(hash_node) => current_entry = hash_node,
@@ -139,9 +615,9 @@ impl HashDict {
     }
 
     #[inline(always)]
-    fn start_resizing(&mut self) {
+    fn start_resizing(&mut self, new_size: usize) {
         self.state = ResizeState::Resizing {
-            new_ht: HashTable::new(self.main_ht.table.capacity() * 2),
+            new_ht: HashTable::new(new_size),
             resizing_pos: 0,
         };
     }
@@ -162,17 +638,138 @@ impl HashDict {
         }
     }
 }
+impl<V, T: DictType> HashDict<Box<[u8]>, V, T> {
+    // bytes of the node holding a key and the key allocation, without the heap allocations of
+    // the value
+    pub fn entry_size(&self, key: &[u8]) -> usize {
+        std::mem::size_of::<HashNode<Box<[u8]>, V, T::Metadata>>() + key.len()
+    }
+}
+
+pub struct Iter<'a, K, V, T: DictType> {
+    dict: &'a HashDict<K, V, T>,
+    table: usize,
+    bucket: usize,
+    current: Option<&'a HashNode<K, V, T::Metadata>>,
+}
+
+impl<'a, K, V, T> Iterator for Iter<'a, K, V, T>
+where
+    K: Borrow<T::Key>,
+    T: DictType,
+    T::Key: 'a,
+{
+    type Item = (&'a T::Key, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.current {
+                self.current = node.next.as_deref();
+                return Some((node.key.borrow(), &node.value));
+            }
+
+            let table = self.dict.table(self.table)?;
+            if self.bucket >= table.table.len() {
+                self.table += 1;
+                self.bucket = 0;
+                continue;
+            }
+
+            self.current = table.table[self.bucket].as_deref();
+            self.bucket += 1;
+        }
+    }
+}
+
+// a position in the dict instead of a borrow, the entry it returned last is the one at `depth -
+// 1` in the chain of `bucket`
+pub struct UnsafeIter {
+    table: usize,
+    bucket: usize,
+    depth: usize,
+    fingerprint: u64,
+}
 
-pub struct HashTable {
-    table: Vec<Option<Box<HashNode>>>,
+impl UnsafeIter {
+    pub fn next<'d, K, V, T>(
+        &mut self,
+        dict: &'d mut HashDict<K, V, T>,
+    ) -> Option<(&'d T::Key, &'d mut V)>
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
+        self.check_fingerprint(dict);
+
+        loop {
+            let table = dict.table(self.table)?;
+            if self.bucket >= table.table.len() {
+                self.table += 1;
+                self.bucket = 0;
+                continue;
+            }
+            if table.node_at(self.bucket, self.depth).is_some() {
+                break;
+            }
+
+            self.bucket += 1;
+            self.depth = 0;
+        }
+
+        let node = dict
+            .table_mut(self.table)?
+            .node_at_mut(self.bucket, self.depth)?;
+        self.depth += 1;
+
+        Some((node.key.borrow(), &mut node.value))
+    }
+
+    // removes the entry returned by the last call to next. The dict is neither rehashed nor
+    // shrunk here, the periodic resize check picks up tables that became too empty
+    pub fn delete_current<K, V, T>(&mut self, dict: &mut HashDict<K, V, T>)
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
+        self.check_fingerprint(dict);
+        assert!(self.depth > 0, "no current entry to delete");
+
+        self.depth -= 1;
+        if let Some(table) = dict.table_mut(self.table) {
+            table.remove_at(self.bucket, self.depth);
+        }
+
+        self.fingerprint = dict.fingerprint();
+    }
+
+    #[inline(always)]
+    fn check_fingerprint<K, V, T>(&self, dict: &HashDict<K, V, T>)
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
+        debug_assert_eq!(
+            self.fingerprint,
+            dict.fingerprint(),
+            "dict was modified while an unsafe iterator was in use"
+        );
+    }
+}
+
+pub struct HashTable<K, V, M = ()> {
+    table: Vec<Option<Box<HashNode<K, V, M>>>>,
     used: usize,
     mask: usize,
 }
 
-impl HashTable {
+// the tables only store the hash of each node, hashing and comparing keys is up to the DictType
+// of the dict they belong to
+impl<K, V, M> HashTable<K, V, M> {
     pub fn new(size: usize) -> Self {
         assert!(size > 0 && ((size - 1) & size) == 0);
-        let table: Vec<Option<Box<HashNode>>> = vec![None; size];
+        // vec! would need the nodes to be Clone
+        let table: Vec<Option<Box<HashNode<K, V, M>>>> =
+            std::iter::repeat_with(|| None).take(size).collect();
 
         HashTable {
             table: table,
@@ -181,13 +778,64 @@ impl HashTable {
         }
     }
 
-    fn insert(&mut self, mut node: Box<HashNode>) {
+    fn node_at(&self, bucket: usize, depth: usize) -> Option<&HashNode<K, V, M>> {
+        let mut current = self.table[bucket].as_deref()?;
+        for _ in 0..depth {
+            current = current.next.as_deref()?;
+        }
+        Some(current)
+    }
+
+    fn node_at_mut(&mut self, bucket: usize, depth: usize) -> Option<&mut HashNode<K, V, M>> {
+        let mut current = self.table[bucket].as_deref_mut()?;
+        for _ in 0..depth {
+            current = current.next.as_deref_mut()?;
+        }
+        Some(current)
+    }
+
+    // unlinks the node at `depth` in the chain of `bucket`
+    fn remove_at(&mut self, bucket: usize, depth: usize) {
+        let mut link = &mut self.table[bucket];
+        for _ in 0..depth {
+            link = &mut link
+                .as_mut()
+                .expect("depth is past the end of the chain")
+                .next;
+        }
+
+        let mut removed = link.take().expect("depth is past the end of the chain");
+        *link = removed.next.take();
+        self.used -= 1;
+    }
+
+    fn scan_bucket<T>(&self, pos: u64, f: &mut impl FnMut(&T::Key, &V))
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
+        let mut current = self.table[pos as usize].as_deref();
+        while let Some(node) = current {
+            f(node.key.borrow(), &node.value);
+            current = node.next.as_deref();
+        }
+    }
+
+    // the hash of the node has to be set already
+    fn insert<T>(&mut self, mut node: Box<HashNode<K, V, M>>)
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
         let pos = (node.hash as usize) & self.mask;
 
         let mut current = self.table[pos].as_mut();
         while let Some(existing_node) = current {
-            if existing_node.key == node.key {
+            if existing_node.hash == node.hash
+                && T::key_eq(existing_node.key.borrow(), node.key.borrow())
+            {
                 existing_node.value = node.value;
+                existing_node.metadata = node.metadata;
                 return;
             }
 
@@ -201,107 +849,152 @@ impl HashTable {
         self.used += 1;
     }
 
-    fn lookup(&mut self, key: &[u8]) -> Option<&RedisObject> {
-        let hash = hash_bytes(key);
+    fn lookup<T>(&self, hash: u64, key: &T::Key) -> Option<&V>
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
+        self.node::<T>(hash, key).map(|node| &node.value)
+    }
+
+    fn node<T>(&self, hash: u64, key: &T::Key) -> Option<&HashNode<K, V, M>>
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
         let pos = hash as usize & self.mask;
 
         let mut current = self.table[pos].as_deref()?;
 
         loop {
-            if current.hash == hash && current.key.as_ref() == key {
-                return Some(&current.value);
+            if current.hash == hash && T::key_eq(current.key.borrow(), key) {
+                return Some(current);
             }
             current = current.next.as_deref()?;
         }
     }
 
-    fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut RedisObject> {
-        let hash = hash_bytes(key);
+    fn node_mut<T>(&mut self, hash: u64, key: &T::Key) -> Option<&mut HashNode<K, V, M>>
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
         let pos = hash as usize & self.mask;
 
         let mut current = self.table[pos].as_deref_mut()?;
 
         loop {
-            if current.hash == hash && current.key.as_ref() == key {
-                return Some(&mut current.value);
+            if current.hash == hash && T::key_eq(current.key.borrow(), key) {
+                return Some(current);
             }
             current = current.next.as_deref_mut()?;
         }
     }
 
-    fn delete(&mut self, key: &[u8]) -> bool {
-        let hash = hash_bytes(key);
+    // unlinks the node of the key from its chain and returns it
+    fn remove<T>(&mut self, hash: u64, key: &T::Key) -> Option<Box<HashNode<K, V, M>>>
+    where
+        K: Borrow<T::Key>,
+        T: DictType,
+    {
         let pos = hash as usize & self.mask;
 
-        let head = match self.table[pos].as_mut() {
-            Some(hash_node) => hash_node,
-            None => return false,
-        };
+        let head = self.table[pos].as_mut()?;
 
-        if head.hash == hash && head.key.as_ref() == key {
-            self.table[pos] = head.next.take();
+        if head.hash == hash && T::key_eq(head.key.borrow(), key) {
+            let mut removed_node = self.table[pos].take()?;
+            self.table[pos] = removed_node.next.take();
             self.used -= 1;
-            return true;
+            return Some(removed_node);
         }
 
         let mut current = head;
 
         loop {
             let next_matches = match &current.next {
-                Some(next) => next.hash == hash && next.key.as_ref() == key,
-                None => return false,
+                Some(next) => next.hash == hash && T::key_eq(next.key.borrow(), key),
+                None => return None,
             };
 
             if next_matches {
-                let removed_node = current.next.as_mut().unwrap();
+                let mut removed_node = current.next.take()?;
                 current.next = removed_node.next.take();
                 self.used -= 1;
-                return true;
+                return Some(removed_node);
             }
 
-            current = match current.next.as_mut() {
-                Some(node) => node,
-                None => return false,
-            };
+            current = current.next.as_mut()?;
         }
     }
 }
 
 #[derive(Clone, PartialEq, Debug)]
-pub struct HashNode {
-    key: Box<[u8]>,
-    pub value: RedisObject,
-    next: Option<Box<HashNode>>,
+pub struct HashNode<K, V, M = ()> {
+    key: K,
+    pub value: V,
+    next: Option<Box<HashNode<K, V, M>>>,
+    // filled in by the dict on insert with the hash of its DictType
     hash: u64,
+    // the DictType metadata, updated by lookups while peeks leave it alone
+    metadata: M,
 }
 
-impl HashNode {
-    pub fn new_from_object(key: &[u8], value: RedisObject) -> HashNode {
-        let node_key = slice_to_box(key);
-        let node_hash = hash_bytes(key);
-
+impl<K, V, M: Default> HashNode<K, V, M> {
+    pub fn new(key: K, value: V) -> Self {
         HashNode {
-            key: node_key,
-            value: value,
+            key,
+            value,
             next: None,
-            hash: node_hash,
+            hash: 0,
+            metadata: M::default(),
         }
     }
+}
 
-    pub fn new_from_bytes(key: &[u8], value: &[u8]) -> HashNode {
-        let node_key = slice_to_box(key);
-        let node_value = RedisObject::new_from_bytes(value);
-        let node_hash = hash_bytes(key);
+impl<K, V, M> HashNode<K, V, M> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn metadata(&self) -> &M {
+        &self.metadata
+    }
+
+    pub fn set_metadata(&mut self, metadata: M) {
+        self.metadata = metadata;
+    }
+
+    // gives the node another key, the dict computes the new hash when it is inserted again
+    pub fn set_key(&mut self, key: K) {
+        self.key = key;
+    }
 
+    // the key, value and metadata, for dicts that store them without the node
+    pub(super) fn into_parts(self) -> (K, V, M) {
+        (self.key, self.value, self.metadata)
+    }
+
+    pub(super) fn from_parts(key: K, value: V, metadata: M) -> Self {
         HashNode {
-            key: node_key,
-            value: node_value,
+            key,
+            value,
             next: None,
-            hash: node_hash,
+            hash: 0,
+            metadata,
         }
     }
 }
 
+impl<M: Default> HashNode<Box<[u8]>, RedisObject, M> {
+    pub fn new_from_object(key: &[u8], value: RedisObject) -> Self {
+        HashNode::new(slice_to_box(key), value)
+    }
+
+    pub fn new_from_bytes(key: &[u8], value: &[u8]) -> Self {
+        HashNode::new(slice_to_box(key), RedisObject::new_from_bytes(value))
+    }
+}
+
 // Helpers
 
 fn slice_to_box(slice: &[u8]) -> Box<[u8]> {
@@ -315,21 +1008,40 @@ fn slice_to_box(slice: &[u8]) -> Box<[u8]> {
     }
 }
 
-// FNV HASH FROM "Writing your own redis in C"
-fn hash_bytes(bytes: &[u8]) -> u64 {
-    let mut h: u32 = 0x811C9DC5;
+// the siphash key is picked randomly once per process so clients can not predict which keys end
+// up in the same bucket
+static HASH_SEED: OnceLock<(u64, u64)> = OnceLock::new();
 
-    for &byte in bytes {
-        h = h.wrapping_add(byte as u32).wrapping_mul(0x01000193);
-    }
+fn hash_seed() -> (u64, u64) {
+    *HASH_SEED.get_or_init(|| {
+        let random_state = RandomState::new();
+        (random_state.hash_one(0u64), random_state.hash_one(1u64))
+    })
+}
 
-    h as u64
+pub(super) fn hash_bytes(bytes: &[u8]) -> u64 {
+    let (k0, k1) = hash_seed();
+    siphash13(k0, k1, bytes)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    type Dict = HashDict<Box<[u8]>, RedisObject>;
+    type Table = HashTable<Box<[u8]>, RedisObject>;
+
+    // the tables expect nodes that were already hashed by the dict
+    fn table_insert(ht: &mut Table, key: &[u8], value: &[u8]) {
+        let mut node = Box::new(HashNode::new_from_bytes(key, value));
+        node.hash = hash_bytes(key);
+        ht.insert::<ByteKeys>(node);
+    }
+
+    fn table_lookup<'a>(ht: &'a Table, key: &[u8]) -> Option<&'a RedisObject> {
+        ht.lookup::<ByteKeys>(hash_bytes(key), key)
+    }
+
     #[test]
     fn test_slice_to_box() {
         struct TestData {
@@ -363,7 +1075,7 @@ mod tests {
         struct TestData {
             key: &'static [u8],
             value: &'static [u8],
-            expected: HashNode,
+            expected: HashNode<Box<[u8]>, RedisObject>,
         }
 
         let tests = vec![
@@ -388,23 +1100,23 @@ mod tests {
 
         // insert nodes into hash table
         for test in &tests {
-            ht.insert(Box::new(HashNode::new_from_bytes(test.key, test.value)));
+            table_insert(&mut ht, test.key, test.value);
         }
 
         assert_eq!(ht.used, 3);
 
         // extract nodes
         for test in &tests {
-            let result = ht.lookup(test.key).unwrap();
+            let result = table_lookup(&ht, test.key).unwrap();
             assert_eq!(&test.expected.value, result);
         }
 
         // delete nodes
         for test in &tests {
-            let result = ht.delete(test.key);
-            assert_eq!(true, result);
+            let result = ht.remove::<ByteKeys>(hash_bytes(test.key), test.key);
+            assert!(result.is_some());
 
-            let lookup = ht.lookup(test.key);
+            let lookup = table_lookup(&ht, test.key);
             assert!(lookup.is_none());
         }
     }
@@ -414,7 +1126,7 @@ mod tests {
         struct TestData {
             key: &'static [u8],
             value: &'static [u8],
-            expected: HashNode,
+            expected: HashNode<Box<[u8]>, RedisObject>,
         }
 
         let tests = vec![
@@ -438,8 +1150,8 @@ mod tests {
         let mut ht = HashTable::new(128);
 
         for test in tests {
-            ht.insert(Box::new(HashNode::new_from_bytes(test.key, test.value)));
-            let result = ht.lookup(test.key).unwrap();
+            table_insert(&mut ht, test.key, test.value);
+            let result = table_lookup(&ht, test.key).unwrap();
             assert_eq!(&test.expected.value, result);
             assert_eq!(ht.used, 1);
         }
@@ -498,4 +1210,781 @@ mod tests {
             assert_eq!(expected, redis_object.clone());
         }
     }
+
+    // the 32 bit FNV style hash the dict used before, only here to build colliding keys
+    fn fnv_hash(bytes: &[u8]) -> u64 {
+        let mut h: u32 = 0x811C9DC5;
+
+        for &byte in bytes {
+            h = h.wrapping_add(byte as u32).wrapping_mul(0x01000193);
+        }
+
+        h as u64
+    }
+
+    #[test]
+    fn test_fnv_collisions_do_not_share_a_chain() {
+        const TABLE_SIZE: usize = 1024;
+        const AMOUNT_KEYS: usize = 64;
+
+        // keys an attacker could compute offline that all land in bucket 0 with the old hash
+        let mut keys = Vec::new();
+        let mut i = 0;
+        while keys.len() < AMOUNT_KEYS {
+            let key = format!("attack:{}", i);
+            if fnv_hash(key.as_bytes()) as usize & (TABLE_SIZE - 1) == 0 {
+                keys.push(key);
+            }
+            i += 1;
+        }
+
+        let mut ht = HashTable::new(TABLE_SIZE);
+        for key in &keys {
+            table_insert(&mut ht, key.as_bytes(), b"value");
+        }
+
+        let longest_chain = ht
+            .table
+            .iter()
+            .map(|bucket| {
+                let mut len = 0;
+                let mut current = bucket.as_deref();
+                while let Some(node) = current {
+                    len += 1;
+                    current = node.next.as_deref();
+                }
+                len
+            })
+            .max()
+            .unwrap();
+
+        // 64 keys spread over 1024 buckets, a chain of 8 is already extremely unlikely
+        assert!(longest_chain < 8, "longest chain {}", longest_chain);
+        assert_eq!(AMOUNT_KEYS, ht.used);
+
+        for key in &keys {
+            assert!(table_lookup(&ht, key.as_bytes()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_hash_uses_all_64_bits() {
+        // a widened 32 bit hash would leave the top half empty for every key
+        let high_bits = (0..64)
+            .map(|i| hash_bytes(format!("key{}", i).as_bytes()) >> 32)
+            .fold(0, |acc, bits| acc | bits);
+
+        assert_ne!(0, high_bits);
+        assert_eq!(hash_bytes(b"key"), hash_bytes(b"key"));
+    }
+
+    fn finish_resizing(hash_dict: &mut Dict) {
+        while let ResizeState::Resizing { .. } = hash_dict.state {
+            let _ = hash_dict.lookup(b"");
+        }
+    }
+
+    fn table_size(hash_dict: &Dict) -> usize {
+        hash_dict.main_ht.mask + 1
+    }
+
+    #[test]
+    fn test_grow_and_shrink_cycles() {
+        let mut hash_dict = HashDict::new();
+
+        for cycle in 0..3 {
+            // grow
+            for i in 0..1000 {
+                let key = format!("key{}", i);
+                let value = format!("value{}:{}", cycle, i);
+                hash_dict.insert(Box::new(HashNode::new_from_bytes(
+                    key.as_bytes(),
+                    value.as_bytes(),
+                )));
+            }
+            finish_resizing(&mut hash_dict);
+
+            assert_eq!(1000, hash_dict.len());
+            assert_eq!(1024, table_size(&hash_dict));
+
+            // mass delete, every delete that happens outside of a resize can start a shrink
+            for i in 10..1000 {
+                let key = format!("key{}", i);
+                assert!(hash_dict.delete(key.as_bytes()), "missing {}", key);
+            }
+            finish_resizing(&mut hash_dict);
+
+            // the cron catches what the deletes during a shrink could not
+            while hash_dict.resize_if_needed() {
+                finish_resizing(&mut hash_dict);
+            }
+
+            assert_eq!(10, hash_dict.len());
+            assert_eq!(16, table_size(&hash_dict));
+
+            for i in 0..10 {
+                let key = format!("key{}", i);
+                let expected = format!("value{}:{}", cycle, i);
+                assert_eq!(
+                    Some(&RedisObject::new_from_bytes(expected.as_bytes())),
+                    hash_dict.lookup(key.as_bytes())
+                );
+            }
+            for i in 10..1000 {
+                let key = format!("key{}", i);
+                assert!(hash_dict.lookup(key.as_bytes()).is_none());
+            }
+        }
+
+        // deleting everything shrinks back to the initial size
+        for i in 0..10 {
+            hash_dict.delete(format!("key{}", i).as_bytes());
+        }
+        finish_resizing(&mut hash_dict);
+        while hash_dict.resize_if_needed() {
+            finish_resizing(&mut hash_dict);
+        }
+
+        assert!(hash_dict.is_empty());
+        assert_eq!(INIT_HT_SIZE, table_size(&hash_dict));
+    }
+
+    #[test]
+    fn test_resize_if_needed() {
+        let mut hash_dict = HashDict::new();
+        assert!(!hash_dict.resize_if_needed());
+
+        // a table left underfilled, like after deletes that happened during a resize
+        hash_dict.main_ht = HashTable::new(128);
+        table_insert(&mut hash_dict.main_ht, b"key1", b"value");
+        table_insert(&mut hash_dict.main_ht, b"key2", b"value");
+
+        assert!(hash_dict.resize_if_needed());
+        // only one resize at a time
+        assert!(!hash_dict.resize_if_needed());
+        finish_resizing(&mut hash_dict);
+        assert_eq!(INIT_HT_SIZE, table_size(&hash_dict));
+        assert!(!hash_dict.resize_if_needed());
+
+        // and an overfilled one
+        for i in 3..=8 {
+            let key = format!("key{}", i);
+            table_insert(&mut hash_dict.main_ht, key.as_bytes(), b"value");
+        }
+
+        assert!(hash_dict.resize_if_needed());
+        finish_resizing(&mut hash_dict);
+        assert_eq!(2 * INIT_HT_SIZE, table_size(&hash_dict));
+
+        assert_eq!(8, hash_dict.len());
+        for i in 1..=8 {
+            let key = format!("key{}", i);
+            assert!(hash_dict.lookup(key.as_bytes()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_overwrite_while_resizing() {
+        let mut hash_dict = HashDict::new();
+
+        for i in 0..INIT_HT_SIZE {
+            let key = format!("key{}", i);
+            hash_dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"old")));
+        }
+        assert!(matches!(hash_dict.state, ResizeState::Resizing { .. }));
+
+        // overwrite keys that have not been moved to the new table yet
+        for i in 0..INIT_HT_SIZE {
+            let key = format!("key{}", i);
+            hash_dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"new")));
+            // moved keys are only counted by the new table
+            assert_eq!(INIT_HT_SIZE, hash_dict.len());
+        }
+        finish_resizing(&mut hash_dict);
+
+        assert_eq!(INIT_HT_SIZE, hash_dict.len());
+        for i in 0..INIT_HT_SIZE {
+            let key = format!("key{}", i);
+            assert_eq!(
+                Some(&RedisObject::new_from_bytes(b"new")),
+                hash_dict.lookup(key.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_rehash_for() {
+        let mut hash_dict = HashDict::new();
+        assert_eq!(0, hash_dict.rehash_for(Duration::from_secs(1)));
+        assert_eq!(None, hash_dict.rehash_progress());
+
+        // fill the dict up to the point where it starts growing from 4096 buckets
+        for i in 0..4095 {
+            let key = format!("key{}", i);
+            hash_dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"value")));
+        }
+        finish_resizing(&mut hash_dict);
+        hash_dict.insert(Box::new(HashNode::new_from_bytes(b"key4095", b"value")));
+
+        let (done, total) = hash_dict.rehash_progress().unwrap();
+        assert!(done < total);
+        assert_eq!(4096, total);
+
+        // an empty budget still moves one batch
+        let moved = hash_dict.rehash_for(Duration::ZERO);
+        assert!(moved > 0 && moved < total);
+        assert_eq!(Some((done + moved, total)), hash_dict.rehash_progress());
+        // moved keys are only counted by the new table
+        assert_eq!(4096, hash_dict.len());
+
+        // without any key accesses the resize finishes within the budget
+        hash_dict.rehash_for(Duration::from_secs(10));
+        assert_eq!(None, hash_dict.rehash_progress());
+        assert_eq!(8192, table_size(&hash_dict));
+
+        assert_eq!(4096, hash_dict.len());
+        for i in 0..4096 {
+            let key = format!("key{}", i);
+            assert!(hash_dict.lookup(key.as_bytes()).is_some());
+        }
+    }
+
+    fn scan_all(hash_dict: &Dict) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            cursor = hash_dict.scan(cursor, |key, _| keys.push(key.to_vec()));
+            if cursor == 0 {
+                return keys;
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut hash_dict = HashDict::new();
+        assert!(scan_all(&hash_dict).is_empty());
+
+        for i in 0..1024 {
+            let key = format!("key{}", i);
+            hash_dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"value")));
+        }
+
+        // the last insert started a grow. Once in the middle of the resize and once after it,
+        // without changes in between every key comes back exactly once
+        assert!(hash_dict.rehash_progress().is_some());
+        for _ in 0..2 {
+            let mut keys = scan_all(&hash_dict);
+            keys.sort();
+            let mut expected: Vec<Vec<u8>> = (0..1024)
+                .map(|i| format!("key{}", i).into_bytes())
+                .collect();
+            expected.sort();
+            assert_eq!(expected, keys);
+
+            finish_resizing(&mut hash_dict);
+        }
+    }
+
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn test_scan_with_interleaved_changes() {
+        const AMOUNT_STABLE: usize = 50;
+
+        let mut grows = 0;
+        let mut shrinks = 0;
+
+        for seed in 1..=8u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E3779B97F4A7C15));
+            // even seeds mostly insert so the dict grows during the scan, odd seeds start big
+            // and mostly delete so it shrinks
+            let growing = seed % 2 == 0;
+
+            let mut hash_dict = HashDict::new();
+            let mut volatile: Vec<Vec<u8>> = Vec::new();
+            let mut next_volatile = 0;
+
+            // keys that are present for the whole scan
+            for i in 0..AMOUNT_STABLE {
+                let key = format!("stable{}", i);
+                hash_dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"value")));
+            }
+            let initial_volatile = if growing { 0 } else { 3000 };
+            for _ in 0..initial_volatile {
+                let key = format!("volatile{}", next_volatile).into_bytes();
+                next_volatile += 1;
+                hash_dict.insert(Box::new(HashNode::new_from_bytes(&key, b"value")));
+                volatile.push(key);
+            }
+            // some seeds start the scan while a resize is in progress
+            if rng.below(2) == 0 {
+                finish_resizing(&mut hash_dict);
+            }
+
+            let (insert_chance, delete_chance) = if growing { (50, 10) } else { (5, 55) };
+
+            let mut seen = std::collections::HashSet::new();
+            let mut cursor = 0;
+            let mut steps = 0;
+
+            loop {
+                let was_resizing = hash_dict.rehash_progress().is_some();
+                let size_before = table_size(&hash_dict);
+
+                let roll = rng.below(100);
+                if roll < 25 {
+                    cursor = hash_dict.scan(cursor, |key, _| {
+                        seen.insert(key.to_vec());
+                    });
+                    steps += 1;
+                    if cursor == 0 {
+                        break;
+                    }
+                } else if roll < 25 + insert_chance {
+                    let key = format!("volatile{}", next_volatile).into_bytes();
+                    next_volatile += 1;
+                    hash_dict.insert(Box::new(HashNode::new_from_bytes(&key, b"value")));
+                    volatile.push(key);
+                } else if roll < 25 + insert_chance + delete_chance {
+                    if !volatile.is_empty() {
+                        let key = volatile.swap_remove(rng.below(volatile.len()));
+                        assert!(hash_dict.delete(&key));
+                    }
+                } else {
+                    match rng.below(4) {
+                        0 => {
+                            hash_dict.rehash_for(Duration::ZERO);
+                        }
+                        1 => {
+                            hash_dict.resize_if_needed();
+                        }
+                        2 => {
+                            // overwriting a stable key keeps it present
+                            let key = format!("stable{}", rng.below(AMOUNT_STABLE));
+                            hash_dict.insert(Box::new(HashNode::new_from_bytes(
+                                key.as_bytes(),
+                                b"other",
+                            )));
+                        }
+                        _ => {
+                            let key = format!("stable{}", rng.below(AMOUNT_STABLE));
+                            assert!(hash_dict.lookup(key.as_bytes()).is_some());
+                        }
+                    }
+                }
+
+                if let ResizeState::Resizing { new_ht, .. } = &hash_dict.state
+                    && !was_resizing
+                {
+                    if new_ht.mask + 1 > size_before {
+                        grows += 1;
+                    } else {
+                        shrinks += 1;
+                    }
+                }
+
+                assert!(steps < 1_000_000, "seed {} scan did not finish", seed);
+            }
+
+            for i in 0..AMOUNT_STABLE {
+                let key = format!("stable{}", i).into_bytes();
+                assert!(seen.contains(&key), "seed {} missed {:?}", seed, key);
+            }
+            assert_eq!(AMOUNT_STABLE + volatile.len(), hash_dict.len());
+        }
+
+        // the scans have to have run into both kinds of resizes
+        assert!(
+            grows > 0 && shrinks > 0,
+            "grows {} shrinks {}",
+            grows,
+            shrinks
+        );
+    }
+
+    fn insert_keys(hash_dict: &mut Dict, amount: usize) {
+        for i in 0..amount {
+            let key = format!("key{}", i);
+            hash_dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"value")));
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut hash_dict = HashDict::new();
+        assert_eq!(0, hash_dict.iter().count());
+
+        // the last insert starts a grow, so the first pass sees both tables
+        insert_keys(&mut hash_dict, 1024);
+        assert!(hash_dict.rehash_progress().is_some());
+
+        for _ in 0..2 {
+            let mut keys: Vec<Vec<u8>> = hash_dict.iter().map(|(key, _)| key.to_vec()).collect();
+            keys.sort();
+            let mut expected: Vec<Vec<u8>> = (0..1024)
+                .map(|i| format!("key{}", i).into_bytes())
+                .collect();
+            expected.sort();
+            assert_eq!(expected, keys);
+
+            assert!(
+                hash_dict
+                    .iter()
+                    .all(|(_, value)| *value == RedisObject::new_from_bytes(b"value"))
+            );
+
+            finish_resizing(&mut hash_dict);
+        }
+    }
+
+    #[test]
+    fn test_unsafe_iter() {
+        let mut hash_dict = HashDict::new();
+        insert_keys(&mut hash_dict, 1024);
+        assert!(hash_dict.rehash_progress().is_some());
+        let progress = hash_dict.rehash_progress();
+
+        // delete every even key and change the value of the odd ones while iterating
+        let mut it = hash_dict.unsafe_iter();
+        let mut visited = 0;
+        while let Some((key, value)) = it.next(&mut hash_dict) {
+            visited += 1;
+            let i: usize = std::str::from_utf8(&key[3..]).unwrap().parse().unwrap();
+            if i.is_multiple_of(2) {
+                it.delete_current(&mut hash_dict);
+            } else {
+                *value = RedisObject::Int(i as i64);
+            }
+        }
+        assert_eq!(1024, visited);
+
+        // the iteration did not move any buckets
+        assert_eq!(progress, hash_dict.rehash_progress());
+        assert_eq!(512, hash_dict.len());
+
+        for i in 0..1024 {
+            let key = format!("key{}", i);
+            let expected = (i % 2 == 1).then_some(RedisObject::Int(i as i64));
+            assert_eq!(expected.as_ref(), hash_dict.peek(key.as_bytes()));
+        }
+
+        // deleting everything leaves the shrink to the periodic resize check
+        finish_resizing(&mut hash_dict);
+        let mut it = hash_dict.unsafe_iter();
+        while it.next(&mut hash_dict).is_some() {
+            it.delete_current(&mut hash_dict);
+        }
+        assert!(hash_dict.is_empty());
+        assert_eq!(2048, table_size(&hash_dict));
+        assert!(hash_dict.resize_if_needed());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dict was modified while an unsafe iterator was in use")]
+    fn test_unsafe_iter_insert_panics() {
+        let mut hash_dict = HashDict::new();
+        insert_keys(&mut hash_dict, 2);
+
+        let mut it = hash_dict.unsafe_iter();
+        it.next(&mut hash_dict);
+        hash_dict.insert(Box::new(HashNode::new_from_bytes(b"new", b"value")));
+        it.next(&mut hash_dict);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dict was modified while an unsafe iterator was in use")]
+    fn test_unsafe_iter_rehash_step_panics() {
+        let mut hash_dict = HashDict::new();
+        insert_keys(&mut hash_dict, 1024);
+        assert!(hash_dict.rehash_progress().is_some());
+
+        // a lookup moves a bucket of the resize
+        let mut it = hash_dict.unsafe_iter();
+        it.next(&mut hash_dict);
+        hash_dict.lookup(b"key0");
+        it.next(&mut hash_dict);
+    }
+
+    #[test]
+    fn test_unsafe_iter_allows_overwrites() {
+        let mut hash_dict = HashDict::new();
+        insert_keys(&mut hash_dict, 2);
+        finish_resizing(&mut hash_dict);
+
+        // replacing a value does not change the structure of the dict
+        let mut it = hash_dict.unsafe_iter();
+        it.next(&mut hash_dict);
+        hash_dict.insert(Box::new(HashNode::new_from_bytes(b"key0", b"other")));
+        assert!(it.next(&mut hash_dict).is_some());
+        assert!(it.next(&mut hash_dict).is_none());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut hash_dict = HashDict::new();
+        assert!(hash_dict.sample(10).is_empty());
+        assert!(hash_dict.random_entry().is_none());
+
+        // a small dict is sampled completely
+        insert_keys(&mut hash_dict, 3);
+        let mut keys: Vec<&[u8]> = hash_dict
+            .sample(10)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        assert_eq!(vec![&b"key0"[..], b"key1", b"key2"], keys);
+        assert_eq!(2, hash_dict.sample(2).len());
+        assert!(hash_dict.random_entry().is_some());
+    }
+
+    #[test]
+    fn test_sample_while_resizing() {
+        let mut hash_dict = HashDict::new();
+        insert_keys(&mut hash_dict, 64);
+        finish_resizing(&mut hash_dict);
+
+        // a shrink that has moved part of the buckets
+        hash_dict.start_resizing(16);
+        for _ in 0..10 {
+            hash_dict.lookup(b"key0");
+        }
+        let progress = hash_dict.rehash_progress();
+        assert!(progress.is_some_and(|(done, total)| done > 0 && done < total));
+
+        hash_dict.rng = super::Rng::with_seed(7);
+
+        // the chains of the buckets left in the old table and of the new table, walked by hand
+        // since the layout depends on the hash seed
+        let ResizeState::Resizing {
+            new_ht,
+            resizing_pos,
+        } = &hash_dict.state
+        else {
+            panic!("the dict stopped resizing");
+        };
+        let chains =
+            |table: &HashTable<Box<[u8]>, RedisObject>, start: usize| -> Vec<Vec<Vec<u8>>> {
+                table.table[start..]
+                    .iter()
+                    .filter_map(|bucket| bucket.as_deref())
+                    .map(|head| {
+                        std::iter::successors(Some(head), |node| node.next.as_deref())
+                            .map(|node| node.key.to_vec())
+                            .collect()
+                    })
+                    .collect()
+            };
+        let old_chains = chains(&hash_dict.main_ht, *resizing_pos);
+        let new_chains = chains(new_ht, 0);
+
+        // sampling without rehash steps has to find keys in both tables. Like in redis it takes
+        // at most n entries of a chain, so keys deep in a long chain may never be sampled
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            for (key, _) in hash_dict.sample_entries(5) {
+                assert!(hash_dict.peek(key).is_some());
+                seen.insert(key.to_vec());
+            }
+        }
+        for table in [&old_chains, &new_chains] {
+            assert!(table.iter().flatten().any(|key| seen.contains(key)));
+        }
+        assert_eq!(progress, hash_dict.rehash_progress());
+
+        // the fallback for sparse tables as well. A key is drawn with the chance of picking its
+        // bucket out of the non empty ones times the one of its place in the chain
+        let amount_chains = old_chains.len() + new_chains.len();
+        let mut chances = std::collections::HashMap::new();
+        for chain in old_chains.iter().chain(&new_chains) {
+            for key in chain {
+                chances.insert(key.clone(), 1.0 / (amount_chains * chain.len()) as f64);
+            }
+        }
+        assert_eq!(64, chances.len());
+
+        const AMOUNT_DRAWS: usize = 20_000;
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..AMOUNT_DRAWS {
+            let (key, _) = hash_dict.random_bucket_entry().unwrap();
+            *counts.entry(key.to_vec()).or_insert(0) += 1;
+        }
+        // within six standard deviations of the expected draws
+        for (key, chance) in chances {
+            let expected = chance * AMOUNT_DRAWS as f64;
+            let count = counts.get(&key).copied().unwrap_or(0) as f64;
+            assert!(
+                (count - expected).abs() <= 6.0 * expected.sqrt() + 1.0,
+                "{} drawn {} times, expected {:.0}",
+                String::from_utf8_lossy(&key),
+                count,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_random_entry_is_fair() {
+        const AMOUNT_DRAWS: usize = 17_000;
+
+        // one bucket with a chain of 8 keys and 9 buckets with a single key, built by hand so the
+        // chain does not depend on the hash seed
+        let mut hash_dict = HashDict::new();
+        hash_dict.main_ht = HashTable::new(32);
+        for i in 0..17u64 {
+            let key = format!("key{}", i);
+            let mut node = HashNode::new_from_bytes(key.as_bytes(), b"value");
+            node.hash = if i < 8 { 0 } else { i * 3 };
+            hash_dict.main_ht.insert::<ByteKeys>(Box::new(node));
+        }
+
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..AMOUNT_DRAWS {
+            let (key, _) = hash_dict.random_entry().unwrap();
+            *counts.entry(key.to_vec()).or_insert(0) += 1;
+        }
+
+        // every key is expected 1000 times, picking a random bucket first would give each chained
+        // key about 210 draws and each single key 1700
+        assert_eq!(17, counts.len());
+        for (key, count) in counts {
+            assert!(
+                (600..1400).contains(&count),
+                "{} drawn {} times",
+                String::from_utf8_lossy(&key),
+                count
+            );
+        }
+    }
+
+    // keys that only differ in case are the same key
+    struct CaseInsensitiveKeys;
+
+    impl DictType for CaseInsensitiveKeys {
+        type Key = str;
+        type Metadata = ();
+
+        fn hash(key: &str) -> u64 {
+            hash_bytes(key.to_ascii_lowercase().as_bytes())
+        }
+
+        fn key_eq(a: &str, b: &str) -> bool {
+            a.eq_ignore_ascii_case(b)
+        }
+    }
+
+    #[test]
+    fn test_custom_dict_type() {
+        let mut hash_dict: HashDict<String, usize, CaseInsensitiveKeys> = HashDict::default();
+
+        // enough keys to go through a few resizes
+        for i in 0..100 {
+            hash_dict.insert(Box::new(HashNode::new(format!("Field{}", i), i)));
+        }
+        hash_dict.insert(Box::new(HashNode::new("FIELD7".to_string(), 700)));
+        hash_dict.rehash_for(Duration::from_secs(10));
+
+        struct TestData {
+            key: &'static str,
+            expected: Option<usize>,
+        }
+
+        let tests = vec![
+            TestData {
+                key: "field0",
+                expected: Some(0),
+            },
+            TestData {
+                key: "FIELD99",
+                expected: Some(99),
+            },
+            TestData {
+                key: "field7",
+                expected: Some(700),
+            },
+            TestData {
+                key: "field100",
+                expected: None,
+            },
+        ];
+
+        assert_eq!(100, hash_dict.len());
+        for test in tests {
+            assert_eq!(
+                test.expected.as_ref(),
+                hash_dict.lookup(test.key),
+                "{}",
+                test.key
+            );
+        }
+
+        assert!(hash_dict.delete("fIeLd1"));
+        assert!(!hash_dict.delete("field1"));
+        assert_eq!(99, hash_dict.iter().count());
+    }
+    // counts the lookups of every key
+    struct CountedKeys;
+
+    impl DictType for CountedKeys {
+        type Key = [u8];
+        type Metadata = u32;
+
+        fn hash(key: &[u8]) -> u64 {
+            hash_bytes(key)
+        }
+
+        fn key_eq(a: &[u8], b: &[u8]) -> bool {
+            a == b
+        }
+
+        fn touch(lookups: &mut u32, _random: u64) {
+            *lookups += 1;
+        }
+    }
+
+    #[test]
+    fn test_dict_type_metadata() {
+        let mut hash_dict: HashDict<Box<[u8]>, usize, CountedKeys> = HashDict::default();
+
+        // lookups while the dict is resizing find the node in either table
+        for i in 0..100 {
+            hash_dict.insert(Box::new(HashNode::new(
+                format!("key{}", i).into_bytes().into(),
+                i,
+            )));
+            hash_dict.lookup(b"key0");
+        }
+        assert_eq!(Some(100), hash_dict.metadata(b"key0"));
+        assert_eq!(Some(0), hash_dict.metadata(b"key1"));
+        assert_eq!(None, hash_dict.metadata(b"key100"));
+
+        // peeks and reading the metadata do not count
+        hash_dict.peek(b"key1");
+        hash_dict.metadata(b"key1");
+        *hash_dict.lookup_mut(b"key1").unwrap() += 1;
+        assert_eq!(Some(1), hash_dict.metadata(b"key1"));
+
+        // an overwrite takes the metadata of the new node
+        let mut node = HashNode::new(Box::from(&b"key0"[..]), 0);
+        node.set_metadata(7);
+        hash_dict.insert(Box::new(node));
+        assert_eq!(Some(7), hash_dict.metadata(b"key0"));
+        assert_eq!(7, *hash_dict.remove(b"key0").unwrap().metadata());
+    }
 }
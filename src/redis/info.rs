@@ -0,0 +1,216 @@
+use std::io::Write;
+
+use crate::redis::{Redis, RedisResult};
+
+impl Redis {
+    // INFO [section ...], no sections or all, default and everything give every section
+    pub(super) fn info(&self, sections: &[&[u8]]) -> RedisResult {
+        let wanted = |name: &[u8]| {
+            sections.is_empty()
+                || sections.iter().any(|section| {
+                    section.eq_ignore_ascii_case(name)
+                        || section.eq_ignore_ascii_case(b"all")
+                        || section.eq_ignore_ascii_case(b"default")
+                        || section.eq_ignore_ascii_case(b"everything")
+                })
+        };
+
+        let mut info = Vec::new();
+
+        if wanted(b"persistence") {
+            Self::start_section(&mut info, "Persistence");
+            self.write_persistence(&mut info);
+        }
+
+        if wanted(b"stats") {
+            Self::start_section(&mut info, "Stats");
+            self.write_stats(&mut info);
+        }
+
+        if wanted(b"replication") {
+            Self::start_section(&mut info, "Replication");
+            self.write_replication_info(&mut info);
+        }
+
+        if wanted(b"cluster") {
+            Self::start_section(&mut info, "Cluster");
+            self.write_cluster_info(&mut info);
+        }
+
+        if wanted(b"keyspace") {
+            Self::start_section(&mut info, "Keyspace");
+            self.write_keyspace(&mut info);
+        }
+
+        let mut reply = Vec::with_capacity(info.len() + 16);
+        write!(reply, "${}\r\n", info.len()).unwrap();
+        reply.extend_from_slice(&info);
+        reply.extend_from_slice(b"\r\n");
+
+        RedisResult::BulkString(reply)
+    }
+
+    fn start_section(info: &mut Vec<u8>, name: &str) {
+        // sections are separated by an empty line
+        if !info.is_empty() {
+            info.extend_from_slice(b"\r\n");
+        }
+        write!(info, "# {}\r\n", name).unwrap();
+    }
+
+    fn write_persistence(&self, info: &mut Vec<u8>) {
+        write!(
+            info,
+            "rdb_changes_since_last_save:{}\r\n\
+             rdb_bgsave_in_progress:{}\r\n\
+             rdb_last_save_time:{}\r\n\
+             rdb_last_bgsave_status:{}\r\n\
+             aof_enabled:{}\r\n\
+             aof_rewrite_in_progress:{}\r\n\
+             aof_rewrite_scheduled:{}\r\n\
+             aof_last_bgrewrite_status:{}\r\n\
+             aof_last_write_status:{}\r\n",
+            self.rdb.dirty,
+            self.bgsave_in_progress() as u8,
+            self.rdb.last_save,
+            status(self.last_bgsave_ok()),
+            self.aof_enabled() as u8,
+            self.aof_rewrite_in_progress() as u8,
+            self.aof_rewrite_scheduled() as u8,
+            status(self.aof_last_rewrite_ok()),
+            status(self.aof_last_write_ok()),
+        )
+        .unwrap();
+    }
+
+    fn write_stats(&self, info: &mut Vec<u8>) {
+        // summed over every database that is rehashing
+        let (rehashing, done, total) = self
+            .dbs
+            .iter()
+            .filter_map(|db| db.dict.rehash_progress())
+            .fold(
+                (false, 0, 0),
+                |(_, done, total), (dict_done, dict_total)| {
+                    (true, done + dict_done, total + dict_total)
+                },
+            );
+
+        write!(
+            info,
+            "active_rehashing:{}\r\n\
+             rehashing:{}\r\n\
+             rehash_buckets_done:{}\r\n\
+             rehash_buckets_total:{}\r\n",
+            self.config.active_rehashing as u8, rehashing as u8, done, total,
+        )
+        .unwrap();
+    }
+
+    fn write_keyspace(&self, info: &mut Vec<u8>) {
+        // like redis empty databases are left out
+        for (index, db) in self.dbs.iter().enumerate() {
+            if !db.dict.is_empty() {
+                write!(
+                    info,
+                    "db{}:keys={},expires={}\r\n",
+                    index,
+                    db.dict.len(),
+                    db.expires.len()
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+fn status(ok: bool) -> &'static str {
+    if ok { "ok" } else { "err" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::RedisCommand;
+
+    fn info(redis: &mut Redis, sections: Vec<&[u8]>) -> Vec<u8> {
+        match redis.execute_command(&RedisCommand::Info { sections }) {
+            RedisResult::BulkString(reply) => reply,
+            _ => panic!("INFO should reply with a bulk string"),
+        }
+    }
+
+    #[test]
+    fn test_info() {
+        let mut redis = Redis::new();
+
+        let stats: &[u8] = b"# Stats\r\nactive_rehashing:1\r\nrehashing:0\r\n\
+            rehash_buckets_done:0\r\nrehash_buckets_total:0\r\n";
+        let mut expected = format!("${}\r\n", stats.len() + 14).into_bytes();
+        expected.extend_from_slice(stats);
+        expected.extend_from_slice(b"\r\n# Keyspace\r\n\r\n");
+        assert_eq!(expected, info(&mut redis, vec![b"stats", b"keyspace"]));
+
+        // insert until the dict starts growing, the point depends on which dict is built
+        let mut amount_keys = 0;
+        while redis.dbs[0].dict.rehash_progress().is_none() {
+            let key = format!("key{}", amount_keys);
+            redis.execute_command(&RedisCommand::Set {
+                key: key.as_bytes(),
+                value: b"value",
+            });
+            amount_keys += 1;
+        }
+
+        let (done, total) = redis.dbs[0].dict.rehash_progress().unwrap();
+        let reply = info(&mut redis, vec![b"STATS"]);
+        let expected = format!(
+            "rehashing:1\r\nrehash_buckets_done:{}\r\nrehash_buckets_total:{}\r\n\r\n",
+            done, total
+        );
+        assert!(reply.ends_with(expected.as_bytes()));
+        assert!(!reply.windows(10).any(|w| w == b"# Keyspace"));
+
+        // an idle server finishes the resize in its periodic tasks
+        redis.run_periodic_tasks();
+
+        let reply = info(&mut redis, vec![b"stats"]);
+        assert!(
+            reply.ends_with(
+                b"rehashing:0\r\nrehash_buckets_done:0\r\nrehash_buckets_total:0\r\n\r\n"
+            )
+        );
+
+        let keyspace = format!("# Keyspace\r\ndb0:keys={},expires=0\r\n", amount_keys);
+        assert_eq!(
+            format!("${}\r\n{}\r\n", keyspace.len(), keyspace).into_bytes(),
+            info(&mut redis, vec![b"keyspace"])
+        );
+        assert_eq!(
+            b"$0\r\n\r\n".to_vec(),
+            info(&mut redis, vec![b"nosuchsection"])
+        );
+    }
+
+    #[test]
+    fn test_info_persistence() {
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"key",
+            value: b"value",
+        });
+        redis.execute_command(&RedisCommand::Get { key: b"key" });
+
+        let persistence = format!(
+            "# Persistence\r\nrdb_changes_since_last_save:1\r\nrdb_bgsave_in_progress:0\r\n\
+             rdb_last_save_time:{}\r\nrdb_last_bgsave_status:ok\r\naof_enabled:0\r\n\
+             aof_rewrite_in_progress:0\r\naof_rewrite_scheduled:0\r\n\
+             aof_last_bgrewrite_status:ok\r\naof_last_write_status:ok\r\n",
+            redis.rdb.last_save
+        );
+        assert_eq!(
+            format!("${}\r\n{}\r\n", persistence.len(), persistence).into_bytes(),
+            info(&mut redis, vec![b"persistence"])
+        );
+    }
+}
@@ -0,0 +1,684 @@
+use std::io::Write;
+
+use crate::{
+    error::{CommandError, RedisError},
+    glob::glob_match,
+    redis::{Redis, RedisResult, db::unix_time_ms, hash_table::HashNode},
+};
+
+impl Redis {
+    // SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]. COUNT is only a hint for the amount
+    // of work, like in redis the filters are applied to the keys of the visited buckets so a call
+    // can return fewer keys or none at all while the cursor is not 0 yet. Expired keys that were
+    // not reclaimed yet are left out
+    pub(super) fn scan(
+        &self,
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: usize,
+        object_type: Option<&[u8]>,
+    ) -> RedisResult {
+        let db = &self.dbs[self.db];
+        let now = unix_time_ms();
+
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        let mut visited = 0;
+        // bounds the work when the dict is sparse or the filters reject most keys
+        let mut max_iterations = count.saturating_mul(10);
+
+        let mut cursor = cursor;
+        loop {
+            cursor = db.dict.scan(cursor, |key, value| {
+                visited += 1;
+
+                if db.is_expired(key, now) {
+                    return;
+                }
+                if pattern.is_some_and(|pattern| !glob_match(pattern, key)) {
+                    return;
+                }
+                if object_type.is_some_and(|object_type| {
+                    !object_type.eq_ignore_ascii_case(value.type_name().as_bytes())
+                }) {
+                    return;
+                }
+
+                keys.push(key.to_vec());
+            });
+
+            max_iterations -= 1;
+            if cursor == 0 || max_iterations == 0 || visited >= count {
+                break;
+            }
+        }
+
+        let mut cursor_digits = Vec::with_capacity(20);
+        write!(cursor_digits, "{}", cursor).unwrap();
+
+        let mut reply = Vec::new();
+        Self::write_array_header(&mut reply, 2);
+        write!(reply, "${}\r\n", cursor_digits.len()).unwrap();
+        reply.extend_from_slice(&cursor_digits);
+        reply.extend_from_slice(b"\r\n");
+
+        Self::write_array_header(&mut reply, keys.len());
+        for key in keys {
+            write!(reply, "${}\r\n", key.len()).unwrap();
+            reply.extend_from_slice(&key);
+            reply.extend_from_slice(b"\r\n");
+        }
+
+        RedisResult::BulkString(reply)
+    }
+
+    pub(super) fn random_key(&mut self) -> RedisResult {
+        let now = unix_time_ms();
+
+        loop {
+            let key = match self.dbs[self.db].dict.random_entry() {
+                Some((key, _)) => key.to_vec(),
+                None => return RedisResult::BulkString(b"$-1\r\n".to_vec()),
+            };
+            // like redis a key whose ttl ran out is deleted and another one is picked, a replica
+            // leaves that to its master and may return it
+            if self.expire_if_needed(self.db, &key, now) {
+                continue;
+            }
+
+            let mut reply = Vec::with_capacity(key.len() + 16);
+            write!(reply, "${}\r\n", key.len()).unwrap();
+            reply.extend_from_slice(&key);
+            reply.extend_from_slice(b"\r\n");
+            return RedisResult::BulkString(reply);
+        }
+    }
+
+    // KEYS pattern, walks the whole dict in one go so it blocks the server for as long as that
+    // takes. Meant for debugging small instances, SCAN is the way to go through a big keyspace
+    pub(super) fn keys(&self, pattern: &[u8]) -> RedisResult {
+        let db = &self.dbs[self.db];
+        let now = unix_time_ms();
+        // like allkeys in redis, the pattern is not even looked at for every key
+        let all_keys = pattern == b"*";
+
+        let mut amount_keys = 0;
+        let mut keys = Vec::new();
+        for (key, _) in db.dict.iter() {
+            if db.is_expired(key, now) {
+                continue;
+            }
+            if all_keys || glob_match(pattern, key) {
+                write!(keys, "${}\r\n", key.len()).unwrap();
+                keys.extend_from_slice(key);
+                keys.extend_from_slice(b"\r\n");
+                amount_keys += 1;
+            }
+        }
+
+        let mut reply = Vec::with_capacity(keys.len() + 16);
+        Self::write_array_header(&mut reply, amount_keys);
+        reply.extend_from_slice(&keys);
+        RedisResult::BulkString(reply)
+    }
+
+    // a key given more than once is counted more than once, like in redis
+    pub(super) fn exists(&self, keys: &[&[u8]]) -> RedisResult {
+        let count = keys
+            .iter()
+            .filter(|key| self.dbs[self.db].dict.peek(key).is_some())
+            .count();
+        RedisResult::Int(count as i64)
+    }
+
+    pub(super) fn key_type(&self, key: &[u8]) -> RedisResult {
+        let type_name = self.dbs[self.db]
+            .dict
+            .peek(key)
+            .map_or("none", |value| value.type_name());
+        RedisResult::BulkString(format!("+{}\r\n", type_name).into_bytes())
+    }
+
+    // the node is taken out of the dict and inserted again under the new key, so the value is
+    // moved and never copied no matter how big it is
+    pub(super) fn rename(&mut self, key: &[u8], new_key: &[u8]) -> RedisResult {
+        let Some((mut node, expire)) = self.dbs[self.db].remove(key) else {
+            return RedisResult::Error(RedisError::CommandError(CommandError::NoSuchKey));
+        };
+
+        // the ttl goes along with the value, one the new key had is dropped
+        node.set_key(Box::from(new_key));
+        self.dbs[self.db].insert(node, expire);
+        self.rdb.dirty += 1;
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+
+    pub(super) fn rename_nx(&mut self, key: &[u8], new_key: &[u8]) -> RedisResult {
+        if self.dbs[self.db].dict.peek(key).is_none() {
+            return RedisResult::Error(RedisError::CommandError(CommandError::NoSuchKey));
+        }
+        // also covers renaming a key to itself
+        if self.dbs[self.db].dict.peek(new_key).is_some() {
+            return RedisResult::Int(0);
+        }
+
+        self.rename(key, new_key);
+        RedisResult::Int(1)
+    }
+
+    pub(super) fn copy(&mut self, source: &[u8], destination: &[u8], replace: bool) -> RedisResult {
+        if source == destination {
+            return RedisResult::Error(RedisError::CommandError(CommandError::SameObject));
+        }
+        if !replace && self.dbs[self.db].dict.peek(destination).is_some() {
+            return RedisResult::Int(0);
+        }
+
+        let db = &mut self.dbs[self.db];
+        match db.dict.peek(source).cloned() {
+            Some(value) => {
+                let node = Box::new(HashNode::new_from_object(destination, value));
+                let expire = db.expire(source);
+                db.insert(node, expire);
+                self.rdb.dirty += 1;
+                RedisResult::Int(1)
+            }
+            None => RedisResult::Int(0),
+        }
+    }
+
+    // the lookup is what touches the key, it records the access like any read
+    pub(super) fn touch(&mut self, keys: &[&[u8]]) -> RedisResult {
+        let mut count = 0;
+        for key in keys {
+            if self.dbs[self.db].dict.lookup(key).is_some() {
+                count += 1;
+            }
+        }
+        RedisResult::Int(count)
+    }
+
+    // values are still freed inline, there is no background thread to hand them to like in
+    // redis, so this is DEL with a different name for now
+    pub(super) fn unlink(&mut self, keys: &[&[u8]]) -> RedisResult {
+        let count = keys
+            .iter()
+            .filter(|key| self.dbs[self.db].delete(key))
+            .count();
+        self.rdb.dirty += count as u64;
+        RedisResult::Int(count as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commands::RedisCommand, redis::resp_or_error};
+
+    // runs a whole scan and returns the keys of every reply
+    fn scan_all(
+        redis: &mut Redis,
+        pattern: Option<&[u8]>,
+        object_type: Option<&[u8]>,
+    ) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+
+        loop {
+            let reply = match redis.execute_command(&RedisCommand::Scan {
+                cursor,
+                pattern,
+                count: 10,
+                object_type,
+            }) {
+                RedisResult::BulkString(reply) => reply,
+                _ => panic!("SCAN should reply with an array"),
+            };
+
+            let (next_cursor, reply_keys) = split_scan_reply(&reply);
+            keys.extend(reply_keys);
+
+            cursor = next_cursor;
+            if cursor == 0 {
+                keys.sort();
+                return keys;
+            }
+        }
+    }
+
+    fn split_scan_reply(reply: &[u8]) -> (u64, Vec<Vec<u8>>) {
+        let mut lines = reply
+            .split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+
+        assert_eq!(Some(&b"*2"[..]), lines.next());
+        lines.next();
+        let cursor = std::str::from_utf8(lines.next().unwrap())
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let amount_keys: usize = std::str::from_utf8(&lines.next().unwrap()[1..])
+            .unwrap()
+            .parse()
+            .unwrap();
+        let keys = (0..amount_keys)
+            .map(|_| {
+                lines.next();
+                lines.next().unwrap().to_vec()
+            })
+            .collect();
+
+        (cursor, keys)
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut redis = Redis::new();
+        assert!(scan_all(&mut redis, None, None).is_empty());
+
+        for i in 0..100 {
+            let key = format!("user:{}", i);
+            redis.execute_command(&RedisCommand::Set {
+                key: key.as_bytes(),
+                value: b"value",
+            });
+        }
+        for i in 0..5 {
+            let key = format!("list:{}", i);
+            redis.execute_command(&RedisCommand::RPush {
+                key: key.as_bytes(),
+                value: b"value",
+            });
+        }
+
+        struct TestData {
+            pattern: Option<&'static [u8]>,
+            object_type: Option<&'static [u8]>,
+            expected: Vec<Vec<u8>>,
+        }
+
+        let mut users: Vec<Vec<u8>> = (0..100)
+            .map(|i| format!("user:{}", i).into_bytes())
+            .collect();
+        users.sort();
+        let lists: Vec<Vec<u8>> = (0..5).map(|i| format!("list:{}", i).into_bytes()).collect();
+
+        let tests = vec![
+            TestData {
+                pattern: None,
+                object_type: None,
+                expected: {
+                    let mut all = [lists.clone(), users.clone()].concat();
+                    all.sort();
+                    all
+                },
+            },
+            TestData {
+                pattern: Some(b"user:1?"),
+                object_type: None,
+                expected: (10..20)
+                    .map(|i| format!("user:{}", i).into_bytes())
+                    .collect(),
+            },
+            TestData {
+                pattern: None,
+                object_type: Some(b"list"),
+                expected: lists.clone(),
+            },
+            TestData {
+                pattern: Some(b"user:*"),
+                object_type: Some(b"STRING"),
+                expected: users.clone(),
+            },
+            TestData {
+                pattern: Some(b"user:*"),
+                object_type: Some(b"list"),
+                expected: vec![],
+            },
+            TestData {
+                pattern: None,
+                object_type: Some(b"hash"),
+                expected: vec![],
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                test.expected,
+                scan_all(&mut redis, test.pattern, test.object_type)
+            );
+        }
+    }
+
+    #[test]
+    fn test_scan_reply() {
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"key",
+            value: b"value",
+        });
+
+        let reply = match redis.execute_command(&RedisCommand::Scan {
+            cursor: 0,
+            pattern: None,
+            count: 10,
+            object_type: None,
+        }) {
+            RedisResult::BulkString(reply) => reply,
+            _ => panic!("SCAN should reply with an array"),
+        };
+
+        // a table this small is done in one call
+        assert_eq!(b"*2\r\n$1\r\n0\r\n*1\r\n$3\r\nkey\r\n".to_vec(), reply);
+    }
+    #[test]
+    fn test_keys() {
+        let mut redis = Redis::new();
+        for key in [&b"user:1"[..], b"user:2", b"user:10", b"session:1", b""] {
+            redis.execute_command(&RedisCommand::Set {
+                key,
+                value: b"value",
+            });
+        }
+
+        // the keys of the reply sorted, the order of the dict is not defined
+        let keys = |redis: &mut Redis, pattern: &[u8]| {
+            let reply = match redis.execute_command(&RedisCommand::Keys { pattern }) {
+                RedisResult::BulkString(reply) => reply,
+                _ => panic!("KEYS should reply with an array"),
+            };
+            let mut lines = reply
+                .split(|&b| b == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+            let amount_keys: usize = std::str::from_utf8(&lines.next().unwrap()[1..])
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut keys: Vec<Vec<u8>> = (0..amount_keys)
+                .map(|_| {
+                    lines.next();
+                    lines.next().unwrap().to_vec()
+                })
+                .collect();
+            keys.sort();
+            keys
+        };
+
+        struct TestData {
+            pattern: &'static [u8],
+            expected: Vec<&'static [u8]>,
+        }
+
+        let tests = vec![
+            // a lone star also returns the empty key, which the matcher itself would not
+            TestData {
+                pattern: b"*",
+                expected: vec![b"", b"session:1", b"user:1", b"user:10", b"user:2"],
+            },
+            TestData {
+                pattern: b"user:?",
+                expected: vec![b"user:1", b"user:2"],
+            },
+            TestData {
+                pattern: b"*:1*",
+                expected: vec![b"session:1", b"user:1", b"user:10"],
+            },
+            TestData {
+                pattern: b"user:[^1]",
+                expected: vec![b"user:2"],
+            },
+            TestData {
+                pattern: b"missing:*",
+                expected: vec![],
+            },
+        ];
+
+        for test in tests {
+            let expected: Vec<Vec<u8>> = test.expected.iter().map(|key| key.to_vec()).collect();
+            assert_eq!(expected, keys(&mut redis, test.pattern));
+        }
+    }
+
+    #[test]
+    fn test_random_key() {
+        let mut redis = Redis::new();
+
+        let random_key = |redis: &mut Redis| match redis.execute_command(&RedisCommand::RandomKey) {
+            RedisResult::BulkString(reply) => reply,
+            _ => panic!("RANDOMKEY should reply with a bulk string"),
+        };
+
+        assert_eq!(b"$-1\r\n".to_vec(), random_key(&mut redis));
+
+        for key in [&b"a"[..], b"b", b"c"] {
+            redis.execute_command(&RedisCommand::Set {
+                key,
+                value: b"value",
+            });
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..100 {
+            seen.insert(random_key(&mut redis));
+        }
+
+        let expected: std::collections::HashSet<Vec<u8>> = [
+            b"$1\r\na\r\n".to_vec(),
+            b"$1\r\nb\r\n".to_vec(),
+            b"$1\r\nc\r\n".to_vec(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expected, seen);
+    }
+
+    fn reply(redis: &mut Redis, command: RedisCommand) -> Result<Vec<u8>, CommandError> {
+        resp_or_error(redis.execute_command(&command))
+    }
+
+    #[test]
+    fn test_keyspace_commands() {
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"string",
+            value: b"value",
+        });
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"list",
+            value: b"value",
+        });
+
+        struct TestData {
+            command: RedisCommand<'static>,
+            expected: Result<&'static [u8], CommandError>,
+        }
+
+        // runs in order, later commands see the keys earlier ones renamed, copied or removed
+        let tests = vec![
+            TestData {
+                command: RedisCommand::Exists {
+                    keys: vec![b"string", b"missing", b"string", b"list"],
+                },
+                expected: Ok(b":3\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Type { key: b"string" },
+                expected: Ok(b"+string\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Type { key: b"list" },
+                expected: Ok(b"+list\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Type { key: b"missing" },
+                expected: Ok(b"+none\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Rename {
+                    key: b"missing",
+                    new_key: b"other",
+                },
+                expected: Err(CommandError::NoSuchKey),
+            },
+            TestData {
+                command: RedisCommand::Rename {
+                    key: b"string",
+                    new_key: b"string",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Rename {
+                    key: b"string",
+                    new_key: b"renamed",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Get { key: b"renamed" },
+                expected: Ok(b"$5\r\nvalue\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Exists {
+                    keys: vec![b"string"],
+                },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: RedisCommand::RenameNx {
+                    key: b"string",
+                    new_key: b"other",
+                },
+                expected: Err(CommandError::NoSuchKey),
+            },
+            TestData {
+                command: RedisCommand::RenameNx {
+                    key: b"renamed",
+                    new_key: b"list",
+                },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: RedisCommand::RenameNx {
+                    key: b"renamed",
+                    new_key: b"string",
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Copy {
+                    source: b"string",
+                    destination: b"string",
+                    replace: false,
+                },
+                expected: Err(CommandError::SameObject),
+            },
+            TestData {
+                command: RedisCommand::Copy {
+                    source: b"missing",
+                    destination: b"copy",
+                    replace: false,
+                },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Copy {
+                    source: b"list",
+                    destination: b"string",
+                    replace: false,
+                },
+                expected: Ok(b":0\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Copy {
+                    source: b"list",
+                    destination: b"string",
+                    replace: true,
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Type { key: b"string" },
+                expected: Ok(b"+list\r\n"),
+            },
+            // the copy does not share the value with the source
+            TestData {
+                command: RedisCommand::RPush {
+                    key: b"string",
+                    value: b"more",
+                },
+                expected: Ok(b"+OK\r\n"),
+            },
+            TestData {
+                command: RedisCommand::LRange {
+                    key: b"list",
+                    start: 0,
+                    stop: -1,
+                },
+                expected: Ok(b"*1\r\n$5\r\nvalue\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Touch {
+                    keys: vec![b"list", b"missing", b"list"],
+                },
+                expected: Ok(b":2\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Unlink {
+                    keys: vec![b"list", b"missing", b"list"],
+                },
+                expected: Ok(b":1\r\n"),
+            },
+            TestData {
+                command: RedisCommand::Exists {
+                    keys: vec![b"list", b"string"],
+                },
+                expected: Ok(b":1\r\n"),
+            },
+        ];
+
+        for test in tests {
+            let expected = test.expected.map(|reply| reply.to_vec());
+            assert_eq!(expected, reply(&mut redis, test.command));
+        }
+    }
+
+    // renaming has to find keys again when the node lands in the other table of a resizing dict
+    #[test]
+    fn test_rename_while_resizing() {
+        let mut redis = Redis::new();
+        for i in 0..1000 {
+            let key = format!("key:{}", i);
+            redis.execute_command(&RedisCommand::Set {
+                key: key.as_bytes(),
+                value: key.as_bytes(),
+            });
+        }
+
+        for i in 0..1000 {
+            let key = format!("key:{}", i);
+            let new_key = format!("renamed:{}", i);
+            let result = reply(
+                &mut redis,
+                RedisCommand::Rename {
+                    key: key.as_bytes(),
+                    new_key: new_key.as_bytes(),
+                },
+            );
+            assert_eq!(Ok(b"+OK\r\n".to_vec()), result);
+        }
+
+        assert_eq!(1000, redis.dbs[0].dict.len());
+        for i in 0..1000 {
+            let new_key = format!("renamed:{}", i);
+            let expected = format!("${}\r\nkey:{}\r\n", 4 + i.to_string().len(), i);
+            let result = reply(
+                &mut redis,
+                RedisCommand::Get {
+                    key: new_key.as_bytes(),
+                },
+            );
+            assert_eq!(Ok(expected.into_bytes()), result);
+        }
+    }
+}
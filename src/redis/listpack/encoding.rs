@@ -0,0 +1,118 @@
+use crate::redis::listpack::{
+    INT13_MASK, INT13_TAG, INT16_TAG, INT24_TAG, INT32_TAG, INT64_TAG, STR6_MASK, STR6_TAG,
+    STR12_MASK, STR12_TAG, STR32_TAG, UINT7_MASK, UINT7_TAG,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum EncodingType {
+    Uint7,
+    Int13,
+    Int16,
+    Int24,
+    Int32,
+    Int64,
+    Str6BitsLength,
+    Str12BitsLength,
+    Str32BitsLength,
+}
+
+impl EncodingType {
+    pub fn from_header(header: u8) -> EncodingType {
+        match Self::try_from_header(header) {
+            Some(encoding_type) => encoding_type,
+            None => panic!("invalid header"),
+        }
+    }
+
+    // same as from_header but for bytes that might not come from a listpack we built ourselves,
+    // 0xF5-0xFE are unused and 0xFF is the end byte
+    pub fn try_from_header(header: u8) -> Option<EncodingType> {
+        match header {
+            INT16_TAG => Some(EncodingType::Int16),
+            INT24_TAG => Some(EncodingType::Int24),
+            INT32_TAG => Some(EncodingType::Int32),
+            INT64_TAG => Some(EncodingType::Int64),
+            STR32_TAG => Some(EncodingType::Str32BitsLength),
+
+            _ if (header & UINT7_MASK) == UINT7_TAG => Some(EncodingType::Uint7),
+            _ if (header & STR6_MASK) == STR6_TAG => Some(EncodingType::Str6BitsLength),
+            _ if (header & INT13_MASK) == INT13_TAG => Some(EncodingType::Int13),
+            _ if (header & STR12_MASK) == STR12_TAG => Some(EncodingType::Str12BitsLength),
+
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_encoding_from_header() {
+        struct TestData {
+            header: u8,
+            expected: EncodingType,
+        }
+
+        let tests = vec![
+            TestData {
+                header: 0b0000_0000,
+                expected: EncodingType::Uint7,
+            },
+            TestData {
+                header: 0b0111_1111,
+                expected: EncodingType::Uint7,
+            },
+            TestData {
+                header: 0b1000_0000,
+                expected: EncodingType::Str6BitsLength,
+            },
+            TestData {
+                header: 0b1011_1111,
+                expected: EncodingType::Str6BitsLength,
+            },
+            TestData {
+                header: 0b1100_0000,
+                expected: EncodingType::Int13,
+            },
+            TestData {
+                header: 0b1101_1111,
+                expected: EncodingType::Int13,
+            },
+            TestData {
+                header: 0b1110_0000,
+                expected: EncodingType::Str12BitsLength,
+            },
+            TestData {
+                header: 0b1110_1111,
+                expected: EncodingType::Str12BitsLength,
+            },
+            TestData {
+                header: 0b1111_0000,
+                expected: EncodingType::Str32BitsLength,
+            },
+            TestData {
+                header: 0b1111_0001,
+                expected: EncodingType::Int16,
+            },
+            TestData {
+                header: 0b1111_0010,
+                expected: EncodingType::Int24,
+            },
+            TestData {
+                header: 0b1111_0011,
+                expected: EncodingType::Int32,
+            },
+            TestData {
+                header: 0b1111_0100,
+                expected: EncodingType::Int64,
+            },
+        ];
+
+        for test in tests {
+            let result = EncodingType::from_header(test.header);
+            assert_eq!(test.expected, result);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::redis::redis_object::try_parse_int;
+
+#[derive(Debug, PartialEq)]
+pub enum ListPackEntry<'a> {
+    Uint7(u8),
+    Int13(i16),
+    Int16(i16),
+    Int24(i32),
+    Int32(i32),
+    Int64(i64),
+
+    // String encodings
+    Str6BitsLength(&'a [u8]),  // 6-bit immediate
+    Str12BitsLength(&'a [u8]), // 12-bit big-endian
+    Str32BitsLength(&'a [u8]), // 32-bit little-endian
+}
+
+impl<'a> ListPackEntry<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> ListPackEntry<'a> {
+        const INT13_MIN: i64 = -4096;
+        const INT13_MAX: i64 = 4095;
+        const INT16_MIN: i64 = i16::MIN as i64;
+        const INT16_MAX: i64 = i16::MAX as i64;
+        const INT24_MIN: i64 = -8388608;
+        const INT24_MAX: i64 = 8388607;
+        const INT32_MIN: i64 = i32::MIN as i64;
+        const INT32_MAX: i64 = i32::MAX as i64;
+
+        const U32_MAX: usize = u32::MAX as usize;
+
+        match try_parse_int(bytes) {
+            Some(i) => match i {
+                0..=127 => ListPackEntry::Uint7(i as u8),
+                INT13_MIN..=INT13_MAX => ListPackEntry::Int13(i as i16),
+                INT16_MIN..=INT16_MAX => ListPackEntry::Int16(i as i16),
+                INT24_MIN..=INT24_MAX => ListPackEntry::Int24(i as i32),
+                INT32_MIN..=INT32_MAX => ListPackEntry::Int32(i as i32),
+                _ => ListPackEntry::Int64(i),
+            },
+            None => match bytes.len() {
+                0..=63 => ListPackEntry::Str6BitsLength(bytes),
+                64..=4095 => ListPackEntry::Str12BitsLength(bytes),
+                4096..=U32_MAX => ListPackEntry::Str32BitsLength(bytes),
+                _ => panic!("string to long for listpack"),
+            },
+        }
+    }
+
+    // gives the length of the entry in how many bytes header + payload, the backlen is not
+    // included
+    pub fn amount_bytes(&self) -> usize {
+        match self {
+            ListPackEntry::Uint7(_) => 1,
+            ListPackEntry::Int13(_) => 2,
+            ListPackEntry::Int16(_) => 3,
+            ListPackEntry::Int24(_) => 4,
+            ListPackEntry::Int32(_) => 5,
+            ListPackEntry::Int64(_) => 9,
+            ListPackEntry::Str6BitsLength(s) => s.len() + 1,
+            ListPackEntry::Str12BitsLength(s) => s.len() + 2,
+            ListPackEntry::Str32BitsLength(s) => s.len() + 5,
+        }
+    }
+}
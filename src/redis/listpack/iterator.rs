@@ -0,0 +1,61 @@
+use crate::redis::listpack::{LP_END, LP_HEADERS_SIZE, get_entry_total_size, get_prev_offset};
+
+pub struct ListPackIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ListPackIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ListPackIter {
+            data,
+            offset: LP_HEADERS_SIZE,
+        }
+    }
+}
+
+impl<'a> Iterator for ListPackIter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() - 1 || self.data[self.offset] == LP_END {
+            return None;
+        }
+
+        let current_offset = self.offset;
+
+        // entries carry their own backlen so the next entry starts right after it
+        self.offset += get_entry_total_size(&self.data[self.offset..]);
+
+        Some(current_offset)
+    }
+}
+
+pub struct ListPackIterRev<'a> {
+    data: &'a [u8],
+    // offset of the entry after the one that is yielded next, starts at the end byte
+    offset: usize,
+}
+
+impl<'a> ListPackIterRev<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ListPackIterRev {
+            data,
+            offset: data.len() - 1,
+        }
+    }
+}
+
+impl<'a> Iterator for ListPackIterRev<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset <= LP_HEADERS_SIZE {
+            return None;
+        }
+
+        self.offset = get_prev_offset(self.data, self.offset);
+
+        Some(self.offset)
+    }
+}
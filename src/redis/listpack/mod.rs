@@ -0,0 +1,1211 @@
+mod encoding;
+mod entry;
+mod iterator;
+mod validation;
+
+use std::mem;
+
+pub use encoding::EncodingType;
+pub use entry::ListPackEntry;
+pub use iterator::{ListPackIter, ListPackIterRev};
+
+use crate::redis::redis_object::RedisObject;
+
+const LP_HEADERS_SIZE: usize = mem::size_of::<u32>() + mem::size_of::<u16>();
+const LP_END_SIZE: usize = mem::size_of::<u8>();
+const LP_END: u8 = 0xFF;
+
+// the element count saturates here, after that the list has to be walked to get the length
+const LP_LEN_UNKNOWN: u16 = u16::MAX;
+
+const UINT7_TAG: u8 = 0b0000_0000;
+const STR6_TAG: u8 = 0b1000_0000;
+const INT13_TAG: u8 = 0b1100_0000;
+const STR12_TAG: u8 = 0b1110_0000;
+const STR32_TAG: u8 = 0b1111_0000;
+const INT16_TAG: u8 = 0b1111_0001;
+const INT24_TAG: u8 = 0b1111_0010;
+const INT32_TAG: u8 = 0b1111_0011;
+const INT64_TAG: u8 = 0b1111_0100;
+
+const UINT7_MASK: u8 = 0b1000_0000;
+const STR6_MASK: u8 = 0b1100_0000;
+const INT13_MASK: u8 = 0b1110_0000;
+const STR12_MASK: u8 = 0b1111_0000;
+
+// A listpack stores the length of every entry *after* the entry (the backlen) instead of the
+// length of the previous entry in front of it like the ziplist does. An entry never depends on
+// the size of its neighbours so inserts, deletes and replaces can never cascade.
+//
+// layout: <lp bytes u32> <lp len u16> <entry> ... <entry> <0xFF>
+// entry:  <encoding + data> <backlen>
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListPack {
+    data: Vec<u8>,
+}
+
+impl Default for ListPack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ListPack {
+    pub fn new() -> ListPack {
+        const LP_BYTES: u32 = (LP_HEADERS_SIZE + LP_END_SIZE) as u32;
+        const LP_LEN: u16 = 0;
+
+        let mut data = Vec::<u8>::with_capacity(LP_BYTES as usize);
+        data.extend_from_slice(&LP_BYTES.to_le_bytes());
+        data.extend_from_slice(&LP_LEN.to_le_bytes());
+        data.push(LP_END);
+
+        ListPack { data }
+    }
+
+    pub fn len(&self) -> usize {
+        let len = self.get_lp_len();
+        if len != LP_LEN_UNKNOWN {
+            return len as usize;
+        }
+
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data[LP_HEADERS_SIZE] == LP_END
+    }
+
+    // bytes reserved for the buffer, which can be more than the encoded size
+    pub fn allocated_bytes(&self) -> usize {
+        self.data.capacity()
+    }
+
+    // the encoded list, laid out the same as in redis
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn iter(&self) -> ListPackIter<'_> {
+        ListPackIter::new(&self.data)
+    }
+
+    pub fn iter_rev(&self) -> ListPackIterRev<'_> {
+        ListPackIterRev::new(&self.data)
+    }
+
+    pub fn push(&mut self, entry: ListPackEntry) {
+        let end_offset = self.data.len() - 1;
+        self.insert_at_offset(end_offset, entry);
+    }
+
+    pub fn insert(&mut self, index: usize, entry: ListPackEntry) {
+        let len = self.len();
+
+        // if the index is at the end just use the push logic
+        if index == len {
+            self.push(entry);
+            return;
+        } else if index > len {
+            panic!("inserted in a index that does not exist")
+        }
+
+        let offset = self.get_index_offset(index);
+
+        self.insert_at_offset(offset, entry);
+    }
+
+    pub fn insert_at_offset(&mut self, offset: usize, entry: ListPackEntry) {
+        let entry_len = entry.amount_bytes();
+        let total_insertion_len = entry_len + get_backlen_size(entry_len);
+
+        unsafe {
+            self.shift_bytes(offset, total_insertion_len);
+
+            let mut write_ptr = self.data.as_mut_ptr().add(offset);
+            Self::write_entry(write_ptr, entry);
+            write_ptr = write_ptr.add(entry_len);
+            Self::write_backlen(write_ptr, entry_len);
+        }
+
+        self.increment_lp_bytes(total_insertion_len as u32);
+        self.increment_lp_len(1);
+    }
+
+    pub fn replace(&mut self, index: usize, entry: ListPackEntry) {
+        let offset = self.get_index_offset(index);
+        self.replace_at_offset(offset, entry);
+    }
+
+    pub fn replace_at_offset(&mut self, offset: usize, entry: ListPackEntry) {
+        if self.data[offset] == LP_END {
+            panic!("replaced past the end of the listpack");
+        }
+
+        let old_total_len = get_entry_total_size(&self.data[offset..]);
+
+        let entry_len = entry.amount_bytes();
+        let new_total_len = entry_len + get_backlen_size(entry_len);
+
+        // only the bytes of this entry move, nothing after it has to be rewritten
+        if new_total_len > old_total_len {
+            let grow_by = new_total_len - old_total_len;
+            unsafe {
+                self.shift_bytes(offset + old_total_len, grow_by);
+            }
+            self.increment_lp_bytes(grow_by as u32);
+        } else if new_total_len < old_total_len {
+            let shrink_by = old_total_len - new_total_len;
+            self.data
+                .drain(offset + new_total_len..offset + old_total_len);
+            self.decrement_lp_bytes(shrink_by as u32);
+        }
+
+        unsafe {
+            let mut write_ptr = self.data.as_mut_ptr().add(offset);
+            Self::write_entry(write_ptr, entry);
+            write_ptr = write_ptr.add(entry_len);
+            Self::write_backlen(write_ptr, entry_len);
+        }
+    }
+
+    pub fn remove_at_index(&mut self, index: usize) {
+        let offset = self.get_index_offset(index);
+        self.remove_at_offset(offset);
+    }
+
+    pub fn remove_at_offset(&mut self, offset: usize) {
+        if self.data[offset] == LP_END {
+            panic!("removed past the end of the listpack");
+        }
+
+        let bytes_deleted = get_entry_total_size(&self.data[offset..]);
+
+        self.data.drain(offset..offset + bytes_deleted);
+
+        self.decrement_lp_bytes(bytes_deleted as u32);
+        self.decrement_lp_len(1);
+    }
+
+    pub fn get(&self, index: usize) -> RedisObject {
+        let offset = self.get_index_offset(index);
+        self.get_at_offset(offset)
+    }
+
+    pub fn get_at_offset(&self, offset: usize) -> RedisObject {
+        if self.data[offset] == LP_END {
+            panic!("invalid encoding");
+        }
+
+        let encoding_type = EncodingType::from_header(self.data[offset]);
+        let entry = &self.data[offset..];
+
+        match encoding_type {
+            EncodingType::Uint7 => RedisObject::Int((entry[0] & 0b0111_1111) as i64),
+            EncodingType::Int13 => {
+                let unsigned = (((entry[0] & 0b0001_1111) as u16) << 8) | entry[1] as u16;
+                // move the sign bit of the 13 bit number to the top and shift it back down
+                let num = ((unsigned << 3) as i16) >> 3;
+                RedisObject::Int(num as i64)
+            }
+            EncodingType::Int16 => {
+                let num = i16::from_le_bytes([entry[1], entry[2]]);
+                RedisObject::Int(num as i64)
+            }
+            EncodingType::Int24 => {
+                let num = Self::i24_from_le_bytes([entry[1], entry[2], entry[3]]);
+                RedisObject::Int(num as i64)
+            }
+            EncodingType::Int32 => {
+                let num = i32::from_le_bytes([entry[1], entry[2], entry[3], entry[4]]);
+                RedisObject::Int(num as i64)
+            }
+            EncodingType::Int64 => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&entry[1..9]);
+                RedisObject::Int(i64::from_le_bytes(bytes))
+            }
+            EncodingType::Str6BitsLength => {
+                let str_len = (entry[0] & 0b0011_1111) as usize;
+                RedisObject::String(Box::from(&entry[1..1 + str_len]))
+            }
+            EncodingType::Str12BitsLength => {
+                let str_len = u16::from_be_bytes([entry[0] & 0b0000_1111, entry[1]]) as usize;
+                RedisObject::String(Box::from(&entry[2..2 + str_len]))
+            }
+            EncodingType::Str32BitsLength => {
+                let str_len = u32::from_le_bytes([entry[1], entry[2], entry[3], entry[4]]) as usize;
+                RedisObject::String(Box::from(&entry[5..5 + str_len]))
+            }
+        }
+    }
+
+    pub fn pop_head(&mut self) -> RedisObject {
+        let object = self.get_at_offset(LP_HEADERS_SIZE);
+        self.remove_at_offset(LP_HEADERS_SIZE);
+
+        object
+    }
+
+    pub fn pop_tail(&mut self) -> RedisObject {
+        let tail_offset = get_prev_offset(&self.data, self.data.len() - 1);
+        let object = self.get_at_offset(tail_offset);
+        self.remove_at_offset(tail_offset);
+
+        object
+    }
+
+    unsafe fn write_entry(ptr: *mut u8, entry: ListPackEntry) {
+        unsafe {
+            match entry {
+                ListPackEntry::Uint7(i) => {
+                    *ptr = i & 0b0111_1111;
+                }
+                ListPackEntry::Int13(i) => {
+                    let unsigned = (i as u16) & 0x1FFF;
+                    *ptr = ((unsigned >> 8) as u8) | INT13_TAG;
+                    *ptr.add(1) = unsigned as u8;
+                }
+                ListPackEntry::Int16(i) => {
+                    *ptr = INT16_TAG;
+                    std::ptr::copy_nonoverlapping(i.to_le_bytes().as_ptr(), ptr.add(1), 2);
+                }
+                ListPackEntry::Int24(i) => {
+                    *ptr = INT24_TAG;
+                    std::ptr::copy_nonoverlapping(i.to_le_bytes().as_ptr(), ptr.add(1), 3);
+                }
+                ListPackEntry::Int32(i) => {
+                    *ptr = INT32_TAG;
+                    std::ptr::copy_nonoverlapping(i.to_le_bytes().as_ptr(), ptr.add(1), 4);
+                }
+                ListPackEntry::Int64(i) => {
+                    *ptr = INT64_TAG;
+                    std::ptr::copy_nonoverlapping(i.to_le_bytes().as_ptr(), ptr.add(1), 8);
+                }
+                ListPackEntry::Str6BitsLength(s) => {
+                    *ptr = (s.len() as u8 & 0b0011_1111) | STR6_TAG;
+                    std::ptr::copy_nonoverlapping(s.as_ptr(), ptr.add(1), s.len());
+                }
+                ListPackEntry::Str12BitsLength(s) => {
+                    let str_len = s.len() as u16;
+                    *ptr = ((str_len >> 8) as u8 & 0b0000_1111) | STR12_TAG;
+                    *ptr.add(1) = str_len as u8;
+                    std::ptr::copy_nonoverlapping(s.as_ptr(), ptr.add(2), s.len());
+                }
+                ListPackEntry::Str32BitsLength(s) => {
+                    let str_len = s.len() as u32;
+
+                    *ptr = STR32_TAG;
+                    std::ptr::copy_nonoverlapping(str_len.to_le_bytes().as_ptr(), ptr.add(1), 4);
+                    std::ptr::copy_nonoverlapping(s.as_ptr(), ptr.add(5), s.len());
+                }
+            }
+        }
+    }
+
+    // the backlen is written big-endian in 7 bit groups, every byte except the first one has the
+    // high bit set so it can be decoded by walking backwards from the last byte
+    unsafe fn write_backlen(ptr: *mut u8, entry_len: usize) {
+        let backlen_size = get_backlen_size(entry_len);
+
+        unsafe {
+            for i in 0..backlen_size {
+                let shift = 7 * (backlen_size - 1 - i);
+                let mut byte = ((entry_len >> shift) & 0b0111_1111) as u8;
+                if i > 0 {
+                    byte |= 0b1000_0000;
+                }
+                *ptr.add(i) = byte;
+            }
+        }
+    }
+
+    // Helpers
+
+    fn get_index_offset(&self, index: usize) -> usize {
+        let len = self.len();
+        assert!(index < len, "index out of bounds");
+
+        // walk from whichever end is closest
+        if index < len / 2 {
+            self.iter().nth(index).unwrap()
+        } else {
+            self.iter_rev().nth(len - index - 1).unwrap()
+        }
+    }
+
+    unsafe fn shift_bytes(&mut self, offset: usize, n: usize) {
+        let original_len = self.data.len();
+
+        debug_assert!(offset <= original_len, "index out of bounds");
+
+        self.data.reserve(n);
+
+        unsafe {
+            let ptr = self.data.as_mut_ptr();
+
+            std::ptr::copy(ptr.add(offset), ptr.add(offset + n), original_len - offset);
+
+            self.data.set_len(original_len + n);
+        }
+    }
+
+    #[inline(always)]
+    fn get_lp_bytes(&self) -> u32 {
+        debug_assert!(self.data.len() >= LP_HEADERS_SIZE + LP_END_SIZE);
+        u32::from_le_bytes([self.data[0], self.data[1], self.data[2], self.data[3]])
+    }
+
+    #[inline(always)]
+    fn get_lp_len(&self) -> u16 {
+        debug_assert!(self.data.len() >= LP_HEADERS_SIZE + LP_END_SIZE);
+        u16::from_le_bytes([self.data[4], self.data[5]])
+    }
+
+    #[inline(always)]
+    fn set_lp_bytes(&mut self, new_value: u32) {
+        self.data[0..4].copy_from_slice(&new_value.to_le_bytes());
+    }
+
+    #[inline(always)]
+    fn set_lp_len(&mut self, new_value: u16) {
+        self.data[4..6].copy_from_slice(&new_value.to_le_bytes());
+    }
+
+    #[inline(always)]
+    fn increment_lp_bytes(&mut self, n: u32) {
+        let num = self.get_lp_bytes() + n;
+        self.set_lp_bytes(num);
+    }
+
+    #[inline(always)]
+    fn decrement_lp_bytes(&mut self, n: u32) {
+        let num = self.get_lp_bytes() - n;
+        self.set_lp_bytes(num);
+    }
+
+    #[inline(always)]
+    fn increment_lp_len(&mut self, n: u16) {
+        let num = self.get_lp_len();
+        if num == LP_LEN_UNKNOWN {
+            return;
+        }
+
+        self.set_lp_len(num.saturating_add(n));
+    }
+
+    #[inline(always)]
+    fn decrement_lp_len(&mut self, n: u16) {
+        let num = self.get_lp_len();
+        if num == LP_LEN_UNKNOWN {
+            return;
+        }
+
+        self.set_lp_len(num - n);
+    }
+
+    #[inline(always)]
+    fn i24_from_le_bytes(num: [u8; 3]) -> i32 {
+        let value = (num[0] as i32) | ((num[1] as i32) << 8) | ((num[2] as i32) << 16);
+        (value << 8) >> 8
+    }
+}
+
+// Global helpers
+
+#[inline(always)]
+fn get_backlen_size(entry_len: usize) -> usize {
+    match entry_len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+// decodes the backlen that ends at `end` (inclusive), returns the entry length and how many bytes
+// the backlen took up
+fn decode_backlen(data: &[u8], mut end: usize) -> (usize, usize) {
+    let mut value = 0;
+    let mut shift = 0;
+    let mut size = 0;
+
+    loop {
+        let byte = data[end];
+        value |= ((byte & 0b0111_1111) as usize) << shift;
+        size += 1;
+
+        if byte & 0b1000_0000 == 0 {
+            return (value, size);
+        }
+
+        shift += 7;
+        end -= 1;
+    }
+}
+
+// size of the encoding + data of the entry at the start of `entry`
+fn get_entry_size(entry: &[u8]) -> usize {
+    match EncodingType::from_header(entry[0]) {
+        EncodingType::Uint7 => 1,
+        EncodingType::Int13 => 2,
+        EncodingType::Int16 => 3,
+        EncodingType::Int24 => 4,
+        EncodingType::Int32 => 5,
+        EncodingType::Int64 => 9,
+        EncodingType::Str6BitsLength => 1 + (entry[0] & 0b0011_1111) as usize,
+        EncodingType::Str12BitsLength => {
+            2 + u16::from_be_bytes([entry[0] & 0b0000_1111, entry[1]]) as usize
+        }
+        EncodingType::Str32BitsLength => {
+            5 + u32::from_le_bytes([entry[1], entry[2], entry[3], entry[4]]) as usize
+        }
+    }
+}
+
+#[inline(always)]
+fn get_entry_total_size(entry: &[u8]) -> usize {
+    let entry_len = get_entry_size(entry);
+    entry_len + get_backlen_size(entry_len)
+}
+
+// offset of the entry in front of the entry (or end byte) at `offset`
+#[inline(always)]
+fn get_prev_offset(data: &[u8], offset: usize) -> usize {
+    let (entry_len, backlen_size) = decode_backlen(data, offset - 1);
+    offset - backlen_size - entry_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_pack_entry_from_bytes() {
+        struct TestData {
+            obj: &'static [u8],
+            expected: ListPackEntry<'static>,
+        }
+
+        let tests = vec![
+            TestData {
+                obj: b"5",
+                expected: ListPackEntry::Uint7(5),
+            },
+            TestData {
+                obj: b"0",
+                expected: ListPackEntry::Uint7(0),
+            },
+            TestData {
+                obj: b"127",
+                expected: ListPackEntry::Uint7(127),
+            },
+            TestData {
+                obj: b"128",
+                expected: ListPackEntry::Int13(128),
+            },
+            TestData {
+                obj: b"-1",
+                expected: ListPackEntry::Int13(-1),
+            },
+            TestData {
+                obj: b"4095",
+                expected: ListPackEntry::Int13(4095),
+            },
+            TestData {
+                obj: b"-4096",
+                expected: ListPackEntry::Int13(-4096),
+            },
+            TestData {
+                obj: b"4096",
+                expected: ListPackEntry::Int16(4096),
+            },
+            TestData {
+                obj: b"32767",
+                expected: ListPackEntry::Int16(32767),
+            },
+            TestData {
+                obj: b"-32768",
+                expected: ListPackEntry::Int16(-32768),
+            },
+            TestData {
+                obj: b"100000",
+                expected: ListPackEntry::Int24(100000),
+            },
+            TestData {
+                obj: b"-8388608",
+                expected: ListPackEntry::Int24(-8388608),
+            },
+            TestData {
+                obj: b"2147483647",
+                expected: ListPackEntry::Int32(2147483647),
+            },
+            TestData {
+                obj: b"-2147483648",
+                expected: ListPackEntry::Int32(-2147483648),
+            },
+            TestData {
+                obj: b"5000000000",
+                expected: ListPackEntry::Int64(5000000000),
+            },
+            TestData {
+                obj: b"-9223372036854775808",
+                expected: ListPackEntry::Int64(-9223372036854775808),
+            },
+            // strings
+            TestData {
+                obj: b"hello",
+                expected: ListPackEntry::Str6BitsLength(b"hello"),
+            },
+            TestData {
+                obj: b"",
+                expected: ListPackEntry::Str6BitsLength(b""),
+            },
+            TestData {
+                obj: b"01",
+                expected: ListPackEntry::Str6BitsLength(b"01"),
+            },
+            TestData {
+                obj: &[b'a'; 63],
+                expected: ListPackEntry::Str6BitsLength(&[b'a'; 63]),
+            },
+            TestData {
+                obj: &[b'b'; 64],
+                expected: ListPackEntry::Str12BitsLength(&[b'b'; 64]),
+            },
+            TestData {
+                obj: &[b'c'; 4095],
+                expected: ListPackEntry::Str12BitsLength(&[b'c'; 4095]),
+            },
+            TestData {
+                obj: &[b'd'; 4096],
+                expected: ListPackEntry::Str32BitsLength(&[b'd'; 4096]),
+            },
+        ];
+
+        for test in tests {
+            let result = ListPackEntry::from_bytes(test.obj);
+            assert_eq!(test.expected, result);
+        }
+    }
+
+    #[test]
+    fn test_backlen_encoding() {
+        struct TestData {
+            entry_len: usize,
+            expected: Vec<u8>,
+        }
+
+        let tests = vec![
+            TestData {
+                entry_len: 1,
+                expected: vec![1],
+            },
+            TestData {
+                entry_len: 127,
+                expected: vec![127],
+            },
+            TestData {
+                entry_len: 202,
+                expected: vec![0x01, 0xCA],
+            },
+            TestData {
+                entry_len: 16382,
+                expected: vec![0x7F, 0xFE],
+            },
+            TestData {
+                entry_len: 70005,
+                expected: vec![0x04, 0xA2, 0xF5],
+            },
+            TestData {
+                entry_len: 300_000_000,
+                expected: vec![0x01, 0x8F, 0x86, 0xC6, 0x80],
+            },
+        ];
+
+        for test in tests {
+            let mut buf = vec![0u8; get_backlen_size(test.entry_len)];
+            unsafe {
+                ListPack::write_backlen(buf.as_mut_ptr(), test.entry_len);
+            }
+            assert_eq!(test.expected, buf);
+
+            let (decoded, size) = decode_backlen(&buf, buf.len() - 1);
+            assert_eq!(test.entry_len, decoded);
+            assert_eq!(buf.len(), size);
+        }
+    }
+
+    #[test]
+    fn test_list_pack_push() {
+        struct TestData {
+            entries: Vec<ListPackEntry<'static>>,
+            expected: Vec<u8>,
+        }
+
+        let tests = vec![
+            TestData {
+                entries: vec![ListPackEntry::Uint7(5)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 9, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data + tag*/ 5, /*backlen*/ 1,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Int13(-100)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 10, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data + tag*/ 0xDF, 0x9C, /*backlen*/ 2,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Int13(1000)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 10, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data + tag*/ 0xC3, 0xE8, /*backlen*/ 2,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Int16(10000)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 11, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data*/ INT16_TAG, 0x10, 0x27, /*backlen*/ 3,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Int24(8388607)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 12, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data*/ INT24_TAG, 0xFF, 0xFF, 0x7F, /*backlen*/ 4,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Int32(2147483647)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 13, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data*/ INT32_TAG, 0xFF, 0xFF, 0xFF, 0x7F, /*backlen*/ 5,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Int64(5000000000)],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 17, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data*/ INT64_TAG, 0x00, 0xF2, 0x05, 0x2A, 0x01, 0x00, 0x00, 0x00, /*backlen*/ 9,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![
+                    ListPackEntry::Uint7(5),
+                    ListPackEntry::Int13(1000),
+                    ListPackEntry::Str6BitsLength(b"hello"),
+                ],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 19, 0, 0, 0, /*lp len*/ 3, 0,
+                    /*data + tag*/ 5, /*backlen*/ 1,
+                    /*data + tag*/ 0xC3, 0xE8, /*backlen*/ 2,
+                    /*tag*/ 0b10_000101, /*data*/ b'h', b'e', b'l', b'l', b'o', /*backlen*/ 6,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![ListPackEntry::Str12BitsLength(&[b'a'; 200])],
+                #[rustfmt::skip]
+                expected: {
+                    let mut e = vec![
+                    /*lp bytes*/ 0xD3, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*tag*/ 0b1110_0000, 0xC8,
+                    ];
+                    e.extend_from_slice(&[b'a'; 200]);
+                    e.extend_from_slice(&[/*backlen*/ 0x01, 0xCA]);
+                    e.push(0xFF);
+                    e
+                },
+            },
+            TestData {
+                entries: vec![ListPackEntry::Str32BitsLength(&[b'b'; 70_000])],
+                #[rustfmt::skip]
+                expected: {
+                    let mut e = vec![
+                    /*lp bytes*/ 0x7F, 0x11, 0x01, 0x00, /*lp len*/ 1, 0,
+                    /*tag*/ STR32_TAG, 0x70, 0x11, 0x01, 0x00,
+                    ];
+                    e.extend_from_slice(&[b'b'; 70_000]);
+                    e.extend_from_slice(&[/*backlen*/ 0x04, 0xA2, 0xF5]);
+                    e.push(0xFF);
+                    e
+                },
+            },
+        ];
+
+        for test in tests {
+            let mut lp = ListPack::new();
+            for entry in test.entries {
+                lp.push(entry);
+            }
+
+            assert_eq!(&test.expected, &lp.data);
+        }
+    }
+
+    #[test]
+    fn test_list_pack_insert() {
+        struct InsertEntry {
+            entry: ListPackEntry<'static>,
+            index: usize,
+        }
+
+        struct TestData {
+            entries: Vec<InsertEntry>,
+            expected: Vec<u8>,
+        }
+
+        let tests = vec![
+            TestData {
+                entries: vec![
+                    InsertEntry {
+                        entry: ListPackEntry::Uint7(5),
+                        index: 0,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Uint7(4),
+                        index: 0,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Uint7(3),
+                        index: 1,
+                    },
+                ],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 13, 0, 0, 0, /*lp len*/ 3, 0,
+                    /*data + tag*/ 4, /*backlen*/ 1,
+                    /*data + tag*/ 3, /*backlen*/ 1,
+                    /*data + tag*/ 5, /*backlen*/ 1,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                entries: vec![
+                    InsertEntry {
+                        entry: ListPackEntry::Int13(50),
+                        index: 0,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Int16(-30000),
+                        index: 0,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Int24(8388607),
+                        index: 1,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Int32(2147483647),
+                        index: 2,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Int64(5000000000),
+                        index: 1,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Uint7(4),
+                        index: 3,
+                    },
+                ],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 37, 0, 0, 0, /*lp len*/ 6, 0,
+
+                    /*data + tag*/ INT16_TAG, 0xD0, 0x8A, /*backlen*/ 3,
+
+                    /*data + tag*/ INT64_TAG, 0x00, 0xF2, 0x05, 0x2A, 0x01, 0x00, 0x00, 0x00, /*backlen*/ 9,
+
+                    /*data + tag*/ INT24_TAG, 0xFF, 0xFF, 0x7F, /*backlen*/ 4,
+
+                    /*data + tag*/ 4, /*backlen*/ 1,
+
+                    /*data + tag*/ INT32_TAG, 0xFF, 0xFF, 0xFF, 0x7F, /*backlen*/ 5,
+
+                    /*data + tag*/ INT13_TAG, 50, /*backlen*/ 2,
+
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            // string tests
+            TestData {
+                entries: vec![
+                    InsertEntry {
+                        entry: ListPackEntry::Str32BitsLength(&[b'b'; 70_000]),
+                        index: 0,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Str12BitsLength(&[b'a'; 70]),
+                        index: 0,
+                    },
+                    InsertEntry {
+                        entry: ListPackEntry::Str6BitsLength(b"Hello World"),
+                        index: 0,
+                    },
+                ],
+                #[rustfmt::skip]
+                expected: {
+                    let mut e = vec![
+                    /*lp bytes*/ 0xD5, 0x11, 0x01, 0x00, /*lp len*/ 3, 0,
+                    /*tag*/ 0b10_001011,
+                    /*data*/ 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x20, 0x57, 0x6F, 0x72, 0x6C, 0x64,
+                    /*backlen*/ 12,
+                    /*tag*/ 0b1110_0000, 0b0_1000110,
+                    ];
+                    e.extend_from_slice(&[b'a'; 70]); // add 70 bytes string
+                    e.extend_from_slice(&[
+                    /*backlen*/ 72,
+                    /*tag*/ STR32_TAG, 0x70, 0x11, 0x01, 0x00,
+                    ]);
+                    e.extend_from_slice(&[b'b'; 70_000]); // add 70 000 bytes string
+                    e.extend_from_slice(&[/*backlen*/ 0x04, 0xA2, 0xF5]);
+                    e.push(0xFF);
+                    e
+                },
+            },
+        ];
+
+        for test in tests {
+            let mut lp = ListPack::new();
+            for entry in test.entries {
+                lp.insert(entry.index, entry.entry);
+            }
+
+            assert_eq!(&test.expected, &lp.data);
+        }
+    }
+
+    #[test]
+    fn test_list_pack_remove() {
+        struct TestData {
+            init_state: Vec<u8>,
+            deletions: Vec<usize>,
+            expected: Vec<u8>,
+        }
+
+        #[rustfmt::skip]
+        let int_state = vec![
+            /*lp bytes*/ 37, 0, 0, 0, /*lp len*/ 6, 0,
+            /*data + tag*/ INT16_TAG, 0xD0, 0x8A, /*backlen*/ 3,
+            /*data + tag*/ INT64_TAG, 0x00, 0xF2, 0x05, 0x2A, 0x01, 0x00, 0x00, 0x00, /*backlen*/ 9,
+            /*data + tag*/ INT24_TAG, 0xFF, 0xFF, 0x7F, /*backlen*/ 4,
+            /*data + tag*/ 4, /*backlen*/ 1,
+            /*data + tag*/ INT32_TAG, 0xFF, 0xFF, 0xFF, 0x7F, /*backlen*/ 5,
+            /*data + tag*/ INT13_TAG, 50, /*backlen*/ 2,
+            /*lp end*/ 0xFF,
+        ];
+
+        #[rustfmt::skip]
+        let string_state = {
+            let mut e = vec![
+            /*lp bytes*/ 0xD5, 0x11, 0x01, 0x00, /*lp len*/ 3, 0,
+            /*tag*/ 0b10_001011,
+            /*data*/ 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x20, 0x57, 0x6F, 0x72, 0x6C, 0x64,
+            /*backlen*/ 12,
+            /*tag*/ 0b1110_0000, 0b0_1000110,
+            ];
+            e.extend_from_slice(&[b'a'; 70]);
+            e.extend_from_slice(&[/*backlen*/ 72, /*tag*/ STR32_TAG, 0x70, 0x11, 0x01, 0x00]);
+            e.extend_from_slice(&[b'b'; 70_000]);
+            e.extend_from_slice(&[/*backlen*/ 0x04, 0xA2, 0xF5]);
+            e.push(0xFF);
+            e
+        };
+
+        let tests = vec![
+            TestData {
+                #[rustfmt::skip]
+                init_state: vec![
+                    /*lp bytes*/ 9, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*data + tag*/ 5, /*backlen*/ 1,
+                    /*lp end*/ 0xFF,
+                ],
+                deletions: vec![0],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 7, 0, 0, 0, /*lp len*/ 0, 0,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                init_state: int_state.clone(),
+                deletions: vec![1, 4],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 24, 0, 0, 0, /*lp len*/ 4, 0,
+                    /*data + tag*/ INT16_TAG, 0xD0, 0x8A, /*backlen*/ 3,
+                    /*data + tag*/ INT24_TAG, 0xFF, 0xFF, 0x7F, /*backlen*/ 4,
+                    /*data + tag*/ 4, /*backlen*/ 1,
+                    /*data + tag*/ INT32_TAG, 0xFF, 0xFF, 0xFF, 0x7F, /*backlen*/ 5,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                init_state: int_state,
+                deletions: vec![3, 4, 0, 1, 0, 0],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 7, 0, 0, 0, /*lp len*/ 0, 0,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            // string tests
+            TestData {
+                init_state: string_state.clone(),
+                deletions: vec![1, 1],
+                #[rustfmt::skip]
+                expected: vec![
+                    /*lp bytes*/ 20, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*tag*/ 0b10_001011,
+                    /*data*/ 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x20, 0x57, 0x6F, 0x72, 0x6C, 0x64,
+                    /*backlen*/ 12,
+                    /*lp end*/ 0xFF,
+                ],
+            },
+            TestData {
+                init_state: string_state,
+                deletions: vec![0, 1],
+                #[rustfmt::skip]
+                expected: {
+                    let mut e = vec![
+                    /*lp bytes*/ 80, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*tag*/ 0b1110_0000, 0b0_1000110,
+                    ];
+                    e.extend_from_slice(&[b'a'; 70]);
+                    e.push(/*backlen*/ 72);
+                    e.push(0xFF);
+                    e
+                },
+            },
+        ];
+
+        for test in tests {
+            let mut lp = ListPack::new();
+            lp.data = test.init_state;
+            for index in test.deletions {
+                lp.remove_at_index(index);
+            }
+
+            assert_eq!(&test.expected, &lp.data);
+        }
+    }
+
+    #[test]
+    fn test_list_pack_replace() {
+        let mut lp = ListPack::new();
+        lp.push(ListPackEntry::Uint7(5));
+        lp.push(ListPackEntry::Str6BitsLength(b"hello"));
+        lp.push(ListPackEntry::Int13(1000));
+
+        // grow the middle entry so its backlen needs two bytes
+        lp.replace(1, ListPackEntry::Str12BitsLength(&[b'a'; 200]));
+
+        #[rustfmt::skip]
+        let expected = {
+            let mut e = vec![
+            /*lp bytes*/ 0xD8, 0, 0, 0, /*lp len*/ 3, 0,
+            /*data + tag*/ 5, /*backlen*/ 1,
+            /*tag*/ 0b1110_0000, 0xC8,
+            ];
+            e.extend_from_slice(&[b'a'; 200]);
+            e.extend_from_slice(&[
+            /*backlen*/ 0x01, 0xCA,
+            /*data + tag*/ 0xC3, 0xE8, /*backlen*/ 2,
+            /*lp end*/ 0xFF,
+            ]);
+            e
+        };
+        assert_eq!(&expected, &lp.data);
+
+        // and shrink it back down again
+        lp.replace(1, ListPackEntry::Uint7(7));
+
+        #[rustfmt::skip]
+        let expected = vec![
+            /*lp bytes*/ 14, 0, 0, 0, /*lp len*/ 3, 0,
+            /*data + tag*/ 5, /*backlen*/ 1,
+            /*data + tag*/ 7, /*backlen*/ 1,
+            /*data + tag*/ 0xC3, 0xE8, /*backlen*/ 2,
+            /*lp end*/ 0xFF,
+        ];
+        assert_eq!(&expected, &lp.data);
+
+        assert_eq!(RedisObject::Int(5), lp.get(0));
+        assert_eq!(RedisObject::Int(7), lp.get(1));
+        assert_eq!(RedisObject::Int(1000), lp.get(2));
+    }
+
+    #[test]
+    fn test_list_pack_get() {
+        struct TestData {
+            entries: Vec<ListPackEntry<'static>>,
+            get: Vec<usize>,
+            expected: Vec<RedisObject>,
+        }
+
+        let tests = vec![
+            TestData {
+                entries: vec![
+                    ListPackEntry::Uint7(5),
+                    ListPackEntry::Int13(100),
+                    ListPackEntry::Int13(-4096),
+                    ListPackEntry::Int16(-30000),
+                    ListPackEntry::Int24(8388607),
+                    ListPackEntry::Int24(-8388608),
+                    ListPackEntry::Int32(-2147483647),
+                    ListPackEntry::Int64(5000000000),
+                    ListPackEntry::Int64(-5000000000),
+                ],
+                get: vec![0, 1, 2, 3, 4, 5, 6, 7, 8],
+                expected: vec![
+                    RedisObject::Int(5),
+                    RedisObject::Int(100),
+                    RedisObject::Int(-4096),
+                    RedisObject::Int(-30000),
+                    RedisObject::Int(8388607),
+                    RedisObject::Int(-8388608),
+                    RedisObject::Int(-2147483647),
+                    RedisObject::Int(5000000000),
+                    RedisObject::Int(-5000000000),
+                ],
+            },
+            // get strings
+            TestData {
+                entries: vec![
+                    ListPackEntry::Str6BitsLength(b"Hello World"),
+                    ListPackEntry::Str12BitsLength(&[b'a'; 70]),
+                    ListPackEntry::Str32BitsLength(&[b'b'; 70_000]),
+                    ListPackEntry::Str32BitsLength(&[b'c'; 70_000]),
+                ],
+                get: vec![2, 3, 1, 0],
+                expected: vec![
+                    RedisObject::String([b'b'; 70_000].to_vec().into_boxed_slice()),
+                    RedisObject::String([b'c'; 70_000].to_vec().into_boxed_slice()),
+                    RedisObject::String([b'a'; 70].to_vec().into_boxed_slice()),
+                    RedisObject::String(b"Hello World".to_vec().into_boxed_slice()),
+                ],
+            },
+        ];
+
+        for test in tests {
+            let mut lp = ListPack::new();
+            for entry in test.entries {
+                lp.push(entry);
+            }
+
+            for (index, expected) in test.get.iter().zip(test.expected) {
+                let result = lp.get(*index);
+                assert_eq!(expected, result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_list_pack_pop() {
+        let mut lp = ListPack::new();
+        lp.push(ListPackEntry::from_bytes(b"first"));
+        lp.push(ListPackEntry::from_bytes(&[b'x'; 300]));
+        lp.push(ListPackEntry::from_bytes(b"-70000"));
+        lp.push(ListPackEntry::from_bytes(b"last"));
+
+        assert_eq!(RedisObject::String(Box::from(&b"first"[..])), lp.pop_head());
+        assert_eq!(RedisObject::String(Box::from(&b"last"[..])), lp.pop_tail());
+        assert_eq!(RedisObject::Int(-70000), lp.pop_tail());
+        assert_eq!(
+            RedisObject::String(Box::from(&[b'x'; 300][..])),
+            lp.pop_head()
+        );
+
+        assert!(lp.is_empty());
+        assert_eq!(ListPack::new(), lp);
+    }
+
+    #[test]
+    fn test_list_pack_iter() {
+        let mut lp = ListPack::new();
+        let values: Vec<Vec<u8>> = (0..200)
+            .map(|i| match i % 3 {
+                0 => i.to_string().into_bytes(),
+                1 => vec![b'a'; i * 10],
+                _ => format!("value{}", i).into_bytes(),
+            })
+            .collect();
+
+        for value in &values {
+            lp.push(ListPackEntry::from_bytes(value));
+        }
+
+        let forward: Vec<usize> = lp.iter().collect();
+        let mut backward: Vec<usize> = lp.iter_rev().collect();
+        backward.reverse();
+
+        assert_eq!(values.len(), forward.len());
+        assert_eq!(forward, backward);
+
+        for (offset, value) in forward.iter().zip(&values) {
+            assert_eq!(
+                RedisObject::new_from_bytes(value),
+                lp.get_at_offset(*offset)
+            );
+        }
+    }
+
+    #[test]
+    fn test_list_pack_no_cascade() {
+        // every entry sits right under the size where a ziplist prevlen would grow
+        let value = [b'a'; 250];
+
+        let mut lp = ListPack::new();
+        for _ in 0..100 {
+            lp.push(ListPackEntry::from_bytes(&value));
+        }
+
+        let tail_before = lp.data[LP_HEADERS_SIZE + 254..].to_vec();
+
+        // growing the head entry only moves the bytes behind it, none of them change
+        lp.replace(0, ListPackEntry::from_bytes(&[b'b'; 300]));
+
+        let head_len = get_entry_total_size(&lp.data[LP_HEADERS_SIZE..]);
+        assert_eq!(tail_before, lp.data[LP_HEADERS_SIZE + head_len..].to_vec());
+
+        assert_eq!(100, lp.len());
+        assert_eq!(RedisObject::String(Box::from(&[b'b'; 300][..])), lp.get(0));
+        assert_eq!(RedisObject::String(Box::from(&value[..])), lp.get(99));
+    }
+
+    #[test]
+    fn test_list_pack_len_unknown() {
+        let amount = LP_LEN_UNKNOWN as usize + 10;
+
+        let mut lp = ListPack::new();
+        for _ in 0..amount {
+            lp.push(ListPackEntry::Uint7(1));
+        }
+
+        assert_eq!(LP_LEN_UNKNOWN, lp.get_lp_len());
+        assert_eq!(amount, lp.len());
+
+        lp.pop_tail();
+        assert_eq!(LP_LEN_UNKNOWN, lp.get_lp_len());
+        assert_eq!(amount - 1, lp.len());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    error::ListPackError,
+    redis::{
+        listpack::{
+            EncodingType, LP_END, LP_END_SIZE, LP_HEADERS_SIZE, LP_LEN_UNKNOWN, ListPack,
+            get_backlen_size,
+        },
+        ziplist::ValidationMode,
+    },
+};
+
+impl ListPack {
+    // same checks as ZipList::from_bytes_validated, the entries are only walked in deep mode
+    pub fn from_bytes_validated(
+        bytes: &[u8],
+        mode: ValidationMode,
+    ) -> Result<ListPack, ListPackError> {
+        validate_header(bytes)?;
+
+        if mode == ValidationMode::Deep {
+            validate_entries(bytes)?;
+        }
+
+        Ok(ListPack {
+            data: bytes.to_vec(),
+        })
+    }
+}
+
+fn validate_header(bytes: &[u8]) -> Result<(), ListPackError> {
+    if bytes.len() < LP_HEADERS_SIZE + LP_END_SIZE {
+        return Err(ListPackError::TooShort { len: bytes.len() });
+    }
+
+    let lp_bytes = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if lp_bytes as usize != bytes.len() {
+        return Err(ListPackError::BytesMismatch {
+            lp_bytes,
+            len: bytes.len(),
+        });
+    }
+
+    if bytes[bytes.len() - 1] != LP_END {
+        return Err(ListPackError::MissingEnd {
+            offset: bytes.len() - 1,
+        });
+    }
+
+    Ok(())
+}
+
+fn validate_entries(bytes: &[u8]) -> Result<(), ListPackError> {
+    let end = bytes.len() - LP_END_SIZE;
+
+    let mut offset = LP_HEADERS_SIZE;
+    let mut amount_entries = 0;
+
+    while offset < end {
+        let header = bytes[offset];
+        let encoding_type = match EncodingType::try_from_header(header) {
+            Some(encoding_type) => encoding_type,
+            None => return Err(ListPackError::InvalidEncoding { offset, header }),
+        };
+
+        // the length bytes of the string encodings have to be in bounds before they are read
+        let length_bytes = match encoding_type {
+            EncodingType::Str12BitsLength => 2,
+            EncodingType::Str32BitsLength => 5,
+            _ => 1,
+        };
+        if offset + length_bytes > end {
+            return Err(ListPackError::EntryOutOfBounds { offset });
+        }
+
+        let entry_len = match encoding_type {
+            EncodingType::Uint7 => 1,
+            EncodingType::Int13 => 2,
+            EncodingType::Int16 => 3,
+            EncodingType::Int24 => 4,
+            EncodingType::Int32 => 5,
+            EncodingType::Int64 => 9,
+            EncodingType::Str6BitsLength => 1 + (header & 0b0011_1111) as usize,
+            EncodingType::Str12BitsLength => {
+                2 + u16::from_be_bytes([header & 0b0000_1111, bytes[offset + 1]]) as usize
+            }
+            EncodingType::Str32BitsLength => {
+                let str_len = [
+                    bytes[offset + 1],
+                    bytes[offset + 2],
+                    bytes[offset + 3],
+                    bytes[offset + 4],
+                ];
+                5 + u32::from_le_bytes(str_len) as usize
+            }
+        };
+
+        let backlen_size = get_backlen_size(entry_len);
+        let backlen_offset = match offset.checked_add(entry_len) {
+            Some(backlen_offset) if backlen_offset + backlen_size <= end => backlen_offset,
+            _ => return Err(ListPackError::EntryOutOfBounds { offset }),
+        };
+
+        // compared byte by byte, decoding a corrupted backlen could walk off the front
+        let backlen = &bytes[backlen_offset..backlen_offset + backlen_size];
+        let backlen_matches = backlen.iter().enumerate().all(|(i, &byte)| {
+            let shift = 7 * (backlen_size - 1 - i);
+            let continuation = if i > 0 { 0b1000_0000 } else { 0 };
+            byte == ((entry_len >> shift) & 0b0111_1111) as u8 | continuation
+        });
+        if !backlen_matches {
+            return Err(ListPackError::BacklenMismatch { offset });
+        }
+
+        offset = backlen_offset + backlen_size;
+        amount_entries += 1;
+    }
+
+    // a saturated length means the entries have to be counted, so any amount is fine
+    let lp_len = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if lp_len != LP_LEN_UNKNOWN && lp_len as usize != amount_entries {
+        return Err(ListPackError::LenMismatch {
+            lp_len,
+            amount_entries,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::{listpack::ListPackEntry, redis_object::RedisObject};
+
+    // hello @ 6, 1000 @ 13, 300 * 'a' @ 16 with a 2 byte backlen, 5 @ 320, end byte @ 322
+    fn valid_bytes() -> Vec<u8> {
+        let mut lp = ListPack::new();
+        lp.push(ListPackEntry::from_bytes(b"hello"));
+        lp.push(ListPackEntry::from_bytes(b"1000"));
+        lp.push(ListPackEntry::from_bytes(&[b'a'; 300]));
+        lp.push(ListPackEntry::from_bytes(b"5"));
+        lp.data
+    }
+
+    #[test]
+    fn test_from_bytes_validated() {
+        struct TestData {
+            bytes: Vec<u8>,
+            mode: ValidationMode,
+            expected: Result<(), ListPackError>,
+        }
+
+        let corrupted = |f: fn(&mut Vec<u8>)| {
+            let mut bytes = valid_bytes();
+            f(&mut bytes);
+            bytes
+        };
+
+        let tests = vec![
+            TestData {
+                bytes: ListPack::new().data,
+                mode: ValidationMode::Deep,
+                expected: Ok(()),
+            },
+            TestData {
+                bytes: valid_bytes(),
+                mode: ValidationMode::Deep,
+                expected: Ok(()),
+            },
+            // header checks
+            TestData {
+                bytes: vec![7, 0, 0],
+                mode: ValidationMode::Shallow,
+                expected: Err(ListPackError::TooShort { len: 3 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[0] = 0),
+                mode: ValidationMode::Shallow,
+                expected: Err(ListPackError::BytesMismatch {
+                    lp_bytes: 0x100,
+                    len: 323,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[322] = 0),
+                mode: ValidationMode::Shallow,
+                expected: Err(ListPackError::MissingEnd { offset: 322 }),
+            },
+            // entry checks are only done in deep mode
+            TestData {
+                bytes: corrupted(|b| b[6] = 0xF5),
+                mode: ValidationMode::Shallow,
+                expected: Ok(()),
+            },
+            TestData {
+                bytes: corrupted(|b| b[6] = 0xF5),
+                mode: ValidationMode::Deep,
+                expected: Err(ListPackError::InvalidEncoding {
+                    offset: 6,
+                    header: 0xF5,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[12] = 7),
+                mode: ValidationMode::Deep,
+                expected: Err(ListPackError::BacklenMismatch { offset: 6 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[16..18].copy_from_slice(&[0b1110_1111, 0xFF])),
+                mode: ValidationMode::Deep,
+                expected: Err(ListPackError::EntryOutOfBounds { offset: 16 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[4] = 3),
+                mode: ValidationMode::Deep,
+                expected: Err(ListPackError::LenMismatch {
+                    lp_len: 3,
+                    amount_entries: 4,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[4..6].copy_from_slice(&LP_LEN_UNKNOWN.to_le_bytes())),
+                mode: ValidationMode::Deep,
+                expected: Ok(()),
+            },
+            // a 32 bit string whose length bytes run into the end byte
+            TestData {
+                #[rustfmt::skip]
+                bytes: vec![
+                    /*lp bytes*/ 10, 0, 0, 0, /*lp len*/ 1, 0,
+                    /*tag*/ 0xF0, /*len*/ 1, 0,
+                    /*lp end*/ 0xFF,
+                ],
+                mode: ValidationMode::Deep,
+                expected: Err(ListPackError::EntryOutOfBounds { offset: 6 }),
+            },
+        ];
+
+        for test in tests {
+            let result = ListPack::from_bytes_validated(&test.bytes, test.mode).map(|_| ());
+            assert_eq!(test.expected, result);
+        }
+    }
+
+    #[test]
+    fn test_validated_list_pack_is_usable() {
+        let mut lp = ListPack::from_bytes_validated(&valid_bytes(), ValidationMode::Deep).unwrap();
+
+        assert_eq!(RedisObject::Int(5), lp.pop_tail());
+        assert_eq!(RedisObject::String(Box::from(&b"hello"[..])), lp.pop_head());
+        assert_eq!(RedisObject::Int(1000), lp.get(0));
+        assert_eq!(2, lp.len());
+    }
+}
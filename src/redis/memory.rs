@@ -0,0 +1,262 @@
+use std::io::Write;
+
+use crate::redis::{Redis, RedisResult};
+
+impl Redis {
+    // MEMORY USAGE key [SAMPLES count], the bytes of the dict entry, the key and the value. Like
+    // in redis it is an estimate of what was allocated, not what the allocator handed out. Every
+    // value is a single buffer whose size is known, so SAMPLES is accepted and changes nothing
+    pub(super) fn memory_usage(&self, key: &[u8], _samples: usize) -> RedisResult {
+        match self.dbs[self.db].dict.peek(key) {
+            Some(value) => {
+                let usage = self.dbs[self.db].dict.entry_size(key) + value.heap_usage();
+                RedisResult::Int(usage as i64)
+            }
+            None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+        }
+    }
+
+    // MEMORY STATS, flat name value pairs like redis with the per db numbers nested under db.N
+    // for every database that has keys. The databases keep the byte counts up to date, so this
+    // is O(databases) and not O(keys). The expire entries count as overhead like in redis
+    pub(super) fn memory_stats(&self) -> RedisResult {
+        let dataset: usize = self.dbs.iter().map(|db| db.dataset_bytes).sum();
+        // index, main and expires overhead
+        let hashtables: Vec<(usize, usize, usize)> = self
+            .dbs
+            .iter()
+            .enumerate()
+            .filter(|(_, db)| !db.dict.is_empty())
+            .map(|(index, db)| {
+                let expires = db.expires.table_overhead() + db.expires_bytes;
+                (index, db.dict.table_overhead(), expires)
+            })
+            .collect();
+        let hashtable: usize = hashtables
+            .iter()
+            .map(|(_, main, expires)| main + expires)
+            .sum();
+        let overhead = hashtable + self.clients_memory;
+        let keys: usize = self.dbs.iter().map(|db| db.dict.len()).sum();
+
+        let mut reply = Vec::new();
+        Self::write_array_header(&mut reply, 12 + 2 * hashtables.len());
+        Self::write_stat(&mut reply, "total.allocated", dataset + overhead);
+        Self::write_stat(&mut reply, "clients.normal", self.clients_memory);
+        Self::write_stat(&mut reply, "overhead.total", overhead);
+
+        for (index, main, expires) in hashtables {
+            let name = format!("db.{}", index);
+            write!(reply, "${}\r\n{}\r\n", name.len(), name).unwrap();
+            Self::write_array_header(&mut reply, 4);
+            Self::write_stat(&mut reply, "overhead.hashtable.main", main);
+            Self::write_stat(&mut reply, "overhead.hashtable.expires", expires);
+        }
+
+        Self::write_stat(&mut reply, "keys.count", keys);
+        Self::write_stat(
+            &mut reply,
+            "keys.bytes-per-key",
+            dataset.checked_div(keys).unwrap_or(0),
+        );
+        Self::write_stat(&mut reply, "dataset.bytes", dataset);
+
+        RedisResult::BulkString(reply)
+    }
+
+    // called by the server with the buffer sizes of every connection, like the clients cron in
+    // redis the number is only as fresh as the last run of the periodic tasks
+    pub fn set_clients_memory(&mut self, bytes: usize) {
+        self.clients_memory = bytes;
+    }
+
+    fn write_stat(reply: &mut Vec<u8>, name: &str, value: usize) {
+        write!(reply, "${}\r\n{}\r\n:{}\r\n", name.len(), name, value).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{RedisCommand, TimeUnit};
+
+    fn memory_usage(redis: &mut Redis, key: &[u8]) -> Option<i64> {
+        match redis.execute_command(&RedisCommand::MemoryUsage { key, samples: 5 }) {
+            RedisResult::Int(usage) => Some(usage),
+            RedisResult::BulkString(reply) if reply == b"$-1\r\n" => None,
+            _ => panic!("MEMORY USAGE should reply with an integer or nil"),
+        }
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut redis = Redis::new();
+        assert_eq!(None, memory_usage(&mut redis, b"missing"));
+
+        redis.execute_command(&RedisCommand::Set {
+            key: b"int",
+            value: b"12345",
+        });
+        redis.execute_command(&RedisCommand::Set {
+            key: b"string",
+            value: &[b'x'; 1000],
+        });
+        for _ in 0..100 {
+            redis.execute_command(&RedisCommand::RPush {
+                key: b"list",
+                value: &[b'x'; 100],
+            });
+        }
+
+        // ints are stored in the object itself, so only the entry and the key count
+        let int = memory_usage(&mut redis, b"int").unwrap();
+        let entry = redis.dbs[0].dict.entry_size(b"int") as i64;
+        assert_eq!(entry, int);
+
+        let string = memory_usage(&mut redis, b"string").unwrap();
+        assert_eq!(
+            redis.dbs[0].dict.entry_size(b"string") as i64 + 1000,
+            string
+        );
+
+        // the list buffer holds 100 entries of 100 bytes and is at least that big
+        let list = memory_usage(&mut redis, b"list").unwrap();
+        assert!(list >= redis.dbs[0].dict.entry_size(b"list") as i64 + 100 * 100);
+
+        // the size of a single buffer is exact, there is nothing to sample
+        for samples in [0, 1, 100] {
+            let usage = redis.execute_command(&RedisCommand::MemoryUsage {
+                key: b"list",
+                samples,
+            });
+            assert!(matches!(usage, RedisResult::Int(usage) if usage == list));
+        }
+    }
+
+    #[test]
+    fn test_dataset_bytes() {
+        // what walking the databases gives, the running counts have to match it
+        fn walked(redis: &Redis) -> Vec<(usize, usize)> {
+            redis
+                .dbs
+                .iter()
+                .map(|db| {
+                    let dataset = db
+                        .dict
+                        .iter()
+                        .map(|(key, value)| db.dict.entry_size(key) + value.heap_usage())
+                        .sum();
+                    let expires = db
+                        .expires
+                        .iter()
+                        .map(|(key, _)| db.expires.entry_size(key))
+                        .sum();
+                    (dataset, expires)
+                })
+                .collect()
+        }
+        fn counted(redis: &Redis) -> Vec<(usize, usize)> {
+            redis
+                .dbs
+                .iter()
+                .map(|db| (db.dataset_bytes, db.expires_bytes))
+                .collect()
+        }
+
+        let commands = vec![
+            RedisCommand::Set {
+                key: b"string",
+                value: b"hello",
+            },
+            RedisCommand::Set {
+                key: b"string",
+                value: &[b'x'; 1000],
+            },
+            RedisCommand::Set {
+                key: b"int",
+                value: b"12345",
+            },
+            RedisCommand::RPush {
+                key: b"list",
+                value: &[b'x'; 100],
+            },
+            RedisCommand::LPush {
+                key: b"list",
+                value: b"head",
+            },
+            RedisCommand::LPop { key: b"list" },
+            RedisCommand::Expire {
+                key: b"string",
+                time: 100,
+                unit: TimeUnit::Seconds,
+                absolute: false,
+                flags: Default::default(),
+            },
+            RedisCommand::Rename {
+                key: b"string",
+                new_key: b"renamed",
+            },
+            RedisCommand::Copy {
+                source: b"renamed",
+                destination: b"copy",
+                replace: false,
+            },
+            RedisCommand::Move {
+                key: b"copy",
+                db: 1,
+            },
+            RedisCommand::Persist { key: b"renamed" },
+            RedisCommand::Del { keys: vec![b"int"] },
+            RedisCommand::SwapDb {
+                index1: 0,
+                index2: 1,
+            },
+            RedisCommand::FlushDb { lazy: false },
+        ];
+
+        let mut redis = Redis::new();
+        for command in commands {
+            redis.execute_command(&command);
+            assert_eq!(walked(&redis), counted(&redis), "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let mut redis = Redis::new();
+        for i in 0..10 {
+            let key = format!("key{}", i);
+            redis.execute_command(&RedisCommand::Set {
+                key: key.as_bytes(),
+                value: &[b'x'; 100],
+            });
+        }
+        redis.set_clients_memory(8192);
+
+        let reply = match redis.execute_command(&RedisCommand::MemoryStats) {
+            RedisResult::BulkString(reply) => reply,
+            _ => panic!("MEMORY STATS should reply with an array"),
+        };
+
+        let dataset: usize = (0..10)
+            .map(|i| redis.dbs[0].dict.entry_size(format!("key{}", i).as_bytes()) + 100)
+            .sum();
+        let main = redis.dbs[0].dict.table_overhead();
+        // no key has a ttl, only the empty table of the expires counts
+        let expires = redis.dbs[0].expires.table_overhead();
+        let hashtable = main + expires;
+
+        let mut expected = b"*14\r\n".to_vec();
+        Redis::write_stat(&mut expected, "total.allocated", dataset + hashtable + 8192);
+        Redis::write_stat(&mut expected, "clients.normal", 8192);
+        Redis::write_stat(&mut expected, "overhead.total", hashtable + 8192);
+        expected.extend_from_slice(b"$4\r\ndb.0\r\n*4\r\n");
+        Redis::write_stat(&mut expected, "overhead.hashtable.main", main);
+        Redis::write_stat(&mut expected, "overhead.hashtable.expires", expires);
+        Redis::write_stat(&mut expected, "keys.count", 10);
+        Redis::write_stat(&mut expected, "keys.bytes-per-key", dataset / 10);
+        Redis::write_stat(&mut expected, "dataset.bytes", dataset);
+
+        assert_eq!(expected, reply);
+    }
+}
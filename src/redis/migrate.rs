@@ -0,0 +1,117 @@
+use std::{
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    error::{CommandError, RedisError},
+    net::read_line,
+    protocol::parser::write_command,
+    rdb,
+    redis::{Redis, RedisResult, db::unix_time_ms},
+};
+
+impl Redis {
+    // MIGRATE, the keys are sent to the target with RESTORE and deleted here once it took all of
+    // them. Like redis the server waits for the target, nothing else runs meanwhile
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn migrate(
+        &mut self,
+        host: &[u8],
+        port: u16,
+        keys: &[&[u8]],
+        db: usize,
+        timeout: u64,
+        copy: bool,
+        replace: bool,
+    ) -> RedisResult {
+        let now = unix_time_ms();
+        let source = &self.dbs[self.db];
+        let keys: Vec<&[u8]> = keys
+            .iter()
+            .copied()
+            .filter(|key| source.dict.peek(key).is_some() && !source.is_expired(key, now))
+            .collect();
+        if keys.is_empty() {
+            return RedisResult::SimpleString(b"+NOKEY\r\n");
+        }
+
+        // the target of a slot that is moving only takes its keys from RESTORE-ASKING
+        let restore: &[u8] = if self.cluster_enabled() {
+            b"RESTORE-ASKING"
+        } else {
+            b"RESTORE"
+        };
+
+        let mut commands = Vec::new();
+        write_command(&mut commands, &[&b"SELECT"[..], db.to_string().as_bytes()]);
+        for key in &keys {
+            let value = source.dict.peek(key).expect("the key was checked above");
+            let ttl = source.expire(key).map_or(0, |when| (when - now).max(1));
+            let ttl = ttl.to_string();
+            let payload = rdb::dump(value);
+
+            let mut args: Vec<&[u8]> = vec![restore, key, ttl.as_bytes(), &payload];
+            if replace {
+                args.push(b"REPLACE");
+            }
+            write_command(&mut commands, &args);
+        }
+
+        let host = String::from_utf8_lossy(host);
+        let mut stream = match connect(&host, port, Duration::from_millis(timeout)) {
+            Ok(stream) => stream,
+            Err(_) => return error(CommandError::MigrateConnect),
+        };
+        if stream.write_all(&commands).is_err() {
+            return error(CommandError::MigrateIo);
+        }
+
+        // a reply for SELECT and one for every RESTORE, every reply is read before giving up so
+        // the error names the first one that failed
+        let mut buf = Vec::new();
+        let mut target_error = None;
+        for _ in 0..keys.len() + 1 {
+            match read_line(&mut stream, &mut buf) {
+                Ok(reply) if reply.starts_with(b"-") => {
+                    target_error.get_or_insert_with(|| reply[1..].to_vec());
+                }
+                Ok(_) => {}
+                Err(_) => return error(CommandError::MigrateIo),
+            }
+        }
+
+        // the keys stay here when a single one failed, the deletions couldn't be propagated as
+        // the DEL of all keys otherwise
+        if let Some(message) = target_error {
+            return error(CommandError::MigrateTargetError { message });
+        }
+
+        if !copy {
+            let db = &mut self.dbs[self.db];
+            for key in keys {
+                if db.delete(key) {
+                    self.rdb.dirty += 1;
+                }
+            }
+        }
+
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "can't resolve the host"))?;
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+fn error(err: CommandError) -> RedisResult {
+    RedisResult::Error(RedisError::CommandError(err))
+}
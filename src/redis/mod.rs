@@ -1,17 +1,53 @@
+mod aof;
+pub mod cluster;
+mod crc16;
+mod db;
+mod dict_util;
+mod dump;
+mod expire;
 pub mod hash_table;
+mod info;
+mod keyspace;
+pub mod listpack;
+mod memory;
+mod migrate;
+mod object;
+mod persistence;
 pub mod redis_object;
+mod replication;
+mod siphash;
+mod sort;
+pub mod swiss_table;
 pub mod ziplist;
 
+use std::{io::Write, time::Duration};
+
 use crate::{
     commands::RedisCommand,
-    error::RedisError,
+    config::{Config, ListEncoding},
+    error::{CommandError, RedisError},
     redis::{
-        hash_table::{HashDict, HashNode},
-        redis_object::RedisObject,
+        aof::AofState,
+        cluster::{ClusterState, SlotKeys},
+        db::Db,
+        hash_table::HashNode,
+        listpack::{ListPack, ListPackEntry},
+        persistence::RdbState,
+        redis_object::{RedisObject, update_access_clock},
+        replication::ReplicationState,
         ziplist::{ZipEntry, ZipList},
     },
 };
 
+// the keyspace dict, the open addressing one is picked at build time with the swiss-dict feature
+#[cfg(not(feature = "swiss-dict"))]
+pub(crate) type Dict = hash_table::HashDict<Box<[u8]>, RedisObject, hash_table::KeyspaceKeys>;
+#[cfg(feature = "swiss-dict")]
+pub(crate) type Dict = swiss_table::SwissDict;
+
+// time spent moving dict buckets per run of the periodic tasks
+const ACTIVE_REHASHING_BUDGET: Duration = Duration::from_millis(1);
+
 pub enum RedisResult {
     SimpleString(&'static [u8]),
     BulkString(Vec<u8>),
@@ -20,25 +56,77 @@ pub enum RedisResult {
 }
 
 pub struct Redis {
-    dict: HashDict,
+    dbs: Vec<Db>,
+    // the database commands run against, set to the one selected by the connection of the
+    // command being executed
+    db: usize,
+    // the database the next active expire cycle starts with
+    active_expire_db: usize,
+    config: Config,
+    // read and write buffers of all connections, kept up to date by the server
+    clients_memory: usize,
+    rdb: RdbState,
+    aof: AofState,
+    repl: ReplicationState,
+    // set when the server runs as a node of a cluster
+    cluster: Option<ClusterState>,
 }
 
 impl Redis {
     pub fn new() -> Self {
+        Self::with_config(Config::new())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        update_access_clock();
+        let repl = ReplicationState::new(config.replicaof.clone());
+        let cluster = config.cluster_enabled.then(|| {
+            ClusterState::new(
+                config.port,
+                Duration::from_millis(config.cluster_node_timeout),
+            )
+        });
+        let mut dbs: Vec<Db> = (0..config.databases).map(|_| Db::new()).collect();
+        // the keys are only ever in db 0 of a cluster node
+        if cluster.is_some() {
+            dbs[0].slot_keys = Some(SlotKeys::new());
+        }
         Redis {
-            dict: HashDict::new(),
+            dbs,
+            db: 0,
+            active_expire_db: 0,
+            config,
+            clients_memory: 0,
+            rdb: RdbState::new(),
+            aof: AofState::new(),
+            repl,
+            cluster,
         }
     }
 
+    // runs a command against the database selected by a connection, SELECT changes the index
+    pub fn execute_command_in_db(&mut self, db: &mut usize, command: &RedisCommand) -> RedisResult {
+        self.db = *db;
+        let result = self.execute_command(command);
+        *db = self.db;
+        result
+    }
+
     pub fn execute_command(&mut self, command: &RedisCommand) -> RedisResult {
-        match command {
+        self.expire_command_keys(command);
+
+        // like server.dirty in redis, writes count the changes they made, only the ones that
+        // changed something are propagated
+        let dirty = self.rdb.dirty;
+        let result = match command {
             RedisCommand::Set { key, value } => {
                 let node = Box::new(HashNode::new_from_bytes(key, value));
-                self.dict.insert(node);
+                self.dbs[self.db].insert(node, None);
+                self.rdb.dirty += 1;
                 RedisResult::SimpleString(b"+OK\r\n")
             }
             RedisCommand::Get { key } => {
-                let lookup_node = self.dict.lookup(key);
+                let lookup_node = self.dbs[self.db].dict.lookup(key);
                 match lookup_node {
                     Some(value) => {
                         let response = value.to_resp();
@@ -50,108 +138,321 @@ impl Redis {
             RedisCommand::Del { keys } => {
                 let mut amount_deletions: i64 = 0;
                 for key in keys {
-                    let result = self.dict.delete(key);
+                    let result = self.dbs[self.db].delete(key);
 
                     if result == true {
                         amount_deletions += 1;
                     }
                 }
 
+                self.rdb.dirty += amount_deletions as u64;
                 RedisResult::Int(amount_deletions)
             }
             // code duplication for these two but i think it is the most optimal way could be
             // solved with macros or a function that takes a bool or something although this could
             // create extra unecisary branching
             RedisCommand::LPush { key, value } => {
-                // let value_object = RedisObject::new_from_bytes(value);
-                let zip_entry = ZipEntry::from_bytes(value);
-                let possible_node = self.dict.lookup_mut(key);
-                match possible_node {
-                    // insert into list
-                    Some(node) => match node {
-                        RedisObject::List(list) => {
-                            list.insert(0, zip_entry);
-                            RedisResult::SimpleString(b"+OK\r\n")
-                        }
-                        _ => return RedisResult::BulkString(b"$-1\r\n".to_vec()),
-                    },
+                // insert into list
+                let pushed = self.dbs[self.db].modify(key, |node| match node {
+                    RedisObject::List(list) => {
+                        list.insert(0, ZipEntry::from_bytes(value));
+                        true
+                    }
+                    RedisObject::ListPack(list) => {
+                        list.insert(0, ListPackEntry::from_bytes(value));
+                        true
+                    }
+                    _ => false,
+                });
+                match pushed {
+                    Some(true) => {
+                        self.rdb.dirty += 1;
+                        RedisResult::SimpleString(b"+OK\r\n")
+                    }
+                    Some(false) => return RedisResult::BulkString(b"$-1\r\n".to_vec()),
                     // create the list
                     None => {
-                        let mut new_zip_list = ZipList::new();
-                        let zip_entry = ZipEntry::from_bytes(value);
-                        new_zip_list.push(zip_entry);
-                        let value_object = RedisObject::List(new_zip_list);
-
+                        let value_object = Self::new_list(self.config.list_encoding, value);
                         let new_node = HashNode::new_from_object(key, value_object);
 
-                        self.dict.insert(Box::new(new_node));
+                        self.dbs[self.db].insert(Box::new(new_node), None);
+                        self.rdb.dirty += 1;
 
                         RedisResult::SimpleString(b"+OK\r\n")
                     }
                 }
             }
             RedisCommand::RPush { key, value } => {
-                let value_object = RedisObject::new_from_bytes(value);
-                let zip_entry = ZipEntry::from_bytes(value);
-                let possible_node = self.dict.lookup_mut(key);
-                match possible_node {
-                    // insert into list
-                    Some(node) => match node {
-                        RedisObject::List(list) => {
-                            list.push(zip_entry);
-                            RedisResult::SimpleString(b"+OK\r\n")
-                        }
-                        _ => return RedisResult::BulkString(b"$-1\r\n".to_vec()),
-                    },
+                // insert into list
+                let pushed = self.dbs[self.db].modify(key, |node| match node {
+                    RedisObject::List(list) => {
+                        list.push(ZipEntry::from_bytes(value));
+                        true
+                    }
+                    RedisObject::ListPack(list) => {
+                        list.push(ListPackEntry::from_bytes(value));
+                        true
+                    }
+                    _ => false,
+                });
+                match pushed {
+                    Some(true) => {
+                        self.rdb.dirty += 1;
+                        RedisResult::SimpleString(b"+OK\r\n")
+                    }
+                    Some(false) => return RedisResult::BulkString(b"$-1\r\n".to_vec()),
                     // create the list
                     None => {
-                        let mut new_zip_list = ZipList::new();
-                        let zip_entry = ZipEntry::from_bytes(value);
-                        new_zip_list.push(zip_entry);
-                        let value_object = RedisObject::List(new_zip_list);
-
+                        let value_object = Self::new_list(self.config.list_encoding, value);
                         let new_node = HashNode::new_from_object(key, value_object);
 
-                        self.dict.insert(Box::new(new_node));
+                        self.dbs[self.db].insert(Box::new(new_node), None);
+                        self.rdb.dirty += 1;
 
                         RedisResult::SimpleString(b"+OK\r\n")
                     }
                 }
             }
             RedisCommand::LPop { key } => {
-                let possible_node = self.dict.lookup_mut(key);
-                match possible_node {
-                    Some(redis_object) => match redis_object {
-                        RedisObject::List(list) => {
-                            let value = list.pop_head();
-                            let response = value.to_resp();
-                            RedisResult::BulkString(response)
-                        }
-                        _ => todo!("implement error stuff"),
-                    },
-                    None => {
-                        todo!("implement error stuff here");
+                let popped = self.dbs[self.db].modify(key, |redis_object| match redis_object {
+                    RedisObject::List(list) => Some(list.pop_head()),
+                    RedisObject::ListPack(list) => Some(list.pop_head()),
+                    _ => None,
+                });
+                match popped {
+                    Some(Some(value)) => {
+                        self.rdb.dirty += 1;
+                        RedisResult::BulkString(value.to_resp())
                     }
+                    Some(None) => todo!("implement error stuff"),
+                    None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
                 }
             }
             RedisCommand::RPop { key } => {
-                let possible_node = self.dict.lookup_mut(key);
-                match possible_node {
-                    Some(redis_object) => match redis_object {
-                        RedisObject::List(list) => {
-                            let value = list.pop_tail();
-                            let response = value.to_resp();
-                            RedisResult::BulkString(response)
-                        }
-                        // panic is not here
-                        _ => todo!("implement error stuff"),
-                    },
-                    None => {
-                        // panic is not here
-                        todo!("implement error stuff here");
+                let popped = self.dbs[self.db].modify(key, |redis_object| match redis_object {
+                    RedisObject::List(list) => Some(list.pop_tail()),
+                    RedisObject::ListPack(list) => Some(list.pop_tail()),
+                    _ => None,
+                });
+                match popped {
+                    Some(Some(value)) => {
+                        self.rdb.dirty += 1;
+                        RedisResult::BulkString(value.to_resp())
+                    }
+                    // panic is not here
+                    Some(None) => todo!("implement error stuff"),
+                    None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+                }
+            }
+            RedisCommand::LRange { key, start, stop } => match self.dbs[self.db].dict.lookup(key) {
+                Some(RedisObject::List(list)) => {
+                    let (start, count) = Self::range_bounds(*start, *stop, list.len());
+
+                    // the values borrow the ziplist so they go into the reply without copies
+                    let mut reply = Vec::new();
+                    Self::write_array_header(&mut reply, count);
+                    for value in list.values_from(start).take(count) {
+                        value.write_resp(&mut reply);
+                    }
+
+                    RedisResult::BulkString(reply)
+                }
+                Some(RedisObject::ListPack(list)) => {
+                    let (start, count) = Self::range_bounds(*start, *stop, list.len());
+
+                    let mut reply = Vec::new();
+                    Self::write_array_header(&mut reply, count);
+                    for offset in list.iter().skip(start).take(count) {
+                        reply.extend_from_slice(&list.get_at_offset(offset).to_resp());
                     }
+
+                    RedisResult::BulkString(reply)
+                }
+                Some(_) => RedisResult::Error(RedisError::CommandError(CommandError::WrongType)),
+                None => RedisResult::BulkString(b"*0\r\n".to_vec()),
+            },
+            RedisCommand::Sort { key, options } => self.sort(key, options),
+            RedisCommand::Scan {
+                cursor,
+                pattern,
+                count,
+                object_type,
+            } => self.scan(*cursor, *pattern, *count, *object_type),
+            RedisCommand::RandomKey => self.random_key(),
+            RedisCommand::Keys { pattern } => self.keys(pattern),
+            RedisCommand::Exists { keys } => self.exists(keys),
+            RedisCommand::Type { key } => self.key_type(key),
+            RedisCommand::Rename { key, new_key } => self.rename(key, new_key),
+            RedisCommand::RenameNx { key, new_key } => self.rename_nx(key, new_key),
+            RedisCommand::Copy {
+                source,
+                destination,
+                replace,
+            } => self.copy(source, destination, *replace),
+            RedisCommand::Touch { keys } => self.touch(keys),
+            RedisCommand::Unlink { keys } => self.unlink(keys),
+            RedisCommand::Dump { key } => self.dump(key),
+            RedisCommand::Restore {
+                key,
+                ttl,
+                payload,
+                options,
+            } => self.restore(key, *ttl, payload, options),
+            RedisCommand::Expire {
+                key,
+                time,
+                unit,
+                absolute,
+                flags,
+            } => self.expire(key, *time, *unit, *absolute, *flags),
+            RedisCommand::Ttl { key, unit } => self.ttl(key, *unit),
+            RedisCommand::ExpireTime { key, unit } => self.expire_time(key, *unit),
+            RedisCommand::Persist { key } => self.persist(key),
+            RedisCommand::Select { index } => self.select(*index),
+            RedisCommand::SwapDb { index1, index2 } => self.swap_db(*index1, *index2),
+            RedisCommand::Move { key, db } => self.move_key(key, *db),
+            RedisCommand::DbSize => RedisResult::Int(self.dbs[self.db].dict.len() as i64),
+            RedisCommand::FlushDb { lazy } => self.flush_db(*lazy),
+            RedisCommand::FlushAll { lazy } => self.flush_all(*lazy),
+            RedisCommand::Info { sections } => self.info(sections),
+            RedisCommand::MemoryUsage { key, samples } => self.memory_usage(key, *samples),
+            RedisCommand::MemoryStats => self.memory_stats(),
+            RedisCommand::ObjectEncoding { key } => self.object_encoding(key),
+            RedisCommand::ObjectRefCount { key } => self.object_refcount(key),
+            RedisCommand::ObjectIdleTime { key } => self.object_idle_time(key),
+            RedisCommand::ObjectFreq { key } => self.object_freq(key),
+            RedisCommand::ObjectHelp => self.object_help(),
+            RedisCommand::Save => self.save(),
+            RedisCommand::BgSave => self.bgsave(),
+            RedisCommand::LastSave => self.last_save(),
+            RedisCommand::BgRewriteAof => self.bgrewriteaof(),
+            RedisCommand::ReplicaOf { master } => self.replica_of(*master),
+            RedisCommand::Ping { message } => match message {
+                Some(message) => {
+                    let mut reply = Vec::with_capacity(message.len() + 16);
+                    write!(reply, "${}\r\n", message.len()).unwrap();
+                    reply.extend_from_slice(message);
+                    reply.extend_from_slice(b"\r\n");
+                    RedisResult::BulkString(reply)
                 }
+                None => RedisResult::SimpleString(b"+PONG\r\n"),
+            },
+            RedisCommand::Migrate {
+                host,
+                port,
+                keys,
+                db,
+                timeout,
+                copy,
+                replace,
+            } => self.migrate(host, *port, keys, *db, *timeout, *copy, *replace),
+            RedisCommand::Cluster { subcommand } => self.cluster_command(subcommand),
+            // run by the server, they need the connection
+            RedisCommand::Psync { .. } => connection_only(b"psync"),
+            RedisCommand::ReplConf { .. } => connection_only(b"replconf"),
+            RedisCommand::Wait { .. } => connection_only(b"wait"),
+            RedisCommand::Asking => connection_only(b"asking"),
+        };
+
+        if self.rdb.dirty > dirty {
+            self.feed_append_only_file(command, &result);
+            self.feed_replication(command, &result);
+        }
+
+        result
+    }
+
+    // called by the server every few milliseconds
+    pub fn run_periodic_tasks(&mut self) {
+        update_access_clock();
+        self.active_expire_cycle();
+        self.check_bgsave_done();
+        self.check_save_points();
+        self.check_aof_rewrite_done();
+        self.replication_cron();
+        self.cluster_cron();
+
+        for db in &mut self.dbs {
+            db.dict.resize_if_needed();
+            db.expires.resize_if_needed();
+        }
+
+        if !self.config.active_rehashing {
+            return;
+        }
+
+        // like redis the whole budget goes to the first table that is rehashing, the keyspace of
+        // a database before its expires
+        for db in &mut self.dbs {
+            if db.dict.rehash_progress().is_some() {
+                db.dict.rehash_for(ACTIVE_REHASHING_BUDGET);
+                return;
+            }
+            if db.expires.rehash_progress().is_some() {
+                db.expires.rehash_for(ACTIVE_REHASHING_BUDGET);
+                return;
             }
         }
     }
+
+    // Helpers
+
+    // turns redis style inclusive start and stop indexes, which can be negative to count from the
+    // end, into a start index and an amount of elements clamped to the list
+    fn range_bounds(start: i64, stop: i64, len: usize) -> (usize, usize) {
+        let len = len as i64;
+        let start = if start < 0 {
+            (start + len).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            stop + len
+        } else {
+            stop.min(len - 1)
+        };
+
+        if start > stop || start >= len {
+            return (0, 0);
+        }
+
+        (start as usize, (stop - start + 1) as usize)
+    }
+
+    fn write_array_header(reply: &mut Vec<u8>, len: usize) {
+        write!(reply, "*{}\r\n", len).unwrap();
+    }
+
+    fn new_list(encoding: ListEncoding, value: &[u8]) -> RedisObject {
+        match encoding {
+            ListEncoding::ZipList => {
+                let mut list = ZipList::new();
+                list.push(ZipEntry::from_bytes(value));
+                RedisObject::List(list)
+            }
+            ListEncoding::ListPack => {
+                let mut list = ListPack::new();
+                list.push(ListPackEntry::from_bytes(value));
+                RedisObject::ListPack(list)
+            }
+        }
+    }
+}
+
+fn connection_only(cmd: &[u8]) -> RedisResult {
+    RedisResult::Error(RedisError::CommandError(CommandError::ConnectionOnly {
+        cmd: cmd.to_vec(),
+    }))
+}
+
+// the reply of a command as resp bytes or the command error, for comparing whole replies in tests
+#[cfg(test)]
+fn resp_or_error(result: RedisResult) -> Result<Vec<u8>, CommandError> {
+    match result {
+        RedisResult::SimpleString(reply) => Ok(reply.to_vec()),
+        RedisResult::BulkString(reply) => Ok(reply),
+        RedisResult::Int(int) => Ok(format!(":{}\r\n", int).into_bytes()),
+        RedisResult::Error(RedisError::CommandError(err)) => Err(err),
+        RedisResult::Error(err) => panic!("unexpected error {:?}", err),
+    }
 }
@@ -0,0 +1,226 @@
+use std::io::Write;
+
+use crate::redis::{Redis, RedisResult};
+
+const OBJECT_HELP: &[&str] = &[
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+// the subcommands look at the key without counting as an access of it, so asking for the idle
+// time does not reset it. Missing keys reply with nil like in redis
+impl Redis {
+    pub(super) fn object_encoding(&self, key: &[u8]) -> RedisResult {
+        match self.dbs[self.db].dict.peek(key) {
+            Some(value) => {
+                let encoding = value.encoding();
+                let mut reply = Vec::new();
+                write!(reply, "${}\r\n{}\r\n", encoding.len(), encoding).unwrap();
+                RedisResult::BulkString(reply)
+            }
+            None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+        }
+    }
+
+    pub(super) fn object_refcount(&self, key: &[u8]) -> RedisResult {
+        match self.dbs[self.db].dict.peek(key) {
+            Some(value) => RedisResult::Int(value.refcount()),
+            None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+        }
+    }
+
+    // both the clock and the counter are always kept, so unlike redis neither depends on the
+    // eviction policy
+    pub(super) fn object_idle_time(&self, key: &[u8]) -> RedisResult {
+        match self.dbs[self.db].dict.metadata(key) {
+            Some(access) => RedisResult::Int(access.idle_time() as i64),
+            None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+        }
+    }
+
+    pub(super) fn object_freq(&self, key: &[u8]) -> RedisResult {
+        match self.dbs[self.db].dict.metadata(key) {
+            Some(access) => RedisResult::Int(access.frequency() as i64),
+            None => RedisResult::BulkString(b"$-1\r\n".to_vec()),
+        }
+    }
+
+    pub(super) fn object_help(&self) -> RedisResult {
+        let mut reply = Vec::new();
+        Self::write_array_header(&mut reply, OBJECT_HELP.len());
+        for line in OBJECT_HELP {
+            write!(reply, "+{}\r\n", line).unwrap();
+        }
+        RedisResult::BulkString(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::RedisCommand,
+        config::ListEncoding,
+        redis::{
+            hash_table::HashNode,
+            redis_object::{Access, RedisObject},
+            resp_or_error,
+        },
+    };
+
+    #[test]
+    fn test_object_commands() {
+        struct TestData {
+            command: RedisCommand<'static>,
+            expected: &'static [u8],
+        }
+
+        let mut redis = Redis::new();
+        redis.execute_command(&RedisCommand::Set {
+            key: b"int",
+            value: b"123",
+        });
+        redis.execute_command(&RedisCommand::Set {
+            key: b"big-int",
+            value: b"123456",
+        });
+        redis.execute_command(&RedisCommand::Set {
+            key: b"embstr",
+            value: &[b'x'; 44],
+        });
+        redis.execute_command(&RedisCommand::Set {
+            key: b"raw",
+            value: &[b'x'; 45],
+        });
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"ziplist",
+            value: b"a",
+        });
+        redis.config.list_encoding = ListEncoding::ListPack;
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"listpack",
+            value: b"a",
+        });
+
+        let tests = vec![
+            TestData {
+                command: RedisCommand::ObjectEncoding { key: b"int" },
+                expected: b"$3\r\nint\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectEncoding { key: b"embstr" },
+                expected: b"$6\r\nembstr\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectEncoding { key: b"raw" },
+                expected: b"$3\r\nraw\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectEncoding { key: b"ziplist" },
+                expected: b"$7\r\nziplist\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectEncoding { key: b"listpack" },
+                expected: b"$8\r\nlistpack\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectEncoding { key: b"missing" },
+                expected: b"$-1\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectRefCount { key: b"int" },
+                expected: b":2147483647\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectRefCount { key: b"big-int" },
+                expected: b":1\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectRefCount { key: b"raw" },
+                expected: b":1\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectRefCount { key: b"missing" },
+                expected: b"$-1\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectIdleTime { key: b"missing" },
+                expected: b"$-1\r\n",
+            },
+            // new keys start with a counter of 5
+            TestData {
+                command: RedisCommand::ObjectFreq { key: b"raw" },
+                expected: b":5\r\n",
+            },
+            TestData {
+                command: RedisCommand::ObjectFreq { key: b"missing" },
+                expected: b"$-1\r\n",
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                Ok(test.expected.to_vec()),
+                resp_or_error(redis.execute_command(&test.command)),
+                "{:?}",
+                test.command
+            );
+        }
+
+        let help = resp_or_error(redis.execute_command(&RedisCommand::ObjectHelp)).unwrap();
+        assert!(help.starts_with(b"*15\r\n+OBJECT <subcommand>"));
+    }
+
+    #[test]
+    fn test_access_tracking() {
+        let mut redis = Redis::new();
+        // a key last accessed 10 minutes ago with a counter that decays by a minute each
+        let mut node = HashNode::new_from_object(b"key", RedisObject::Int(1));
+        node.set_metadata(Access::since(600, 20));
+        redis.dbs[0].insert(Box::new(node), None);
+
+        let object = |redis: &mut Redis, command| match redis.execute_command(&command) {
+            RedisResult::Int(value) => value,
+            _ => panic!("OBJECT should reply with an integer"),
+        };
+
+        // OBJECT does not count as an access, the clock may tick a second while the test runs
+        for _ in 0..2 {
+            let idle = object(&mut redis, RedisCommand::ObjectIdleTime { key: b"key" });
+            assert!((600..=601).contains(&idle), "{}", idle);
+            let freq = object(&mut redis, RedisCommand::ObjectFreq { key: b"key" });
+            assert_eq!(10, freq);
+        }
+
+        // reads reset the idle time and keep the decayed counter, it might have grown by one
+        redis.execute_command(&RedisCommand::Get { key: b"key" });
+        let idle = object(&mut redis, RedisCommand::ObjectIdleTime { key: b"key" });
+        assert!(idle <= 1, "{}", idle);
+        let freq = object(&mut redis, RedisCommand::ObjectFreq { key: b"key" });
+        assert!(freq == 10 || freq == 11, "{}", freq);
+
+        // the counter grows slower the higher it is, new keys climb quickly at first
+        redis.execute_command(&RedisCommand::Set {
+            key: b"hot",
+            value: b"1",
+        });
+        for _ in 0..1000 {
+            redis.execute_command(&RedisCommand::Get { key: b"hot" });
+        }
+        let freq = object(&mut redis, RedisCommand::ObjectFreq { key: b"hot" });
+        assert!((10..30).contains(&freq), "{}", freq);
+    }
+}
@@ -0,0 +1,558 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{CommandError, RdbError, RedisError},
+    rdb::{self, RdbKey, RdbWriter},
+    redis::{Redis, RedisResult, db::unix_time_ms, hash_table::HashNode, redis_object::Access},
+};
+
+// how long to wait before a save point tries again after a failed BGSAVE, in seconds
+const BGSAVE_RETRY_DELAY: i64 = 5;
+
+// the rdb snapshot state, like the rdb fields of the server struct in redis
+pub(super) struct RdbState {
+    // writes since the last successful save
+    pub(super) dirty: u64,
+    // dirty when the running BGSAVE started, that many changes are saved once it is done
+    dirty_before_bgsave: u64,
+    // unix times in seconds
+    pub(super) last_save: i64,
+    last_bgsave_try: i64,
+    last_bgsave_ok: bool,
+    child: Option<BgSaveChild>,
+}
+
+struct BgSaveChild {
+    pid: libc::pid_t,
+    // unix time in seconds, the time of the snapshot once it is done
+    start: i64,
+}
+
+impl RdbState {
+    pub(super) fn new() -> Self {
+        RdbState {
+            dirty: 0,
+            dirty_before_bgsave: 0,
+            // like redis the server starts out as if it was just saved
+            last_save: unix_time_ms() / 1000,
+            last_bgsave_try: 0,
+            last_bgsave_ok: true,
+            child: None,
+        }
+    }
+}
+
+impl Redis {
+    // blocks the server until the snapshot is written
+    pub(super) fn save(&mut self) -> RedisResult {
+        if self.rdb.child.is_some() {
+            return RedisResult::Error(RedisError::CommandError(CommandError::BgSaveInProgress));
+        }
+
+        let now = unix_time_ms() / 1000;
+        match self.write_rdb(now) {
+            Ok(()) => {
+                self.rdb.dirty = 0;
+                self.rdb.last_save = now;
+                RedisResult::SimpleString(b"+OK\r\n")
+            }
+            Err(err) => {
+                eprintln!("Warning: failed saving the rdb file: {}", err);
+                RedisResult::Error(RedisError::CommandError(CommandError::SaveFailed))
+            }
+        }
+    }
+
+    // forks a child that writes the snapshot from its copy of the keyspace while the parent keeps
+    // serving. The periodic tasks pick up the child once it exits
+    pub(super) fn bgsave(&mut self) -> RedisResult {
+        if self.rdb.child.is_some() {
+            return RedisResult::Error(RedisError::CommandError(CommandError::BgSaveInProgress));
+        }
+        if self.aof_rewrite_in_progress() {
+            return RedisResult::Error(RedisError::CommandError(CommandError::ChildActive));
+        }
+
+        match self.fork_bgsave() {
+            Ok(()) => RedisResult::SimpleString(b"+Background saving started\r\n"),
+            Err(err) => {
+                eprintln!("Warning: can't fork for BGSAVE: {}", err);
+                RedisResult::Error(RedisError::CommandError(CommandError::SaveFailed))
+            }
+        }
+    }
+
+    pub(super) fn last_save(&self) -> RedisResult {
+        RedisResult::Int(self.rdb.last_save)
+    }
+
+    // reads the snapshot in dir at startup, a missing file is an empty keyspace. Keys that
+    // expired while the server was down are left out, see rdb::load for the types that can't be
+    // loaded
+    pub fn load_rdb(&mut self) -> Result<(), RedisError> {
+        let data = match fs::read(self.rdb_path()) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        self.load_rdb_data(&data)
+    }
+
+    // adds the keys of a snapshot to the keyspace, also used for the one a master sends
+    pub(super) fn load_rdb_data(&mut self, data: &[u8]) -> Result<(), RedisError> {
+        let now = unix_time_ms();
+        let dbs = &mut self.dbs;
+        let mode = self.config.sanitize_dump_payload;
+        rdb::load(data, mode, self.config.rdb_strict_load, |key: RdbKey| {
+            let db = dbs
+                .get_mut(key.db)
+                .ok_or(RdbError::InvalidDbIndex { index: key.db })?;
+            if key.expire.is_some_and(|expire| expire <= now) {
+                return Ok(());
+            }
+
+            let mut node = HashNode::new_from_object(key.key, key.value);
+            if let Some(idle_time) = key.idle_time {
+                node.set_metadata(Access::with_idle_time(idle_time));
+            } else if let Some(freq) = key.freq {
+                node.set_metadata(Access::with_frequency(freq));
+            }
+            db.insert(Box::new(node), key.expire);
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    // starts a BGSAVE when a save point is reached, unless the last one failed only a moment
+    // ago. Called from the periodic tasks
+    pub(super) fn check_save_points(&mut self) {
+        if self.rdb.child.is_some() || self.aof_rewrite_in_progress() {
+            return;
+        }
+
+        let now = unix_time_ms() / 1000;
+        let since_last_save = now - self.rdb.last_save;
+        let reached = self.config.save_points.iter().any(|point| {
+            self.rdb.dirty >= point.changes && since_last_save >= point.seconds as i64
+        });
+        let may_retry =
+            self.rdb.last_bgsave_ok || now - self.rdb.last_bgsave_try > BGSAVE_RETRY_DELAY;
+
+        if reached
+            && may_retry
+            && let Err(err) = self.fork_bgsave()
+        {
+            eprintln!("Warning: can't fork for BGSAVE: {}", err);
+        }
+    }
+
+    // reaps the BGSAVE child without blocking. Changes made while it ran are still dirty
+    pub(super) fn check_bgsave_done(&mut self) {
+        let Some(child) = &self.rdb.child else {
+            return;
+        };
+
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(child.pid, &mut status, libc::WNOHANG) };
+        if pid == 0 {
+            return;
+        }
+
+        let ok = pid == child.pid && libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0;
+        if ok {
+            self.rdb.dirty -= self.rdb.dirty_before_bgsave;
+            self.rdb.last_save = child.start;
+        } else {
+            eprintln!("Warning: background saving failed");
+        }
+        self.rdb.last_bgsave_ok = ok;
+        self.rdb.child = None;
+    }
+
+    pub(super) fn bgsave_in_progress(&self) -> bool {
+        self.rdb.child.is_some()
+    }
+
+    pub(super) fn last_bgsave_ok(&self) -> bool {
+        self.rdb.last_bgsave_ok
+    }
+
+    fn fork_bgsave(&mut self) -> io::Result<()> {
+        let now = unix_time_ms() / 1000;
+        self.rdb.last_bgsave_try = now;
+
+        match unsafe { libc::fork() } {
+            -1 => {
+                self.rdb.last_bgsave_ok = false;
+                Err(io::Error::last_os_error())
+            }
+            // the child owns a copy on write copy of the keyspace, it must not return into the
+            // event loop or run the destructors of the sockets it shares with the parent
+            0 => {
+                let code = match self.write_rdb(now) {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        eprintln!("Warning: failed saving the rdb file: {}", err);
+                        1
+                    }
+                };
+                unsafe { libc::_exit(code) }
+            }
+            pid => {
+                self.rdb.dirty_before_bgsave = self.rdb.dirty;
+                self.rdb.child = Some(BgSaveChild { pid, start: now });
+                Ok(())
+            }
+        }
+    }
+
+    // writes a temp file that replaces the snapshot once it is complete, a crash in the middle
+    // never leaves a truncated one behind
+    fn write_rdb(&self, now: i64) -> io::Result<()> {
+        let temp_path = self
+            .config
+            .dir
+            .join(format!("temp-{}.rdb", std::process::id()));
+
+        let result = self.write_rdb_to(&temp_path, now);
+        match result {
+            Ok(()) => fs::rename(&temp_path, self.rdb_path()),
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(err)
+            }
+        }
+    }
+
+    fn write_rdb_to(&self, path: &Path, now: i64) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut file = self.write_rdb_into(file, now)?;
+        file.flush()?;
+        file.get_ref().sync_all()
+    }
+
+    // the snapshot a replica gets for a full resync, built in memory instead of on disk
+    pub(super) fn rdb_snapshot(&self) -> Vec<u8> {
+        self.write_rdb_into(Vec::new(), unix_time_ms() / 1000)
+            .expect("writing to a vec can't fail")
+    }
+
+    fn write_rdb_into<W: Write>(&self, out: W, now: i64) -> io::Result<W> {
+        let mut writer = RdbWriter::new(out, now)?;
+
+        for (index, db) in self.dbs.iter().enumerate() {
+            if db.dict.is_empty() {
+                continue;
+            }
+
+            writer.select_db(index, db.dict.len(), db.expires.len())?;
+            for (key, value) in db.dict.iter() {
+                writer.write_key(key, value, db.expire(key))?;
+            }
+        }
+
+        writer.finish()
+    }
+
+    fn rdb_path(&self) -> PathBuf {
+        self.config.dir.join(&self.config.dbfilename)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{RedisCommand, TimeUnit},
+        config::{Config, SavePoint},
+        redis::{redis_object::RedisObject, resp_or_error},
+    };
+
+    // a redis that saves to its own directory, the tests run in parallel
+    fn redis_in_temp_dir(name: &str) -> Redis {
+        let dir = std::env::temp_dir().join(format!("redis-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut config = Config::new();
+        config.dir = dir;
+        Redis::with_config(config)
+    }
+
+    fn fill(redis: &mut Redis) {
+        redis.execute_command(&RedisCommand::Set {
+            key: b"string",
+            value: b"hello",
+        });
+        redis.execute_command(&RedisCommand::RPush {
+            key: b"list",
+            value: b"a",
+        });
+        redis.execute_command(&RedisCommand::Set {
+            key: b"ttl",
+            value: b"10",
+        });
+        redis.execute_command(&RedisCommand::Expire {
+            key: b"ttl",
+            time: 1000,
+            unit: TimeUnit::Seconds,
+            absolute: false,
+            flags: Default::default(),
+        });
+        redis.execute_command(&RedisCommand::Select { index: 2 });
+        redis.execute_command(&RedisCommand::Set {
+            key: b"other",
+            value: b"db",
+        });
+        redis.execute_command(&RedisCommand::Select { index: 0 });
+    }
+
+    fn assert_loaded(redis: &Redis) {
+        let mut loaded = Redis::with_config(redis.config.clone());
+        loaded.load_rdb().unwrap();
+
+        for index in [0, 2] {
+            let db = &loaded.dbs[index];
+            assert_eq!(redis.dbs[index].dict.len(), db.dict.len());
+            for (key, value) in redis.dbs[index].dict.iter() {
+                assert_eq!(Some(value), db.dict.peek(key));
+                assert_eq!(redis.dbs[index].expire(key), db.expire(key));
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut redis = redis_in_temp_dir("save");
+        fill(&mut redis);
+        assert_eq!(5, redis.rdb.dirty);
+
+        assert_eq!(
+            Ok(b"+OK\r\n".to_vec()),
+            resp_or_error(redis.execute_command(&RedisCommand::Save))
+        );
+        assert_eq!(0, redis.rdb.dirty);
+        assert!(redis.rdb_path().exists());
+        assert_loaded(&redis);
+
+        // an empty key is a key like any other
+        redis.execute_command(&RedisCommand::Set {
+            key: b"",
+            value: b"empty",
+        });
+        redis.execute_command(&RedisCommand::Save);
+        assert_loaded(&redis);
+        redis.execute_command(&RedisCommand::Del { keys: vec![b""] });
+
+        // keys that expired while the server was down are not loaded
+        redis.dbs[0].set_expire(b"ttl", 1);
+        redis.execute_command(&RedisCommand::Save);
+        let mut loaded = Redis::with_config(redis.config.clone());
+        loaded.load_rdb().unwrap();
+        assert!(loaded.dbs[0].dict.peek(b"ttl").is_none());
+        assert_eq!(
+            Some(&RedisObject::new_from_bytes(b"hello")),
+            loaded.dbs[0].dict.peek(b"string")
+        );
+
+        // a server with fewer databases can't hold the keys of the file
+        let mut config = redis.config.clone();
+        config.databases = 1;
+        assert!(matches!(
+            Redis::with_config(config).load_rdb(),
+            Err(RedisError::RdbError(RdbError::InvalidDbIndex { index: 2 }))
+        ));
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let mut redis = redis_in_temp_dir("missing");
+        redis.load_rdb().unwrap();
+        assert!(redis.dbs[0].dict.is_empty());
+
+        fs::write(redis.rdb_path(), b"REDIS0010 not a snapshot").unwrap();
+        assert!(redis.load_rdb().is_err());
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_rdb_fixture() {
+        let mut redis = redis_in_temp_dir("redis-file");
+        fs::write(
+            redis.rdb_path(),
+            include_bytes!("../../tests/fixtures/rdb-v10.rdb"),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            redis.load_rdb(),
+            Err(RedisError::RdbError(RdbError::UnsupportedType {
+                rdb_type: 245
+            }))
+        ));
+        assert!(redis.dbs[0].dict.is_empty());
+
+        // the function library and the hash are skipped, the lfu counter is kept
+        redis.config.rdb_strict_load = false;
+        redis.load_rdb().unwrap();
+        assert_eq!(3, redis.dbs[0].dict.len());
+        assert!(redis.dbs[0].dict.peek(b"hash").is_none());
+        assert_eq!(100, redis.dbs[0].dict.metadata(b"hot").unwrap().frequency());
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_bgsave() {
+        let mut redis = redis_in_temp_dir("bgsave");
+        fill(&mut redis);
+
+        assert_eq!(
+            Ok(b"+Background saving started\r\n".to_vec()),
+            resp_or_error(redis.execute_command(&RedisCommand::BgSave))
+        );
+        assert_eq!(
+            Err(CommandError::BgSaveInProgress),
+            resp_or_error(redis.execute_command(&RedisCommand::Save))
+        );
+        // written after the fork, it stays dirty
+        redis.execute_command(&RedisCommand::Set {
+            key: b"after",
+            value: b"fork",
+        });
+
+        while redis.bgsave_in_progress() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            redis.check_bgsave_done();
+        }
+        assert!(redis.last_bgsave_ok());
+        assert_eq!(1, redis.rdb.dirty);
+
+        let mut loaded = Redis::with_config(redis.config.clone());
+        loaded.load_rdb().unwrap();
+        assert!(loaded.dbs[0].dict.peek(b"after").is_none());
+        assert_eq!(3, loaded.dbs[0].dict.len());
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_points() {
+        let mut redis = redis_in_temp_dir("save-points");
+        redis.config.save_points = vec![SavePoint {
+            seconds: 10,
+            changes: 2,
+        }];
+        fill(&mut redis);
+
+        // not long enough since the last save
+        redis.check_save_points();
+        assert!(!redis.bgsave_in_progress());
+
+        redis.rdb.last_save -= 10;
+        redis.check_save_points();
+        assert!(redis.bgsave_in_progress());
+        while redis.bgsave_in_progress() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            redis.check_bgsave_done();
+        }
+        assert_eq!(0, redis.rdb.dirty);
+        assert_loaded(&redis);
+
+        fs::remove_dir_all(&redis.config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_dirty() {
+        struct TestData {
+            command: RedisCommand<'static>,
+            changes: u64,
+        }
+
+        // only what a write changed counts, a write that changed nothing is no change
+        let tests = vec![
+            TestData {
+                command: RedisCommand::Del {
+                    keys: vec![b"string", b"missing"],
+                },
+                changes: 1,
+            },
+            TestData {
+                command: RedisCommand::Del {
+                    keys: vec![b"missing"],
+                },
+                changes: 0,
+            },
+            TestData {
+                command: RedisCommand::Unlink {
+                    keys: vec![b"missing"],
+                },
+                changes: 0,
+            },
+            TestData {
+                command: RedisCommand::LPop { key: b"list" },
+                changes: 1,
+            },
+            TestData {
+                command: RedisCommand::RPop { key: b"missing" },
+                changes: 0,
+            },
+            TestData {
+                command: RedisCommand::RenameNx {
+                    key: b"ttl",
+                    new_key: b"ttl",
+                },
+                changes: 0,
+            },
+            TestData {
+                command: RedisCommand::Persist { key: b"counter" },
+                changes: 0,
+            },
+            TestData {
+                command: RedisCommand::Persist { key: b"ttl" },
+                changes: 1,
+            },
+            TestData {
+                command: RedisCommand::Copy {
+                    source: b"missing",
+                    destination: b"copy",
+                    replace: false,
+                },
+                changes: 0,
+            },
+            TestData {
+                command: RedisCommand::Move {
+                    key: b"missing",
+                    db: 1,
+                },
+                changes: 0,
+            },
+            // every key is a change and the flush itself one more
+            TestData {
+                command: RedisCommand::FlushDb { lazy: false },
+                changes: 3,
+            },
+            TestData {
+                command: RedisCommand::FlushDb { lazy: false },
+                changes: 1,
+            },
+        ];
+
+        let mut redis = Redis::new();
+        fill(&mut redis);
+        for test in tests {
+            let dirty = redis.rdb.dirty;
+            redis.execute_command(&test.command);
+            assert_eq!(test.changes, redis.rdb.dirty - dirty, "{:?}", test.command);
+        }
+    }
+}
@@ -1,10 +1,38 @@
-use crate::redis::ziplist::ZipList;
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::redis::{listpack::ListPack, ziplist::ZipList};
+
+// strings up to this long are allocated together with their object in redis
+const EMBSTR_SIZE_LIMIT: usize = 44;
+// ints below this are shared objects in redis, OBJECT REFCOUNT reports them as never freed
+const SHARED_INTEGERS: i64 = 10000;
+const SHARED_REFCOUNT: i64 = i32::MAX as i64;
+
+// the access clock counts seconds and wraps around like the 24 bit lru clock of redis, the 8
+// bits above it hold the access counter
+const ACCESS_CLOCK_BITS: u32 = 24;
+const ACCESS_CLOCK_MAX: u32 = (1 << ACCESS_CLOCK_BITS) - 1;
+// new keys start above 0 so they are not the first to go before they had a chance to be used
+const LFU_INIT_VAL: u8 = 5;
+// the higher the factor the more accesses it takes to grow the counter by one
+const LFU_LOG_FACTOR: f64 = 10.0;
+// minutes without access that decay the counter by one
+const LFU_DECAY_TIME: u32 = 1;
+
+// the access clock is read on every lookup of a key, like server.lruclock in redis it is only
+// refreshed by the periodic tasks instead of asking the system every time. No 24 bit clock is
+// ever u32::MAX, it stands for a clock that was not set yet
+static ACCESS_CLOCK: AtomicU32 = AtomicU32::new(u32::MAX);
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum RedisObject {
     String(Box<[u8]>),
     Int(i64),
     List(ZipList), // lists are just ziplists for now quicklists down the line
+    ListPack(ListPack),
 }
 
 impl RedisObject {
@@ -20,6 +48,47 @@ impl RedisObject {
         }
     }
 
+    // the name TYPE reports and SCAN filters on
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisObject::String(_) | RedisObject::Int(_) => "string",
+            RedisObject::List(_) | RedisObject::ListPack(_) => "list",
+        }
+    }
+
+    // the name OBJECT ENCODING reports, strings are split by length like redis allocates them.
+    // Lists are not quicklists yet so they are a single ziplist or listpack
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            RedisObject::Int(_) => "int",
+            RedisObject::String(s) if s.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            RedisObject::String(_) => "raw",
+            RedisObject::List(_) => "ziplist",
+            RedisObject::ListPack(_) => "listpack",
+        }
+    }
+
+    // values are never shared between keys here, small ints report the refcount of the shared
+    // integers redis keeps for them
+    pub fn refcount(&self) -> i64 {
+        match self {
+            RedisObject::Int(i) if (0..SHARED_INTEGERS).contains(i) => SHARED_REFCOUNT,
+            _ => 1,
+        }
+    }
+
+    // heap bytes owned by the value, the object itself is counted by the dict entry holding it.
+    // Every encoding is a single buffer, so unlike in redis the number is exact and never
+    // estimated from samples of the elements
+    pub fn heap_usage(&self) -> usize {
+        match self {
+            RedisObject::String(s) => s.len(),
+            RedisObject::Int(_) => 0,
+            RedisObject::List(list) => list.allocated_bytes(),
+            RedisObject::ListPack(list) => list.allocated_bytes(),
+        }
+    }
+
     // TODO - this method should be optimized with how it handles bytes but for now this will just
     // be doing a bunch of allocations and conversions to strings and stuff
     pub fn to_resp(&self) -> Vec<u8> {
@@ -45,6 +114,111 @@ impl RedisObject {
     }
 }
 
+// when a key was last accessed and how often, the metadata of the keyspace dict entries. Both
+// share 32 bits, the clock in the low 24 and the logarithmic counter of the LFU policy of redis in
+// the high 8, so every entry pays for them once
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Access(u32);
+
+impl Default for Access {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Access::with(access_clock(), LFU_INIT_VAL)
+    }
+
+    // for keys restored with IDLETIME, the clock only goes back so far
+    pub fn with_idle_time(idle: u64) -> Self {
+        let idle = idle.min(ACCESS_CLOCK_MAX as u64) as u32;
+        Access::with(access_clock().wrapping_sub(idle), LFU_INIT_VAL)
+    }
+
+    // for keys restored with FREQ
+    pub fn with_frequency(counter: u8) -> Self {
+        Access::with(access_clock(), counter)
+    }
+
+    // an access `idle` seconds ago
+    #[cfg(test)]
+    pub fn since(idle: u32, counter: u8) -> Self {
+        Access::with(access_clock().wrapping_sub(idle), counter)
+    }
+
+    fn with(clock: u32, counter: u8) -> Self {
+        Access(((counter as u32) << ACCESS_CLOCK_BITS) | (clock & ACCESS_CLOCK_MAX))
+    }
+
+    fn clock(self) -> u32 {
+        self.0 & ACCESS_CLOCK_MAX
+    }
+
+    fn counter(self) -> u8 {
+        (self.0 >> ACCESS_CLOCK_BITS) as u8
+    }
+
+    // seconds since the last access, a clock that wrapped around since counts from the start
+    pub fn idle_time(self) -> u64 {
+        idle_seconds(access_clock(), self.clock())
+    }
+
+    // the counter decayed by the minutes since the last access
+    pub fn frequency(self) -> u8 {
+        self.frequency_at(access_clock())
+    }
+
+    // records an access, the counter only grows with a chance that gets smaller the higher it
+    // already is. `random` is any uniformly random number
+    pub fn touch(&mut self, random: u64) {
+        let now = access_clock();
+        let mut counter = self.frequency_at(now);
+        if counter < u8::MAX {
+            let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+            let chance = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+            // the top 53 bits as a float between 0 and 1
+            if ((random >> 11) as f64 / (1u64 << 53) as f64) < chance {
+                counter += 1;
+            }
+        }
+        *self = Access::with(now, counter);
+    }
+
+    fn frequency_at(self, now: u32) -> u8 {
+        let periods = idle_seconds(now, self.clock()) / 60 / LFU_DECAY_TIME as u64;
+        self.counter()
+            .saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
+
+// called by the periodic tasks, accesses in between share the clock of the last run
+pub fn update_access_clock() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let clock = (now & ACCESS_CLOCK_MAX as u64) as u32;
+    ACCESS_CLOCK.store(clock, Ordering::Relaxed);
+    clock
+}
+
+fn access_clock() -> u32 {
+    match ACCESS_CLOCK.load(Ordering::Relaxed) {
+        u32::MAX => update_access_clock(),
+        clock => clock,
+    }
+}
+
+fn idle_seconds(now: u32, clock: u32) -> u64 {
+    if now >= clock {
+        (now - clock) as u64
+    } else {
+        (now + (ACCESS_CLOCK_MAX - clock)) as u64
+    }
+}
+
 pub fn try_parse_int(bytes: &[u8]) -> Option<i64> {
     if bytes.is_empty() {
         return None;
@@ -0,0 +1,912 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Read, Write},
+    mem,
+    time::{Duration, Instant},
+};
+
+use libc::c_int;
+
+use crate::{
+    commands::{RedisCommand, ReplConfOption},
+    error::{CommandError, RedisError},
+    protocol::parser::write_command,
+    redis::{Redis, RedisResult, db::unix_time_ms, siphash::siphash13},
+};
+
+// the second replid of a server that never was a replica, like redis
+const EMPTY_REPLID: &str = "0000000000000000000000000000000000000000";
+// how long a replica waits before it connects to its master again after a failed try
+const MASTER_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// the replication state, like the repl fields of the server struct in redis. Replicas keep a
+// backlog of the stream from their master as well, so after one of them is promoted the others
+// can carry on with a partial resync
+pub(super) struct ReplicationState {
+    // 40 random hex characters, a new one each time the server becomes a master
+    replid: String,
+    // the replid of the master before this server was promoted, a PSYNC with it is still
+    // accepted up to second_replid_offset
+    replid2: String,
+    second_replid_offset: i64,
+    // bytes of the stream so far, a replica in sync has the same offset as its master
+    offset: i64,
+    // the end of the stream, created when the first replica syncs
+    backlog: Option<VecDeque<u8>>,
+    // the database the last command in the stream ran in, a SELECT goes first when it changes
+    selected_db: Option<usize>,
+    replicas: Vec<Replica>,
+    // set by WAIT, the replicas are asked for their offset once for all WAITs of an event loop
+    // round
+    get_ack: bool,
+    last_ping: Instant,
+    // set on a replica
+    master: Option<MasterLink>,
+    // connections the server has to close, the link to an old master and replicas that have to
+    // sync again
+    dropped_connections: Vec<c_int>,
+}
+
+// a connection that sent REPLCONF or PSYNC, a replica client in redis
+struct Replica {
+    // the fd of its connection
+    id: c_int,
+    ip: Option<String>,
+    listening_port: u16,
+    // false during the handshake, true once it synced with PSYNC
+    online: bool,
+    // how far the stream was handed to the connection
+    sent_offset: i64,
+    // how far the replica says it processed the stream
+    ack_offset: i64,
+    last_ack: Instant,
+}
+
+struct MasterLink {
+    host: String,
+    port: u16,
+    // the fd of the connection to the master once it synced
+    connection: Option<c_int>,
+    last_try: Option<Instant>,
+    last_io: Instant,
+}
+
+impl ReplicationState {
+    pub(super) fn new(master: Option<(String, u16)>) -> Self {
+        ReplicationState {
+            replid: random_replid(),
+            replid2: EMPTY_REPLID.to_string(),
+            second_replid_offset: -1,
+            offset: 0,
+            backlog: None,
+            selected_db: None,
+            replicas: Vec::new(),
+            get_ack: false,
+            last_ping: Instant::now(),
+            master: master.map(|(host, port)| MasterLink::new(host, port)),
+            dropped_connections: Vec::new(),
+        }
+    }
+
+    // the offset of the first byte in the backlog
+    fn backlog_first_offset(&self) -> i64 {
+        let len = self.backlog.as_ref().map_or(0, |backlog| backlog.len());
+        self.offset - len as i64 + 1
+    }
+}
+
+impl MasterLink {
+    fn new(host: String, port: u16) -> Self {
+        MasterLink {
+            host,
+            port,
+            connection: None,
+            last_try: None,
+            last_io: Instant::now(),
+        }
+    }
+}
+
+impl Redis {
+    pub fn is_replica(&self) -> bool {
+        self.repl.master.is_some()
+    }
+
+    // write commands from clients are refused with replica-read-only
+    pub fn is_read_only(&self) -> bool {
+        self.is_replica() && self.config.replica_read_only
+    }
+
+    pub fn replication_offset(&self) -> i64 {
+        self.repl.offset
+    }
+
+    pub fn repl_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.repl_timeout)
+    }
+
+    // REPLICAOF host port, the server connects to the new master in its periodic tasks. REPLICAOF
+    // NO ONE promotes a replica to a master that keeps its data
+    pub(super) fn replica_of(&mut self, master: Option<(&[u8], u16)>) -> RedisResult {
+        // cluster nodes are all masters here, there are no cluster replicas
+        if self.cluster_enabled() {
+            return RedisResult::Error(RedisError::CommandError(
+                CommandError::NotAllowedInClusterMode {
+                    cmd: b"replicaof".to_vec(),
+                },
+            ));
+        }
+        let Some((host, port)) = master else {
+            if self.repl.master.is_some() {
+                self.drop_master_link();
+                self.repl.master = None;
+                self.shift_replid();
+            }
+            return RedisResult::SimpleString(b"+OK\r\n");
+        };
+
+        let host = String::from_utf8_lossy(host).into_owned();
+        if let Some(link) = &self.repl.master
+            && link.host == host
+            && link.port == port
+        {
+            return RedisResult::SimpleString(b"+OK Already connected to specified master\r\n");
+        }
+
+        self.drop_master_link();
+        // like redis the replicas of this server sync again, with a partial resync if the new
+        // master continues the same history
+        let replicas = self.repl.replicas.iter().map(|replica| replica.id);
+        self.repl.dropped_connections.extend(replicas);
+        self.repl.master = Some(MasterLink::new(host, port));
+
+        RedisResult::SimpleString(b"+OK\r\n")
+    }
+
+    // PSYNC from a replica. A partial resync continues from the backlog when it still has
+    // everything the replica misses, otherwise the replica gets a snapshot of the keyspace and
+    // the stream after it. The rest of the stream comes from replication_output
+    pub fn sync_replica(
+        &mut self,
+        id: c_int,
+        ip: Option<String>,
+        replid: &[u8],
+        offset: i64,
+    ) -> RedisResult {
+        if self
+            .repl
+            .master
+            .as_ref()
+            .is_some_and(|link| link.connection.is_none())
+        {
+            return RedisResult::Error(RedisError::CommandError(CommandError::NoMasterLink));
+        }
+
+        let same_history = replid == self.repl.replid.as_bytes()
+            || (replid == self.repl.replid2.as_bytes() && offset <= self.repl.second_replid_offset);
+        let in_backlog = self.repl.backlog.is_some()
+            && offset >= self.repl.backlog_first_offset()
+            && offset <= self.repl.offset + 1;
+
+        let mut reply = Vec::new();
+        let sent_offset = if same_history && in_backlog {
+            write!(reply, "+CONTINUE {}\r\n", self.repl.replid).unwrap();
+            offset - 1
+        } else {
+            self.repl.backlog.get_or_insert_with(VecDeque::new);
+            let snapshot = self.rdb_snapshot();
+            write!(
+                reply,
+                "+FULLRESYNC {} {}\r\n${}\r\n",
+                self.repl.replid,
+                self.repl.offset,
+                snapshot.len()
+            )
+            .unwrap();
+            reply.extend_from_slice(&snapshot);
+            // the replica starts out in database 0
+            self.repl.selected_db = None;
+            self.repl.offset
+        };
+
+        let replica = self.replica_mut(id);
+        replica.ip = replica.ip.take().or(ip);
+        replica.online = true;
+        replica.sent_offset = sent_offset;
+        replica.ack_offset = sent_offset;
+        replica.last_ack = Instant::now();
+
+        RedisResult::BulkString(reply)
+    }
+
+    // REPLCONF from a replica, ACK and GETACK are not answered
+    pub fn replconf(&mut self, id: c_int, options: &[ReplConfOption]) -> Option<RedisResult> {
+        let mut reply = true;
+        for option in options {
+            match option {
+                ReplConfOption::ListeningPort(port) => self.replica_mut(id).listening_port = *port,
+                ReplConfOption::IpAddress(ip) => {
+                    self.replica_mut(id).ip = Some(String::from_utf8_lossy(ip).into_owned());
+                }
+                ReplConfOption::Capa(_) => {}
+                ReplConfOption::Ack(offset) => {
+                    reply = false;
+                    if let Some(replica) = self.repl.replicas.iter_mut().find(|r| r.id == id) {
+                        replica.ack_offset = replica.ack_offset.max(*offset);
+                        replica.last_ack = Instant::now();
+                    }
+                }
+                ReplConfOption::GetAck => reply = false,
+            }
+        }
+
+        reply.then_some(RedisResult::SimpleString(b"+OK\r\n"))
+    }
+
+    // appends the part of the stream a replica wasn't handed yet. False when it is not in the
+    // backlog anymore, the replica has to sync again
+    pub fn replication_output(&mut self, id: c_int, out: &mut Vec<u8>) -> bool {
+        let first = self.repl.backlog_first_offset();
+        let Some(backlog) = &self.repl.backlog else {
+            return false;
+        };
+        let Some(replica) = self.repl.replicas.iter_mut().find(|r| r.id == id) else {
+            return false;
+        };
+        if replica.sent_offset + 1 < first {
+            return false;
+        }
+
+        let start = (replica.sent_offset + 1 - first) as usize;
+        let (front, back) = backlog.as_slices();
+        if start < front.len() {
+            out.extend_from_slice(&front[start..]);
+            out.extend_from_slice(back);
+        } else {
+            out.extend_from_slice(&back[start - front.len()..]);
+        }
+        replica.sent_offset = self.repl.offset;
+        true
+    }
+
+    // the connections of replicas that synced
+    pub fn online_replicas(&self) -> Vec<c_int> {
+        self.repl
+            .replicas
+            .iter()
+            .filter(|replica| replica.online)
+            .map(|replica| replica.id)
+            .collect()
+    }
+
+    // how many replicas processed the stream up to offset, what WAIT waits for
+    pub fn replicas_acked(&self, offset: i64) -> usize {
+        self.repl
+            .replicas
+            .iter()
+            .filter(|replica| replica.online && replica.ack_offset >= offset)
+            .count()
+    }
+
+    pub fn request_replica_acks(&mut self) {
+        self.repl.get_ack = true;
+    }
+
+    // called by the server before it waits for events, so the GETACK goes out with the writes
+    // the WAITs of this round wait for
+    pub fn send_replica_ack_requests(&mut self) {
+        if !mem::take(&mut self.repl.get_ack) || self.repl.replicas.is_empty() {
+            return;
+        }
+
+        let mut resp = Vec::new();
+        write_command(&mut resp, &[&b"REPLCONF"[..], b"GETACK", b"*"]);
+        self.feed_replication_stream(&resp);
+    }
+
+    // the connections the server has to close
+    pub fn take_dropped_connections(&mut self) -> Vec<c_int> {
+        mem::take(&mut self.repl.dropped_connections)
+    }
+
+    // forgets a replica or the link to the master once its connection is closed
+    pub fn connection_closed(&mut self, id: c_int) {
+        self.repl.replicas.retain(|replica| replica.id != id);
+
+        if let Some(link) = &mut self.repl.master
+            && link.connection == Some(id)
+        {
+            link.connection = None;
+            link.last_try = None;
+        }
+    }
+
+    // the master a replica connects to, when it isn't connected and the last try was long
+    // enough ago
+    pub fn master_to_connect(&mut self) -> Option<(String, u16)> {
+        let link = self.repl.master.as_mut()?;
+        if link.connection.is_some()
+            || link
+                .last_try
+                .is_some_and(|last_try| last_try.elapsed() < MASTER_RETRY_INTERVAL)
+        {
+            return None;
+        }
+
+        link.last_try = Some(Instant::now());
+        Some((link.host.clone(), link.port))
+    }
+
+    // the replid and offset a replica sends with PSYNC. It is the history of this server, so a
+    // replica that reconnects or follows a promoted replica can continue with a partial resync,
+    // any other master answers with a full resync
+    pub fn psync_args(&self) -> (String, i64) {
+        (self.repl.replid.clone(), self.repl.offset + 1)
+    }
+
+    // +FULLRESYNC, the keyspace is replaced with the snapshot of the master
+    pub fn load_master_snapshot(
+        &mut self,
+        replid: &str,
+        offset: i64,
+        data: &[u8],
+    ) -> Result<(), RedisError> {
+        for db in &mut self.dbs {
+            db.flush();
+        }
+        self.load_rdb_data(data)?;
+
+        self.repl.replid = replid.to_string();
+        self.repl.replid2 = EMPTY_REPLID.to_string();
+        self.repl.second_replid_offset = -1;
+        self.repl.offset = offset;
+        // the history before the snapshot is a different one
+        self.repl.backlog = Some(VecDeque::new());
+        let replicas = self.repl.replicas.iter().map(|replica| replica.id);
+        self.repl.dropped_connections.extend(replicas);
+
+        if self.aof_enabled() {
+            self.restart_append_only_file()?;
+        }
+        Ok(())
+    }
+
+    // +CONTINUE, with the replid of the master when it changed since the last sync because it
+    // was promoted
+    pub fn continue_with_master(&mut self, replid: Option<&str>) {
+        if let Some(replid) = replid
+            && replid != self.repl.replid
+        {
+            self.repl.replid2 = mem::replace(&mut self.repl.replid, replid.to_string());
+            self.repl.second_replid_offset = self.repl.offset + 1;
+        }
+        self.repl.backlog.get_or_insert_with(VecDeque::new);
+    }
+
+    // the handshake is done, the stream from the master comes in over the connection
+    pub fn master_link_up(&mut self, id: c_int) {
+        if let Some(link) = &mut self.repl.master {
+            link.connection = Some(id);
+            link.last_io = Instant::now();
+        }
+    }
+
+    // a command the master sent, it is counted in the offset like it was sent and kept in the
+    // backlog for the replicas of this replica
+    pub fn feed_master_stream(&mut self, resp: &[u8]) {
+        if let Some(link) = &mut self.repl.master {
+            link.last_io = Instant::now();
+        }
+        self.feed_replication_stream(resp);
+    }
+
+    // what a replica sends its master about once a second and after a GETACK
+    pub fn replica_ack(&self) -> Vec<u8> {
+        let mut resp = Vec::new();
+        write_command(
+            &mut resp,
+            &[
+                &b"REPLCONF"[..],
+                b"ACK",
+                self.repl.offset.to_string().as_bytes(),
+            ],
+        );
+        resp
+    }
+
+    // adds a write command to the stream of the replicas. Replicas only pass on what their master
+    // sent
+    pub(super) fn feed_replication(&mut self, command: &RedisCommand, result: &RedisResult) {
+        if self.repl.backlog.is_none() || self.is_replica() {
+            return;
+        }
+        let Some(args) = self.propagated_args(command, result) else {
+            return;
+        };
+
+        let mut resp = Vec::new();
+        if self.repl.selected_db != Some(self.db) {
+            write_command(&mut resp, &[&b"SELECT"[..], self.db.to_string().as_bytes()]);
+            self.repl.selected_db = Some(self.db);
+        }
+        write_command(&mut resp, &args);
+        self.feed_replication_stream(&resp);
+    }
+
+    // pings the replicas so they notice a master that is gone, and drops links that timed out.
+    // Called from the periodic tasks
+    pub(super) fn replication_cron(&mut self) {
+        let timeout = Duration::from_secs(self.config.repl_timeout);
+
+        if let Some(link) = &mut self.repl.master
+            && let Some(id) = link.connection
+            && link.last_io.elapsed() > timeout
+        {
+            eprintln!("Warning: timeout on the link with the master, connecting again");
+            link.connection = None;
+            self.repl.dropped_connections.push(id);
+        }
+
+        let timed_out = self
+            .repl
+            .replicas
+            .iter()
+            .filter(|replica| replica.online && replica.last_ack.elapsed() > timeout)
+            .map(|replica| replica.id);
+        self.repl.dropped_connections.extend(timed_out);
+
+        let ping_period = Duration::from_secs(self.config.repl_ping_replica_period);
+        if self.repl.last_ping.elapsed() >= ping_period {
+            self.repl.last_ping = Instant::now();
+            if !self.is_replica() && !self.repl.replicas.is_empty() {
+                let mut resp = Vec::new();
+                write_command(&mut resp, &[&b"PING"[..]]);
+                self.feed_replication_stream(&resp);
+            }
+        }
+    }
+
+    pub(super) fn write_replication_info(&self, info: &mut Vec<u8>) {
+        match &self.repl.master {
+            Some(link) => {
+                let up = link.connection.is_some();
+                write!(
+                    info,
+                    "role:slave\r\n\
+                     master_host:{}\r\n\
+                     master_port:{}\r\n\
+                     master_link_status:{}\r\n\
+                     master_last_io_seconds_ago:{}\r\n\
+                     slave_repl_offset:{}\r\n\
+                     slave_read_only:{}\r\n",
+                    link.host,
+                    link.port,
+                    if up { "up" } else { "down" },
+                    if up {
+                        link.last_io.elapsed().as_secs() as i64
+                    } else {
+                        -1
+                    },
+                    self.repl.offset,
+                    self.config.replica_read_only as u8,
+                )
+                .unwrap();
+            }
+            None => info.extend_from_slice(b"role:master\r\n"),
+        }
+
+        let online = self.repl.replicas.iter().filter(|replica| replica.online);
+        write!(info, "connected_slaves:{}\r\n", online.clone().count()).unwrap();
+        for (index, replica) in online.enumerate() {
+            write!(
+                info,
+                "slave{}:ip={},port={},state=online,offset={},lag={}\r\n",
+                index,
+                replica.ip.as_deref().unwrap_or("?"),
+                replica.listening_port,
+                replica.ack_offset,
+                replica.last_ack.elapsed().as_secs(),
+            )
+            .unwrap();
+        }
+
+        let histlen = self
+            .repl
+            .backlog
+            .as_ref()
+            .map_or(0, |backlog| backlog.len());
+        write!(
+            info,
+            "master_replid:{}\r\n\
+             master_replid2:{}\r\n\
+             master_repl_offset:{}\r\n\
+             second_repl_offset:{}\r\n\
+             repl_backlog_active:{}\r\n\
+             repl_backlog_size:{}\r\n\
+             repl_backlog_first_byte_offset:{}\r\n\
+             repl_backlog_histlen:{}\r\n",
+            self.repl.replid,
+            self.repl.replid2,
+            self.repl.offset,
+            self.repl.second_replid_offset,
+            self.repl.backlog.is_some() as u8,
+            self.config.repl_backlog_size,
+            self.repl.backlog_first_offset(),
+            histlen,
+        )
+        .unwrap();
+    }
+
+    // the stream goes into the backlog, which keeps the last repl-backlog-size bytes of it. Like
+    // redis there is no stream before the first replica
+    fn feed_replication_stream(&mut self, resp: &[u8]) {
+        let Some(backlog) = &mut self.repl.backlog else {
+            return;
+        };
+
+        self.repl.offset += resp.len() as i64;
+        backlog.extend(resp);
+        let excess = backlog.len().saturating_sub(self.config.repl_backlog_size);
+        backlog.drain(..excess);
+    }
+
+    fn replica_mut(&mut self, id: c_int) -> &mut Replica {
+        match self
+            .repl
+            .replicas
+            .iter()
+            .position(|replica| replica.id == id)
+        {
+            Some(index) => &mut self.repl.replicas[index],
+            None => {
+                self.repl.replicas.push(Replica {
+                    id,
+                    ip: None,
+                    listening_port: 0,
+                    online: false,
+                    sent_offset: 0,
+                    ack_offset: 0,
+                    last_ack: Instant::now(),
+                });
+                self.repl.replicas.last_mut().unwrap()
+            }
+        }
+    }
+
+    fn drop_master_link(&mut self) {
+        if let Some(link) = &mut self.repl.master
+            && let Some(id) = link.connection.take()
+        {
+            self.repl.dropped_connections.push(id);
+        }
+    }
+
+    // a promoted replica starts a new history, its replicas can continue the old one up to here
+    fn shift_replid(&mut self) {
+        self.repl.replid2 = mem::replace(&mut self.repl.replid, random_replid());
+        self.repl.second_replid_offset = self.repl.offset + 1;
+        self.repl.selected_db = None;
+    }
+}
+
+// 40 hex characters from /dev/urandom like redis, or a hash of the time and pid without it
+pub(super) fn random_replid() -> String {
+    let mut bytes = [0u8; 20];
+    let read = File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut bytes));
+    if read.is_err() {
+        let seed = format!("{}-{}", unix_time_ms(), std::process::id());
+        for (index, chunk) in bytes.chunks_mut(8).enumerate() {
+            let hash = siphash13(index as u64, 0, seed.as_bytes()).to_le_bytes();
+            chunk.copy_from_slice(&hash[..chunk.len()]);
+        }
+    }
+
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::TimeUnit,
+        config::Config,
+        protocol::parser::{
+            CommandParseState, convert_command_parse_state_to_redis_command, parse_command,
+        },
+        redis::resp_or_error,
+    };
+
+    fn set(redis: &mut Redis, key: &'static [u8], value: &'static [u8]) {
+        redis.execute_command(&RedisCommand::Set { key, value });
+    }
+
+    // the replid, offset and snapshot of a +FULLRESYNC reply
+    fn full_resync(reply: Vec<u8>) -> (String, i64, Vec<u8>) {
+        let line_end = reply.windows(2).position(|w| w == b"\r\n").unwrap();
+        let line = std::str::from_utf8(&reply[..line_end]).unwrap();
+        let words: Vec<&str> = line.split(' ').collect();
+        assert_eq!("+FULLRESYNC", words[0]);
+
+        let rest = &reply[line_end + 2..];
+        let len_end = rest.windows(2).position(|w| w == b"\r\n").unwrap();
+        let len: usize = std::str::from_utf8(&rest[1..len_end])
+            .unwrap()
+            .parse()
+            .unwrap();
+        let snapshot = &rest[len_end + 2..];
+        assert_eq!(len, snapshot.len());
+
+        (
+            words[1].to_string(),
+            words[2].parse().unwrap(),
+            snapshot.to_vec(),
+        )
+    }
+
+    // runs the stream of a master on a replica the way the server does it
+    fn apply_stream(replica: &mut Redis, stream: &[u8]) {
+        let mut pos = 0;
+        let mut db = 0;
+        let mut state = CommandParseState::new();
+        while pos < stream.len() {
+            parse_command(stream, &mut pos, &mut state).unwrap();
+            let mut resp = Vec::new();
+            state.write_resp(&mut resp);
+            replica.feed_master_stream(&resp);
+
+            let command = convert_command_parse_state_to_redis_command(&state).unwrap();
+            replica.execute_command_in_db(&mut db, &command);
+            state.clear();
+        }
+    }
+
+    #[test]
+    fn test_full_and_partial_resync() {
+        let mut master = Redis::new();
+        set(&mut master, b"before", b"sync");
+        // there is no stream before the first replica
+        assert_eq!(0, master.replication_offset());
+
+        let reply = master.sync_replica(7, Some("127.0.0.1".to_string()), b"?", -1);
+        let (replid, offset, snapshot) = full_resync(resp_or_error(reply).unwrap());
+        assert_eq!(master.repl.replid, replid);
+        assert_eq!(0, offset);
+
+        let mut replica = Redis::new();
+        replica.replica_of(Some((b"127.0.0.1", 6379)));
+        replica
+            .load_master_snapshot(&replid, offset, &snapshot)
+            .unwrap();
+        assert!(replica.dbs[0].dict.peek(b"before").is_some());
+
+        set(&mut master, b"after", b"sync");
+        master.execute_command(&RedisCommand::Get { key: b"after" });
+        let mut out = Vec::new();
+        assert!(master.replication_output(7, &mut out));
+
+        let mut expected = Vec::new();
+        write_command(&mut expected, &[&b"SELECT"[..], b"0"]);
+        write_command(&mut expected, &[&b"SET"[..], b"after", b"sync"]);
+        assert_eq!(expected, out);
+        assert_eq!(out.len() as i64, master.replication_offset());
+
+        apply_stream(&mut replica, &out);
+        assert_eq!(master.replication_offset(), replica.replication_offset());
+        assert!(replica.dbs[0].dict.peek(b"after").is_some());
+
+        // nothing new for the replica
+        out.clear();
+        assert!(master.replication_output(7, &mut out));
+        assert!(out.is_empty());
+
+        // a replica that reconnects gets what it misses from the backlog
+        let (replid, offset) = replica.psync_args();
+        set(&mut master, b"missed", b"write");
+        let reply = master.sync_replica(8, None, replid.as_bytes(), offset);
+        assert_eq!(
+            Ok(format!("+CONTINUE {}\r\n", replid).into_bytes()),
+            resp_or_error(reply)
+        );
+        let mut out = Vec::new();
+        assert!(master.replication_output(8, &mut out));
+        apply_stream(&mut replica, &out);
+        assert_eq!(master.replication_offset(), replica.replication_offset());
+        assert!(replica.dbs[0].dict.peek(b"missed").is_some());
+
+        // another history or an offset the master never had needs a full resync
+        let other = random_replid();
+        for (replid, offset) in [(other.as_str(), 1), (replid.as_str(), 1000)] {
+            let reply = master.sync_replica(9, None, replid.as_bytes(), offset);
+            full_resync(resp_or_error(reply).unwrap());
+        }
+
+        assert!(!master.replication_output(10, &mut out));
+    }
+
+    #[test]
+    fn test_expired_keys_propagated() {
+        let mut master = Redis::new();
+        let reply = master.sync_replica(7, None, b"?", -1);
+        let (replid, offset, snapshot) = full_resync(resp_or_error(reply).unwrap());
+        let mut replica = Redis::new();
+        replica.replica_of(Some((b"127.0.0.1", 6379)));
+        replica
+            .load_master_snapshot(&replid, offset, &snapshot)
+            .unwrap();
+
+        for key in [&b"read"[..], b"idle"] {
+            master.execute_command(&RedisCommand::Set { key, value: b"v" });
+            master.execute_command(&RedisCommand::Expire {
+                key,
+                time: 1,
+                unit: TimeUnit::Milliseconds,
+                absolute: false,
+                flags: Default::default(),
+            });
+        }
+        // the ttls already ran out when the replica gets them
+        std::thread::sleep(Duration::from_millis(5));
+        let mut out = Vec::new();
+        master.replication_output(7, &mut out);
+        apply_stream(&mut replica, &out);
+
+        // the replica keeps the keys until its master deletes them
+        replica.run_periodic_tasks();
+        replica.execute_command(&RedisCommand::Get { key: b"read" });
+        assert_eq!(2, replica.dbs[0].dict.len());
+
+        // one is deleted when it is read, the other one by the active expire cycle
+        master.execute_command(&RedisCommand::Get { key: b"read" });
+        master.run_periodic_tasks();
+        let mut out = Vec::new();
+        master.replication_output(7, &mut out);
+        let mut expected = Vec::new();
+        for key in [&b"read"[..], b"idle"] {
+            write_command(&mut expected, &[&b"DEL"[..], key]);
+        }
+        assert_eq!(expected, out);
+
+        apply_stream(&mut replica, &out);
+        assert!(replica.dbs[0].dict.is_empty());
+        assert!(replica.dbs[0].expires.is_empty());
+    }
+
+    #[test]
+    fn test_backlog_trimmed() {
+        let mut config = Config::new();
+        config.repl_backlog_size = 64;
+        let mut master = Redis::with_config(config);
+
+        resp_or_error(master.sync_replica(7, None, b"?", -1)).unwrap();
+        for _ in 0..10 {
+            set(&mut master, b"key", b"value");
+        }
+        assert_eq!(64, master.repl.backlog.as_ref().unwrap().len());
+
+        // the replica wasn't handed the stream in time
+        let mut out = Vec::new();
+        assert!(!master.replication_output(7, &mut out));
+
+        let replid = master.repl.replid.clone();
+        let reply = master.sync_replica(7, None, replid.as_bytes(), 1);
+        full_resync(resp_or_error(reply).unwrap());
+        assert!(master.replication_output(7, &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_acks() {
+        let mut master = Redis::new();
+
+        assert_eq!(
+            Ok(b"+OK\r\n".to_vec()),
+            resp_or_error(
+                master
+                    .replconf(7, &[ReplConfOption::ListeningPort(6380)])
+                    .unwrap()
+            )
+        );
+        // still in the handshake
+        assert!(master.online_replicas().is_empty());
+
+        resp_or_error(master.sync_replica(7, None, b"?", -1)).unwrap();
+        assert_eq!(vec![7], master.online_replicas());
+        set(&mut master, b"key", b"value");
+        let offset = master.replication_offset();
+        assert_eq!(0, master.replicas_acked(offset));
+
+        // WAIT asks for acks once for the round
+        master.request_replica_acks();
+        master.send_replica_ack_requests();
+        master.send_replica_ack_requests();
+        let mut out = Vec::new();
+        master.replication_output(7, &mut out);
+        let mut getack = Vec::new();
+        write_command(&mut getack, &[&b"REPLCONF"[..], b"GETACK", b"*"]);
+        assert!(out.ends_with(&getack));
+        assert!(!out[..out.len() - getack.len()].ends_with(&getack));
+
+        assert!(master.replconf(7, &[ReplConfOption::Ack(offset)]).is_none());
+        assert_eq!(1, master.replicas_acked(offset));
+        assert_eq!(0, master.replicas_acked(offset + 1));
+
+        let info = resp_or_error(master.info(&[b"replication"])).unwrap();
+        let info = String::from_utf8(info).unwrap();
+        assert!(info.contains(&format!(
+            "connected_slaves:1\r\nslave0:ip=?,port=6380,state=online,offset={},lag=0\r\n",
+            offset
+        )));
+
+        // WAIT needs the connection, only the server runs it
+        assert_eq!(
+            Err(CommandError::ConnectionOnly {
+                cmd: b"wait".to_vec()
+            }),
+            resp_or_error(master.execute_command(&RedisCommand::Wait {
+                numreplicas: 1,
+                timeout: 0
+            }))
+        );
+
+        master.connection_closed(7);
+        assert_eq!(0, master.replicas_acked(offset));
+        assert!(master.online_replicas().is_empty());
+    }
+
+    #[test]
+    fn test_replica_of() {
+        let mut replica = Redis::new();
+        assert!(!replica.is_read_only());
+
+        assert_eq!(
+            Ok(b"+OK\r\n".to_vec()),
+            resp_or_error(replica.replica_of(Some((b"127.0.0.1", 6379))))
+        );
+        assert!(replica.is_read_only());
+        assert_eq!(
+            Ok(b"+OK Already connected to specified master\r\n".to_vec()),
+            resp_or_error(replica.replica_of(Some((b"127.0.0.1", 6379))))
+        );
+        assert_eq!(
+            Some(("127.0.0.1".to_string(), 6379)),
+            replica.master_to_connect()
+        );
+        // tried again only after a moment
+        assert_eq!(None, replica.master_to_connect());
+
+        // a replica that isn't synced can't have replicas of its own
+        assert_eq!(
+            Err(CommandError::NoMasterLink),
+            resp_or_error(replica.sync_replica(8, None, b"?", -1))
+        );
+
+        let mut master = Redis::new();
+        let reply = master.sync_replica(7, None, b"?", -1);
+        let (replid, offset, snapshot) = full_resync(resp_or_error(reply).unwrap());
+        replica
+            .load_master_snapshot(&replid, offset, &snapshot)
+            .unwrap();
+        replica.master_link_up(3);
+        set(&mut master, b"key", b"value");
+        let mut out = Vec::new();
+        master.replication_output(7, &mut out);
+        apply_stream(&mut replica, &out);
+
+        // the promoted replica starts a new history, its replicas can carry on with a partial
+        // resync
+        let (old_replid, offset) = replica.psync_args();
+        replica.replica_of(None);
+        assert!(!replica.is_replica());
+        assert_eq!(vec![3], replica.take_dropped_connections());
+        assert_ne!(old_replid, replica.repl.replid);
+        assert_eq!(old_replid, replica.repl.replid2);
+        assert_eq!(offset, replica.repl.second_replid_offset);
+
+        let reply = replica.sync_replica(8, None, old_replid.as_bytes(), offset);
+        assert_eq!(
+            Ok(format!("+CONTINUE {}\r\n", replica.repl.replid).into_bytes()),
+            resp_or_error(reply)
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use std::{borrow::Cow, cmp::Ordering, io::Write};
+
+use crate::{
+    commands::SortOptions,
+    config::ListEncoding,
+    error::{CommandError, RedisError},
+    redis::{
+        Redis, RedisResult,
+        db::{Db, unix_time_ms},
+        hash_table::HashNode,
+        listpack::{ListPack, ListPackEntry},
+        redis_object::RedisObject,
+        ziplist::{ZipEntry, ZipList, ZipListValue},
+    },
+};
+
+struct SortItem<'a> {
+    value: ZipListValue<'a>,
+    // numeric sorts compare the score, alpha sorts the bytes of the BY value or the element
+    score: f64,
+    cmp_bytes: Option<Cow<'a, [u8]>>,
+}
+
+impl Redis {
+    pub(super) fn sort(&mut self, key: &[u8], options: &SortOptions) -> RedisResult {
+        let db = &self.dbs[self.db];
+        let now = unix_time_ms();
+
+        // listpacks do not have borrowed values so their elements are materialized first
+        let materialized: Vec<RedisObject>;
+        let values: Vec<ZipListValue> = match db.dict.peek(key) {
+            Some(RedisObject::List(list)) => list.values().collect(),
+            Some(RedisObject::ListPack(list)) => {
+                materialized = list.iter().map(|o| list.get_at_offset(o)).collect();
+                materialized.iter().map(object_value).collect()
+            }
+            Some(_) => {
+                return RedisResult::Error(RedisError::CommandError(CommandError::WrongType));
+            }
+            None => Vec::new(),
+        };
+
+        // a BY pattern without a * can never match another key so the list order is kept
+        let dont_sort = options.by.is_some_and(|by| !by.contains(&b'*'));
+
+        let mut items = Vec::with_capacity(values.len());
+        for value in values {
+            let mut item = SortItem {
+                value,
+                score: 0.0,
+                cmp_bytes: None,
+            };
+
+            if !dont_sort {
+                let by_value = match options.by {
+                    Some(by) => lookup_by_pattern(db, now, by, &value),
+                    None => Some(value),
+                };
+
+                if options.alpha {
+                    item.cmp_bytes = by_value.map(value_bytes);
+                } else if let Some(by_value) = by_value {
+                    item.score = match parse_score(&by_value) {
+                        Some(score) => score,
+                        None => {
+                            return RedisResult::Error(RedisError::CommandError(
+                                CommandError::NotADouble,
+                            ));
+                        }
+                    };
+                }
+            }
+
+            items.push(item);
+        }
+
+        if !dont_sort {
+            items.sort_by(|a, b| {
+                let ordering = compare_items(a, b, options.alpha);
+                if options.desc {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+
+        let (start, count) = limit_bounds(options.limit, items.len());
+        let items = &items[start..start + count];
+
+        match options.store {
+            Some(dest) => {
+                let mut output = Vec::with_capacity(count * options.get.len().max(1));
+                for item in items {
+                    if options.get.is_empty() {
+                        output.push(value_bytes(item.value).into_owned());
+                    }
+                    for pattern in &options.get {
+                        // missing keys are stored as empty strings
+                        let value = get_value(db, now, pattern, &item.value);
+                        output.push(value.map(value_bytes).unwrap_or_default().into_owned());
+                    }
+                }
+
+                self.store_list(dest, &output);
+                RedisResult::Int(output.len() as i64)
+            }
+            None => {
+                let mut reply = Vec::new();
+                Self::write_array_header(&mut reply, count * options.get.len().max(1));
+                for item in items {
+                    if options.get.is_empty() {
+                        item.value.write_resp(&mut reply);
+                    }
+                    for pattern in &options.get {
+                        match get_value(db, now, pattern, &item.value) {
+                            Some(value) => value.write_resp(&mut reply),
+                            None => reply.extend_from_slice(b"$-1\r\n"),
+                        }
+                    }
+                }
+
+                RedisResult::BulkString(reply)
+            }
+        }
+    }
+
+    // replaces dest with a list of the values, an empty result removes dest like in redis. Every
+    // stored value counts as a change
+    fn store_list(&mut self, dest: &[u8], values: &[Vec<u8>]) {
+        if values.is_empty() {
+            if self.dbs[self.db].delete(dest) {
+                self.rdb.dirty += 1;
+            }
+            return;
+        }
+
+        let list = match self.config.list_encoding {
+            ListEncoding::ZipList => {
+                let mut list = ZipList::new();
+                for value in values {
+                    list.push(ZipEntry::from_bytes(value));
+                }
+                RedisObject::List(list)
+            }
+            ListEncoding::ListPack => {
+                let mut list = ListPack::new();
+                for value in values {
+                    list.push(ListPackEntry::from_bytes(value));
+                }
+                RedisObject::ListPack(list)
+            }
+        };
+
+        self.dbs[self.db].insert(Box::new(HashNode::new_from_object(dest, list)), None);
+        self.rdb.dirty += values.len() as u64;
+    }
+}
+
+fn compare_items(a: &SortItem, b: &SortItem, alpha: bool) -> Ordering {
+    if alpha {
+        // elements without a BY value sort first
+        return match (&a.cmp_bytes, &b.cmp_bytes) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+    }
+
+    // equal scores fall back to the elements themselves so the result is deterministic
+    a.score
+        .partial_cmp(&b.score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| value_bytes(a.value).cmp(&value_bytes(b.value)))
+}
+
+// redis style LIMIT offset count, a negative count means everything after the offset
+fn limit_bounds(limit: Option<(i64, i64)>, len: usize) -> (usize, usize) {
+    let (offset, count) = match limit {
+        Some(limit) => limit,
+        None => return (0, len),
+    };
+
+    let start = offset.max(0) as usize;
+    if start >= len {
+        return (0, 0);
+    }
+
+    let count = if count < 0 {
+        len - start
+    } else {
+        (count as usize).min(len - start)
+    };
+
+    (start, count)
+}
+
+// GET # returns the element itself, other patterns are looked up like BY patterns
+fn get_value<'a>(
+    db: &'a Db,
+    now: i64,
+    pattern: &[u8],
+    element: &ZipListValue<'a>,
+) -> Option<ZipListValue<'a>> {
+    if pattern == b"#" {
+        return Some(*element);
+    }
+
+    lookup_by_pattern(db, now, pattern, element)
+}
+
+// substitutes the first * in the pattern with the element and looks the key up, a pattern like
+// `obj_*->field` looks up a field of a hash instead. Keys whose ttl ran out are missing even if
+// they were not deleted yet
+fn lookup_by_pattern<'a>(
+    db: &'a Db,
+    now: i64,
+    pattern: &[u8],
+    element: &ZipListValue,
+) -> Option<ZipListValue<'a>> {
+    let star = pattern.iter().position(|&b| b == b'*')?;
+
+    let key_end = pattern[star + 1..]
+        .windows(2)
+        .position(|w| w == b"->")
+        .map(|pos| star + 1 + pos)
+        .filter(|&arrow| arrow + 2 < pattern.len());
+
+    let mut key = Vec::with_capacity(pattern.len() + 20);
+    key.extend_from_slice(&pattern[..star]);
+    key.extend_from_slice(&value_bytes(*element));
+    key.extend_from_slice(&pattern[star + 1..key_end.unwrap_or(pattern.len())]);
+
+    if db.is_expired(&key, now) {
+        return None;
+    }
+
+    match (db.dict.peek(&key)?, key_end) {
+        (RedisObject::String(s), None) => Some(ZipListValue::Str(s)),
+        (RedisObject::Int(i), None) => Some(ZipListValue::Int(*i)),
+        // there is no hash type yet so a field lookup never finds anything
+        _ => None,
+    }
+}
+
+fn object_value(object: &RedisObject) -> ZipListValue<'_> {
+    match object {
+        RedisObject::String(s) => ZipListValue::Str(s),
+        RedisObject::Int(i) => ZipListValue::Int(*i),
+        _ => unreachable!("lists only hold strings and ints"),
+    }
+}
+
+fn value_bytes(value: ZipListValue<'_>) -> Cow<'_, [u8]> {
+    match value {
+        ZipListValue::Str(s) => Cow::Borrowed(s),
+        ZipListValue::Int(i) => {
+            let mut bytes = Vec::with_capacity(20);
+            write!(bytes, "{}", i).unwrap();
+            Cow::Owned(bytes)
+        }
+    }
+}
+
+fn parse_score(value: &ZipListValue) -> Option<f64> {
+    match value {
+        ZipListValue::Int(i) => Some(*i as f64),
+        ZipListValue::Str(s) => {
+            let score = std::str::from_utf8(s)
+                .ok()?
+                .trim_ascii()
+                .parse::<f64>()
+                .ok()?;
+            if score.is_nan() { None } else { Some(score) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_bounds() {
+        struct TestData {
+            limit: Option<(i64, i64)>,
+            len: usize,
+            expected: (usize, usize),
+        }
+
+        let tests = vec![
+            TestData {
+                limit: None,
+                len: 5,
+                expected: (0, 5),
+            },
+            TestData {
+                limit: Some((1, 2)),
+                len: 5,
+                expected: (1, 2),
+            },
+            TestData {
+                limit: Some((-3, 2)),
+                len: 5,
+                expected: (0, 2),
+            },
+            TestData {
+                limit: Some((3, -1)),
+                len: 5,
+                expected: (3, 2),
+            },
+            TestData {
+                limit: Some((4, 100)),
+                len: 5,
+                expected: (4, 1),
+            },
+            TestData {
+                limit: Some((5, 1)),
+                len: 5,
+                expected: (0, 0),
+            },
+            TestData {
+                limit: Some((0, 0)),
+                len: 5,
+                expected: (0, 0),
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(test.expected, limit_bounds(test.limit, test.len));
+        }
+    }
+
+    #[test]
+    fn test_lookup_by_pattern() {
+        let now = 1000;
+        let mut db = Db::new();
+        db.insert(Box::new(HashNode::new_from_bytes(b"weight_a", b"10")), None);
+        db.insert(
+            Box::new(HashNode::new_from_bytes(b"name_5_x", b"five")),
+            None,
+        );
+        // expired keys that the lazy and active expiry did not get to yet
+        db.insert(
+            Box::new(HashNode::new_from_bytes(b"weight_c", b"30")),
+            Some(now),
+        );
+        db.insert(
+            Box::new(HashNode::new_from_bytes(b"weight_d", b"40")),
+            Some(now + 1),
+        );
+
+        struct TestData {
+            pattern: &'static [u8],
+            element: ZipListValue<'static>,
+            expected: Option<ZipListValue<'static>>,
+        }
+
+        let tests = vec![
+            TestData {
+                pattern: b"weight_*",
+                element: ZipListValue::Str(b"a"),
+                expected: Some(ZipListValue::Int(10)),
+            },
+            TestData {
+                pattern: b"name_*_x",
+                element: ZipListValue::Int(5),
+                expected: Some(ZipListValue::Str(b"five")),
+            },
+            TestData {
+                pattern: b"weight_*",
+                element: ZipListValue::Str(b"b"),
+                expected: None,
+            },
+            TestData {
+                pattern: b"weight_a",
+                element: ZipListValue::Str(b"a"),
+                expected: None,
+            },
+            TestData {
+                pattern: b"weight_*",
+                element: ZipListValue::Str(b"c"),
+                expected: None,
+            },
+            TestData {
+                pattern: b"weight_*",
+                element: ZipListValue::Str(b"d"),
+                expected: Some(ZipListValue::Int(40)),
+            },
+            // strings have no fields
+            TestData {
+                pattern: b"weight_*->field",
+                element: ZipListValue::Str(b"a"),
+                expected: None,
+            },
+            // an arrow without a field is part of the key
+            TestData {
+                pattern: b"weight_*->",
+                element: ZipListValue::Str(b"a"),
+                expected: None,
+            },
+        ];
+
+        for test in tests {
+            assert_eq!(
+                test.expected,
+                lookup_by_pattern(&db, now, test.pattern, &test.element)
+            );
+        }
+
+        assert_eq!(
+            Some(ZipListValue::Str(b"a")),
+            get_value(&db, now, b"#", &ZipListValue::Str(b"a"))
+        );
+        assert_eq!(
+            None,
+            get_value(&db, now, b"weight_*", &ZipListValue::Str(b"c"))
+        );
+    }
+}
@@ -0,0 +1,1112 @@
+use std::time::{Duration, Instant};
+
+use crate::redis::{
+    dict_util::{FAIR_RANDOM_SAMPLES, Rng, fingerprint, next_scan_cursor},
+    hash_table::{HashNode, hash_bytes},
+    redis_object::{Access, RedisObject},
+};
+
+// open addressing alternative to HashDict in the style of swiss tables. Slots are grouped by 16
+// and every slot has a control byte that is either empty, deleted or the top 7 bits of the hash
+// of its key, so a probe compares 16 control bytes at once and only touches the slots whose
+// bytes match. Keys up to INLINE_KEY_CAPACITY bytes and the values live in the slot array
+// itself, a lookup of a short key does not follow any pointer.
+//
+// It has the same api and the same incremental resizing as HashDict, the old table is moved
+// over one group at a time while both tables are used for lookups
+
+// the nodes HashDict keeps the keyspace in
+type KeyNode = HashNode<Box<[u8]>, RedisObject, Access>;
+
+const GROUP_WIDTH: usize = 16;
+const CTRL_EMPTY: u8 = 0x80;
+const CTRL_DELETED: u8 = 0xFE;
+// groups moved per dict operation while resizing
+const REHASHING_SPEED: usize = 1;
+// groups moved between time checks when rehashing on a time budget
+const REHASHING_BATCH: usize = 100;
+// tables grow once 7/8 of the slots are used or deleted
+const MAX_LOAD_NUMERATOR: usize = 7;
+const MAX_LOAD_DENOMINATOR: usize = 8;
+// tables that are less than this percentage full get shrunk
+const MIN_FILL_PERCENT: usize = 10;
+const INLINE_KEY_CAPACITY: usize = 22;
+
+pub enum ResizeState {
+    NotResizing,
+    Resizing {
+        new_ht: SwissTable,
+        // position in groups of the old table
+        resizing_pos: usize,
+    },
+}
+
+pub struct SwissDict {
+    main_ht: SwissTable,
+    state: ResizeState,
+    rng: Rng,
+}
+
+impl Default for SwissDict {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SwissDict {
+    pub fn new() -> Self {
+        SwissDict {
+            main_ht: SwissTable::new(1),
+            state: ResizeState::NotResizing,
+            rng: Rng::new(),
+        }
+    }
+
+    // takes the same nodes as HashDict, the key and value are moved out of the node into a slot.
+    // The box is part of the api both dicts share, callers don't know which one the db uses
+    #[allow(clippy::boxed_local)]
+    pub fn insert(&mut self, node: Box<KeyNode>) {
+        let (key, value, access) = (*node).into_parts();
+        let hash = hash_bytes(&key);
+        self.try_finish_resizing();
+
+        match &mut self.state {
+            ResizeState::NotResizing => {
+                self.main_ht.insert(hash, key, value, access);
+                self.grow_if_needed();
+            }
+            ResizeState::Resizing {
+                new_ht,
+                resizing_pos,
+            } => {
+                Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_SPEED);
+
+                // the new table has to keep free slots, if the keys that are left would not fit
+                // next to the insert the resize is finished right away
+                if new_ht.load() + self.main_ht.used + 1 > new_ht.max_load() {
+                    let groups = self.main_ht.groups();
+                    Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, groups);
+                    self.try_finish_resizing();
+                    self.main_ht.insert(hash, key, value, access);
+                    self.grow_if_needed();
+                    return;
+                }
+
+                // the key might not have been moved yet, it would overwrite the new value once
+                // its group gets moved over
+                if let Some(index) = self.main_ht.find(hash, &key) {
+                    self.main_ht.remove(index);
+                }
+                new_ht.insert(hash, key, value, access);
+            }
+        }
+    }
+
+    // lookups count as an access of the key like in HashDict
+    pub fn lookup(&mut self, key: &[u8]) -> Option<&RedisObject> {
+        self.lookup_mut(key).map(|value| &*value)
+    }
+
+    pub fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut RedisObject> {
+        self.rehash_step(REHASHING_SPEED);
+        let random = self.rng.next();
+
+        let hash = hash_bytes(key);
+        let slot = match &mut self.state {
+            ResizeState::NotResizing => self.main_ht.slot_mut(hash, key),
+            ResizeState::Resizing { new_ht, .. } => match new_ht.find(hash, key) {
+                Some(index) => new_ht.slots[index].as_mut(),
+                None => self.main_ht.slot_mut(hash, key),
+            },
+        }?;
+        slot.access.touch(random);
+        Some(&mut slot.value)
+    }
+
+    // the last access of the key without counting this as one, the metadata HashDict keeps for
+    // the keyspace
+    pub fn metadata(&self, key: &[u8]) -> Option<Access> {
+        let hash = hash_bytes(key);
+        match &self.state {
+            ResizeState::NotResizing => self.main_ht.slot(hash, key),
+            ResizeState::Resizing { new_ht, .. } => new_ht
+                .slot(hash, key)
+                .or_else(|| self.main_ht.slot(hash, key)),
+        }
+        .map(|slot| slot.access)
+    }
+
+    // lookup that does not help the resizing along
+    pub fn peek(&self, key: &[u8]) -> Option<&RedisObject> {
+        let hash = hash_bytes(key);
+        match &self.state {
+            ResizeState::NotResizing => self.main_ht.lookup(hash, key),
+            ResizeState::Resizing { new_ht, .. } => new_ht
+                .lookup(hash, key)
+                .or_else(|| self.main_ht.lookup(hash, key)),
+        }
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> bool {
+        self.take_slot(key).is_some()
+    }
+
+    // same as HashDict::remove, the value is moved out of its slot into a new node
+    pub fn remove(&mut self, key: &[u8]) -> Option<Box<KeyNode>> {
+        let slot = self.take_slot(key)?;
+        Some(Box::new(HashNode::from_parts(
+            slot.key.into_boxed(),
+            slot.value,
+            slot.access,
+        )))
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.state {
+            ResizeState::NotResizing => self.main_ht.used,
+            ResizeState::Resizing { new_ht, .. } => self.main_ht.used + new_ht.used,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // returns true if a resize was started
+    pub fn resize_if_needed(&mut self) -> bool {
+        self.try_finish_resizing();
+
+        match self.state {
+            ResizeState::NotResizing => self.grow_if_needed() || self.shrink_if_needed(),
+            ResizeState::Resizing { .. } => false,
+        }
+    }
+
+    // moves groups to the new table until the time budget runs out, returns the amount of
+    // groups moved
+    pub fn rehash_for(&mut self, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut moved = 0;
+
+        loop {
+            self.try_finish_resizing();
+
+            match &mut self.state {
+                ResizeState::NotResizing => return moved,
+                ResizeState::Resizing {
+                    new_ht,
+                    resizing_pos,
+                } => {
+                    let before = *resizing_pos;
+                    Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_BATCH);
+                    moved += *resizing_pos - before;
+                }
+            }
+
+            if start.elapsed() >= budget {
+                self.try_finish_resizing();
+                return moved;
+            }
+        }
+    }
+
+    // groups of the old table moved so far and the amount of groups of the old table
+    pub fn rehash_progress(&self) -> Option<(usize, usize)> {
+        match &self.state {
+            ResizeState::NotResizing => None,
+            ResizeState::Resizing { resizing_pos, .. } => {
+                Some((*resizing_pos, self.main_ht.groups()))
+            }
+        }
+    }
+
+    // bytes of the control bytes and of the slots without an entry, the used slots are counted
+    // per key by entry_size
+    pub fn table_overhead(&self) -> usize {
+        let slot_size = std::mem::size_of::<Option<Slot>>();
+        (0..2)
+            .filter_map(|index| self.table(index))
+            .map(|table| table.ctrl.capacity() + (table.slots.capacity() - table.used) * slot_size)
+            .sum()
+    }
+
+    // bytes of the slot holding a key, keys too long to be stored inline add their allocation.
+    // The heap allocations of the value are not included
+    pub fn entry_size(&self, key: &[u8]) -> usize {
+        let key_allocation = if key.len() > INLINE_KEY_CAPACITY {
+            key.len()
+        } else {
+            0
+        };
+        std::mem::size_of::<Option<Slot>>() + key_allocation
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            dict: self,
+            table: 0,
+            slot: 0,
+        }
+    }
+
+    pub fn unsafe_iter(&self) -> UnsafeIter {
+        UnsafeIter {
+            table: 0,
+            slot: 0,
+            current: None,
+            fingerprint: self.fingerprint(),
+        }
+    }
+
+    // slots are not chained so a sample of neighbouring slots is not biased toward any key
+    pub fn random_entry(&mut self) -> Option<(&[u8], &RedisObject)> {
+        self.rehash_step(REHASHING_SPEED);
+
+        let entries = self.sample_entries(FAIR_RANDOM_SAMPLES);
+        self.rng.pick(&entries).or_else(|| self.random_slot_entry())
+    }
+
+    // up to `n` entries from the slots following a random one
+    pub fn sample(&mut self, n: usize) -> Vec<(&[u8], &RedisObject)> {
+        self.rehash_step(n);
+        self.sample_entries(n)
+    }
+
+    // same reverse binary cursor as HashDict::scan, over the home groups of the keys. A home
+    // group is visited by probing from it like a lookup does, so keys that were pushed into a
+    // later group are still returned for their own home group
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&[u8], &RedisObject)) -> u64 {
+        let mut cursor = cursor;
+
+        match &self.state {
+            ResizeState::NotResizing => {
+                let mask = self.main_ht.group_mask as u64;
+                self.main_ht.scan_group(cursor & mask, &mut f);
+                cursor = next_scan_cursor(cursor, mask);
+            }
+            ResizeState::Resizing { new_ht, .. } => {
+                let (small, large) = if self.main_ht.group_mask <= new_ht.group_mask {
+                    (&self.main_ht, new_ht)
+                } else {
+                    (new_ht, &self.main_ht)
+                };
+                let small_mask = small.group_mask as u64;
+                let large_mask = large.group_mask as u64;
+
+                small.scan_group(cursor & small_mask, &mut f);
+
+                loop {
+                    large.scan_group(cursor & large_mask, &mut f);
+                    cursor = next_scan_cursor(cursor, large_mask);
+
+                    if cursor & (small_mask ^ large_mask) == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        cursor
+    }
+
+    fn grow_if_needed(&mut self) -> bool {
+        if self.main_ht.load() < self.main_ht.max_load() {
+            return false;
+        }
+
+        // a table that is mostly deleted slots is rebuilt at the same size
+        let groups = if self.main_ht.used * 2 >= self.main_ht.max_load() {
+            self.main_ht.groups() * 2
+        } else {
+            self.main_ht.groups()
+        };
+        self.start_resizing(groups);
+        true
+    }
+
+    fn shrink_if_needed(&mut self) -> bool {
+        let capacity = self.main_ht.capacity();
+        if self.main_ht.groups() > 1 && self.main_ht.used * 100 / capacity < MIN_FILL_PERCENT {
+            // a quarter full, so inserts during the resize have room
+            let groups = (self.main_ht.used * 4)
+                .div_ceil(GROUP_WIDTH)
+                .next_power_of_two();
+            self.start_resizing(groups);
+            return true;
+        }
+
+        false
+    }
+
+    fn help_resizing(
+        main_ht: &mut SwissTable,
+        new_ht: &mut SwissTable,
+        resizing_pos: &mut usize,
+        nwork: usize,
+    ) {
+        let mut amount_non_empty_groups = 0;
+        let ceiling = resizing_pos.saturating_add(nwork.saturating_mul(10));
+
+        while *resizing_pos < ceiling && *resizing_pos < main_ht.groups() {
+            let start = *resizing_pos * GROUP_WIDTH;
+            *resizing_pos += 1;
+
+            let mut moved_any = false;
+            for index in start..start + GROUP_WIDTH {
+                if let Some(slot) = main_ht.take(index) {
+                    new_ht.insert_unique(slot);
+                    moved_any = true;
+                }
+            }
+
+            if moved_any {
+                amount_non_empty_groups += 1;
+                if amount_non_empty_groups >= nwork {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn rehash_step(&mut self, nwork: usize) {
+        self.try_finish_resizing();
+
+        if let ResizeState::Resizing {
+            new_ht,
+            resizing_pos,
+        } = &mut self.state
+        {
+            Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, nwork);
+        }
+
+        self.try_finish_resizing();
+    }
+
+    fn start_resizing(&mut self, groups: usize) {
+        self.state = ResizeState::Resizing {
+            new_ht: SwissTable::new(groups),
+            resizing_pos: 0,
+        };
+    }
+
+    fn try_finish_resizing(&mut self) {
+        if let ResizeState::Resizing {
+            new_ht,
+            resizing_pos,
+        } = &mut self.state
+            && *resizing_pos >= self.main_ht.groups()
+        {
+            std::mem::swap(&mut self.main_ht, new_ht);
+            self.state = ResizeState::NotResizing;
+        }
+    }
+
+    fn take_slot(&mut self, key: &[u8]) -> Option<Slot> {
+        self.try_finish_resizing();
+
+        let hash = hash_bytes(key);
+        match &mut self.state {
+            ResizeState::NotResizing => {
+                let slot = self.main_ht.take_key(hash, key);
+                if slot.is_some() {
+                    self.shrink_if_needed();
+                }
+                slot
+            }
+            ResizeState::Resizing {
+                new_ht,
+                resizing_pos,
+            } => {
+                Self::help_resizing(&mut self.main_ht, new_ht, resizing_pos, REHASHING_SPEED);
+                // inserts take the key out of the old table, so it is only ever in one of them
+                self.main_ht
+                    .take_key(hash, key)
+                    .or_else(|| new_ht.take_key(hash, key))
+            }
+        }
+    }
+
+    fn table(&self, index: usize) -> Option<&SwissTable> {
+        match (index, &self.state) {
+            (0, _) => Some(&self.main_ht),
+            (1, ResizeState::Resizing { new_ht, .. }) => Some(new_ht),
+            _ => None,
+        }
+    }
+
+    fn table_mut(&mut self, index: usize) -> Option<&mut SwissTable> {
+        match (index, &mut self.state) {
+            (0, _) => Some(&mut self.main_ht),
+            (1, ResizeState::Resizing { new_ht, .. }) => Some(new_ht),
+            _ => None,
+        }
+    }
+
+    fn sample_entries(&self, n: usize) -> Vec<(&[u8], &RedisObject)> {
+        let n = n.min(self.len());
+        let mut entries = Vec::with_capacity(n);
+        if n == 0 {
+            return entries;
+        }
+
+        // the slots of both tables as one range, the moved part of the old table is skipped
+        let (new_ht, skipped) = match &self.state {
+            ResizeState::NotResizing => (None, 0),
+            ResizeState::Resizing {
+                new_ht,
+                resizing_pos,
+            } => (Some(new_ht), resizing_pos * GROUP_WIDTH),
+        };
+        let old_capacity = self.main_ht.capacity();
+        let range = old_capacity - skipped + new_ht.map_or(0, |new_ht| new_ht.capacity());
+
+        let mut pos = self.rng.next() as usize % range;
+        for _ in 0..(n * GROUP_WIDTH * 10).min(range) {
+            let (table, index) = if pos < old_capacity - skipped {
+                (&self.main_ht, skipped + pos)
+            } else {
+                (new_ht.unwrap(), pos - (old_capacity - skipped))
+            };
+
+            if let Some(slot) = &table.slots[index] {
+                entries.push((slot.key.as_bytes(), &slot.value));
+                if entries.len() == n {
+                    break;
+                }
+            }
+
+            pos = (pos + 1) % range;
+        }
+
+        entries
+    }
+
+    fn random_slot_entry(&self) -> Option<(&[u8], &RedisObject)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        loop {
+            let table = match &self.state {
+                ResizeState::Resizing { new_ht, .. } if self.rng.next().is_multiple_of(2) => new_ht,
+                _ => &self.main_ht,
+            };
+            let index = self.rng.next() as usize % table.capacity();
+            if let Some(slot) = &table.slots[index] {
+                return Some((slot.key.as_bytes(), &slot.value));
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut parts = [0u64; 7];
+        parts[0] = self.main_ht.slots.as_ptr() as u64;
+        parts[1] = self.main_ht.capacity() as u64;
+        parts[2] = self.main_ht.used as u64;
+        if let ResizeState::Resizing {
+            new_ht,
+            resizing_pos,
+        } = &self.state
+        {
+            parts[3] = new_ht.slots.as_ptr() as u64;
+            parts[4] = new_ht.capacity() as u64;
+            parts[5] = new_ht.used as u64;
+            parts[6] = *resizing_pos as u64;
+        }
+
+        fingerprint(parts)
+    }
+}
+
+pub struct Iter<'a> {
+    dict: &'a SwissDict,
+    table: usize,
+    slot: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], &'a RedisObject);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let table = self.dict.table(self.table)?;
+            if self.slot >= table.capacity() {
+                self.table += 1;
+                self.slot = 0;
+                continue;
+            }
+
+            self.slot += 1;
+            if let Some(slot) = &table.slots[self.slot - 1] {
+                return Some((slot.key.as_bytes(), &slot.value));
+            }
+        }
+    }
+}
+
+pub struct UnsafeIter {
+    table: usize,
+    slot: usize,
+    current: Option<usize>,
+    fingerprint: u64,
+}
+
+impl UnsafeIter {
+    pub fn next<'d>(&mut self, dict: &'d mut SwissDict) -> Option<(&'d [u8], &'d mut RedisObject)> {
+        self.check_fingerprint(dict);
+
+        loop {
+            let table = dict.table(self.table)?;
+            if self.slot >= table.capacity() {
+                self.table += 1;
+                self.slot = 0;
+                continue;
+            }
+
+            self.slot += 1;
+            if table.slots[self.slot - 1].is_some() {
+                break;
+            }
+        }
+
+        self.current = Some(self.slot - 1);
+        let slot = dict.table_mut(self.table)?.slots[self.slot - 1].as_mut()?;
+        Some((slot.key.as_bytes(), &mut slot.value))
+    }
+
+    // removing a slot does not move any other slot
+    pub fn delete_current(&mut self, dict: &mut SwissDict) {
+        self.check_fingerprint(dict);
+        let index = self.current.take().expect("no current entry to delete");
+
+        if let Some(table) = dict.table_mut(self.table) {
+            table.remove(index);
+        }
+
+        self.fingerprint = dict.fingerprint();
+    }
+
+    #[inline(always)]
+    fn check_fingerprint(&self, dict: &SwissDict) {
+        debug_assert_eq!(
+            self.fingerprint,
+            dict.fingerprint(),
+            "dict was modified while an unsafe iterator was in use"
+        );
+    }
+}
+
+pub struct SwissTable {
+    // one control byte per slot
+    ctrl: Vec<u8>,
+    slots: Vec<Option<Slot>>,
+    used: usize,
+    deleted: usize,
+    group_mask: usize,
+}
+
+struct Slot {
+    hash: u64,
+    key: InlineKey,
+    value: RedisObject,
+    access: Access,
+}
+
+impl SwissTable {
+    pub fn new(groups: usize) -> Self {
+        assert!(groups > 0 && ((groups - 1) & groups) == 0);
+        let capacity = groups * GROUP_WIDTH;
+
+        SwissTable {
+            ctrl: vec![CTRL_EMPTY; capacity],
+            slots: (0..capacity).map(|_| None).collect(),
+            used: 0,
+            deleted: 0,
+            group_mask: groups - 1,
+        }
+    }
+
+    #[inline(always)]
+    fn groups(&self) -> usize {
+        self.group_mask + 1
+    }
+
+    #[inline(always)]
+    fn capacity(&self) -> usize {
+        self.groups() * GROUP_WIDTH
+    }
+
+    // deleted slots still make probes longer so they count toward the load
+    #[inline(always)]
+    fn load(&self) -> usize {
+        self.used + self.deleted
+    }
+
+    #[inline(always)]
+    fn max_load(&self) -> usize {
+        self.capacity() * MAX_LOAD_NUMERATOR / MAX_LOAD_DENOMINATOR
+    }
+
+    #[inline(always)]
+    fn home_group(&self, hash: u64) -> usize {
+        hash as usize & self.group_mask
+    }
+
+    fn find(&self, hash: u64, key: &[u8]) -> Option<usize> {
+        let tag = ctrl_tag(hash);
+        let mut group = self.home_group(hash);
+
+        for _ in 0..self.groups() {
+            let start = group * GROUP_WIDTH;
+            let ctrl = self.group_ctrl(group);
+
+            let mut matches = match_byte(ctrl, tag);
+            while matches != 0 {
+                let index = start + matches.trailing_zeros() as usize;
+                if let Some(slot) = &self.slots[index]
+                    && slot.hash == hash
+                    && slot.key.as_bytes() == key
+                {
+                    return Some(index);
+                }
+                matches &= matches - 1;
+            }
+
+            // a key is never placed past a group that had a free slot
+            if match_byte(ctrl, CTRL_EMPTY) != 0 {
+                return None;
+            }
+            group = (group + 1) & self.group_mask;
+        }
+
+        None
+    }
+
+    fn lookup(&self, hash: u64, key: &[u8]) -> Option<&RedisObject> {
+        self.slot(hash, key).map(|slot| &slot.value)
+    }
+
+    fn slot(&self, hash: u64, key: &[u8]) -> Option<&Slot> {
+        let index = self.find(hash, key)?;
+        self.slots[index].as_ref()
+    }
+
+    fn slot_mut(&mut self, hash: u64, key: &[u8]) -> Option<&mut Slot> {
+        let index = self.find(hash, key)?;
+        self.slots[index].as_mut()
+    }
+
+    fn insert(&mut self, hash: u64, key: Box<[u8]>, value: RedisObject, access: Access) {
+        if let Some(slot) = self.slot_mut(hash, &key) {
+            slot.value = value;
+            slot.access = access;
+            return;
+        }
+
+        self.insert_unique(Slot {
+            hash,
+            key: InlineKey::new(key),
+            value,
+            access,
+        });
+    }
+
+    // insert of a key that is known not to be in the table
+    fn insert_unique(&mut self, slot: Slot) {
+        let mut group = self.home_group(slot.hash);
+
+        loop {
+            let ctrl = self.group_ctrl(group);
+            let free = match_byte(ctrl, CTRL_EMPTY) | match_byte(ctrl, CTRL_DELETED);
+            if free != 0 {
+                let index = group * GROUP_WIDTH + free.trailing_zeros() as usize;
+                if self.ctrl[index] == CTRL_DELETED {
+                    self.deleted -= 1;
+                }
+
+                self.ctrl[index] = ctrl_tag(slot.hash);
+                self.slots[index] = Some(slot);
+                self.used += 1;
+                return;
+            }
+
+            group = (group + 1) & self.group_mask;
+        }
+    }
+
+    fn take_key(&mut self, hash: u64, key: &[u8]) -> Option<Slot> {
+        let index = self.find(hash, key)?;
+        self.take(index)
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.take(index);
+    }
+
+    // empties a slot and returns what was in it. A probe for another key can only have passed
+    // this group if it was full, so the slot can become empty again if the group still has an
+    // empty slot, otherwise it is marked deleted
+    fn take(&mut self, index: usize) -> Option<Slot> {
+        let slot = self.slots[index].take()?;
+
+        let group = index / GROUP_WIDTH;
+        if match_byte(self.group_ctrl(group), CTRL_EMPTY) != 0 {
+            self.ctrl[index] = CTRL_EMPTY;
+        } else {
+            self.ctrl[index] = CTRL_DELETED;
+            self.deleted += 1;
+        }
+        self.used -= 1;
+
+        Some(slot)
+    }
+
+    // every key whose home is `group`, found by probing from it like a lookup
+    fn scan_group(&self, group: u64, f: &mut impl FnMut(&[u8], &RedisObject)) {
+        let home = group as usize;
+        let mut group = home;
+
+        for _ in 0..self.groups() {
+            let start = group * GROUP_WIDTH;
+            for slot in self.slots[start..start + GROUP_WIDTH].iter().flatten() {
+                if self.home_group(slot.hash) == home {
+                    f(slot.key.as_bytes(), &slot.value);
+                }
+            }
+
+            if match_byte(self.group_ctrl(group), CTRL_EMPTY) != 0 {
+                return;
+            }
+            group = (group + 1) & self.group_mask;
+        }
+    }
+
+    #[inline(always)]
+    fn group_ctrl(&self, group: usize) -> &[u8; GROUP_WIDTH] {
+        let start = group * GROUP_WIDTH;
+        self.ctrl[start..start + GROUP_WIDTH].try_into().unwrap()
+    }
+}
+
+// keys that fit are stored in the slot, longer ones keep their allocation
+enum InlineKey {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_KEY_CAPACITY],
+    },
+    Heap(Box<[u8]>),
+}
+
+impl InlineKey {
+    fn new(key: Box<[u8]>) -> Self {
+        if key.len() > INLINE_KEY_CAPACITY {
+            return InlineKey::Heap(key);
+        }
+
+        let mut bytes = [0u8; INLINE_KEY_CAPACITY];
+        bytes[..key.len()].copy_from_slice(&key);
+        InlineKey::Inline {
+            len: key.len() as u8,
+            bytes,
+        }
+    }
+
+    fn into_boxed(self) -> Box<[u8]> {
+        match self {
+            InlineKey::Inline { len, bytes } => Box::from(&bytes[..len as usize]),
+            InlineKey::Heap(key) => key,
+        }
+    }
+
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            InlineKey::Inline { len, bytes } => &bytes[..*len as usize],
+            InlineKey::Heap(key) => key,
+        }
+    }
+}
+
+// the top 7 bits of the hash, the home group comes from the low bits so the two do not overlap
+// for any table below 2^57 groups
+#[inline(always)]
+fn ctrl_tag(hash: u64) -> u8 {
+    (hash >> 57) as u8
+}
+
+// bit i is set if control byte i equals `byte`, written so the compiler turns it into a vector
+// compare and movemask
+#[inline(always)]
+fn match_byte(ctrl: &[u8; GROUP_WIDTH], byte: u8) -> u16 {
+    let mut mask = 0u16;
+    for (i, &c) in ctrl.iter().enumerate() {
+        mask |= ((c == byte) as u16) << i;
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_keys(dict: &mut SwissDict, keys: std::ops::Range<usize>) {
+        for i in keys {
+            let key = format!("key{}", i);
+            dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"value")));
+        }
+    }
+
+    fn finish_resizing(dict: &mut SwissDict) {
+        while dict.rehash_progress().is_some() {
+            dict.rehash_for(Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_inline_key() {
+        struct TestData {
+            key: Vec<u8>,
+            inline: bool,
+        }
+
+        let tests = vec![
+            TestData {
+                key: vec![],
+                inline: true,
+            },
+            TestData {
+                key: b"key".to_vec(),
+                inline: true,
+            },
+            TestData {
+                key: vec![b'a'; INLINE_KEY_CAPACITY],
+                inline: true,
+            },
+            TestData {
+                key: vec![b'a'; INLINE_KEY_CAPACITY + 1],
+                inline: false,
+            },
+        ];
+
+        for test in tests {
+            let key = InlineKey::new(test.key.clone().into_boxed_slice());
+            assert_eq!(test.inline, matches!(key, InlineKey::Inline { .. }));
+            assert_eq!(test.key.as_slice(), key.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_insert_lookup_and_delete() {
+        let mut dict = SwissDict::new();
+
+        for i in 0..10_000 {
+            let key = format!("key{}", i);
+            let value = format!("value{}", i);
+            dict.insert(Box::new(HashNode::new_from_bytes(
+                key.as_bytes(),
+                value.as_bytes(),
+            )));
+        }
+        assert_eq!(10_000, dict.len());
+
+        // overwrites, some of them while a resize is in progress
+        for i in (0..10_000).step_by(3) {
+            let key = format!("key{}", i);
+            dict.insert(Box::new(HashNode::new_from_bytes(key.as_bytes(), b"new")));
+        }
+        assert_eq!(10_000, dict.len());
+
+        // a long key is not inlined but behaves the same
+        let long_key = [b'k'; 100];
+        dict.insert(Box::new(HashNode::new_from_object(
+            &long_key,
+            RedisObject::Int(7),
+        )));
+        assert_eq!(Some(&RedisObject::Int(7)), dict.lookup(&long_key));
+
+        for i in 0..10_000 {
+            let key = format!("key{}", i);
+            let expected = if i % 3 == 0 {
+                RedisObject::new_from_bytes(b"new")
+            } else {
+                RedisObject::new_from_bytes(format!("value{}", i).as_bytes())
+            };
+            assert_eq!(Some(&expected), dict.lookup(key.as_bytes()), "{}", key);
+        }
+        assert!(dict.lookup(b"missing").is_none());
+
+        if let Some(RedisObject::Int(i)) = dict.lookup_mut(&long_key) {
+            *i += 1;
+        }
+        assert_eq!(Some(&RedisObject::Int(8)), dict.peek(&long_key));
+
+        for i in 0..10_000 {
+            let key = format!("key{}", i);
+            assert!(dict.delete(key.as_bytes()), "{}", key);
+            assert!(!dict.delete(key.as_bytes()));
+        }
+        assert!(dict.delete(&long_key));
+        assert!(dict.is_empty());
+    }
+
+    #[test]
+    fn test_grow_and_shrink() {
+        let mut dict = SwissDict::new();
+
+        insert_keys(&mut dict, 0..1000);
+        finish_resizing(&mut dict);
+        // 1000 keys are past 7/8 of 1024 slots
+        assert_eq!(128, dict.main_ht.groups());
+
+        for i in 10..1000 {
+            assert!(dict.delete(format!("key{}", i).as_bytes()));
+        }
+        finish_resizing(&mut dict);
+        while dict.resize_if_needed() {
+            finish_resizing(&mut dict);
+        }
+
+        assert_eq!(10, dict.len());
+        assert_eq!(4, dict.main_ht.groups());
+        for i in 0..10 {
+            assert!(dict.peek(format!("key{}", i).as_bytes()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_deleted_slots_are_cleaned_up() {
+        let mut dict = SwissDict::new();
+
+        // churn on a dict that keeps the same size fills it with deleted slots, which have to be
+        // dropped by a same size rebuild instead of growing forever
+        for round in 0..200 {
+            insert_keys(&mut dict, round * 50..round * 50 + 50);
+            for i in round * 50..round * 50 + 50 {
+                assert!(dict.delete(format!("key{}", i).as_bytes()));
+            }
+            insert_keys(&mut dict, 1_000_000..1_000_050);
+        }
+
+        finish_resizing(&mut dict);
+        assert_eq!(50, dict.len());
+        // 100 keys at the peak of a round need 16 groups
+        assert!(
+            dict.main_ht.groups() <= 16,
+            "{} groups",
+            dict.main_ht.groups()
+        );
+        assert!(dict.main_ht.load() < dict.main_ht.max_load());
+    }
+
+    #[test]
+    fn test_inserts_during_shrink() {
+        let mut dict = SwissDict::new();
+        insert_keys(&mut dict, 0..10_000);
+        finish_resizing(&mut dict);
+
+        // shrink a mostly empty table and then insert far more keys than the new table was
+        // sized for while the shrink is still going
+        for i in 20..10_000 {
+            dict.delete(format!("key{}", i).as_bytes());
+        }
+        finish_resizing(&mut dict);
+        dict.start_resizing(2);
+
+        insert_keys(&mut dict, 20..5000);
+        finish_resizing(&mut dict);
+
+        assert_eq!(5000, dict.len());
+        for i in 0..5000 {
+            assert!(dict.peek(format!("key{}", i).as_bytes()).is_some());
+        }
+        assert!(dict.main_ht.load() <= dict.main_ht.max_load());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut dict = SwissDict::new();
+        // the last insert reaches 7/8 of 2048 slots and starts a grow
+        insert_keys(&mut dict, 0..1792);
+        assert!(dict.rehash_progress().is_some());
+
+        let mut keys: Vec<Vec<u8>> = dict.iter().map(|(key, _)| key.to_vec()).collect();
+        keys.sort();
+        let mut expected: Vec<Vec<u8>> = (0..1792)
+            .map(|i| format!("key{}", i).into_bytes())
+            .collect();
+        expected.sort();
+        assert_eq!(expected, keys);
+
+        let mut it = dict.unsafe_iter();
+        while let Some((key, _)) = it.next(&mut dict) {
+            let i: usize = std::str::from_utf8(&key[3..]).unwrap().parse().unwrap();
+            if i.is_multiple_of(2) {
+                it.delete_current(&mut dict);
+            }
+        }
+
+        assert_eq!(896, dict.len());
+        assert_eq!(896, dict.iter().count());
+        assert!(dict.iter().all(|(key, _)| key.last().unwrap() % 2 == 1));
+    }
+
+    #[test]
+    fn test_scan_with_interleaved_changes() {
+        let mut dict = SwissDict::new();
+        insert_keys(&mut dict, 0..100);
+        let mut rng: u64 = 0x2545F4914F6CDD1D;
+        let mut next_key = 100_000;
+
+        // grow by inserts and shrink by deletes of the volatile keys while the scan runs, the
+        // first 100 keys have to be returned
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut step = 0;
+        loop {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+
+            if rng.is_multiple_of(4) {
+                cursor = dict.scan(cursor, |key, _| {
+                    seen.insert(key.to_vec());
+                });
+                if cursor == 0 {
+                    break;
+                }
+            } else if step < 3000 {
+                insert_keys(&mut dict, next_key..next_key + 1);
+                next_key += 1;
+            } else if next_key > 100_000 {
+                next_key -= 1;
+                dict.delete(format!("key{}", next_key).as_bytes());
+            } else {
+                dict.rehash_for(Duration::ZERO);
+            }
+            step += 1;
+        }
+
+        for i in 0..100 {
+            assert!(
+                seen.contains(format!("key{}", i).as_bytes()),
+                "missed key{}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_random_entry() {
+        let mut dict = SwissDict::new();
+        assert!(dict.random_entry().is_none());
+
+        insert_keys(&mut dict, 0..20);
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            let (key, _) = dict.random_entry().unwrap();
+            seen.insert(key.to_vec());
+        }
+        assert_eq!(20, seen.len());
+        assert_eq!(5, dict.sample(5).len());
+    }
+}
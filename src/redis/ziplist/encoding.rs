@@ -18,21 +18,29 @@ pub enum EncodingType {
 
 impl EncodingType {
     pub fn from_header(header: u8) -> EncodingType {
+        match Self::try_from_header(header) {
+            Some(encoding_type) => encoding_type,
+            None => panic!("invalid header"),
+        }
+    }
+
+    // same as from_header but for bytes that might not come from a ziplist we built ourselves
+    pub fn try_from_header(header: u8) -> Option<EncodingType> {
         match header {
-            INT8_TAG => EncodingType::Int8,
-            INT16_TAG => EncodingType::Int16,
-            INT24_TAG => EncodingType::Int24,
-            INT32_TAG => EncodingType::Int32,
-            INT64_TAG => EncodingType::Int64,
-            STR32_TAG => EncodingType::Str32BitsLength,
+            INT8_TAG => Some(EncodingType::Int8),
+            INT16_TAG => Some(EncodingType::Int16),
+            INT24_TAG => Some(EncodingType::Int24),
+            INT32_TAG => Some(EncodingType::Int32),
+            INT64_TAG => Some(EncodingType::Int64),
+            STR32_TAG => Some(EncodingType::Str32BitsLength),
 
             // Int4: 1111xxxx where xxxx is 0001-1101 (0xF1-0xFD)
-            0xF1..=0xFD => EncodingType::Int4BitsImmediate,
+            0xF1..=0xFD => Some(EncodingType::Int4BitsImmediate),
 
-            _ if (header & STR14_MASK) == STR14_TAG => EncodingType::Str14BitsLength,
-            _ if (header & STR6_MASK) == STR6_TAG => EncodingType::Str6BitsLength,
+            _ if (header & STR14_MASK) == STR14_TAG => Some(EncodingType::Str14BitsLength),
+            _ if (header & STR6_MASK) == STR6_TAG => Some(EncodingType::Str6BitsLength),
 
-            _ => panic!("invalid header"),
+            _ => None,
         }
     }
 }
@@ -104,4 +112,20 @@ mod tests {
             assert_eq!(test.expected, result);
         }
     }
+
+    #[test]
+    fn test_try_get_encoding_from_invalid_header() {
+        // 10xxxxxx other than the 32 bit string tag, 11xx0000 int tags with junk in the low bits
+        // and the end byte are not headers
+        let invalid_headers: Vec<u8> = vec![0x81, 0xBF, 0xC1, 0xD8, 0xEF, 0xFF];
+
+        for header in invalid_headers {
+            assert_eq!(
+                None,
+                EncodingType::try_from_header(header),
+                "header: {:#x}",
+                header
+            );
+        }
+    }
 }
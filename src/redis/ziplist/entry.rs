@@ -31,12 +31,8 @@ impl<'a> ZipEntry<'a> {
 
         match try_parse_int(bytes) {
             Some(i) => match i {
-                0..=12 => {
-                    let num = i as u8 + 1;
-                    let tag = 0b1111_0000;
-                    let val = num | tag;
-                    ZipEntry::Int4BitsImmediate(val)
-                }
+                // the tag and the + 1 offset are added when the entry is written
+                0..=12 => ZipEntry::Int4BitsImmediate(i as u8),
                 INT8_MIN..=INT8_MAX => ZipEntry::Int8(i as i8),
                 INT16_MIN..=INT16_MAX => ZipEntry::Int16(i as i16),
                 INT24_MIN..=INT24_MAX => ZipEntry::Int24(i as i32),
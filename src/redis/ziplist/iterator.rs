@@ -5,6 +5,12 @@ pub struct ZipListIter<'a> {
     offset: usize,
 }
 
+impl<'a> ZipListIter<'a> {
+    pub fn new(data: &'a [u8], offset: usize) -> Self {
+        ZipListIter { data, offset }
+    }
+}
+
 impl<'a> Iterator for ZipListIter<'a> {
     type Item = usize;
 
@@ -57,26 +63,59 @@ impl<'a> Iterator for ZipListIter<'a> {
 pub struct ZipListIterRev<'a> {
     data: &'a [u8],
     offset: usize,
+    done: bool,
+}
+
+impl<'a> ZipListIterRev<'a> {
+    pub fn new(data: &'a [u8], offset: usize) -> Self {
+        ZipListIterRev {
+            data,
+            offset,
+            done: false,
+        }
+    }
 }
 
 impl<'a> Iterator for ZipListIterRev<'a> {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.offset <= 10 {
+        // an empty list has its tail pointing at the end byte
+        if self.done || self.data[self.offset] == ZL_END {
             return None;
         }
 
         let current_offset = self.offset;
 
-        self.offset -= get_prevlen(&self.data[self.offset..]);
+        // only the head has a prevlen of 0
+        match get_prevlen(&self.data[self.offset..]) {
+            0 => self.done = true,
+            prevlen => self.offset -= prevlen,
+        }
 
         Some(current_offset)
     }
 }
 
-
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::redis::ziplist::{ZipEntry, ZipList};
+
+    #[test]
+    fn test_zip_list_iter() {
+        let mut zl = ZipList::new();
+        assert_eq!(0, zl.iter().count());
+        assert_eq!(0, zl.iter_rev().count());
+
+        zl.push(ZipEntry::Int8(100));
+        assert_eq!(vec![10], zl.iter().collect::<Vec<_>>());
+        assert_eq!(vec![10], zl.iter_rev().collect::<Vec<_>>());
+
+        zl.push(ZipEntry::Str14BitsLength(&[b'a'; 300]));
+        zl.push(ZipEntry::Int4BitsImmediate(5));
+
+        // 100 @ 10, 300 * 'a' @ 13, 5 with a 5 byte prevlen @ 316
+        assert_eq!(vec![10, 13, 316], zl.iter().collect::<Vec<_>>());
+        assert_eq!(vec![316, 13, 10], zl.iter_rev().collect::<Vec<_>>());
+    }
 }
@@ -1,11 +1,16 @@
 mod encoding;
 mod entry;
 mod iterator;
+mod validation;
+mod value;
 
 use std::mem::{self};
 
 pub use encoding::EncodingType;
 pub use entry::ZipEntry;
+pub use iterator::{ZipListIter, ZipListIterRev};
+pub use validation::ValidationMode;
+pub use value::{ZipListValue, ZipListValueIter};
 
 use crate::redis::redis_object::RedisObject;
 
@@ -45,6 +50,34 @@ impl ZipList {
         ZipList { data: data }
     }
 
+    pub fn len(&self) -> usize {
+        self.get_zl_len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // bytes reserved for the buffer, which can be more than the encoded size
+    pub fn allocated_bytes(&self) -> usize {
+        self.data.capacity()
+    }
+
+    // the encoded list, laid out the same as in redis
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    // offsets of the entries from head to tail
+    pub fn iter(&self) -> ZipListIter<'_> {
+        ZipListIter::new(&self.data, ZL_HEADERS_SIZE)
+    }
+
+    // offsets of the entries from tail to head
+    pub fn iter_rev(&self) -> ZipListIterRev<'_> {
+        ZipListIterRev::new(&self.data, self.get_zl_tail() as usize)
+    }
+
     pub fn push(&mut self, entry: ZipEntry) {
         let prevlen = self.get_tail_prevlen();
         let prevlen_len = { if prevlen < 254 { 1 } else { 5 } };
@@ -92,6 +125,8 @@ impl ZipList {
     }
 
     pub fn insert_at_offset(&mut self, offset: usize, entry: ZipEntry) {
+        let is_tail = offset == self.get_zl_tail() as usize;
+
         unsafe {
             let ptr = self.data.as_ptr().add(offset);
             let current_prevlen_size = get_prevlen_size(*ptr);
@@ -108,9 +143,68 @@ impl ZipList {
             write_ptr = write_ptr.add(entry_len);
             Self::write_prevlen(write_ptr, new_prevlen_value as u32);
 
+            // the entry that got pushed back starts right after the new entry
+            let shifted_offset = offset + current_prevlen_size + entry_len;
+
             self.increment_zl_bytes(total_insertion_len as u32);
-            self.increment_zl_tail(total_insertion_len as u32);
+            if is_tail {
+                self.set_zl_tail(shifted_offset as u32);
+            } else {
+                self.increment_zl_tail(total_insertion_len as u32);
+            }
             self.increment_zl_len(1);
+
+            // the shifted entry got a new prevlen so its size changes if the prevlen size did
+            if new_prevlen_size != current_prevlen_size {
+                self.cascade_update(shifted_offset);
+            }
+        }
+    }
+
+    // Walks forward from the entry at `offset`, whose size changed, and rewrites the prevlen of
+    // the entries after it. Growing a prevlen from 1 to 5 bytes grows that entry as well which
+    // can ripple all the way down the list. A prevlen is never shrunk from 5 to 1 bytes, the
+    // smaller value is just written in the 5 byte form, so the cascade stops there.
+    fn cascade_update(&mut self, mut offset: usize) {
+        loop {
+            let entry_len = self.get_entry_len(offset);
+            let next_offset = offset + entry_len;
+
+            if self.data[next_offset] == ZL_END {
+                return;
+            }
+
+            let next_prevlen_size = get_prevlen_size(self.data[next_offset]);
+            if get_prevlen(&self.data[next_offset..]) == entry_len {
+                return;
+            }
+
+            let required_prevlen_size = if entry_len < 254 { 1 } else { 5 };
+
+            unsafe {
+                if required_prevlen_size == next_prevlen_size {
+                    let ptr = self.data.as_mut_ptr().add(next_offset);
+                    Self::write_prevlen(ptr, entry_len as u32);
+                    return;
+                } else if required_prevlen_size < next_prevlen_size {
+                    let ptr = self.data.as_mut_ptr().add(next_offset);
+                    Self::write_prevlen_large(ptr, entry_len as u32);
+                    return;
+                }
+
+                let is_tail = next_offset == self.get_zl_tail() as usize;
+
+                self.shift_bytes(next_offset + 1, 4);
+                let ptr = self.data.as_mut_ptr().add(next_offset);
+                Self::write_prevlen_large(ptr, entry_len as u32);
+
+                self.increment_zl_bytes(4);
+                if !is_tail {
+                    self.increment_zl_tail(4);
+                }
+            }
+
+            offset = next_offset;
         }
     }
 
@@ -176,12 +270,18 @@ impl ZipList {
             if prevlen < 254 {
                 *ptr = prevlen as u8;
             } else {
-                *ptr = 0xFE;
-                std::ptr::copy_nonoverlapping(prevlen.to_le_bytes().as_ptr(), ptr.add(1), 4);
+                Self::write_prevlen_large(ptr, prevlen);
             }
         }
     }
 
+    unsafe fn write_prevlen_large(ptr: *mut u8, prevlen: u32) {
+        unsafe {
+            *ptr = 0xFE;
+            std::ptr::copy_nonoverlapping(prevlen.to_le_bytes().as_ptr(), ptr.add(1), 4);
+        }
+    }
+
     pub fn remove_at_index(&mut self, index: usize) {
         // special case remove
         if index == (self.get_zl_len() - 1) as usize {
@@ -191,93 +291,43 @@ impl ZipList {
 
         let offset = self.get_index_offset(index);
 
-        // special case penultimate remove
-
-        if index == (self.get_zl_len() - 2) as usize {
-            self.remove_penultimate(offset);
-            return;
-        }
-
         self.remove_at_offset(offset);
     }
 
-    pub fn remove_at_offset(&mut self, mut offset: usize) {
-        // skip prevlen
-        if self.data[offset] == 0xFE {
-            offset += 5;
-        } else if self.data[offset] == 0xFF {
+    pub fn remove_at_offset(&mut self, offset: usize) {
+        if self.data[offset] == ZL_END {
             panic!("no hold up");
-        } else {
-            offset += 1;
         }
 
-        let init_pos = offset;
-
-        let data_header = EncodingType::from_header(self.data[offset]);
-
-        match data_header {
-            EncodingType::Int4BitsImmediate => offset += 1,
-            EncodingType::Int8 => offset += 2,
-            EncodingType::Int16 => offset += 3,
-            EncodingType::Int24 => offset += 4,
-            EncodingType::Int32 => offset += 5,
-            EncodingType::Int64 => offset += 9,
-            EncodingType::Str6BitsLength => {
-                let str_len = self.data[offset] & 0b00_111111;
-                offset += 1;
-                offset += str_len as usize;
-            }
-            EncodingType::Str14BitsLength => {
-                let str_len_1 = self.data[offset] & 0b00_111111;
-                let str_len_2 = self.data[offset + 1];
-
-                let str_len: usize = ((str_len_1 as usize) << 8) | (str_len_2 as usize);
+        let prevlen_size = get_prevlen_size(self.data[offset]);
+        let data_offset = offset + prevlen_size;
+        let next_offset = data_offset + get_entry_data_size(&self.data[data_offset..]);
 
-                offset += 2;
-                offset += str_len;
-            }
-            EncodingType::Str32BitsLength => {
-                offset += 1;
-                let str_len_1 = self.data[offset];
-                let str_len_2 = self.data[offset + 1];
-                let str_len_3 = self.data[offset + 2];
-                let str_len_4 = self.data[offset + 3];
-                let str_len =
-                    u32::from_be_bytes([str_len_1, str_len_2, str_len_3, str_len_4]) as usize;
-                offset += 4;
-                offset += str_len;
-            }
+        if self.data[next_offset] == ZL_END {
+            self.remove_tail();
+            return;
         }
 
-        // delete the prevlen
-
-        if self.data[offset] == 0xFE {
-            offset += 5;
-        } else if self.data[offset] == 0xFF {
-            panic!("no hold up");
-        } else {
-            offset += 1;
-        }
+        // the prevlen of the removed entry is kept and becomes the prevlen of the next entry, it
+        // already holds the right value. So the data and the old prevlen of the next entry go
+        let next_prevlen_size = get_prevlen_size(self.data[next_offset]);
+        let is_tail = next_offset == self.get_zl_tail() as usize;
 
-        self.data.drain(init_pos..offset);
+        self.data
+            .drain(data_offset..next_offset + next_prevlen_size);
 
-        let bytes_deleted = offset - init_pos;
+        let bytes_deleted = next_offset + next_prevlen_size - data_offset;
         self.decrement_zl_bytes(bytes_deleted as u32);
-        self.decrement_zl_tail(bytes_deleted as u32);
+        if is_tail {
+            self.set_zl_tail(offset as u32);
+        } else {
+            self.decrement_zl_tail(bytes_deleted as u32);
+        }
         self.decrement_zl_len(1);
-    }
-
-    pub fn remove_penultimate(&mut self, offset: usize) {
-        let long_penultimate_prevlen = self.data[offset] == 0xFE;
-        let long_tail_prevlen = self.data[self.get_zl_tail() as usize] == 0xFE;
-
-        self.remove_at_offset(offset);
 
-        // nudge tail
-        if !long_tail_prevlen && long_penultimate_prevlen {
-            self.decrement_zl_tail(4);
-        } else if long_tail_prevlen && !long_penultimate_prevlen {
-            self.increment_zl_tail(4);
+        // the next entry now starts at offset and changed size if the prevlen sizes differ
+        if prevlen_size != next_prevlen_size {
+            self.cascade_update(offset);
         }
     }
 
@@ -403,15 +453,7 @@ impl ZipList {
 
     pub fn pop_head(&mut self) -> RedisObject {
         let object = self.get_at_offset(ZL_HEADERS_SIZE);
-
-        let len = self.get_zl_len();
-        if len == 2 {
-            self.remove_penultimate(ZL_HEADERS_SIZE);
-        } else if len == 1 {
-            self.remove_tail();
-        } else {
-            self.remove_at_offset(ZL_HEADERS_SIZE);
-        }
+        self.remove_at_offset(ZL_HEADERS_SIZE);
 
         object
     }
@@ -432,6 +474,13 @@ impl ZipList {
         return current_index;
     }
 
+    // size of the entry at `offset` including its prevlen
+    #[inline(always)]
+    fn get_entry_len(&self, offset: usize) -> usize {
+        let prevlen_size = get_prevlen_size(self.data[offset]);
+        prevlen_size + get_entry_data_size(&self.data[offset + prevlen_size..])
+    }
+
     unsafe fn extend_bytes(&mut self, n: usize) {
         self.data.reserve(n);
         unsafe {
@@ -596,6 +645,25 @@ fn get_prevlen_size(size_byte: u8) -> usize {
     if size_byte < 254 { 1 } else { 5 }
 }
 
+// size of the encoding header + payload of the entry data at the start of `entry`
+fn get_entry_data_size(entry: &[u8]) -> usize {
+    match EncodingType::from_header(entry[0]) {
+        EncodingType::Int4BitsImmediate => 1,
+        EncodingType::Int8 => 2,
+        EncodingType::Int16 => 3,
+        EncodingType::Int24 => 4,
+        EncodingType::Int32 => 5,
+        EncodingType::Int64 => 9,
+        EncodingType::Str6BitsLength => 1 + (entry[0] & 0b00_111111) as usize,
+        EncodingType::Str14BitsLength => {
+            2 + u16::from_be_bytes([entry[0] & 0b00_111111, entry[1]]) as usize
+        }
+        EncodingType::Str32BitsLength => {
+            5 + u32::from_be_bytes([entry[1], entry[2], entry[3], entry[4]]) as usize
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -610,15 +678,15 @@ mod tests {
         let tests = vec![
             TestData {
                 obj: b"5",
-                expected: ZipEntry::Int4BitsImmediate(0b1111_0110),
+                expected: ZipEntry::Int4BitsImmediate(5),
             },
             TestData {
                 obj: b"12",
-                expected: ZipEntry::Int4BitsImmediate(0b1111_1101),
+                expected: ZipEntry::Int4BitsImmediate(12),
             },
             TestData {
                 obj: b"0",
-                expected: ZipEntry::Int4BitsImmediate(0b1111_0001),
+                expected: ZipEntry::Int4BitsImmediate(0),
             },
             TestData {
                 obj: b"100",
@@ -1468,4 +1536,165 @@ mod tests {
             }
         }
     }
+
+    // entries of 253 bytes, one byte short of needing a 5 byte prevlen in the next entry
+    const CHAIN_STR: [u8; 250] = [b'x'; 250];
+    const BIG_STR: [u8; 300] = [b'y'; 300];
+
+    fn assert_valid(zl: &ZipList, expected: &[&[u8]]) {
+        ZipList::from_bytes_validated(&zl.data, ValidationMode::Deep).unwrap();
+        assert_eq!(expected.len(), zl.get_zl_len() as usize);
+        for (index, value) in expected.iter().enumerate() {
+            assert_eq!(RedisObject::new_from_bytes(value), zl.get(index));
+        }
+    }
+
+    #[test]
+    fn test_zip_list_cascade_update() {
+        // inserting a big entry at the head makes every chain entry grow by 4 bytes
+        let mut zl = ZipList::new();
+        for _ in 0..5 {
+            zl.push(ZipEntry::Str14BitsLength(&CHAIN_STR));
+        }
+        assert_eq!(ZL_HEADERS_SIZE + 5 * 253 + ZL_END_SIZE, zl.data.len());
+
+        zl.insert(0, ZipEntry::Str14BitsLength(&BIG_STR));
+        assert_eq!(ZL_HEADERS_SIZE + 303 + 5 * 257 + ZL_END_SIZE, zl.data.len());
+        assert_valid(
+            &zl,
+            &[
+                &BIG_STR, &CHAIN_STR, &CHAIN_STR, &CHAIN_STR, &CHAIN_STR, &CHAIN_STR,
+            ],
+        );
+
+        // removing a small entry that sits between a big entry and a chain grows the chain
+        let mut zl = ZipList::new();
+        zl.push(ZipEntry::Str14BitsLength(&BIG_STR));
+        zl.push(ZipEntry::Int8(100));
+        for _ in 0..3 {
+            zl.push(ZipEntry::Str14BitsLength(&CHAIN_STR));
+        }
+        assert_eq!(
+            ZL_HEADERS_SIZE + 303 + 7 + 3 * 253 + ZL_END_SIZE,
+            zl.data.len()
+        );
+
+        zl.remove_at_index(1);
+        assert_eq!(ZL_HEADERS_SIZE + 303 + 3 * 257 + ZL_END_SIZE, zl.data.len());
+        assert_valid(&zl, &[&BIG_STR, &CHAIN_STR, &CHAIN_STR, &CHAIN_STR]);
+
+        // a cascade that reaches the tail has to move the tail offset along
+        let mut zl = ZipList::new();
+        zl.push(ZipEntry::Str14BitsLength(&CHAIN_STR));
+        zl.push(ZipEntry::Str14BitsLength(&CHAIN_STR));
+        zl.insert(1, ZipEntry::Str14BitsLength(&BIG_STR));
+        assert_valid(&zl, &[&CHAIN_STR, &BIG_STR, &CHAIN_STR]);
+    }
+
+    #[test]
+    fn test_zip_list_cascade_update_no_shrink() {
+        let mut zl = ZipList::new();
+        zl.push(ZipEntry::Str14BitsLength(&BIG_STR));
+        zl.push(ZipEntry::Str14BitsLength(&CHAIN_STR));
+        zl.push(ZipEntry::Int8(100));
+
+        // the chain entry becomes the head and shrinks from 257 to 253 bytes, the prevlen of
+        // the entry after it keeps its 5 bytes and just holds the smaller value
+        zl.pop_head();
+
+        let tail = zl.get_zl_tail() as usize;
+        assert_eq!(ZL_HEADERS_SIZE + 253, tail);
+        assert_eq!([0xFE, 253, 0, 0, 0], zl.data[tail..tail + 5]);
+        assert_valid(&zl, &[&CHAIN_STR, b"100"]);
+
+        // same when the shrinking entry got smaller because of an insert before it
+        let mut zl = ZipList::new();
+        zl.push(ZipEntry::Str14BitsLength(&BIG_STR));
+        zl.push(ZipEntry::Str14BitsLength(&CHAIN_STR));
+        zl.push(ZipEntry::Int8(100));
+        zl.insert(1, ZipEntry::Int8(5));
+
+        let tail = zl.get_zl_tail() as usize;
+        assert_eq!([0xFE, 253, 0, 0, 0], zl.data[tail..tail + 5]);
+        assert_valid(&zl, &[&BIG_STR, b"5", &CHAIN_STR, b"100"]);
+    }
+
+    // xorshift so the random tests are reproducible from their seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn value(&mut self) -> Vec<u8> {
+            match self.below(8) {
+                0 => self.below(13).to_string().into_bytes(),
+                1 => (self.below(256) as i64 - 128).to_string().into_bytes(),
+                2 => (self.next() as i16).to_string().into_bytes(),
+                3 => ((self.next() as i32) >> 8).to_string().into_bytes(),
+                4 => ((self.next() as i64) >> 1).to_string().into_bytes(),
+                5 => vec![b'a' + self.below(26) as u8; self.below(64)],
+                // sizes around the 254 byte prevlen boundary are what drive the cascades
+                6 => vec![b's'; 240 + self.below(30)],
+                _ => vec![b'l'; 16_000 + self.below(1_000)],
+            }
+        }
+    }
+
+    #[test]
+    fn test_zip_list_random_operations() {
+        for seed in [1, 42, 1337, 0xDEAD_BEEF, 0x1234_5678_9ABC] {
+            let mut rng = Rng(seed);
+            let mut zl = ZipList::new();
+            let mut model: Vec<Vec<u8>> = Vec::new();
+
+            for step in 0..2_000 {
+                // lean towards growing so the list gets long enough for cascades to matter
+                match rng.below(10) {
+                    0..=2 => {
+                        let value = rng.value();
+                        zl.push(ZipEntry::from_bytes(&value));
+                        model.push(value);
+                    }
+                    3..=5 => {
+                        let value = rng.value();
+                        let index = rng.below(model.len() + 1);
+                        zl.insert(index, ZipEntry::from_bytes(&value));
+                        model.insert(index, value);
+                    }
+                    6 | 7 if !model.is_empty() => {
+                        let index = rng.below(model.len());
+                        zl.remove_at_index(index);
+                        model.remove(index);
+                    }
+                    8 if !model.is_empty() => {
+                        let value = model.remove(0);
+                        assert_eq!(RedisObject::new_from_bytes(&value), zl.pop_head());
+                    }
+                    9 if !model.is_empty() => {
+                        let value = model.pop().unwrap();
+                        assert_eq!(RedisObject::new_from_bytes(&value), zl.pop_tail());
+                    }
+                    _ => {}
+                }
+
+                if let Err(err) = ZipList::from_bytes_validated(&zl.data, ValidationMode::Deep) {
+                    panic!("seed {seed} step {step}: {err:?}");
+                }
+                assert_eq!(model.len(), zl.get_zl_len() as usize);
+            }
+
+            for (index, value) in model.iter().enumerate() {
+                assert_eq!(RedisObject::new_from_bytes(value), zl.get(index));
+            }
+        }
+    }
 }
@@ -0,0 +1,332 @@
+use crate::{
+    error::ZipListError,
+    redis::ziplist::{EncodingType, ZL_END, ZL_END_SIZE, ZL_HEADERS_SIZE, ZipList},
+};
+
+// how much of an untrusted ziplist is checked before it is used, same idea as the
+// sanitize-dump-payload option in redis
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ValidationMode {
+    // only the header fields and the end byte are checked, the entries are trusted
+    #[default]
+    Shallow,
+    // every entry is walked and its prevlen, encoding and length are checked as well
+    Deep,
+}
+
+impl ZipList {
+    pub fn from_bytes_validated(
+        bytes: &[u8],
+        mode: ValidationMode,
+    ) -> Result<ZipList, ZipListError> {
+        validate_header(bytes)?;
+
+        if mode == ValidationMode::Deep {
+            validate_entries(bytes)?;
+        }
+
+        Ok(ZipList {
+            data: bytes.to_vec(),
+        })
+    }
+}
+
+fn validate_header(bytes: &[u8]) -> Result<(), ZipListError> {
+    if bytes.len() < ZL_HEADERS_SIZE + ZL_END_SIZE {
+        return Err(ZipListError::TooShort { len: bytes.len() });
+    }
+
+    let zl_bytes = read_u32_le(bytes, 0);
+    if zl_bytes as usize != bytes.len() {
+        return Err(ZipListError::BytesMismatch {
+            zl_bytes,
+            len: bytes.len(),
+        });
+    }
+
+    // an empty list has its tail pointing at the end byte
+    let zl_tail = read_u32_le(bytes, 4);
+    if (zl_tail as usize) < ZL_HEADERS_SIZE || zl_tail as usize > bytes.len() - ZL_END_SIZE {
+        return Err(ZipListError::TailOutOfBounds { zl_tail });
+    }
+
+    if bytes[bytes.len() - 1] != ZL_END {
+        return Err(ZipListError::MissingEnd {
+            offset: bytes.len() - 1,
+        });
+    }
+
+    Ok(())
+}
+
+fn validate_entries(bytes: &[u8]) -> Result<(), ZipListError> {
+    let end = bytes.len() - ZL_END_SIZE;
+
+    let mut offset = ZL_HEADERS_SIZE;
+    let mut prev_entry_len = 0;
+    let mut tail_offset = ZL_HEADERS_SIZE;
+    let mut amount_entries = 0;
+
+    while offset < end {
+        let entry_offset = offset;
+
+        let (prevlen, prevlen_size) = match bytes[offset] {
+            ZL_END => return Err(ZipListError::InvalidPrevlen { offset }),
+            0xFE => {
+                if offset + 5 > end {
+                    return Err(ZipListError::EntryOutOfBounds {
+                        offset: entry_offset,
+                    });
+                }
+                (read_u32_le(bytes, offset + 1) as usize, 5)
+            }
+            byte => (byte as usize, 1),
+        };
+
+        if prevlen != prev_entry_len {
+            return Err(ZipListError::PrevlenMismatch {
+                offset,
+                prevlen,
+                expected: prev_entry_len,
+            });
+        }
+
+        offset += prevlen_size;
+        if offset >= end {
+            return Err(ZipListError::EntryOutOfBounds {
+                offset: entry_offset,
+            });
+        }
+
+        let header = bytes[offset];
+        let encoding_type = match EncodingType::try_from_header(header) {
+            Some(encoding_type) => encoding_type,
+            None => {
+                return Err(ZipListError::InvalidEncoding { offset, header });
+            }
+        };
+
+        // the length bytes of the string encodings have to be in bounds before they are read
+        let length_bytes = match encoding_type {
+            EncodingType::Str14BitsLength => 2,
+            EncodingType::Str32BitsLength => 5,
+            _ => 1,
+        };
+        if offset + length_bytes > end {
+            return Err(ZipListError::EntryOutOfBounds {
+                offset: entry_offset,
+            });
+        }
+
+        let data_len = match encoding_type {
+            EncodingType::Int4BitsImmediate => 1,
+            EncodingType::Int8 => 2,
+            EncodingType::Int16 => 3,
+            EncodingType::Int24 => 4,
+            EncodingType::Int32 => 5,
+            EncodingType::Int64 => 9,
+            EncodingType::Str6BitsLength => 1 + (header & 0b00_111111) as usize,
+            EncodingType::Str14BitsLength => {
+                2 + u16::from_be_bytes([header & 0b00_111111, bytes[offset + 1]]) as usize
+            }
+            EncodingType::Str32BitsLength => {
+                let str_len = [
+                    bytes[offset + 1],
+                    bytes[offset + 2],
+                    bytes[offset + 3],
+                    bytes[offset + 4],
+                ];
+                5 + u32::from_be_bytes(str_len) as usize
+            }
+        };
+
+        offset = match offset.checked_add(data_len) {
+            Some(next_offset) if next_offset <= end => next_offset,
+            _ => {
+                return Err(ZipListError::EntryOutOfBounds {
+                    offset: entry_offset,
+                });
+            }
+        };
+
+        prev_entry_len = offset - entry_offset;
+        tail_offset = entry_offset;
+        amount_entries += 1;
+    }
+
+    let zl_tail = read_u32_le(bytes, 4);
+    if zl_tail as usize != tail_offset {
+        return Err(ZipListError::TailMismatch {
+            zl_tail,
+            tail_offset,
+        });
+    }
+
+    // a saturated length means the entries have to be counted, so any amount is fine
+    let zl_len = u16::from_le_bytes([bytes[8], bytes[9]]);
+    if zl_len != u16::MAX && zl_len as usize != amount_entries {
+        return Err(ZipListError::LenMismatch {
+            zl_len,
+            amount_entries,
+        });
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::{redis_object::RedisObject, ziplist::ZipEntry};
+
+    // hello @ 10, 1000 @ 17, 300 * 'a' @ 21, 5 @ 324 with a 5 byte prevlen, end byte @ 331
+    fn valid_bytes() -> Vec<u8> {
+        let mut zl = ZipList::new();
+        zl.push(ZipEntry::Str6BitsLength(b"hello"));
+        zl.push(ZipEntry::Int16(1000));
+        zl.push(ZipEntry::Str14BitsLength(&[b'a'; 300]));
+        zl.push(ZipEntry::Int8(5));
+        zl.data
+    }
+
+    #[test]
+    fn test_from_bytes_validated() {
+        struct TestData {
+            bytes: Vec<u8>,
+            mode: ValidationMode,
+            expected: Result<(), ZipListError>,
+        }
+
+        let corrupted = |f: fn(&mut Vec<u8>)| {
+            let mut bytes = valid_bytes();
+            f(&mut bytes);
+            bytes
+        };
+
+        let tests = vec![
+            TestData {
+                bytes: ZipList::new().data,
+                mode: ValidationMode::Deep,
+                expected: Ok(()),
+            },
+            TestData {
+                bytes: valid_bytes(),
+                mode: ValidationMode::Deep,
+                expected: Ok(()),
+            },
+            // header checks
+            TestData {
+                bytes: vec![11, 0, 0],
+                mode: ValidationMode::Shallow,
+                expected: Err(ZipListError::TooShort { len: 3 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[0] = 0),
+                mode: ValidationMode::Shallow,
+                expected: Err(ZipListError::BytesMismatch {
+                    zl_bytes: 0x100,
+                    len: 332,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[4..8].copy_from_slice(&400u32.to_le_bytes())),
+                mode: ValidationMode::Shallow,
+                expected: Err(ZipListError::TailOutOfBounds { zl_tail: 400 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[331] = 0),
+                mode: ValidationMode::Shallow,
+                expected: Err(ZipListError::MissingEnd { offset: 331 }),
+            },
+            // entry checks are only done in deep mode
+            TestData {
+                bytes: corrupted(|b| b[11] = 0x81),
+                mode: ValidationMode::Shallow,
+                expected: Ok(()),
+            },
+            TestData {
+                bytes: corrupted(|b| b[11] = 0x81),
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::InvalidEncoding {
+                    offset: 11,
+                    header: 0x81,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[17] = 8),
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::PrevlenMismatch {
+                    offset: 17,
+                    prevlen: 8,
+                    expected: 7,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[324] = ZL_END),
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::InvalidPrevlen { offset: 324 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[22..24].copy_from_slice(&[0b01_111111, 0xFF])),
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::EntryOutOfBounds { offset: 21 }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[4..8].copy_from_slice(&21u32.to_le_bytes())),
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::TailMismatch {
+                    zl_tail: 21,
+                    tail_offset: 324,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[8] = 3),
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::LenMismatch {
+                    zl_len: 3,
+                    amount_entries: 4,
+                }),
+            },
+            TestData {
+                bytes: corrupted(|b| b[8..10].copy_from_slice(&u16::MAX.to_le_bytes())),
+                mode: ValidationMode::Deep,
+                expected: Ok(()),
+            },
+            // a string that claims to run past the end byte
+            TestData {
+                #[rustfmt::skip]
+                bytes: vec![
+                    /*zl bytes*/ 14, 0, 0, 0, /*zl tail*/ 10, 0, 0, 0, /*zl len*/ 1, 0,
+                    /*prevlen*/ 0, /*tag*/ 0b00_000101, /*data*/ b'h',
+                    /*zl end*/ 0xFF,
+                ],
+                mode: ValidationMode::Deep,
+                expected: Err(ZipListError::EntryOutOfBounds { offset: 10 }),
+            },
+        ];
+
+        for test in tests {
+            let result = ZipList::from_bytes_validated(&test.bytes, test.mode).map(|_| ());
+            assert_eq!(test.expected, result);
+        }
+    }
+
+    #[test]
+    fn test_validated_zip_list_is_usable() {
+        let mut zl = ZipList::from_bytes_validated(&valid_bytes(), ValidationMode::Deep).unwrap();
+
+        assert_eq!(RedisObject::Int(5), zl.pop_tail());
+        assert_eq!(RedisObject::String(Box::from(&b"hello"[..])), zl.pop_head());
+        assert_eq!(RedisObject::Int(1000), zl.get(0));
+    }
+}
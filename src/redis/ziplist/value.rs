@@ -0,0 +1,203 @@
+use std::io::Write;
+
+use crate::redis::{
+    redis_object::RedisObject,
+    ziplist::{EncodingType, ZL_END, ZipList, get_prevlen_size, iterator::ZipListIter},
+};
+
+// a borrowed view of a ziplist entry, strings point straight into the ziplist bytes so reading
+// one does not allocate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZipListValue<'a> {
+    Int(i64),
+    Str(&'a [u8]),
+}
+
+impl<'a> ZipListValue<'a> {
+    pub fn to_object(&self) -> RedisObject {
+        match self {
+            ZipListValue::Int(i) => RedisObject::Int(*i),
+            ZipListValue::Str(s) => RedisObject::String(Box::from(*s)),
+        }
+    }
+
+    // append the value as a resp bulk string
+    pub fn write_resp(&self, buf: &mut Vec<u8>) {
+        match self {
+            ZipListValue::Int(i) => {
+                // ints are at most 20 characters so format them on the stack to get the length
+                let mut digits = [0u8; 20];
+                let mut cursor = &mut digits[..];
+                write!(cursor, "{}", i).unwrap();
+                let len = 20 - cursor.len();
+
+                write!(buf, "${}\r\n", len).unwrap();
+                buf.extend_from_slice(&digits[..len]);
+            }
+            ZipListValue::Str(s) => {
+                write!(buf, "${}\r\n", s.len()).unwrap();
+                buf.extend_from_slice(s);
+            }
+        }
+        buf.extend_from_slice(b"\r\n");
+    }
+}
+
+impl ZipList {
+    pub fn get_value(&self, index: usize) -> ZipListValue<'_> {
+        let offset = self.get_index_offset(index);
+        self.get_value_at_offset(offset)
+    }
+
+    pub fn get_value_at_offset(&self, offset: usize) -> ZipListValue<'_> {
+        if self.data[offset] == ZL_END {
+            panic!("invalid encoding");
+        }
+
+        let offset = offset + get_prevlen_size(self.data[offset]);
+        let data = &self.data[offset..];
+
+        match EncodingType::from_header(data[0]) {
+            EncodingType::Int4BitsImmediate => {
+                ZipListValue::Int(((data[0] & 0b0000_1111) - 1) as i64)
+            }
+            EncodingType::Int8 => ZipListValue::Int(data[1] as i8 as i64),
+            EncodingType::Int16 => ZipListValue::Int(i16::from_le_bytes([data[1], data[2]]) as i64),
+            EncodingType::Int24 => {
+                ZipListValue::Int(Self::i24_from_le_bytes([data[1], data[2], data[3]]) as i64)
+            }
+            EncodingType::Int32 => {
+                ZipListValue::Int(i32::from_le_bytes([data[1], data[2], data[3], data[4]]) as i64)
+            }
+            EncodingType::Int64 => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&data[1..9]);
+                ZipListValue::Int(i64::from_le_bytes(bytes))
+            }
+            EncodingType::Str6BitsLength => {
+                let str_len = (data[0] & 0b00_111111) as usize;
+                ZipListValue::Str(&data[1..1 + str_len])
+            }
+            EncodingType::Str14BitsLength => {
+                let str_len = u16::from_be_bytes([data[0] & 0b00_111111, data[1]]) as usize;
+                ZipListValue::Str(&data[2..2 + str_len])
+            }
+            EncodingType::Str32BitsLength => {
+                let str_len = u32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+                ZipListValue::Str(&data[5..5 + str_len])
+            }
+        }
+    }
+
+    pub fn values(&self) -> ZipListValueIter<'_> {
+        self.values_from(0)
+    }
+
+    // values starting at `index`, an index past the end gives an empty iterator
+    pub fn values_from(&self, index: usize) -> ZipListValueIter<'_> {
+        let offset = if index >= self.len() {
+            self.data.len() - 1
+        } else {
+            self.get_index_offset(index)
+        };
+
+        ZipListValueIter {
+            zl: self,
+            offsets: ZipListIter::new(&self.data, offset),
+        }
+    }
+}
+
+pub struct ZipListValueIter<'a> {
+    zl: &'a ZipList,
+    offsets: ZipListIter<'a>,
+}
+
+impl<'a> Iterator for ZipListValueIter<'a> {
+    type Item = ZipListValue<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offsets.next()?;
+        Some(self.zl.get_value_at_offset(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::ziplist::ZipEntry;
+
+    #[test]
+    fn test_zip_list_values() {
+        let s14 = [b'a'; 100];
+        let s32 = [b'b'; 20_000];
+
+        let mut zl = ZipList::new();
+        zl.push(ZipEntry::Int4BitsImmediate(5));
+        zl.push(ZipEntry::Int8(-100));
+        zl.push(ZipEntry::Int16(1000));
+        zl.push(ZipEntry::Int24(-100_000));
+        zl.push(ZipEntry::Int32(2_147_483_647));
+        zl.push(ZipEntry::Int64(-5_000_000_000));
+        zl.push(ZipEntry::Str6BitsLength(b"hello"));
+        zl.push(ZipEntry::Str14BitsLength(&s14));
+        zl.push(ZipEntry::Str32BitsLength(&s32));
+
+        let expected = vec![
+            ZipListValue::Int(5),
+            ZipListValue::Int(-100),
+            ZipListValue::Int(1000),
+            ZipListValue::Int(-100_000),
+            ZipListValue::Int(2_147_483_647),
+            ZipListValue::Int(-5_000_000_000),
+            ZipListValue::Str(b"hello"),
+            ZipListValue::Str(&s14),
+            ZipListValue::Str(&s32),
+        ];
+
+        assert_eq!(expected, zl.values().collect::<Vec<_>>());
+        assert_eq!(expected[7..], zl.values_from(7).collect::<Vec<_>>());
+        assert_eq!(0, zl.values_from(9).count());
+        assert_eq!(0, ZipList::new().values().count());
+
+        // the views have to agree with the allocating path
+        for (index, value) in expected.iter().enumerate() {
+            assert_eq!(zl.get(index), value.to_object());
+            assert_eq!(*value, zl.get_value(index));
+        }
+    }
+
+    #[test]
+    fn test_zip_list_value_write_resp() {
+        struct TestData {
+            value: ZipListValue<'static>,
+            expected: &'static [u8],
+        }
+
+        let tests = vec![
+            TestData {
+                value: ZipListValue::Int(5),
+                expected: b"$1\r\n5\r\n",
+            },
+            TestData {
+                value: ZipListValue::Int(i64::MIN),
+                expected: b"$20\r\n-9223372036854775808\r\n",
+            },
+            TestData {
+                value: ZipListValue::Str(b"hello"),
+                expected: b"$5\r\nhello\r\n",
+            },
+            TestData {
+                value: ZipListValue::Str(b""),
+                expected: b"$0\r\n\r\n",
+            },
+        ];
+
+        for test in tests {
+            let mut buf = Vec::new();
+            test.value.write_resp(&mut buf);
+            assert_eq!(test.expected, buf.as_slice());
+            assert_eq!(test.value.to_object().to_resp(), buf);
+        }
+    }
+}
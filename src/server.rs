@@ -1,30 +1,59 @@
-use std::io;
+use std::{
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::fd::IntoRawFd,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    connection::{Connection, ReadBuffer, WriteBuffer},
-    error::{ProtocolError, RedisError, handle_command_error, handle_protocol_error},
-    net::{Epoll, Socket, make_ipv4_address},
+    commands::{RedisCommand, ReplConfOption},
+    config::Config,
+    connection::{BlockedWait, Connection, ConnectionKind, ReadBuffer, WriteBuffer},
+    error::{CommandError, ProtocolError, RedisError, handle_command_error, handle_protocol_error},
+    net::{Epoll, Socket, make_ipv4_address, read_line, read_more},
     protocol::parser::{
         CommandParseState, ParseState, convert_command_parse_state_to_redis_command, parse_command,
-        parse_partial_command,
+        parse_partial_command, write_command,
+    },
+    redis::{
+        Redis, RedisResult,
+        cluster::{BUS_PORT_OFFSET, message_len},
     },
-    redis::{Redis, RedisResult},
 };
-use libc::{EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT, c_int, epoll_event};
+use libc::{EINPROGRESS, EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT, c_int, epoll_event};
 
 const MAX_CONNECTIONS: usize = 1000;
+// how often the periodic tasks run, same as the default hz of 10 in redis
+const PERIODIC_TASKS_INTERVAL: Duration = Duration::from_millis(100);
+// how often a replica tells its master how far it processed the stream
+const MASTER_ACK_INTERVAL: Duration = Duration::from_secs(1);
+const MASTER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Server {
     redis: Redis,
     epoll: Epoll,
     listener: Socket,
+    // the cluster bus, only there in cluster mode
+    bus_listener: Option<Socket>,
     connections: Vec<Option<Connection>>,
     events: Vec<epoll_event>,
+    last_periodic_tasks: Instant,
+    // sent to the master when this server is a replica
+    port: u16,
+    last_master_ack: Instant,
 }
 
 impl Server {
     pub fn new(ip: u32, port: u16) -> Result<Self, RedisError> {
-        let redis = Redis::new();
+        Self::with_config(ip, port, Config::new())
+    }
+
+    pub fn with_config(ip: u32, port: u16, mut config: Config) -> Result<Self, RedisError> {
+        // a cluster node tells the other nodes the port it listens on
+        config.port = port;
+        let cluster_enabled = config.cluster_enabled;
+        let mut redis = Redis::with_config(config);
+        redis.load_data()?;
 
         let mut connections: Vec<Option<Connection>> = Vec::with_capacity(MAX_CONNECTIONS);
         connections.resize_with(MAX_CONNECTIONS, || None);
@@ -33,29 +62,45 @@ impl Server {
         events.resize_with(MAX_CONNECTIONS, || epoll_event { events: 0, u64: 0 });
 
         // create listening socket
-        let listen_socket = Socket::new_tcp();
-        listen_socket.set_reuseaddr()?;
-        listen_socket.set_non_blocking()?;
-
-        let address = make_ipv4_address(ip, port);
-
-        listen_socket.bind(&address)?;
-
-        listen_socket.listen()?;
+        let listen_socket = Self::listen(ip, port)?;
 
         // create epoll and add listening socket
         let epoll = Epoll::new();
         epoll.add(listen_socket.fd, (EPOLLIN | EPOLLERR | EPOLLHUP) as u32)?;
 
+        let bus_listener = if cluster_enabled {
+            let bus_port = port.checked_add(BUS_PORT_OFFSET).ok_or_else(|| {
+                RedisError::Other(format!("port {} has no cluster bus port", port))
+            })?;
+            let bus_listener = Self::listen(ip, bus_port)?;
+            epoll.add(bus_listener.fd, (EPOLLIN | EPOLLERR | EPOLLHUP) as u32)?;
+            Some(bus_listener)
+        } else {
+            None
+        };
+
         Ok(Server {
             redis: redis,
             epoll: epoll,
             listener: listen_socket,
+            bus_listener,
             connections: connections,
             events: events,
+            last_periodic_tasks: Instant::now(),
+            port,
+            last_master_ack: Instant::now(),
         })
     }
 
+    fn listen(ip: u32, port: u16) -> Result<Socket, RedisError> {
+        let socket = Socket::new_tcp();
+        socket.set_reuseaddr()?;
+        socket.set_non_blocking()?;
+        socket.bind(&make_ipv4_address(ip, port))?;
+        socket.listen()?;
+        Ok(socket)
+    }
+
     pub fn run(&mut self) -> Result<(), RedisError> {
         loop {
             let amount_events = self.get_events()?;
@@ -63,6 +108,23 @@ impl Server {
             for i in 0..amount_events {
                 self.handle_event(i)?
             }
+
+            self.unblock_waiting_connections();
+
+            // like the beforeSleep of redis, the writes of this round go to the aof and the
+            // replicas before the next wait
+            self.redis.flush_append_only_file();
+            self.feed_replicas();
+            self.feed_cluster_links();
+            self.close_dropped_connections();
+
+            if self.last_periodic_tasks.elapsed() >= PERIODIC_TASKS_INTERVAL {
+                self.redis.set_clients_memory(self.clients_memory());
+                self.redis.run_periodic_tasks();
+                self.replication_cron();
+                self.cluster_cron();
+                self.last_periodic_tasks = Instant::now();
+            }
         }
     }
 
@@ -74,35 +136,77 @@ impl Server {
         let flags = event.events;
 
         if (flags & (EPOLLHUP | EPOLLERR) as u32) != 0 {
-            self.connections[fd as usize] = None;
+            self.close_connection(fd);
             return Ok(());
         }
 
         // listen socket
         if fd == self.listener.fd && (flags & EPOLLIN as u32) != 0 {
-            self.accept_new_connections()?;
+            self.accept_new_connections(fd, ConnectionKind::Client)?;
+        }
+        if let Some(bus_listener) = &self.bus_listener
+            && fd == bus_listener.fd
+            && (flags & EPOLLIN as u32) != 0
+        {
+            self.accept_new_connections(fd, ConnectionKind::ClusterBus)?;
         }
 
-        let mut connection = match &mut self.connections[fd as usize] {
+        let connection = match &mut self.connections[fd as usize] {
             Some(connection) => connection,
             None => return Ok(()), // TODO - this should probably return some sort of error since
                                    // there is not a connection to a socket that is till there
         };
 
+        let mut result = Ok(());
         if Self::is_readable(flags) {
-            Self::handle_readable_connection(&mut self.redis, &mut self.epoll, connection)?;
+            result = Self::handle_readable_connection(&mut self.redis, &self.epoll, connection);
+        }
+
+        if result.is_ok() && Self::is_writeable(flags) {
+            result = Self::flush_write_buffer_on_write(&self.epoll, connection);
         }
 
-        if Self::is_writeable(flags) {
-            Self::flush_write_buffer_on_write(&self.epoll, &mut connection)?;
+        // a connection that was closed by the other end or failed is dropped, the server keeps
+        // running
+        if let Err(err) = result {
+            if !matches!(err, RedisError::ConnectionClosed) {
+                eprintln!("Warning: closing connection {}: {:?}", fd, err);
+            }
+            self.close_connection(fd);
         }
 
         Ok(())
     }
 
-    fn accept_new_connections(&mut self) -> Result<(), RedisError> {
+    // the socket is closed when the connection is dropped
+    fn close_connection(&mut self, fd: c_int) {
+        if self.connections[fd as usize].take().is_some() {
+            self.redis.connection_closed(fd);
+            self.redis.cluster_link_closed(fd);
+        }
+    }
+
+    // the links to an old master, replicas that have to sync again and cluster links that broke
+    fn close_dropped_connections(&mut self) {
+        for fd in self.redis.take_dropped_connections() {
+            self.close_connection(fd);
+        }
+        for fd in self.redis.take_dropped_cluster_links() {
+            self.close_connection(fd);
+        }
+    }
+
+    fn accept_new_connections(
+        &mut self,
+        listener_fd: c_int,
+        kind: ConnectionKind,
+    ) -> Result<(), RedisError> {
+        let listener = match &self.bus_listener {
+            Some(bus_listener) if bus_listener.fd == listener_fd => bus_listener,
+            _ => &self.listener,
+        };
         loop {
-            match self.listener.accept() {
+            match listener.accept() {
                 Ok((client_socket, _address)) => {
                     let client_fd = client_socket.fd;
                     client_socket.set_non_blocking()?;
@@ -110,7 +214,8 @@ impl Server {
                     self.epoll
                         .add(client_fd, (EPOLLIN | EPOLLERR | EPOLLHUP) as u32)?;
 
-                    let connection = Connection::new(client_socket);
+                    let mut connection = Connection::new(client_socket);
+                    connection.kind = kind;
                     self.connections[client_fd as usize] = Some(connection);
                 }
 
@@ -123,8 +228,20 @@ impl Server {
         Ok(())
     }
 
+    fn clients_memory(&self) -> usize {
+        self.connections
+            .iter()
+            .flatten()
+            .map(|connection| connection.memory_usage())
+            .sum()
+    }
+
     fn get_events(&mut self) -> Result<usize, RedisError> {
-        let amount_events = self.epoll.wait(&mut self.events, -1)?;
+        // wake up in time for the next run of the periodic tasks even without events
+        let timeout = PERIODIC_TASKS_INTERVAL.saturating_sub(self.last_periodic_tasks.elapsed());
+        let amount_events = self
+            .epoll
+            .wait(&mut self.events, timeout.as_millis() as i32)?;
         Ok(amount_events)
     }
 
@@ -134,7 +251,46 @@ impl Server {
         connection: &mut Connection,
     ) -> Result<(), RedisError> {
         connection.fill_read_buffer()?;
+        if connection.kind == ConnectionKind::ClusterBus {
+            return Self::process_cluster_messages(redis, epoll, connection);
+        }
+        Self::process_read_buffer(redis, epoll, connection)
+    }
+
+    // every whole message in the read buffer, a broken one closes the link
+    fn process_cluster_messages(
+        redis: &mut Redis,
+        epoll: &Epoll,
+        connection: &mut Connection,
+    ) -> Result<(), RedisError> {
+        let fd = connection.soc.fd;
+        let peer_ip = connection.soc.peer_ip()?.to_string();
+        let local_ip = connection.soc.local_ip()?.to_string();
+
+        let read_buffer = &mut connection.read_buffer;
+        while let Some(len) = message_len(&read_buffer.buf[read_buffer.pos..])? {
+            let message = &read_buffer.buf[read_buffer.pos..read_buffer.pos + len];
+            redis.process_cluster_message(fd, &peer_ip, &local_ip, message)?;
+            read_buffer.pos += len;
+        }
+        read_buffer.buf.drain(..read_buffer.pos);
+        read_buffer.pos = 0;
+
+        // the pongs go out with the messages of the cron in feed_cluster_links
+        Self::flush_write_buffer_after_read(epoll, connection)
+    }
+
+    fn process_read_buffer(
+        redis: &mut Redis,
+        epoll: &Epoll,
+        connection: &mut Connection,
+    ) -> Result<(), RedisError> {
         loop {
+            // the commands of a blocked connection wait in the buffer
+            if connection.blocked.is_some() {
+                break;
+            }
+
             let return_value = match connection.command_parse_state.state {
                 ParseState::Empty => parse_command(
                     &connection.read_buffer.buf,
@@ -150,23 +306,19 @@ impl Server {
             };
 
             match return_value {
+                Ok(_) if connection.kind == ConnectionKind::Master => {
+                    Self::run_master_command(redis, connection);
+                }
                 Ok(_) => {
-                    let command = match convert_command_parse_state_to_redis_command(
-                        &connection.command_parse_state,
-                    ) {
-                        Ok(command) => command,
-                        Err(e) => {
-                            Self::handle_parse_failure(
-                                &mut connection.write_buffer,
-                                &mut connection.read_buffer,
-                                &mut connection.command_parse_state,
-                                |wb| handle_command_error(&e, wb),
-                            );
-                            continue;
-                        }
-                    };
-                    let result = redis.execute_command(&command);
-                    Self::handle_redis_result(&result, &mut connection.write_buffer);
+                    if let Err(e) = Self::run_client_command(redis, connection) {
+                        Self::handle_parse_failure(
+                            &mut connection.write_buffer,
+                            &mut connection.read_buffer,
+                            &mut connection.command_parse_state,
+                            |wb| handle_command_error(&e, wb),
+                        );
+                        continue;
+                    }
                 }
                 Err(ProtocolError::Incomplete) => {
                     break;
@@ -196,6 +348,350 @@ impl Server {
         Ok(())
     }
 
+    // PSYNC, REPLCONF and WAIT need the connection, everything else runs in redis
+    fn run_client_command(
+        redis: &mut Redis,
+        connection: &mut Connection,
+    ) -> Result<(), CommandError> {
+        let command =
+            convert_command_parse_state_to_redis_command(&connection.command_parse_state)?;
+        let fd = connection.soc.fd;
+
+        let result = match &command {
+            RedisCommand::Psync { replid, offset } => {
+                let ip = connection.soc.peer_ip().ok().map(|ip| ip.to_string());
+                let result = redis.sync_replica(fd, ip, replid, *offset);
+                if !matches!(result, RedisResult::Error(_)) {
+                    connection.kind = ConnectionKind::Replica;
+                }
+                Some(result)
+            }
+            RedisCommand::ReplConf { options } => redis.replconf(fd, options),
+            RedisCommand::Wait { .. } if redis.is_replica() => Some(RedisResult::Error(
+                RedisError::CommandError(CommandError::WaitOnReplica),
+            )),
+            // like redis it replies right away when enough replicas are there already, otherwise
+            // it blocks until they acknowledged the last write of the connection or it times out
+            RedisCommand::Wait {
+                numreplicas,
+                timeout,
+            } => {
+                let acked = redis.replicas_acked(connection.woff);
+                if acked >= *numreplicas {
+                    Some(RedisResult::Int(acked as i64))
+                } else {
+                    connection.blocked = Some(BlockedWait {
+                        numreplicas: *numreplicas,
+                        offset: connection.woff,
+                        deadline: (*timeout > 0)
+                            .then(|| Instant::now() + Duration::from_millis(*timeout)),
+                    });
+                    redis.request_replica_acks();
+                    None
+                }
+            }
+            _ if command.is_write() && redis.is_read_only() => Some(RedisResult::Error(
+                RedisError::CommandError(CommandError::ReadOnlyReplica),
+            )),
+            RedisCommand::Asking if !redis.cluster_enabled() => Some(RedisResult::Error(
+                RedisError::CommandError(CommandError::ClusterDisabled),
+            )),
+            RedisCommand::Asking => {
+                connection.asking = true;
+                Some(RedisResult::SimpleString(b"+OK\r\n"))
+            }
+            _ if let Err(err) = redis.cluster_redirect(&command, connection.asking) => {
+                Some(RedisResult::Error(RedisError::CommandError(err)))
+            }
+            _ => {
+                let result = redis.execute_command_in_db(&mut connection.db, &command);
+                if command.is_write() {
+                    connection.woff = redis.replication_offset();
+                }
+                Some(result)
+            }
+        };
+
+        // ASKING only counts for the command after it
+        if !matches!(command, RedisCommand::Asking) {
+            connection.asking = false;
+        }
+
+        if let Some(result) = result {
+            Self::handle_redis_result(&result, &mut connection.write_buffer);
+        }
+        Ok(())
+    }
+
+    // the stream from the master runs without replies, only a GETACK is answered. Every command
+    // counts for the offset, also the ones this server doesn't know
+    fn run_master_command(redis: &mut Redis, connection: &mut Connection) {
+        let mut resp = Vec::new();
+        connection.command_parse_state.write_resp(&mut resp);
+        redis.feed_master_stream(&resp);
+
+        let Ok(command) =
+            convert_command_parse_state_to_redis_command(&connection.command_parse_state)
+        else {
+            return;
+        };
+        match &command {
+            RedisCommand::ReplConf { options } => {
+                if options.contains(&ReplConfOption::GetAck) {
+                    connection.write_buffer.append_bytes(&redis.replica_ack());
+                }
+            }
+            RedisCommand::Psync { .. } | RedisCommand::Wait { .. } => {}
+            _ => {
+                redis.execute_command_in_db(&mut connection.db, &command);
+            }
+        }
+    }
+
+    // replies to the WAITs that have enough replicas now or timed out, and runs the commands
+    // their connections sent in the meantime
+    fn unblock_waiting_connections(&mut self) {
+        let now = Instant::now();
+        let mut failed = Vec::new();
+
+        for (fd, connection) in self.connections.iter_mut().enumerate() {
+            let Some(connection) = connection else {
+                continue;
+            };
+            let Some(blocked) = &connection.blocked else {
+                continue;
+            };
+
+            let acked = self.redis.replicas_acked(blocked.offset);
+            let timed_out = blocked.deadline.is_some_and(|deadline| now >= deadline);
+            if acked < blocked.numreplicas && !timed_out {
+                continue;
+            }
+
+            connection.blocked = None;
+            Self::handle_redis_result(
+                &RedisResult::Int(acked as i64),
+                &mut connection.write_buffer,
+            );
+            if Self::process_read_buffer(&mut self.redis, &self.epoll, connection).is_err() {
+                failed.push(fd as c_int);
+            }
+        }
+
+        for fd in failed {
+            self.close_connection(fd);
+        }
+    }
+
+    // hands the replicas the part of the stream they don't have yet
+    fn feed_replicas(&mut self) {
+        self.redis.send_replica_ack_requests();
+
+        for fd in self.redis.online_replicas() {
+            let Some(connection) = &mut self.connections[fd as usize] else {
+                continue;
+            };
+
+            let result = if self
+                .redis
+                .replication_output(fd, &mut connection.write_buffer.buf)
+            {
+                Self::flush_write_buffer_after_read(&self.epoll, connection)
+            } else {
+                eprintln!(
+                    "Warning: replica {} fell out of the backlog, it has to sync again",
+                    fd
+                );
+                Err(RedisError::ConnectionClosed)
+            };
+
+            if result.is_err() {
+                self.close_connection(fd);
+            }
+        }
+    }
+
+    // a replica connects to its master and tells it how far it got
+    fn replication_cron(&mut self) {
+        if let Some((host, port)) = self.redis.master_to_connect()
+            && let Err(err) = self.connect_to_master(&host, port)
+        {
+            eprintln!(
+                "Warning: can't sync with the master {}:{}: {:?}",
+                host, port, err
+            );
+        }
+
+        if self.last_master_ack.elapsed() < MASTER_ACK_INTERVAL {
+            return;
+        }
+        self.last_master_ack = Instant::now();
+
+        let master = self
+            .connections
+            .iter_mut()
+            .flatten()
+            .find(|connection| connection.kind == ConnectionKind::Master);
+        if let Some(connection) = master {
+            connection
+                .write_buffer
+                .append_bytes(&self.redis.replica_ack());
+            let fd = connection.soc.fd;
+            if Self::flush_write_buffer_after_read(&self.epoll, connection).is_err() {
+                self.close_connection(fd);
+            }
+        }
+    }
+
+    // hands the cluster links the messages of this round
+    fn feed_cluster_links(&mut self) {
+        let mut failed = Vec::new();
+        for (fd, message) in self.redis.take_cluster_messages() {
+            let Some(connection) = &mut self.connections[fd as usize] else {
+                continue;
+            };
+            connection.write_buffer.append_bytes(&message);
+            if Self::flush_write_buffer_after_read(&self.epoll, connection).is_err() {
+                failed.push(fd);
+            }
+        }
+
+        for fd in failed {
+            self.close_connection(fd);
+        }
+    }
+
+    // opens the missing links to the other nodes. The connect doesn't block, the messages wait
+    // in the write buffer until it is done
+    fn cluster_cron(&mut self) {
+        let Self {
+            redis,
+            epoll,
+            connections,
+            ..
+        } = self;
+
+        redis.connect_cluster_links(|ip, bus_port| {
+            let ip = ip.parse::<std::net::Ipv4Addr>().ok()?;
+            let socket = Socket::new_tcp();
+            socket.set_non_blocking().ok()?;
+            match socket.connect(&make_ipv4_address(ip.into(), bus_port)) {
+                Ok(()) => {}
+                Err(e) if e.raw_os_error() == Some(EINPROGRESS) => {}
+                Err(_) => return None,
+            }
+
+            let fd = socket.fd;
+            epoll
+                .add(fd, (EPOLLOUT | EPOLLERR | EPOLLHUP) as u32)
+                .ok()?;
+            let mut connection = Connection::new(socket);
+            connection.kind = ConnectionKind::ClusterBus;
+            connections[fd as usize] = Some(connection);
+            Some(fd)
+        });
+    }
+
+    // the handshake with the master and the snapshot transfer block the event loop, unlike redis
+    // which does them a step at a time in it. Afterwards the link is a connection like the others
+    fn connect_to_master(&mut self, host: &str, port: u16) -> Result<(), RedisError> {
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| RedisError::Other(format!("can't resolve {}", host)))?;
+        let mut stream = TcpStream::connect_timeout(&address, MASTER_CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(self.redis.repl_timeout()))?;
+
+        // what the master sent after the reply that was read last
+        let mut buf = Vec::new();
+
+        let listening_port = self.port.to_string();
+        let handshake: [&[&[u8]]; 3] = [
+            &[b"PING"],
+            &[b"REPLCONF", b"listening-port", listening_port.as_bytes()],
+            &[b"REPLCONF", b"capa", b"psync2"],
+        ];
+        for args in handshake {
+            let mut command = Vec::new();
+            write_command(&mut command, args);
+            stream.write_all(&command)?;
+
+            let reply = read_line(&mut stream, &mut buf)?;
+            if !reply.starts_with(b"+") {
+                return Err(RedisError::Other(format!(
+                    "the master replied {}",
+                    String::from_utf8_lossy(&reply)
+                )));
+            }
+        }
+
+        let (replid, offset) = self.redis.psync_args();
+        let mut command = Vec::new();
+        write_command(
+            &mut command,
+            &[
+                &b"PSYNC"[..],
+                replid.as_bytes(),
+                offset.to_string().as_bytes(),
+            ],
+        );
+        stream.write_all(&command)?;
+
+        let reply = String::from_utf8_lossy(&read_line(&mut stream, &mut buf)?).into_owned();
+        match reply
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            ["+FULLRESYNC", replid, offset] => {
+                let offset = offset
+                    .parse()
+                    .map_err(|_| RedisError::Other(format!("invalid offset in {}", reply)))?;
+                let header = read_line(&mut stream, &mut buf)?;
+                let len = std::str::from_utf8(&header)
+                    .ok()
+                    .and_then(|header| header.strip_prefix('$'))
+                    .and_then(|len| len.parse().ok())
+                    .ok_or_else(|| RedisError::Other("invalid snapshot length".to_string()))?;
+
+                while buf.len() < len {
+                    read_more(&mut stream, &mut buf)?;
+                }
+                let snapshot: Vec<u8> = buf.drain(..len).collect();
+                self.redis.load_master_snapshot(replid, offset, &snapshot)?;
+            }
+            ["+CONTINUE"] => self.redis.continue_with_master(None),
+            ["+CONTINUE", replid] => self.redis.continue_with_master(Some(replid)),
+            _ => {
+                return Err(RedisError::Other(format!(
+                    "unexpected reply to PSYNC: {}",
+                    reply
+                )));
+            }
+        }
+
+        stream.set_nonblocking(true)?;
+        let socket = Socket {
+            fd: stream.into_raw_fd(),
+        };
+        let fd = socket.fd;
+        self.epoll.add(fd, (EPOLLIN | EPOLLERR | EPOLLHUP) as u32)?;
+
+        let mut connection = Connection::new(socket);
+        connection.kind = ConnectionKind::Master;
+        // the stream may have come in together with the snapshot
+        connection.read_buffer.buf = buf;
+        self.redis.master_link_up(fd);
+
+        let result = Self::process_read_buffer(&mut self.redis, &self.epoll, &mut connection);
+        self.connections[fd as usize] = Some(connection);
+        if result.is_err() {
+            self.close_connection(fd);
+        }
+
+        Ok(())
+    }
+
     fn handle_redis_result(result: &RedisResult, write_buffer: &mut WriteBuffer) {
         match result {
             RedisResult::SimpleString(simple_string) => {
@@ -208,6 +704,9 @@ impl Server {
                 let response = format!(":{}\r\n", num);
                 write_buffer.append_bytes(response.as_bytes());
             }
+            RedisResult::Error(RedisError::CommandError(e)) => {
+                handle_command_error(e, write_buffer);
+            }
             _ => unreachable!("FOR NOW YOU SHOULD NOT BE ABLE TO GET HERE"),
         }
     }
@@ -1,12 +1,37 @@
-use redis::error::RedisError;
+use redis::config::Config;
+use redis::error::{ConfigError, RedisError};
 use redis::server::Server;
 
-
-
 fn main() -> Result<(), RedisError> {
-    let mut server = Server::new(0, 1234)?;
+    let config = parse_config_args(std::env::args().skip(1))?;
+    let port = config.port;
+    let mut server = Server::with_config(0, port, config)?;
 
     server.run()?;
 
     Ok(())
 }
+
+// options are passed like redis-server does it, eg. `--list-encoding listpack` or
+// `--replicaof "127.0.0.1 1234"`
+fn parse_config_args(args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+    let mut config = Config::new();
+    let args: Vec<String> = args.collect();
+
+    for pair in args.chunks(2) {
+        let name = pair[0].trim_start_matches("--");
+        let value = match pair.get(1) {
+            Some(value) => value,
+            None => {
+                return Err(ConfigError::InvalidValue {
+                    name: name.as_bytes().to_vec(),
+                    value: Vec::new(),
+                });
+            }
+        };
+
+        config.set(name.as_bytes(), value.as_bytes())?;
+    }
+
+    Ok(config)
+}
@@ -0,0 +1,66 @@
+#!/bin/sh
+# Writes rdb-v6/v9/v10/v12.rdb with the redis release that produces each rdb version, using the
+# official docker images. The data set matches the keys test_load_rdb_v* expect; idle times and
+# lfu counters depend on the clock, so check those assertions after regenerating.
+set -eu
+
+cd "$(dirname "$0")"
+
+# fixture, image, maxmemory-policy
+for spec in "rdb-v6 redis:3.0 volatile-lru" \
+    "rdb-v9 redis:6.2 allkeys-lru" \
+    "rdb-v10 redis:7.0 allkeys-lfu" \
+    "rdb-v12 redis:7.4 allkeys-lru"; do
+    set -- $spec
+    name=$1
+    image=$2
+    policy=$3
+    container=$(docker run -d --rm "$image" redis-server --save "" --maxmemory-policy "$policy")
+    cli() {
+        docker exec -i "$container" redis-cli "$@" > /dev/null
+    }
+    sleep 1
+
+    case $name in
+    rdb-v6)
+        cli set string "hello world"
+        cli set counter 12345
+        cli set expiring soon
+        cli pexpireat expiring 4102444800000
+        for i in $(seq 0 11); do cli rpush list "item:$i"; done
+        cli rpush list 1024 7 -3
+        cli rpush linked "$(printf 'a%.0s' $(seq 70))" b
+        cli set compressed "$(printf 'abc%.0s' $(seq 40))"
+        cli sadd set member
+        cli -n 1 set other db
+        ;;
+    rdb-v9)
+        for i in $(seq 0 19); do cli rpush list "node:$i"; done
+        cli rpush list 1 2 3 100000 tail
+        cli set cold value
+        cli set session token
+        cli pexpireat session 4102444800000
+        cli xadd stream '*' field value
+        ;;
+    rdb-v10)
+        cli function load "#!lua name=lib
+redis.register_function('f', function() return 1 end)"
+        cli rpush list a b 300 -7 "$(printf 'c%.0s' $(seq 80))"
+        cli rpush plain first "$(printf 'p%.0s' $(seq 100))" last 42
+        cli set hot value
+        for i in $(seq 100); do cli get hot; done
+        ;;
+    rdb-v12)
+        cli set string hello
+        cli rpush list x y
+        cli pexpireat list 4102444800000
+        cli hset hash field value
+        cli hexpire hash 3600 fields 1 field
+        ;;
+    esac
+
+    cli save
+    docker cp "$container:/data/dump.rdb" "$name.rdb"
+    docker exec "$container" redis-server --version | cut -d ' ' -f 3 > "$name.version"
+    docker stop "$container" > /dev/null
+done
@@ -623,3 +623,1726 @@ fn test_lists() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[serial]
+fn test_lists_listpack_encoding() -> std::io::Result<()> {
+    // spawn server in another thread with lists encoded as listpacks
+
+    thread::spawn(|| {
+        let mut config = redis::config::Config::new();
+        config.list_encoding = redis::config::ListEncoding::ListPack;
+        let mut server = redis::server::Server::with_config(0, 1235, config).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1235")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$5\r\nLPUSH\r\n$2\r\nls\r\n$5\r\nhello\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nls\r\n$4\r\n1000\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nLPUSH\r\n$2\r\nls\r\n$5\r\nworld\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nls\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*3\r\n$5\r\nworld\r\n$5\r\nhello\r\n$4\r\n1000\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nRPOP\r\n$2\r\nls\r\n",
+            expected: b"$4\r\n1000\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nLPOP\r\n$2\r\nls\r\n",
+            expected: b"$5\r\nworld\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nLPOP\r\n$2\r\nls\r\n",
+            expected: b"$5\r\nhello\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_lrange() -> std::io::Result<()> {
+    // spawn server in another thread
+
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nlr\r\n$1\r\na\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nlr\r\n$3\r\n100\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nlr\r\n$1\r\nc\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nlr\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*3\r\n$1\r\na\r\n$3\r\n100\r\n$1\r\nc\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nlr\r\n$1\r\n1\r\n$1\r\n1\r\n",
+            expected: b"*1\r\n$3\r\n100\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nlr\r\n$2\r\n-2\r\n$3\r\n100\r\n",
+            expected: b"*2\r\n$3\r\n100\r\n$1\r\nc\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nlr\r\n$1\r\n2\r\n$1\r\n1\r\n",
+            expected: b"*0\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$7\r\nmissing\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*0\r\n",
+        },
+        // errors
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nlr\r\n$1\r\na\r\n$2\r\n-1\r\n",
+            expected: b"-ERR value is not an integer or out of range\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$2\r\nst\r\n$3\r\nbar\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$2\r\nst\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_sort() -> std::io::Result<()> {
+    // spawn server in another thread
+
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nsl\r\n$1\r\n3\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nsl\r\n$2\r\n10\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nsl\r\n$1\r\n1\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nsl\r\n$1\r\n2\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nw_3\r\n$1\r\n1\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$4\r\nw_10\r\n$1\r\n4\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nw_1\r\n$1\r\n3\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nw_2\r\n$1\r\n2\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nn_3\r\n$5\r\nthree\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$4\r\nn_10\r\n$3\r\nten\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nn_1\r\n$3\r\none\r\n",
+            expected: b"+OK\r\n",
+        },
+        // numeric and alpha order
+        TestData {
+            command: b"*2\r\n$4\r\nSORT\r\n$2\r\nsl\r\n",
+            expected: b"*4\r\n$1\r\n1\r\n$1\r\n2\r\n$1\r\n3\r\n$2\r\n10\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$4\r\nDESC\r\n",
+            expected: b"*4\r\n$2\r\n10\r\n$1\r\n3\r\n$1\r\n2\r\n$1\r\n1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$5\r\nALPHA\r\n",
+            expected: b"*4\r\n$1\r\n1\r\n$2\r\n10\r\n$1\r\n2\r\n$1\r\n3\r\n",
+        },
+        TestData {
+            command: b"*5\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$5\r\nLIMIT\r\n$1\r\n1\r\n$1\r\n2\r\n",
+            expected: b"*2\r\n$1\r\n2\r\n$1\r\n3\r\n",
+        },
+        // sorting by and getting other keys
+        TestData {
+            command: b"*4\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$2\r\nBY\r\n$3\r\nw_*\r\n",
+            expected: b"*4\r\n$1\r\n3\r\n$1\r\n2\r\n$1\r\n1\r\n$2\r\n10\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$2\r\nBY\r\n$6\r\nnosort\r\n",
+            expected: b"*4\r\n$1\r\n3\r\n$2\r\n10\r\n$1\r\n1\r\n$1\r\n2\r\n",
+        },
+        TestData {
+            command: b"*6\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$3\r\nGET\r\n$1\r\n#\r\n$3\r\nGET\r\n$3\r\nn_*\r\n",
+            expected: b"*8\r\n$1\r\n1\r\n$3\r\none\r\n$1\r\n2\r\n$-1\r\n$1\r\n3\r\n$5\r\nthree\r\n$2\r\n10\r\n$3\r\nten\r\n",
+        },
+        TestData {
+            command: b"*7\r\n$7\r\nSORT_RO\r\n$2\r\nsl\r\n$2\r\nBY\r\n$3\r\nw_*\r\n$4\r\nDESC\r\n$3\r\nGET\r\n$3\r\nn_*\r\n",
+            expected: b"*4\r\n$3\r\nten\r\n$3\r\none\r\n$-1\r\n$5\r\nthree\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nSORT\r\n$7\r\nmissing\r\n",
+            expected: b"*0\r\n",
+        },
+        // store
+        TestData {
+            command: b"*5\r\n$4\r\nSORT\r\n$2\r\nsl\r\n$4\r\nDESC\r\n$5\r\nSTORE\r\n$6\r\nsorted\r\n",
+            expected: b":4\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$6\r\nsorted\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*4\r\n$2\r\n10\r\n$1\r\n3\r\n$1\r\n2\r\n$1\r\n1\r\n",
+        },
+        // errors
+        TestData {
+            command: b"*4\r\n$7\r\nSORT_RO\r\n$2\r\nsl\r\n$5\r\nSTORE\r\n$6\r\nsorted\r\n",
+            expected: b"-ERR syntax error\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$2\r\nsl\r\n$3\r\nabc\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nSORT\r\n$2\r\nsl\r\n",
+            expected: b"-ERR One or more scores can't be converted into double\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nSORT\r\n$3\r\nw_1\r\n",
+            expected: b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_scan() -> std::io::Result<()> {
+    // spawn server in another thread
+
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    // the key order of a scan depends on the random hash seed, so every scan here matches at
+    // most one key and uses a COUNT that covers the whole table in one call
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$9\r\nscan:only\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$9\r\nscan:list\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*6\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$7\r\nscan:o*\r\n$5\r\nCOUNT\r\n$4\r\n1000\r\n",
+            expected: b"*2\r\n$1\r\n0\r\n*1\r\n$9\r\nscan:only\r\n",
+        },
+        TestData {
+            command: b"*8\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$6\r\nscan:*\r\n$4\r\nTYPE\r\n$4\r\nlist\r\n$5\r\nCOUNT\r\n$4\r\n1000\r\n",
+            expected: b"*2\r\n$1\r\n0\r\n*1\r\n$9\r\nscan:list\r\n",
+        },
+        TestData {
+            command: b"*6\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$7\r\nnomatch\r\n$5\r\nCOUNT\r\n$4\r\n1000\r\n",
+            expected: b"*2\r\n$1\r\n0\r\n*0\r\n",
+        },
+        // errors
+        TestData {
+            command: b"*2\r\n$4\r\nSCAN\r\n$3\r\nabc\r\n",
+            expected: b"-ERR invalid cursor\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n",
+            expected: b"-ERR syntax error\r\n",
+        },
+        TestData {
+            command: b"*1\r\n$4\r\nSCAN\r\n",
+            expected: b"-ERR wrong number of arguments for 'SCAN' command\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_random_key() -> std::io::Result<()> {
+    // spawn a server of its own so the keyspace starts out empty
+
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1236).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1236")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*1\r\n$9\r\nRANDOMKEY\r\n",
+            expected: b"$-1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$4\r\nonly\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*1\r\n$9\r\nRANDOMKEY\r\n",
+            expected: b"$4\r\nonly\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$9\r\nRANDOMKEY\r\n$5\r\nextra\r\n",
+            expected: b"-ERR wrong number of arguments for 'RANDOMKEY' command\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_memory() -> std::io::Result<()> {
+    // spawn server in another thread
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n$11\r\nmemory:none\r\n",
+            expected: b"$-1\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n",
+            expected: b"-ERR wrong number of arguments for 'MEMORY|USAGE' command\r\n",
+        },
+        TestData {
+            command:
+                b"*5\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n$3\r\nkey\r\n$7\r\nSAMPLES\r\n$2\r\n-1\r\n",
+            expected: b"-ERR syntax error\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$6\r\nmemory\r\n$6\r\ndoctor\r\n",
+            expected: b"-ERR unknown subcommand 'doctor'. Try MEMORY HELP.\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$10\r\nmemory:big\r\n$20\r\nxxxxxxxxxxxxxxxxxxxx\r\n",
+            expected: b"+OK\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    // the size depends on the dict, it has to at least hold the key and the value
+    stream.write_all(b"*3\r\n$6\r\nMEMORY\r\n$5\r\nUSAGE\r\n$10\r\nmemory:big\r\n")?;
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    while !reply.ends_with(b"\r\n") {
+        stream.read_exact(&mut byte)?;
+        reply.push(byte[0]);
+    }
+
+    assert_eq!(b':', reply[0]);
+    let usage: usize = std::str::from_utf8(&reply[1..reply.len() - 2])
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(usage >= 10 + 20, "usage {}", usage);
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_keyspace_commands() -> std::io::Result<()> {
+    // spawn server in another thread
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$8\r\nks:first\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nEXISTS\r\n$8\r\nks:first\r\n$7\r\nks:none\r\n$8\r\nks:first\r\n",
+            expected: b":2\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nTYPE\r\n$8\r\nks:first\r\n",
+            expected: b"+string\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nRENAME\r\n$7\r\nks:none\r\n$8\r\nks:other\r\n",
+            expected: b"-ERR no such key\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nRENAME\r\n$8\r\nks:first\r\n$9\r\nks:second\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nTYPE\r\n$8\r\nks:first\r\n",
+            expected: b"+none\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$4\r\nCOPY\r\n$9\r\nks:second\r\n$9\r\nks:second\r\n",
+            expected: b"-ERR source and destination objects are the same\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$4\r\nCOPY\r\n$9\r\nks:second\r\n$8\r\nks:third\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$8\r\nRENAMENX\r\n$9\r\nks:second\r\n$8\r\nks:third\r\n",
+            expected: b":0\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$3\r\nGET\r\n$8\r\nks:third\r\n",
+            expected: b"$5\r\nvalue\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nTOUCH\r\n$8\r\nks:third\r\n$7\r\nks:none\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nUNLINK\r\n$9\r\nks:second\r\n$8\r\nks:third\r\n",
+            expected: b":2\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_keys() -> std::io::Result<()> {
+    // spawn server in another thread
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    // the server is shared with other tests, the patterns only match keys set here
+    let tests = vec![
+        TestData {
+            command: b"*2\r\n$4\r\nKEYS\r\n$9\r\nkeystest*\r\n",
+            expected: b"*0\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$11\r\nkeystest:ab\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$11\r\nkeystest:cd\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nkeys\r\n$15\r\nkeystest:[a-b]*\r\n",
+            expected: b"*1\r\n$11\r\nkeystest:ab\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nKEYS\r\n$12\r\nkeystest:\\cd\r\n",
+            expected: b"*1\r\n$11\r\nkeystest:cd\r\n",
+        },
+        TestData {
+            command: b"*1\r\n$4\r\nKEYS\r\n",
+            expected: b"-ERR wrong number of arguments for 'KEYS' command\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_databases() -> std::io::Result<()> {
+    // spawn a server of its own, flushing the shared one would break the other tests
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1237).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // two connections that select databases on their own
+    let mut first = TcpStream::connect("127.0.0.1:1237")?;
+    let mut second = TcpStream::connect("127.0.0.1:1237")?;
+
+    struct TestData {
+        second_connection: bool,
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            second_connection: false,
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$4\r\nzero\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$6\r\nSELECT\r\n$2\r\n16\r\n",
+            expected: b"-ERR DB index is out of range\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$6\r\nSELECT\r\n$3\r\none\r\n",
+            expected: b"-ERR invalid DB index\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$6\r\nselect\r\n$1\r\n1\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+            expected: b"$-1\r\n",
+        },
+        TestData {
+            second_connection: false,
+            command: b"*3\r\n$4\r\nMOVE\r\n$3\r\nkey\r\n$1\r\n1\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+            expected: b"$4\r\nzero\r\n",
+        },
+        TestData {
+            second_connection: false,
+            command: b"*1\r\n$6\r\nDBSIZE\r\n",
+            expected: b":0\r\n",
+        },
+        TestData {
+            second_connection: false,
+            command: b"*3\r\n$6\r\nSWAPDB\r\n$1\r\n0\r\n$1\r\n1\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            second_connection: false,
+            command: b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+            expected: b"$4\r\nzero\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$7\r\nFLUSHDB\r\n$5\r\nLATER\r\n",
+            expected: b"-ERR syntax error\r\n",
+        },
+        TestData {
+            second_connection: true,
+            command: b"*2\r\n$8\r\nFLUSHALL\r\n$5\r\nASYNC\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            second_connection: false,
+            command: b"*1\r\n$6\r\nDBSIZE\r\n",
+            expected: b":0\r\n",
+        },
+    ];
+
+    for test in tests {
+        let stream = if test.second_connection {
+            &mut second
+        } else {
+            &mut first
+        };
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_expire() -> std::io::Result<()> {
+    // spawn server in another thread
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$10\r\nexpire:key\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$3\r\nTTL\r\n$10\r\nexpire:key\r\n",
+            expected: b":-1\r\n",
+        },
+        TestData {
+            command:
+                b"*4\r\n$8\r\nEXPIREAT\r\n$10\r\nexpire:key\r\n$10\r\n4102444800\r\n$2\r\nNX\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$11\r\nPEXPIRETIME\r\n$10\r\nexpire:key\r\n",
+            expected: b":4102444800000\r\n",
+        },
+        TestData {
+            command:
+                b"*5\r\n$6\r\nEXPIRE\r\n$10\r\nexpire:key\r\n$2\r\n10\r\n$2\r\nNX\r\n$2\r\nXX\r\n",
+            expected: b"-ERR NX and XX, GT or LT options at the same time are not compatible\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nEXPIRE\r\n$10\r\nexpire:key\r\n$2\r\n10\r\n$4\r\nSOON\r\n",
+            expected: b"-ERR Unsupported option SOON\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$7\r\nPERSIST\r\n$10\r\nexpire:key\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$7\r\nPEXPIRE\r\n$10\r\nexpire:key\r\n$1\r\n1\r\n",
+            expected: b":1\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    // gone once the millisecond passed, whether it was read or reclaimed in the background
+    thread::sleep(Duration::from_millis(10));
+    stream.write_all(b"*2\r\n$4\r\nPTTL\r\n$10\r\nexpire:key\r\n")?;
+    let mut buf = vec![0u8; 5];
+    stream.read_exact(&mut buf)?;
+    assert_eq!(b":-2\r\n", buf.as_slice());
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_object() -> std::io::Result<()> {
+    // spawn server in another thread
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$10\r\nobject:int\r\n$2\r\n42\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$10\r\nobject:str\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nOBJECT\r\n$8\r\nENCODING\r\n$10\r\nobject:int\r\n",
+            expected: b"$3\r\nint\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nobject\r\n$8\r\nencoding\r\n$10\r\nobject:str\r\n",
+            expected: b"$6\r\nembstr\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nOBJECT\r\n$8\r\nENCODING\r\n$11\r\nobject:none\r\n",
+            expected: b"$-1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nOBJECT\r\n$8\r\nREFCOUNT\r\n$10\r\nobject:int\r\n",
+            expected: b":2147483647\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nOBJECT\r\n$8\r\nREFCOUNT\r\n$10\r\nobject:str\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nOBJECT\r\n$4\r\nFREQ\r\n$10\r\nobject:str\r\n",
+            expected: b":5\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$6\r\nOBJECT\r\n$8\r\nIDLETIME\r\n",
+            expected: b"-ERR wrong number of arguments for 'OBJECT|IDLETIME' command\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nOBJECT\r\n$3\r\nlru\r\n$10\r\nobject:str\r\n",
+            expected: b"-ERR unknown subcommand 'lru'. Try OBJECT HELP.\r\n",
+        },
+        // only the first line of the help is read, it is the last command
+        TestData {
+            command: b"*2\r\n$6\r\nOBJECT\r\n$4\r\nHELP\r\n",
+            expected:
+                b"*15\r\n+OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_dump_and_restore() -> std::io::Result<()> {
+    // spawn server in another thread
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1234).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    // connect socket to server
+    let mut stream = TcpStream::connect("127.0.0.1:1234")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$9\r\ndump:from\r\n$2\r\n10\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\nDUMP\r\n$9\r\ndump:from\r\n",
+            expected: b"$13\r\n\x00\xC0\x0A\x0A\x00\x6E\x9F\x57\x45\x0E\xAE\x63\xBB\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$4\r\ndump\r\n$9\r\ndump:none\r\n",
+            expected: b"$-1\r\n",
+        },
+        // the payload from the redis documentation, written by an older rdb version
+        TestData {
+            command: b"*4\r\n$7\r\nRESTORE\r\n$7\r\ndump:to\r\n$1\r\n0\r\n$13\r\n\x00\xC0\x0A\x09\x00\xBE\x6D\x06\x89\x5A\x28\x00\x0A\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$3\r\nGET\r\n$7\r\ndump:to\r\n",
+            expected: b"$2\r\n10\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$7\r\nRESTORE\r\n$7\r\ndump:to\r\n$1\r\n0\r\n$13\r\n\x00\xC0\x0A\x09\x00\xBE\x6D\x06\x89\x5A\x28\x00\x0A\r\n",
+            expected: b"-BUSYKEY Target key name already exists.\r\n",
+        },
+        TestData {
+            command: b"*5\r\n$7\r\nrestore\r\n$7\r\ndump:to\r\n$5\r\n10000\r\n$13\r\n\x00\xC0\x0B\x09\x00\xBE\x6D\x06\x89\x5A\x28\x00\x0A\r\n$7\r\nREPLACE\r\n",
+            expected: b"-ERR DUMP payload version or checksum are wrong\r\n",
+        },
+        TestData {
+            command: b"*5\r\n$7\r\nrestore\r\n$7\r\ndump:to\r\n$5\r\n10000\r\n$13\r\n\x00\xC0\x0A\x09\x00\xBE\x6D\x06\x89\x5A\x28\x00\x0A\r\n$7\r\nREPLACE\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$3\r\nTTL\r\n$7\r\ndump:to\r\n",
+            expected: b":10\r\n",
+        },
+        TestData {
+            command: b"*6\r\n$7\r\nRESTORE\r\n$7\r\ndump:to\r\n$1\r\n0\r\n$13\r\n\x00\xC0\x0A\x09\x00\xBE\x6D\x06\x89\x5A\x28\x00\x0A\r\n$4\r\nFREQ\r\n$3\r\n256\r\n",
+            expected: b"-ERR Invalid FREQ value, must be >= 0 and <= 255\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_save_and_load_at_startup() -> std::io::Result<()> {
+    // a directory of its own so no other test loads the snapshot
+    let dir = std::env::temp_dir().join(format!("redis-server-save-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let config = || {
+        let mut config = redis::config::Config::new();
+        config
+            .set(b"dir", dir.to_str().unwrap().as_bytes())
+            .unwrap();
+        config
+    };
+
+    let first_config = config();
+    thread::spawn(move || {
+        let mut server = redis::server::Server::with_config(0, 1238, first_config).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    let mut stream = TcpStream::connect("127.0.0.1:1238")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$5\r\nsaved\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*1\r\n$4\r\nSAVE\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$10\r\nbackground\r\n$1\r\na\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*1\r\n$6\r\nBGSAVE\r\n",
+            expected: b"+Background saving started\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$6\r\nBGSAVE\r\n$8\r\nSCHEDULE\r\n",
+            expected: b"-ERR syntax error\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    // LASTSAVE is a unix time in seconds
+    stream.write_all(b"*1\r\n$8\r\nLASTSAVE\r\n")?;
+    let mut buf = [0u8; 13];
+    stream.read_exact(&mut buf)?;
+    assert!(
+        buf.starts_with(b":1") && buf.ends_with(b"\r\n"),
+        "{:?}",
+        buf
+    );
+
+    // the child is reaped by the periodic tasks
+    thread::sleep(Duration::from_millis(500));
+
+    // a second server loads the snapshot when it starts
+    let second_config = config();
+    thread::spawn(move || {
+        let mut server = redis::server::Server::with_config(0, 1239, second_config).unwrap();
+        server.run().unwrap();
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    let mut stream = TcpStream::connect("127.0.0.1:1239")?;
+
+    let tests = vec![
+        TestData {
+            command: b"*2\r\n$3\r\nGET\r\n$5\r\nsaved\r\n",
+            expected: b"$5\r\nvalue\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$10\r\nbackground\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*1\r\n$1\r\na\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_append_only_file_at_startup() -> std::io::Result<()> {
+    // a directory of its own so no other test loads the log
+    let dir = std::env::temp_dir().join(format!("redis-server-aof-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let config = || {
+        let mut config = redis::config::Config::new();
+        config
+            .set(b"dir", dir.to_str().unwrap().as_bytes())
+            .unwrap();
+        config.set(b"appendonly", b"yes").unwrap();
+        config.set(b"appendfsync", b"always").unwrap();
+        config
+    };
+
+    let first_config = config();
+    thread::spawn(move || {
+        let mut server = redis::server::Server::with_config(0, 1240, first_config).unwrap();
+        server.run().unwrap();
+    });
+
+    // give server a moment to start
+    thread::sleep(Duration::from_millis(200));
+
+    let mut stream = TcpStream::connect("127.0.0.1:1240")?;
+
+    struct TestData {
+        command: &'static [u8],
+        expected: &'static [u8],
+    }
+
+    let tests = vec![
+        TestData {
+            command: b"*3\r\n$3\r\nSET\r\n$6\r\nlogged\r\n$5\r\nvalue\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$6\r\nEXPIRE\r\n$6\r\nlogged\r\n$3\r\n100\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$6\r\nSELECT\r\n$1\r\n3\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*1\r\n$12\r\nBGREWRITEAOF\r\n",
+            expected: b"+Background append only file rewriting started\r\n",
+        },
+        TestData {
+            command: b"*3\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\nb\r\n",
+            expected: b"+OK\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    // the child is reaped by the periodic tasks
+    thread::sleep(Duration::from_millis(500));
+
+    // a second server replays the log when it starts
+    let second_config = config();
+    thread::spawn(move || {
+        let mut server = redis::server::Server::with_config(0, 1241, second_config).unwrap();
+        server.run().unwrap();
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    let mut stream = TcpStream::connect("127.0.0.1:1241")?;
+
+    let tests = vec![
+        TestData {
+            command: b"*2\r\n$3\r\nGET\r\n$6\r\nlogged\r\n",
+            expected: b"$5\r\nvalue\r\n",
+        },
+        // the ttl came back with the key
+        TestData {
+            command: b"*2\r\n$7\r\nPERSIST\r\n$6\r\nlogged\r\n",
+            expected: b":1\r\n",
+        },
+        TestData {
+            command: b"*2\r\n$6\r\nSELECT\r\n$1\r\n3\r\n",
+            expected: b"+OK\r\n",
+        },
+        TestData {
+            command: b"*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*2\r\n$1\r\na\r\n$1\r\nb\r\n",
+        },
+    ];
+
+    for test in tests {
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn test_replication() -> std::io::Result<()> {
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1242).unwrap();
+        server.run().unwrap();
+    });
+    thread::spawn(|| {
+        let mut server = redis::server::Server::new(0, 1243).unwrap();
+        server.run().unwrap();
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    let mut master = TcpStream::connect("127.0.0.1:1242")?;
+    let mut replica = TcpStream::connect("127.0.0.1:1243")?;
+
+    struct TestData {
+        to_replica: bool,
+        command: &'static [u8],
+        expected: &'static [u8],
+        // time for the replica to connect and sync
+        wait_after: Duration,
+    }
+
+    let tests = vec![
+        // the replica gets this one with the snapshot
+        TestData {
+            to_replica: false,
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: true,
+            command: b"*3\r\n$9\r\nREPLICAOF\r\n$9\r\n127.0.0.1\r\n$4\r\n1242\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::from_millis(1000),
+        },
+        TestData {
+            to_replica: true,
+            command: b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n",
+            expected: b"$3\r\nbar\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: true,
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbaz\r\n",
+            expected: b"-READONLY You can't write against a read only replica.\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: true,
+            command: b"*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$1\r\n0\r\n",
+            expected: b"-ERR WAIT cannot be used with replica instances.\r\n",
+            wait_after: Duration::ZERO,
+        },
+        // the rest comes with the stream of write commands
+        TestData {
+            to_replica: false,
+            command: b"*2\r\n$6\r\nSELECT\r\n$1\r\n2\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: false,
+            command: b"*3\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: false,
+            command: b"*3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$4\r\n5000\r\n",
+            expected: b":1\r\n",
+            wait_after: Duration::ZERO,
+        },
+        // nobody else is there
+        TestData {
+            to_replica: false,
+            command: b"*3\r\n$4\r\nWAIT\r\n$1\r\n2\r\n$3\r\n100\r\n",
+            expected: b":1\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: true,
+            command: b"*2\r\n$6\r\nSELECT\r\n$1\r\n2\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: true,
+            command: b"*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            expected: b"*1\r\n$1\r\na\r\n",
+            wait_after: Duration::ZERO,
+        },
+        // a promoted replica takes writes
+        TestData {
+            to_replica: true,
+            command: b"*3\r\n$9\r\nREPLICAOF\r\n$2\r\nNO\r\n$3\r\nONE\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::ZERO,
+        },
+        TestData {
+            to_replica: true,
+            command: b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbaz\r\n",
+            expected: b"+OK\r\n",
+            wait_after: Duration::ZERO,
+        },
+    ];
+
+    for test in tests {
+        let stream = if test.to_replica {
+            &mut replica
+        } else {
+            &mut master
+        };
+        stream.write_all(test.command)?;
+
+        let mut buf = vec![0u8; test.expected.len()];
+        stream.read_exact(&mut buf)?;
+
+        assert_eq!(
+            test.expected,
+            buf.as_slice(),
+            "expected {:?}\ngot: {:?}",
+            test.expected,
+            buf.as_slice(),
+        );
+
+        thread::sleep(test.wait_after);
+    }
+
+    Ok(())
+}
+
+// a command as a resp array of bulk strings
+fn command(args: &[&str]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        buf.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+    }
+    buf
+}
+
+// a line reply, or the content of a bulk string reply
+fn read_reply(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    line.truncate(line.len() - 2);
+
+    if line[0] != b'$' {
+        return Ok(line);
+    }
+    let len: usize = std::str::from_utf8(&line[1..]).unwrap().parse().unwrap();
+    let mut content = vec![0u8; len + 2];
+    stream.read_exact(&mut content)?;
+    content.truncate(len);
+    Ok(content)
+}
+
+#[test]
+#[serial]
+fn test_cluster() -> std::io::Result<()> {
+    let ports = [1244, 1245, 1246];
+    for port in ports {
+        thread::spawn(move || {
+            let mut config = redis::config::Config::new();
+            config.set(b"cluster-enabled", b"yes").unwrap();
+            config.set(b"cluster-node-timeout", b"2000").unwrap();
+            let mut server = redis::server::Server::with_config(0, port, config).unwrap();
+            server.run().unwrap();
+        });
+    }
+    thread::sleep(Duration::from_millis(200));
+
+    let mut nodes = Vec::new();
+    for port in ports {
+        nodes.push(TcpStream::connect(("127.0.0.1", port))?);
+    }
+
+    struct TestData {
+        node: usize,
+        command: Vec<u8>,
+        expected: &'static [u8],
+    }
+
+    let run = |nodes: &mut Vec<TcpStream>, tests: Vec<TestData>| -> std::io::Result<()> {
+        for test in tests {
+            let stream = &mut nodes[test.node];
+            stream.write_all(&test.command)?;
+            let reply = read_reply(stream)?;
+            assert_eq!(
+                test.expected,
+                reply.as_slice(),
+                "expected {:?}\ngot: {:?}",
+                String::from_utf8_lossy(test.expected),
+                String::from_utf8_lossy(&reply),
+            );
+        }
+        Ok(())
+    };
+
+    // the first node meets the others and each one takes a third of the slots
+    run(
+        &mut nodes,
+        vec![
+            TestData {
+                node: 0,
+                command: command(&["CLUSTER", "MEET", "127.0.0.1", "1245"]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 0,
+                command: command(&["CLUSTER", "MEET", "127.0.0.1", "1246"]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 0,
+                command: command(&["CLUSTER", "ADDSLOTSRANGE", "0", "5460"]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 1,
+                command: command(&["CLUSTER", "ADDSLOTSRANGE", "5461", "10922"]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["CLUSTER", "ADDSLOTSRANGE", "10923", "16383"]),
+                expected: b"+OK",
+            },
+        ],
+    )?;
+
+    // every node has to learn about every other one through the gossip
+    for node in &mut nodes {
+        let mut tries = 0;
+        loop {
+            node.write_all(&command(&["CLUSTER", "INFO"]))?;
+            let info = String::from_utf8(read_reply(node)?).unwrap();
+            if info.contains("cluster_state:ok") && info.contains("cluster_known_nodes:3") {
+                break;
+            }
+            tries += 1;
+            assert!(tries < 100, "the cluster didn't come up: {}", info);
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    let mut ids = Vec::new();
+    for node in &mut nodes {
+        node.write_all(&command(&["CLUSTER", "MYID"]))?;
+        ids.push(String::from_utf8(read_reply(node)?).unwrap());
+    }
+
+    // the nodes start out with config epoch 0 and the collisions give them new ones. The slot
+    // only moves once they agree on three different config epochs, the new one it takes then is
+    // the only one that changes
+    let mut tries = 0;
+    loop {
+        let mut views = Vec::new();
+        for node in &mut nodes {
+            node.write_all(&command(&["CLUSTER", "NODES"]))?;
+            let reply = String::from_utf8(read_reply(node)?).unwrap();
+            let mut epochs: Vec<(String, String)> = reply
+                .lines()
+                .map(|line| {
+                    let fields: Vec<&str> = line.split(' ').collect();
+                    (fields[0].to_string(), fields[6].to_string())
+                })
+                .collect();
+            epochs.sort();
+            views.push(epochs);
+        }
+
+        let mut distinct: Vec<&String> = views[0].iter().map(|(_, epoch)| epoch).collect();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() == 3 && views.iter().all(|view| *view == views[0]) {
+            break;
+        }
+        tries += 1;
+        assert!(tries < 100, "the config epochs didn't settle: {:?}", views);
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    run(
+        &mut nodes,
+        vec![
+            TestData {
+                node: 0,
+                command: command(&["SET", "foo", "bar"]),
+                expected: b"-MOVED 12182 127.0.0.1:1246",
+            },
+            TestData {
+                node: 2,
+                command: command(&["SET", "foo", "bar"]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["GET", "foo"]),
+                expected: b"bar",
+            },
+            TestData {
+                node: 2,
+                command: command(&["DEL", "foo", "bar"]),
+                expected: b"-CROSSSLOT Keys in request don't hash to the same slot",
+            },
+            TestData {
+                node: 0,
+                command: command(&["CLUSTER", "KEYSLOT", "foo"]),
+                expected: b":12182",
+            },
+            // slot 12182 moves from the third node to the second one
+            TestData {
+                node: 1,
+                command: command(&["CLUSTER", "SETSLOT", "12182", "IMPORTING", &ids[2]]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["CLUSTER", "SETSLOT", "12182", "MIGRATING", &ids[1]]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["GET", "{foo}x"]),
+                expected: b"-ASK 12182 127.0.0.1:1245",
+            },
+            TestData {
+                node: 1,
+                command: command(&["GET", "foo"]),
+                expected: b"-MOVED 12182 127.0.0.1:1246",
+            },
+            TestData {
+                node: 2,
+                command: command(&["CLUSTER", "COUNTKEYSINSLOT", "12182"]),
+                expected: b":1",
+            },
+            TestData {
+                node: 2,
+                command: command(&["CLUSTER", "GETKEYSINSLOT", "12182", "10"]),
+                expected: b"*1",
+            },
+        ],
+    )?;
+
+    // the rest of the GETKEYSINSLOT reply
+    assert_eq!(b"foo".to_vec(), read_reply(&mut nodes[2])?);
+
+    run(
+        &mut nodes,
+        vec![
+            TestData {
+                node: 2,
+                command: command(&[
+                    "MIGRATE",
+                    "127.0.0.1",
+                    "1245",
+                    "",
+                    "0",
+                    "5000",
+                    "KEYS",
+                    "foo",
+                ]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["GET", "foo"]),
+                expected: b"-ASK 12182 127.0.0.1:1245",
+            },
+            TestData {
+                node: 1,
+                command: command(&["ASKING"]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 1,
+                command: command(&["GET", "foo"]),
+                expected: b"bar",
+            },
+            // only the command right after ASKING
+            TestData {
+                node: 1,
+                command: command(&["GET", "foo"]),
+                expected: b"-MOVED 12182 127.0.0.1:1246",
+            },
+            TestData {
+                node: 1,
+                command: command(&["CLUSTER", "SETSLOT", "12182", "NODE", &ids[1]]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["CLUSTER", "SETSLOT", "12182", "NODE", &ids[1]]),
+                expected: b"+OK",
+            },
+            TestData {
+                node: 2,
+                command: command(&["GET", "foo"]),
+                expected: b"-MOVED 12182 127.0.0.1:1245",
+            },
+            TestData {
+                node: 1,
+                command: command(&["GET", "foo"]),
+                expected: b"bar",
+            },
+        ],
+    )?;
+
+    // the first node learns about the new owner through the gossip
+    let mut tries = 0;
+    loop {
+        nodes[0].write_all(&command(&["GET", "foo"]))?;
+        let reply = read_reply(&mut nodes[0])?;
+        if reply == b"-MOVED 12182 127.0.0.1:1245" {
+            break;
+        }
+        tries += 1;
+        assert!(tries < 100, "the new owner wasn't gossiped");
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    Ok(())
+}